@@ -8,8 +8,10 @@ use std::time::Duration;
 
 use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use piramid::cli::animation;
+use piramid::collections::{self, CollectionOpenOptions, RecoveryTarget};
 use piramid::config::{self, AppConfig, LogLevel, LoggingConfig};
-use piramid::runtime::AppState;
+use piramid::runtime::{self, AppState};
+use piramid::services::snapshot;
 use piramid::{config::loader::RuntimeConfig, embeddings, server};
 use tokio::runtime::Runtime;
 use tracing_subscriber::EnvFilter;
@@ -52,6 +54,15 @@ enum Commands {
         command: ShowCommands,
     },
 
+    /// Create or restore collection snapshots in a local data dir
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+
+    /// Check collection files in a local data dir for consistency (server must be stopped)
+    Fsck(FsckArgs),
+
     /// Deprecated alias for `show config`
     #[command(hide = true)]
     ShowConfig {
@@ -92,6 +103,78 @@ struct ShowMetricsArgs {
     format: OutputFormat,
 }
 
+#[derive(Subcommand)]
+enum SnapshotCommands {
+    /// Checkpoint a collection and write a snapshot archive
+    Create(SnapshotCreateArgs),
+    /// Replace a collection with the contents of a snapshot
+    Restore(SnapshotRestoreArgs),
+}
+
+#[derive(Args)]
+struct SnapshotCreateArgs {
+    /// Collection to snapshot
+    #[arg(long)]
+    collection: String,
+    /// Also copy the archive to this path
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Optional config file to load (overrides CONFIG_FILE)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Optional data directory (overrides DATA_DIR)
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SnapshotRestoreArgs {
+    /// Collection to restore into (replaced if it exists)
+    #[arg(long)]
+    collection: String,
+    /// Snapshot archive file to restore from
+    #[arg(
+        long,
+        conflicts_with = "snapshot",
+        required_unless_present = "snapshot"
+    )]
+    from: Option<PathBuf>,
+    /// Id of a snapshot stored in the data dir
+    #[arg(long)]
+    snapshot: Option<String>,
+    /// Collection the stored snapshot belongs to (defaults to --collection)
+    #[arg(long, requires = "snapshot")]
+    source: Option<String>,
+    /// Replay archived WAL entries up to this sequence number
+    #[arg(long, conflicts_with = "until_time")]
+    until_seq: Option<u64>,
+    /// Replay archived WAL entries up to the last checkpoint at or before this unix time
+    #[arg(long)]
+    until_time: Option<u64>,
+    /// Optional config file to load (overrides CONFIG_FILE)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Optional data directory (overrides DATA_DIR)
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct FsckArgs {
+    /// Only check this collection (defaults to every collection in the data dir)
+    #[arg(long)]
+    collection: Option<String>,
+    /// Rebuild indexes from the data file and drop entries that cannot be read
+    #[arg(long)]
+    repair: bool,
+    /// Optional config file to load (overrides CONFIG_FILE)
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Optional data directory (overrides DATA_DIR)
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+}
+
 #[derive(Copy, Clone, ValueEnum)]
 enum OutputFormat {
     Yaml,
@@ -114,6 +197,20 @@ fn main() {
                 std::process::exit(1);
             }
         }
+        Some(Commands::Snapshot { command }) => {
+            if let Err(e) = handle_snapshot_command(command) {
+                eprintln!("Snapshot failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Fsck(args)) => match run_fsck(args) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("Fsck failed: {e}");
+                std::process::exit(1);
+            }
+        },
         Some(Commands::ShowConfig { config }) => {
             if let Err(e) = show_config(ShowConfigArgs {
                 config,
@@ -170,12 +267,158 @@ fn show_config(args: ShowConfigArgs) -> std::io::Result<()> {
 }
 
 fn show_metrics(args: ShowMetricsArgs) -> std::io::Result<()> {
+    let state = local_state(args.config, args.data_dir)?;
+    preload_collections_for_metrics(&state)?;
+    let metrics = piramid::services::admin::metrics(&state).map_err(std::io::Error::other)?;
+    print_serialized(&metrics, args.format)
+}
+
+fn handle_snapshot_command(command: SnapshotCommands) -> std::io::Result<()> {
+    match command {
+        SnapshotCommands::Create(args) => {
+            let state = local_state(args.config, args.data_dir)?;
+            let _lock = lock_data_dir(&state.data_dir)?;
+            let info = snapshot::create_snapshot(&state, args.collection.clone())
+                .map_err(std::io::Error::other)?;
+            let archive = snapshot::snapshot_file(&state, &args.collection, &info.id)
+                .map_err(std::io::Error::other)?;
+            if let Some(output) = args.output {
+                fs::copy(&archive, &output)?;
+                println!("Wrote snapshot {} to {}", info.id, output.display());
+            } else {
+                println!("Wrote snapshot {} to {}", info.id, archive.display());
+            }
+            Ok(())
+        }
+        SnapshotCommands::Restore(args) => {
+            let state = local_state(args.config, args.data_dir)?;
+            let _lock = lock_data_dir(&state.data_dir)?;
+            let source = match (args.from, args.snapshot) {
+                (Some(path), _) => snapshot::RestoreSource::File(path),
+                (None, Some(snapshot)) => snapshot::RestoreSource::Stored {
+                    collection: args.source.unwrap_or_else(|| args.collection.clone()),
+                    snapshot,
+                },
+                (None, None) => {
+                    return Err(std::io::Error::other(
+                        "either --from or --snapshot is required",
+                    ))
+                }
+            };
+            let target = match (args.until_seq, args.until_time) {
+                (Some(seq), _) => Some(RecoveryTarget::Seq(seq)),
+                (None, Some(time)) => Some(RecoveryTarget::Time(time)),
+                (None, None) => None,
+            };
+            let restored = snapshot::restore_collection(&state, args.collection, source, target)
+                .map_err(std::io::Error::other)?;
+            println!(
+                "Restored {} from a snapshot of {} ({} vectors)",
+                restored.name, restored.source_collection, restored.count
+            );
+            if let Some(seq) = restored.recovered_seq {
+                println!("Replayed the WAL up to seq {}", seq);
+            }
+            Ok(())
+        }
+    }
+}
+
+// Check (and optionally repair) collections without starting the server. Returns whether every
+// collection ended up clean.
+fn run_fsck(args: FsckArgs) -> std::io::Result<bool> {
     if let Some(path) = args.config {
         std::env::set_var("CONFIG_FILE", path);
     }
     if let Some(dir) = args.data_dir {
         std::env::set_var("DATA_DIR", dir);
     }
+    let RuntimeConfig {
+        app: app_config,
+        data_dir,
+        ..
+    } = piramid::config::loader::load_runtime_config();
+    // Checking only reads; repairing rewrites files a running server has open
+    let _lock = if args.repair {
+        Some(lock_data_dir(&data_dir)?)
+    } else {
+        None
+    };
+
+    let names = match args.collection {
+        Some(name) => vec![name],
+        None => {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(&data_dir)? {
+                if let Some(name) = entry?
+                    .file_name()
+                    .to_str()
+                    .and_then(collections::collection_name_from_file)
+                {
+                    names.push(name);
+                }
+            }
+            names.sort();
+            names
+        }
+    };
+
+    let mut all_clean = true;
+    for name in names {
+        let path = format!("{}/{}.db", data_dir, name);
+        let report = if args.repair {
+            let options = CollectionOpenOptions::from(app_config.to_collection_config());
+            let repaired =
+                collections::repair_collection(&path, options).map_err(std::io::Error::other)?;
+            if !repaired.before.is_clean() {
+                println!(
+                    "{}: repaired {} problems{}, dropped {} documents",
+                    name,
+                    repaired.before.problems.len(),
+                    if repaired.rebuilt_index {
+                        " (index rebuilt from the data file)"
+                    } else {
+                        ""
+                    },
+                    repaired.dropped_documents
+                );
+            }
+            for segment in &repaired.quarantined_wal_segments {
+                println!("{}: quarantined WAL segment {}", name, segment.display());
+            }
+            repaired.after
+        } else {
+            collections::check_collection(&path).map_err(std::io::Error::other)?
+        };
+        if report.is_clean() {
+            println!("{}: ok ({} documents)", name, report.documents);
+        } else {
+            all_clean = false;
+            println!("{}: {} problems", name, report.problems.len());
+            for problem in &report.problems {
+                println!("  {}", problem);
+            }
+        }
+    }
+    Ok(all_clean)
+}
+
+// Refuse to touch a data dir that a running server (or another CLI run) owns
+fn lock_data_dir(data_dir: &str) -> std::io::Result<runtime::DataDirLock> {
+    runtime::DataDirLock::acquire(data_dir).map_err(std::io::Error::other)
+}
+
+// App state over a local data dir, for subcommands that work on collection files directly
+fn local_state(
+    config: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+) -> std::io::Result<std::sync::Arc<AppState>> {
+    if let Some(path) = config {
+        std::env::set_var("CONFIG_FILE", path);
+    }
+    if let Some(dir) = data_dir {
+        std::env::set_var("DATA_DIR", dir);
+    }
     let RuntimeConfig {
         app: app_config,
         data_dir,
@@ -185,7 +428,7 @@ fn show_metrics(args: ShowMetricsArgs) -> std::io::Result<()> {
         ..
     } = piramid::config::loader::load_runtime_config();
 
-    let state = std::sync::Arc::new(
+    Ok(std::sync::Arc::new(
         AppState::new(
             &data_dir,
             app_config,
@@ -194,10 +437,7 @@ fn show_metrics(args: ShowMetricsArgs) -> std::io::Result<()> {
             disk_readonly_on_low_space,
         )
         .map_err(std::io::Error::other)?,
-    );
-    preload_collections_for_metrics(&state)?;
-    let metrics = piramid::services::admin::metrics(&state).map_err(std::io::Error::other)?;
-    print_serialized(&metrics, args.format)
+    ))
 }
 
 fn preload_collections_for_metrics(state: &std::sync::Arc<AppState>) -> std::io::Result<()> {
@@ -208,7 +448,7 @@ fn preload_collections_for_metrics(state: &std::sync::Arc<AppState>) -> std::io:
             Some(v) => v.to_string(),
             None => continue,
         };
-        let collection_name = match collection_name_from_base_db_filename(&file_name) {
+        let collection_name = match collections::collection_name_from_file(&file_name) {
             Some(v) => v,
             None => continue,
         };
@@ -222,24 +462,6 @@ fn preload_collections_for_metrics(state: &std::sync::Arc<AppState>) -> std::io:
     Ok(())
 }
 
-fn collection_name_from_base_db_filename(file_name: &str) -> Option<String> {
-    if !file_name.ends_with(".db") {
-        return None;
-    }
-    if file_name.ends_with(".index.db")
-        || file_name.ends_with(".vecindex.db")
-        || file_name.ends_with(".metadata.db")
-        || file_name.ends_with(".wal.db")
-    {
-        return None;
-    }
-    let name = file_name.strip_suffix(".db")?;
-    if name.is_empty() {
-        return None;
-    }
-    Some(name.to_string())
-}
-
 fn write_config_file(path: &Path, fmt: OutputFormat) -> std::io::Result<()> {
     let cfg = AppConfig::default();
     let contents = serialize_to_string(&cfg, fmt)?;
@@ -265,6 +487,7 @@ fn start_server_inline() -> std::io::Result<()> {
         } = piramid::config::loader::load_runtime_config();
 
         init_tracing(app_config.logging)?;
+        let _lock = lock_data_dir(&data_dir)?;
         if app_config.logging.config {
             tracing::info!(
                 target: "piramid::config",
@@ -308,6 +531,7 @@ fn start_server_inline() -> std::io::Result<()> {
             ),
         };
 
+        runtime::spawn_idle_unloader(state.clone());
         let app = server::create_router(state);
         let addr = format!("0.0.0.0:{}", port);
         tracing::info!(
@@ -0,0 +1,199 @@
+// LRU cache of document metadata with optional per-entry TTL.
+//
+// Reads come from concurrent searches holding the collection read lock, so the LRU sits behind a
+// mutex and hands out `Arc`s that outlive the lock. Entries older than the TTL count as misses and
+// are dropped when looked up.
+//
+// Each entry is tagged with the data file offset of the record it came from. Read views share the
+// cache while the collection moves on, so a lookup names the record it wants and an entry from
+// another version of the document is a miss. Records are only ever appended, so of two versions
+// the one at the higher offset is the newer, and that is the one the cache keeps.
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::config::CacheConfig;
+use crate::metadata::{Metadata, MetadataValue};
+
+struct CachedMetadata {
+    offset: u64,
+    metadata: Arc<Metadata>,
+    cached_at: Instant,
+}
+
+pub struct MetadataCache {
+    // None when caching is disabled or `max_size` is 0
+    entries: Option<Mutex<LruCache<Uuid, CachedMetadata>>>,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+/// Size and hit/miss/eviction counters of a metadata cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetadataCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries pushed out by newer ones once `max_size` was reached.
+    pub evictions: u64,
+    /// Entries dropped because they outlived `ttl_seconds`.
+    pub expirations: u64,
+}
+
+impl MetadataCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let entries = NonZeroUsize::new(config.max_size)
+            .filter(|_| config.enabled)
+            .map(|capacity| Mutex::new(LruCache::new(capacity)));
+        Self {
+            entries,
+            ttl: config.ttl_seconds.map(Duration::from_secs),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// A fresh cache with the same settings that carries on this one's counters, for a collection
+    /// whose data file was rewritten so that offsets no longer name the same records.
+    pub fn renewed(&self, config: CacheConfig) -> Self {
+        let cache = Self::new(config);
+        for (counter, from) in [
+            (&cache.hits, &self.hits),
+            (&cache.misses, &self.misses),
+            (&cache.evictions, &self.evictions),
+            (&cache.expirations, &self.expirations),
+        ] {
+            counter.store(from.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        cache
+    }
+
+    /// Metadata of `id` as stored in its record at `offset`.
+    pub fn get(&self, id: &Uuid, offset: u64) -> Option<Arc<Metadata>> {
+        let Some(entries) = self.entries.as_ref() else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        let mut entries = entries.lock();
+        let expired = match entries.get(id) {
+            Some(entry) if !self.is_expired(entry) => {
+                if entry.offset == offset {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Some(entry.metadata.clone());
+                }
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            entries.pop(id);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Cache the metadata of `id` from its record at `offset`, unless a newer record's is cached.
+    pub fn put(&self, id: Uuid, offset: u64, metadata: Arc<Metadata>) {
+        let Some(entries) = self.entries.as_ref() else {
+            return;
+        };
+        let mut entries = entries.lock();
+        if entries.peek(&id).is_some_and(|entry| entry.offset > offset) {
+            return;
+        }
+        let entry = CachedMetadata {
+            offset,
+            metadata,
+            cached_at: Instant::now(),
+        };
+        if let Some((evicted, _)) = entries.push(id, entry) {
+            // `push` hands back the replaced entry for an id already cached; only others count
+            if evicted != id {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn remove(&self, id: &Uuid) {
+        if let Some(entries) = self.entries.as_ref() {
+            entries.lock().pop(id);
+        }
+    }
+
+    /// Drop every entry; returns the bytes they held.
+    pub fn clear(&self) -> usize {
+        let freed = self.usage_bytes();
+        if let Some(entries) = self.entries.as_ref() {
+            entries.lock().clear();
+        }
+        freed
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .as_ref()
+            .map_or(0, |entries| entries.lock().len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn usage_bytes(&self) -> usize {
+        let Some(entries) = self.entries.as_ref() else {
+            return 0;
+        };
+        entries
+            .lock()
+            .iter()
+            .map(|(id, entry)| std::mem::size_of_val(id) + metadata_usage_bytes(&entry.metadata))
+            .sum()
+    }
+
+    pub fn stats(&self) -> MetadataCacheStats {
+        MetadataCacheStats {
+            entries: self.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_expired(&self, entry: &CachedMetadata) -> bool {
+        self.ttl.is_some_and(|ttl| entry.cached_at.elapsed() >= ttl)
+    }
+}
+
+// Approximate heap bytes held by `metadata`
+fn metadata_usage_bytes(metadata: &Metadata) -> usize {
+    metadata
+        .iter()
+        .map(|(key, value)| key.capacity() + metadata_value_usage_bytes(value))
+        .sum()
+}
+
+fn metadata_value_usage_bytes(value: &MetadataValue) -> usize {
+    match value {
+        MetadataValue::String(value) => value.capacity(),
+        MetadataValue::Integer(_)
+        | MetadataValue::Float(_)
+        | MetadataValue::Boolean(_)
+        | MetadataValue::Null => std::mem::size_of_val(value),
+        MetadataValue::Array(values) => {
+            values.capacity() * std::mem::size_of::<MetadataValue>()
+                + values.iter().map(metadata_value_usage_bytes).sum::<usize>()
+        }
+    }
+}
@@ -1,69 +1,185 @@
-use std::collections::{HashMap, VecDeque};
+mod metadata;
+mod results;
+mod tier;
+
+use std::sync::Arc;
 
 use uuid::Uuid;
 
 use crate::config::CacheConfig;
+use crate::error::Result;
 use crate::index::VectorReader;
-use crate::metadata::{Metadata, MetadataValue};
+use crate::metadata::Metadata;
+use crate::storage::vector_arena::{arena_path, ArenaView, VectorArena};
+use crate::storage::vfs::Vfs;
+
+pub use metadata::{MetadataCache, MetadataCacheStats};
+pub use results::{ResultCache, ResultCacheStats, ResultKey};
+pub use tier::{VectorTier, VectorTierStats};
 
 pub struct CacheManager {
     config: CacheConfig,
-    vectors: HashMap<Uuid, Vec<f32>>,
-    metadata: HashMap<Uuid, Metadata>,
-    metadata_order: VecDeque<Uuid>,
+    vectors: VectorArena,
+    // Hot/cold split of the arena pages, when the collection has a memory budget. Shared with the
+    // read views, which read the same pages.
+    tier: Option<Arc<VectorTier>>,
+    // Shared with the read views; entries are tagged with the record they came from
+    metadata: Arc<MetadataCache>,
+    // Shared with the collection's read views, which cache their own results at their generation
+    results: Arc<ResultCache>,
 }
 
 impl CacheManager {
     pub fn new(config: CacheConfig) -> Self {
         Self {
             config,
-            vectors: HashMap::new(),
-            metadata: HashMap::new(),
-            metadata_order: VecDeque::new(),
+            vectors: VectorArena::in_memory(),
+            tier: None,
+            metadata: Arc::new(MetadataCache::new(config)),
+            results: Arc::new(ResultCache::new(config)),
         }
     }
 
-    pub fn vectors(&self) -> &HashMap<Uuid, Vec<f32>> {
+    // Map the vector arena of the collection at `path` in `vfs`. The arena's rows are only kept
+    // when they were synced by `checkpoint` (generation, seq); the bool reports whether they were.
+    pub fn open(
+        vfs: Arc<dyn Vfs>,
+        path: &str,
+        config: CacheConfig,
+        checkpoint: Option<(u64, u64)>,
+    ) -> Result<(Self, bool)> {
+        let (vectors, trusted) = VectorArena::open_in(vfs, &arena_path(path), checkpoint)?;
+        let cache = Self {
+            vectors,
+            ..Self::new(config)
+        };
+        Ok((cache, trusted))
+    }
+
+    pub fn vectors(&self) -> &VectorArena {
         &self.vectors
     }
 
-    pub fn metadata(&self) -> &HashMap<Uuid, Metadata> {
-        &self.metadata
+    // Share the current vectors with a read view; see `VectorArena::view`
+    pub fn view_vectors(&mut self) -> ArenaView {
+        self.vectors.view()
+    }
+
+    pub fn shared_tier(&self) -> Option<Arc<VectorTier>> {
+        self.tier.clone()
+    }
+
+    // Cached metadata of `id` from its record at `offset`; None on a miss, after which the caller
+    // reads the record store
+    pub fn metadata(&self, id: &Uuid, offset: u64) -> Option<Arc<Metadata>> {
+        self.metadata.get(id, offset)
+    }
+
+    pub fn shared_metadata(&self) -> Arc<MetadataCache> {
+        self.metadata.clone()
+    }
+
+    // Start a new metadata cache once the data file was rewritten and offsets name other
+    // records; views of the old file keep the old cache
+    pub fn renew_metadata(&mut self) {
+        self.metadata = Arc::new(self.metadata.renewed(self.config));
     }
 
-    pub fn put_vector(&mut self, id: Uuid, vector: Vec<f32>) {
-        self.vectors.insert(id, vector);
+    pub fn results(&self) -> &ResultCache {
+        &self.results
     }
 
-    pub fn put_metadata(&mut self, id: Uuid, metadata: Metadata) {
-        if !self.config.enabled {
-            return;
+    pub fn shared_results(&self) -> Arc<ResultCache> {
+        self.results.clone()
+    }
+
+    pub fn metadata_stats(&self) -> MetadataCacheStats {
+        self.metadata.stats()
+    }
+
+    // Keep at most `budget` bytes of vectors in memory, leaving the rest in the arena file.
+    // Only a file-backed arena can be tiered; without mmap every vector stays in memory.
+    pub fn set_vector_budget(&mut self, budget: Option<usize>) {
+        match budget.filter(|_| self.vectors.is_file_backed()) {
+            Some(budget) => {
+                match self.tier.as_mut() {
+                    Some(tier) => tier.set_budget(budget),
+                    None => {
+                        // A new tier starts with every page cold, so drop any the arena faulted in
+                        self.vectors.release(0..self.vectors.mapped_len());
+                        let tier = VectorTier::new(budget, self.vectors.mapped_len());
+                        self.tier = Some(Arc::new(tier));
+                    }
+                }
+                if let (Some(tier), Some(map)) = (self.tier.as_ref(), self.vectors.map()) {
+                    if tier.hot_bytes() > budget {
+                        tier.evict(map);
+                    }
+                }
+            }
+            None => self.tier = None,
         }
-        self.metadata_order.retain(|cached_id| cached_id != &id);
-        self.metadata.insert(id, metadata);
-        self.metadata_order.push_back(id);
-        self.enforce_item_limit();
     }
 
-    pub fn remove(&mut self, id: &Uuid, remove_vector: bool) {
+    pub fn vector_tier_stats(&self) -> VectorTierStats {
+        let total_bytes = self.vectors.usage_bytes();
+        match self.tier.as_ref() {
+            Some(tier) => tier.stats(total_bytes),
+            None => VectorTierStats {
+                hot_bytes: total_bytes,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn put_vector(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        let remaps = self.vectors.remaps();
+        self.vectors.put(id, vector)?;
+        // A grown arena is a new mapping with none of its pages faulted in yet
+        if self.vectors.remaps() != remaps {
+            self.remap_tier();
+        }
+        if let (Some(tier), Some(map)) = (self.tier.as_ref(), self.vectors.map()) {
+            if let Some(row) = self.vectors.row_of(&id) {
+                tier.touch(map, self.vectors.row_range(row));
+            }
+        }
+        Ok(())
+    }
+
+    // Cache the metadata of `id` from its record at `offset`
+    pub fn put_metadata(&self, id: Uuid, offset: u64, metadata: Arc<Metadata>) {
+        self.metadata.put(id, offset, metadata);
+    }
+
+    pub fn remove(&mut self, id: &Uuid, remove_vector: bool) -> Result<()> {
         if remove_vector {
-            self.vectors.remove(id);
+            self.vectors.remove(id)?;
         }
         self.metadata.remove(id);
-        self.metadata_order.retain(|cached_id| cached_id != id);
+        Ok(())
     }
 
-    pub fn clear_all(&mut self) {
-        self.vectors.clear();
+    // Drop cached vectors whose ids fail `keep`, e.g. deleted vectors kept for HNSW traversal
+    pub fn retain_vectors(&mut self, keep: impl FnMut(&Uuid) -> bool) -> Result<()> {
+        self.vectors.retain(keep)
+    }
+
+    pub fn clear_all(&mut self) -> Result<()> {
+        self.vectors.clear()?;
+        self.results.clear();
+        self.remap_tier();
         self.metadata.clear();
-        self.metadata_order.clear();
+        Ok(())
+    }
+
+    // Mark the vector arena as matching checkpoint `generation` at WAL position `seq`
+    pub fn sync_vectors(&mut self, generation: u64, seq: u64) -> Result<()> {
+        self.vectors.sync(generation, seq)
     }
 
     pub fn clear_metadata(&mut self) -> usize {
-        let freed = self.metadata_usage_bytes();
-        self.metadata.clear();
-        self.metadata_order.clear();
-        freed
+        self.metadata.clear()
     }
 
     pub fn memory_usage_bytes(&self) -> usize {
@@ -71,16 +187,7 @@ impl CacheManager {
     }
 
     pub fn metadata_usage_bytes(&self) -> usize {
-        self.metadata
-            .iter()
-            .map(|(id, metadata)| {
-                std::mem::size_of_val(id)
-                    + metadata
-                        .iter()
-                        .map(|(key, value)| key.capacity() + metadata_value_usage_bytes(value))
-                        .sum::<usize>()
-            })
-            .sum()
+        self.metadata.usage_bytes()
     }
 
     pub fn vector_len(&self) -> usize {
@@ -91,41 +198,46 @@ impl CacheManager {
         self.vectors.contains_key(id)
     }
 
+    // Fault the vectors into memory ahead of use. A tiered arena pages them in on demand instead.
+    pub fn warm_vectors(&self) {
+        if self.tier.is_none() {
+            self.vectors.warm();
+        }
+    }
+
     fn vector_usage_bytes(&self) -> usize {
-        self.vectors
-            .values()
-            .map(|vector| std::mem::size_of::<Uuid>() + vector.len() * std::mem::size_of::<f32>())
-            .sum()
+        match self.tier.as_ref() {
+            Some(tier) => tier.hot_bytes().min(self.vectors.usage_bytes()),
+            None => self.vectors.usage_bytes(),
+        }
     }
 
-    fn enforce_item_limit(&mut self) {
-        if self.config.max_size == 0 {
-            self.metadata.clear();
-            self.metadata_order.clear();
-            return;
+    // Track a new arena map with every page cold; views of the old map keep the old tier
+    fn remap_tier(&mut self) {
+        if let Some(tier) = self.tier.as_mut() {
+            *tier = Arc::new(tier.remapped(self.vectors.mapped_len()));
         }
+    }
 
-        while self.metadata.len() > self.config.max_size {
-            match self.metadata_order.pop_front() {
-                Some(id) => {
-                    self.metadata.remove(&id);
-                }
-                None => break,
-            }
+    fn read_row(&self, row: u32) -> &[f32] {
+        if let (Some(tier), Some(map)) = (self.tier.as_ref(), self.vectors.map()) {
+            tier.touch(map, self.vectors.row_range(row));
         }
+        self.vectors.vector_at(row)
     }
 }
 
 impl VectorReader for CacheManager {
     fn get(&self, id: &Uuid) -> Option<&[f32]> {
-        self.vectors.get(id).map(Vec::as_slice)
+        let row = self.vectors.row_of(id)?;
+        Some(self.read_row(row))
     }
 
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Uuid, &'a [f32])> + 'a> {
         Box::new(
             self.vectors
-                .iter()
-                .map(|(id, vector)| (*id, vector.as_slice())),
+                .rows()
+                .map(move |(id, row)| (id, self.read_row(row))),
         )
     }
 
@@ -133,17 +245,3 @@ impl VectorReader for CacheManager {
         self.vectors.len()
     }
 }
-
-fn metadata_value_usage_bytes(value: &MetadataValue) -> usize {
-    match value {
-        MetadataValue::String(value) => value.capacity(),
-        MetadataValue::Integer(_)
-        | MetadataValue::Float(_)
-        | MetadataValue::Boolean(_)
-        | MetadataValue::Null => std::mem::size_of_val(value),
-        MetadataValue::Array(values) => {
-            values.capacity() * std::mem::size_of::<MetadataValue>()
-                + values.iter().map(metadata_value_usage_bytes).sum::<usize>()
-        }
-    }
-}
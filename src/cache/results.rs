@@ -0,0 +1,128 @@
+// Cache of search results for repeated identical queries.
+//
+// An entry is keyed on the exact query vector and everything else that shapes the answer (k,
+// metric, execution mode, filter, effective search config). Each entry remembers the collection's
+// write generation it was computed at; any later write bumps the generation, so the entry is
+// treated as a miss and dropped on its next lookup instead of being tracked down at write time.
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::CacheConfig;
+use crate::search::Hit;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ResultKey {
+    // Bit patterns of the query components, so equal vectors hash equally
+    query: Box<[u32]>,
+    k: usize,
+    // Metric, mode, filter and search config, which all format deterministically
+    params: String,
+}
+
+impl ResultKey {
+    pub fn new(query: &[f32], k: usize, params: String) -> Self {
+        Self {
+            query: query.iter().map(|value| value.to_bits()).collect(),
+            k,
+            params,
+        }
+    }
+}
+
+struct CachedResult {
+    hits: Arc<Vec<Hit>>,
+    generation: u64,
+    cached_at: Instant,
+}
+
+pub struct ResultCache {
+    // None when `result_cache_size` is 0
+    entries: Option<Mutex<LruCache<ResultKey, CachedResult>>>,
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Size and hit/miss counters of a result cache.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResultCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl ResultCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let entries = NonZeroUsize::new(config.result_cache_size)
+            .filter(|_| config.enabled)
+            .map(|capacity| Mutex::new(LruCache::new(capacity)));
+        Self {
+            entries,
+            ttl: config.result_cache_ttl_seconds.map(Duration::from_secs),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.entries.is_some()
+    }
+
+    /// Results cached for `key` at write `generation`, if they have not expired.
+    pub fn get(&self, key: &ResultKey, generation: u64) -> Option<Arc<Vec<Hit>>> {
+        let entries = self.entries.as_ref()?;
+        let mut entries = entries.lock();
+        let cached = entries
+            .get(key)
+            .filter(|entry| {
+                entry.generation == generation
+                    && self.ttl.is_none_or(|ttl| entry.cached_at.elapsed() < ttl)
+            })
+            .map(|entry| entry.hits.clone());
+        match cached {
+            Some(hits) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(hits)
+            }
+            None => {
+                // Stale or expired entries go now rather than waiting to be pushed out
+                entries.pop(key);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: ResultKey, generation: u64, hits: Arc<Vec<Hit>>) {
+        let Some(entries) = self.entries.as_ref() else {
+            return;
+        };
+        let entry = CachedResult {
+            hits,
+            generation,
+            cached_at: Instant::now(),
+        };
+        entries.lock().put(key, entry);
+    }
+
+    pub fn clear(&self) {
+        if let Some(entries) = self.entries.as_ref() {
+            entries.lock().clear();
+        }
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        ResultCacheStats {
+            entries: self
+                .entries
+                .as_ref()
+                .map_or(0, |entries| entries.lock().len()),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
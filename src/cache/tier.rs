@@ -0,0 +1,182 @@
+// Hot/cold tiering of the vector arena under a memory budget.
+//
+// The arena file holds every vector of the collection; the tier decides which of its pages stay in
+// memory. Each page carries a small access weight (GCLOCK). Reading a cold page faults it in and
+// counts as a miss; reading a hot page counts as a hit and raises its weight. Once the hot pages
+// outgrow the budget, a clock hand sweeps the pages, decaying weights and dropping pages whose
+// weight ran out, until the hot set is back under the low watermark. Dropped pages stay in the
+// file and are faulted back in by the next read. Read views share the tier with the collection, so
+// pages they fault in count against the same budget and are dropped by the same sweep.
+use parking_lot::Mutex;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::storage::persistence::page_size;
+use crate::storage::vector_arena::ArenaMap;
+
+// Reads a hot page can bank against eviction
+const MAX_WEIGHT: u8 = 3;
+
+pub struct VectorTier {
+    budget_bytes: AtomicUsize,
+    page_size: usize,
+    // Per page of the arena map: 0 = cold, otherwise hot with that weight
+    weights: Vec<AtomicU8>,
+    hot_pages: AtomicUsize,
+    hand: Mutex<usize>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+/// Memory, hit and miss figures of a collection's vectors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VectorTierStats {
+    /// Bytes the hot vectors may take, if the collection has a memory budget.
+    pub budget_bytes: Option<usize>,
+    pub hot_bytes: usize,
+    pub cold_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl VectorTier {
+    pub fn new(budget_bytes: usize, mapped_len: usize) -> Self {
+        let mut tier = Self {
+            budget_bytes: AtomicUsize::new(budget_bytes),
+            page_size: page_size(),
+            weights: Vec::new(),
+            hot_pages: AtomicUsize::new(0),
+            hand: Mutex::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        };
+        tier.reset(mapped_len);
+        tier
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_budget(&self, budget_bytes: usize) {
+        self.budget_bytes.store(budget_bytes, Ordering::Relaxed);
+    }
+
+    /// A tier for a new map of `mapped_len` bytes, all of it cold, keeping this one's budget and
+    /// counters. Views still reading the old map keep the old tier.
+    pub fn remapped(&self, mapped_len: usize) -> Self {
+        let tier = Self::new(self.budget_bytes(), mapped_len);
+        for (counter, from) in [
+            (&tier.hits, &self.hits),
+            (&tier.misses, &self.misses),
+            (&tier.evictions, &self.evictions),
+        ] {
+            counter.store(from.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        tier
+    }
+
+    // Track a freshly mapped arena of `mapped_len` bytes, all of it cold.
+    fn reset(&mut self, mapped_len: usize) {
+        let pages = mapped_len.div_ceil(self.page_size);
+        self.weights = (0..pages).map(|_| AtomicU8::new(0)).collect();
+        self.hot_pages.store(0, Ordering::Relaxed);
+        *self.hand.get_mut() = 0;
+    }
+
+    pub fn hot_bytes(&self) -> usize {
+        self.hot_pages.load(Ordering::Relaxed) * self.page_size
+    }
+
+    /// Record an access to `range` of `map`, then shrink the hot set if the access pushed it over
+    /// budget.
+    pub fn touch(&self, map: &ArenaMap, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let mut faulted = false;
+        for page in range.start / self.page_size..=(range.end - 1) / self.page_size {
+            let Some(weight) = self.weights.get(page) else {
+                continue;
+            };
+            if weight
+                .compare_exchange(0, 1, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                faulted = true;
+                self.hot_pages.fetch_add(1, Ordering::Relaxed);
+            } else {
+                let _ = weight.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |weight| {
+                    (weight > 0 && weight < MAX_WEIGHT).then_some(weight + 1)
+                });
+            }
+        }
+        if !faulted {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        if self.hot_bytes() > self.budget_bytes() {
+            self.evict(map);
+        }
+    }
+
+    /// Sweep the clock until the hot set is back under the low watermark (7/8 of the budget).
+    pub fn evict(&self, map: &ArenaMap) {
+        // One sweep at a time; readers that find it running carry on
+        let Some(mut hand) = self.hand.try_lock() else {
+            return;
+        };
+        let pages = self.weights.len();
+        if pages == 0 {
+            return;
+        }
+        let budget_pages = self.budget_bytes() / self.page_size;
+        let target = budget_pages - budget_pages / 8;
+        // Enough steps to decay every page from the top weight and drop it
+        let mut steps = pages * (MAX_WEIGHT as usize + 1);
+        while self.hot_pages.load(Ordering::Relaxed) > target && steps > 0 {
+            steps -= 1;
+            let page = *hand;
+            *hand = (page + 1) % pages;
+            let weight = &self.weights[page];
+            match weight.load(Ordering::Relaxed) {
+                0 => {}
+                1 => {
+                    if weight
+                        .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        let start = page * self.page_size;
+                        map.release(start..start + self.page_size);
+                        self.hot_pages.fetch_sub(1, Ordering::Relaxed);
+                        self.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                current => {
+                    let _ = weight.compare_exchange(
+                        current,
+                        current - 1,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self, total_bytes: usize) -> VectorTierStats {
+        let hot_bytes = self.hot_bytes().min(total_bytes);
+        VectorTierStats {
+            budget_bytes: Some(self.budget_bytes()),
+            hot_bytes,
+            cold_bytes: total_bytes - hot_bytes,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
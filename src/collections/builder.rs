@@ -2,7 +2,7 @@
 use std::collections::HashMap;
 use uuid::Uuid;
 
-use super::checkpoint::{load_wal_meta, CheckpointManager};
+use super::checkpoint::{load_wal_meta_in, CheckpointManager};
 use super::collection::Collection;
 use super::CollectionOpenOptions;
 use crate::cache::CacheManager;
@@ -10,7 +10,7 @@ use crate::error::Result;
 use crate::index::HashMapVectorReader;
 use crate::storage::document::Document;
 use crate::storage::metadata::CollectionMetadata;
-use crate::storage::persistence::{get_wal_path, load_index, load_metadata, load_vector_index};
+use crate::storage::persistence::{get_wal_path, load_checkpoint_in};
 use crate::storage::record_store::RecordStore;
 use crate::storage::wal::{Wal, WalEntry};
 
@@ -18,7 +18,7 @@ pub struct CollectionBuilder;
 
 impl CollectionBuilder {
     pub fn open(path: &str, options: CollectionOpenOptions) -> Result<Collection> {
-        let config = options.config;
+        let CollectionOpenOptions { config, vfs } = options;
 
         // Initialize Rayon thread pool based on config
         Collection::init_rayon_pool(&config.parallelism);
@@ -30,12 +30,31 @@ impl CollectionBuilder {
             .unwrap_or("unknown")
             .to_string();
 
-        // Load existing index and metadata if they exist
-        let index = load_index(path)?;
-        let record_store = RecordStore::open(path, &config, &index)?;
+        // Load the index, vector index and metadata of the last complete checkpoint generation
+        let loaded = load_checkpoint_in(&*vfs, path)?;
+        let mut index = loaded.index;
+        let mut record_store = RecordStore::open_in(&*vfs, path, &config, &index)?;
+
+        // A lost or unreadable index is rebuilt by scanning the data file
+        let index_rebuilt = if let Some(reason) = &loaded.index_error {
+            let scan = record_store.rebuild_index()?;
+            tracing::warn!(
+                path = %path,
+                reason = %reason,
+                records = scan.records,
+                tombstones = scan.tombstones,
+                skipped_bytes = scan.skipped_bytes,
+                recovered = scan.index.len(),
+                "record_index_rebuilt"
+            );
+            index = scan.index;
+            true
+        } else {
+            false
+        };
 
         // If metadata exists, update vector count based on loaded index
-        let metadata = match load_metadata(path)? {
+        let metadata = match loaded.metadata {
             Some(meta) => {
                 let mut meta = meta;
                 meta.update_vector_count(index.len());
@@ -44,36 +63,74 @@ impl CollectionBuilder {
             None => CollectionMetadata::new(collection_name),
         };
 
-        // Load or create vector index
-        let loaded_vector_index = load_vector_index(path)?;
+        // Load or create vector index; after an index rebuild the stored one may not match it
+        let loaded_vector_index = loaded.vector_index.filter(|_| !index_rebuilt);
         let vector_index_missing = loaded_vector_index.is_none();
         let mut vector_index = match loaded_vector_index {
             Some(loaded_index) => loaded_index,
             None => config.index.create_index(index.len()),
         };
 
-        // If WAL is enabled, determine the minimum sequence number to replay from
+        // If the index is not empty but the vector index is missing, we need to rebuild the vector index from the existing data
+        if !index.is_empty() && vector_index_missing {
+            Self::rebuild_vector_index(
+                &mut vector_index,
+                &index,
+                &record_store,
+                &config.parallelism,
+            )?;
+        }
+
+        // Vectors come from the arena the last checkpoint synced, if it is still intact. The arena
+        // is a memory map, so backends without mmap keep vectors in anonymous memory.
+        let checkpoint_generation = loaded
+            .manifest
+            .as_ref()
+            .filter(|_| !index_rebuilt)
+            .map(|manifest| (manifest.generation, manifest.last_checkpoint_seq));
+        let (cache, cache_trusted) = if config.memory.use_mmap && vfs.supports_mmap() {
+            CacheManager::open(vfs.clone(), path, config.cache, checkpoint_generation)?
+        } else {
+            (CacheManager::new(config.cache), false)
+        };
+
+        // If WAL is enabled, replay everything after the sequence the loaded generation covers
+        let checkpoint_seq = match &loaded.manifest {
+            Some(manifest) => manifest.last_checkpoint_seq,
+            None => load_wal_meta_in(&*vfs, path)?,
+        };
         let min_seq = if config.wal.enabled {
-            load_wal_meta(path)?
+            checkpoint_seq
         } else {
             0
         };
-        let next_seq = min_seq + 1;
+        // New writes must sort after every record already in the data file
+        let next_seq = checkpoint_seq.max(record_store.last_seq()) + 1;
 
         let wal_path = get_wal_path(path);
 
         // Initialize WAL and checkpoint manager
         let wal = if config.wal.enabled {
-            Wal::new(wal_path.into(), next_seq)?
+            Wal::open_in(vfs.clone(), wal_path.into(), next_seq, &config.wal)?
         } else {
             Wal::disabled(wal_path.into(), next_seq)?
         };
 
-        let checkpoint = CheckpointManager::new(wal);
+        let mut checkpoint = CheckpointManager::new(wal);
 
         // If WAL is enabled, replay entries from the WAL starting from the minimum sequence number
         let wal_entries = if config.wal.enabled {
-            checkpoint.wal.replay(min_seq)?
+            let replay = checkpoint.wal.recover(min_seq)?;
+            if let Some(truncated) = &replay.truncated {
+                tracing::warn!(
+                    path = %path,
+                    offset = truncated.offset,
+                    dropped_bytes = truncated.dropped_bytes,
+                    reason = %truncated.reason,
+                    "wal_tail_truncated"
+                );
+            }
+            replay.entries
         } else {
             Vec::new()
         };
@@ -82,52 +139,87 @@ impl CollectionBuilder {
         // why? Because we need to ensure that the collection state is consistent with the WAL entries before we can checkpoint and clear the WAL. By applying the WAL entries to a temporary collection, we can bring it up to date with all the changes recorded in the WAL, and then checkpoint that state to persist it. This way, we ensure that no changes are lost and that the collection is in sync with the WAL before we clear it.
 
         if !wal_entries.is_empty() {
+            let live_bytes = record_store.live_bytes(&index);
             let mut recovered_collection = Collection {
                 record_store,
                 index,
-                vector_index,
-                cache: CacheManager::new(config.cache),
+                vector_index: vector_index.into(),
+                cache,
                 config: config.clone(),
                 metadata,
                 path: path.to_string(),
                 checkpoint,
+                index_changes: None,
+                live_bytes,
+                record_changes: None,
+                write_generation: 0,
+                vector_memory_cap: None,
+                vfs,
+                view: None,
+                last_retrain: None,
+                dropped: false,
             };
 
+            // Vectors beyond the memory budget stay in the arena file from the start
+            recovered_collection.refresh_vector_budget();
+
             // Replay WAL entries to bring the collection up to date
             Self::replay_wal(&mut recovered_collection, wal_entries)?;
 
-            // After replaying, rebuild the vector cache to ensure it's in sync with the index
-            recovered_collection.rebuild_vector_cache()?;
+            // After replaying, bring the vector cache in sync with the index
+            if cache_trusted {
+                recovered_collection.ensure_cache_consistency()?;
+            } else {
+                recovered_collection.rebuild_vector_cache()?;
+            }
 
             // Checkpoint the collection to persist the changes from the WAL replay, which will also clear the WAL
             super::checkpoint::checkpoint(&mut recovered_collection)?;
+            recovered_collection.start_views();
 
             // After checkpointing, we can use the updated collection as our main collection instance
             return Ok(recovered_collection);
         }
 
-        // If the index is not empty but the vector index is missing, we need to rebuild the vector index from the existing data
-        if !index.is_empty() && vector_index_missing {
-            Self::rebuild_vector_index(&mut vector_index, &index, &record_store)?;
-        }
-
         // Finally, create the collection instance with the loaded index, metadata, and vector index
+        let live_bytes = record_store.live_bytes(&index);
         let mut collection = Collection {
             record_store,
             index,
-            vector_index,
-            cache: CacheManager::new(config.cache),
+            vector_index: vector_index.into(),
+            cache,
             config,
             metadata,
             path: path.to_string(),
             checkpoint,
+            index_changes: None,
+            live_bytes,
+            record_changes: None,
+            write_generation: 0,
+            vector_memory_cap: None,
+            vfs,
+            view: None,
+            last_retrain: None,
+            dropped: false,
         };
 
-        collection.rebuild_vector_cache()?;
+        collection.refresh_vector_budget();
+
+        // A trusted arena only needs rows the index no longer has dropped; otherwise decode every document
+        if cache_trusted {
+            collection.ensure_cache_consistency()?;
+        } else {
+            collection.rebuild_vector_cache()?;
+        }
+        // Persist a rebuilt index so the next open does not have to scan again
+        if index_rebuilt {
+            super::checkpoint::checkpoint(&mut collection)?;
+        }
+        collection.start_views();
         Ok(collection)
     }
 
-    fn replay_wal(collection: &mut Collection, entries: Vec<WalEntry>) -> Result<()> {
+    pub(super) fn replay_wal(collection: &mut Collection, entries: Vec<WalEntry>) -> Result<()> {
         // Apply each WAL entry to the collection. Inserts and updates will add or modify entries, while deletes will remove them.
         for entry in entries {
             match entry {
@@ -137,7 +229,7 @@ impl CollectionBuilder {
                     vector,
                     text,
                     metadata,
-                    ..
+                    seq,
                 } => {
                     let vec_entry = Document {
                         id,
@@ -145,7 +237,7 @@ impl CollectionBuilder {
                         text,
                         metadata,
                     };
-                    super::operations::insert_internal(collection, vec_entry)?;
+                    super::operations::insert_internal(collection, vec_entry, seq)?;
                 }
 
                 WalEntry::Update {
@@ -153,19 +245,20 @@ impl CollectionBuilder {
                     vector,
                     text,
                     metadata,
-                    ..
+                    seq,
                 } => {
-                    super::operations::delete_internal(collection, &id);
+                    super::operations::delete_internal(collection, &id)?;
                     let vec_entry = Document {
                         id,
                         vector,
                         text,
                         metadata,
                     };
-                    super::operations::insert_internal(collection, vec_entry)?;
+                    super::operations::insert_internal(collection, vec_entry, seq)?;
                 }
-                WalEntry::Delete { id, .. } => {
-                    super::operations::delete_internal(collection, &id);
+                WalEntry::Delete { id, seq } => {
+                    collection.record_store.append_tombstone(&id, seq)?;
+                    super::operations::delete_internal(collection, &id)?;
                 }
                 WalEntry::Checkpoint { .. } => {}
             }
@@ -177,6 +270,7 @@ impl CollectionBuilder {
         vector_index: &mut Box<dyn crate::index::VectorIndex>,
         index: &HashMap<Uuid, crate::storage::persistence::EntryPointer>,
         record_store: &RecordStore,
+        parallelism: &crate::config::ParallelismConfig,
     ) -> Result<()> {
         // If the vector index is missing but we have an existing index, we need to rebuild the vector index from the existing data. We read each entry from the memory-mapped file based on the offsets and lengths in the index, deserialize it into a Document, and then insert it into the vector index.
         let mut vectors: HashMap<Uuid, Vec<f32>> = HashMap::new();
@@ -188,9 +282,8 @@ impl CollectionBuilder {
         // Once we have all the vectors loaded from the existing data, we can insert them into the vector index. This will rebuild the vector index so that it is in sync with the existing data in the collection.
 
         let reader = HashMapVectorReader::new(&vectors);
-        for (id, vector) in &vectors {
-            vector_index.insert(*id, vector, &reader);
-        }
+        let ids: Vec<Uuid> = vectors.keys().copied().collect();
+        vector_index.insert_batch(&ids, &reader, parallelism);
         Ok(())
     }
 }
@@ -1,31 +1,50 @@
-use crate::cache::CacheManager;
+use std::sync::Arc;
+
 use crate::Result;
 
 use super::collection::Collection;
 use super::operations;
 
 pub fn rebuild(collection: &mut Collection) -> Result<()> {
-    let mut cache = CacheManager::new(collection.config.cache);
-    for id in collection.index.keys() {
-        if let Some(entry) = operations::get(collection, id)? {
-            cache.put_vector(*id, entry.try_get_vector()?);
-            cache.put_metadata(*id, entry.metadata.clone());
+    collection.cache.clear_all()?;
+    collection.reset_view();
+    let pointers: Vec<_> = collection
+        .index
+        .iter()
+        .map(|(id, pointer)| (*id, pointer.offset))
+        .collect();
+    for (id, offset) in pointers {
+        if let Some(entry) = operations::get(collection, &id)? {
+            collection.cache.put_vector(id, &entry.try_get_vector()?)?;
+            collection
+                .cache
+                .put_metadata(id, offset, Arc::new(entry.metadata));
         }
     }
-    collection.cache = cache;
     Ok(())
 }
 
+// Bring a cache that may have drifted from the index (e.g. an arena reopened from the last
+// checkpoint) back in line, decoding only the documents it is missing.
 pub fn ensure_consistent(collection: &mut Collection) -> Result<()> {
-    if collection.cache.vector_len() != collection.index.len() {
-        rebuild(collection)?;
-        return Ok(());
-    }
+    let index = &collection.index;
+    collection
+        .cache
+        .retain_vectors(|id| index.contains_key(id))?;
+    collection.reset_view();
 
-    for id in collection.index.keys() {
-        if !collection.cache.vector_contains(id) {
-            rebuild(collection)?;
-            break;
+    let missing: Vec<_> = collection
+        .index
+        .iter()
+        .filter(|(id, _)| !collection.cache.vectors().contains_key(id))
+        .map(|(id, pointer)| (*id, pointer.offset))
+        .collect();
+    for (id, offset) in missing {
+        if let Some(entry) = operations::get(collection, &id)? {
+            collection.cache.put_vector(id, &entry.try_get_vector()?)?;
+            collection
+                .cache
+                .put_metadata(id, offset, Arc::new(entry.metadata));
         }
     }
     Ok(())
@@ -2,12 +2,11 @@
 
 use super::collection::Collection;
 use crate::error::Result;
-use crate::storage::persistence::{
-    save_index as save_idx, save_metadata as save_meta, save_vector_index as save_vec_idx,
-};
+use crate::storage::persistence::write_checkpoint_in;
+use crate::storage::vfs::{OsVfs, Vfs};
 use crate::storage::wal::Wal;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::path::PathBuf;
 
 pub struct CheckpointManager {
     pub wal: Wal, // The write-ahead log instance for managing durability and recovery
@@ -46,30 +45,23 @@ impl CheckpointManager {
     }
 }
 
-pub fn save_index(storage: &Collection) -> Result<()> {
-    save_idx(&storage.path, &storage.index)
-}
-
-pub fn save_vector_index(storage: &Collection) -> Result<()> {
-    save_vec_idx(&storage.path, storage.vector_index.as_ref()) // We pass a reference to the vector index to the save function, which will handle serializing and writing it to disk.
-}
-
-pub fn save_metadata(storage: &Collection) -> Result<()> {
-    save_meta(&storage.path, &storage.metadata) // need to save the metadata of the collection during checkpoints. contains their IDs and any associated metadata fields
-}
-
 fn wal_meta_path(path: &str) -> PathBuf {
     PathBuf::from(format!("{}.wal.meta", path)) // The path for the WAL metadata file is constructed by appending ".wal.meta" to the base path of the collection.
 }
 
+// Collections checkpointed before manifests existed record their WAL position in a separate file.
 #[derive(Serialize, Deserialize, Default)]
 struct WalMeta {
     last_checkpoint_seq: u64,
 }
 
 pub fn load_wal_meta(path: &str) -> Result<u64> {
+    load_wal_meta_in(&OsVfs, path)
+}
+
+pub(super) fn load_wal_meta_in(vfs: &dyn Vfs, path: &str) -> Result<u64> {
     let meta_path = wal_meta_path(path);
-    let data = match fs::read(&meta_path) {
+    let data = match vfs.read(&meta_path) {
         Ok(data) => data,
         Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
         Err(error) => return Err(error.into()),
@@ -78,19 +70,6 @@ pub fn load_wal_meta(path: &str) -> Result<u64> {
     Ok(meta.last_checkpoint_seq)
 }
 
-fn save_wal_meta(path: &str, last_checkpoint_seq: u64) -> Result<()> {
-    let meta_path = wal_meta_path(path);
-    let tmp_path = meta_path.with_extension("tmp");
-    let meta = WalMeta {
-        last_checkpoint_seq,
-    };
-    fs::write(&tmp_path, serde_json::to_vec(&meta)?)?;
-    fs::rename(&tmp_path, &meta_path)?;
-    let file = fs::File::open(&meta_path)?;
-    file.sync_all()?;
-    Ok(())
-}
-
 pub fn checkpoint(storage: &mut Collection) -> Result<()> {
     // This timestamp can be used for recovery purposes to determine the point in time at which the checkpoint was taken,
     let timestamp = std::time::SystemTime::now()
@@ -98,17 +77,36 @@ pub fn checkpoint(storage: &mut Collection) -> Result<()> {
         .unwrap()
         .as_secs();
 
-    // serializing the in-memory data structures and writing them to their respective files on disk. ensure that we have a consistent snapshot of the collection's state that can be used for recovery if needed.
-    save_index(storage)?;
-    save_vector_index(storage)?;
-    save_metadata(storage)?;
+    // The index points into the data file, so the data file has to be on disk first.
+    storage.record_store.sync()?;
 
-    // If WAL is enabled in the configuration, checkpoint the WAL to ensure flushing any buffered entries and rotating the log file if it exceeds the configured size or if a checkpoint is triggered based on the operation count.
+    // Log the checkpoint marker so the generation records exactly which WAL entries it covers.
     if storage.config.wal.enabled {
         storage.checkpoint.wal.checkpoint(timestamp)?;
+    }
+    let last_seq = storage.checkpoint.wal.next_seq.saturating_sub(1);
+
+    // Write the index, vector index and metadata as one new generation and atomically switch the manifest to it. A crash before the switch leaves the previous generation and the WAL intact.
+    let manifest = write_checkpoint_in(
+        &*storage.vfs,
+        &storage.path,
+        last_seq,
+        timestamp,
+        &storage.index,
+        storage.vector_index.as_ref(),
+        &storage.metadata,
+    )?;
+
+    // The vector arena is trusted on the next open only if it matches the generation now live.
+    storage
+        .cache
+        .sync_vectors(manifest.generation, manifest.last_checkpoint_seq)?;
+    // The index has grown or shrunk since the last checkpoint; move the vector budget with it.
+    storage.refresh_vector_budget();
+
+    // Only once the generation is live can the WAL segments it covers be dropped.
+    if storage.config.wal.enabled {
         storage.checkpoint.record_checkpoint(timestamp);
-        let last_seq = storage.checkpoint.wal.next_seq.saturating_sub(1);
-        save_wal_meta(&storage.path, last_seq)?;
         storage.checkpoint.wal.rotate()?;
     }
 
@@ -1,24 +1,65 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use uuid::Uuid;
 
 use super::cache_maintenance;
 use super::checkpoint::CheckpointManager;
-use crate::cache::CacheManager;
+use super::view::{self, ViewSlot, ViewState};
+use crate::cache::{CacheManager, MetadataCacheStats, ResultCacheStats};
 use crate::error::Result;
-use crate::index::{HashMapVectorReader, VectorIndex, VectorReader};
+use crate::index::{HashMapVectorReader, MetadataReader, VectorIndex, VectorReader};
+use crate::metadata::Metadata;
+use crate::search::query::Filter;
 use crate::storage::metadata::CollectionMetadata;
-use crate::storage::persistence::{get_wal_path, save_vector_index, warm_file, EntryPointer};
+use crate::storage::persistence::{checkpoint_files, warm_file, EntryPointer};
 use crate::storage::record_store::RecordStore;
+use crate::storage::vector_arena::VectorArena;
+use crate::storage::vfs::Vfs;
 
 pub struct Collection {
     pub(super) record_store: RecordStore,
     pub(super) index: HashMap<Uuid, EntryPointer>,
-    pub(super) vector_index: Box<dyn VectorIndex>,
+    // Shared with the published read view; changed through `view::index_mut`
+    pub(super) vector_index: Arc<dyn VectorIndex>,
     pub(super) cache: CacheManager,
     pub config: crate::config::CollectionConfig,
     pub metadata: CollectionMetadata,
     pub path: String,
     pub checkpoint: CheckpointManager,
+    // Ids written while an online index migration builds off-lock, replayed before the swap
+    pub(super) index_changes: Option<HashSet<Uuid>>,
+    // Bytes of the data file taken by the records the index points at
+    pub(super) live_bytes: u64,
+    // Ids written while an online compaction copies records off-lock, replayed before the swap
+    pub(super) record_changes: Option<HashSet<Uuid>>,
+    // Bumped by every write; cached search results from an older generation are stale
+    pub(super) write_generation: u64,
+    // Vector memory the process-wide memory governor lets this collection keep
+    pub(super) vector_memory_cap: Option<usize>,
+    // File system the data file, WAL and checkpoints are stored in
+    pub(super) vfs: Arc<dyn Vfs>,
+    // Read views searches run against without the lock; None without `parallelism.snapshot_reads`
+    pub(super) view: Option<ViewState>,
+    // When the last background retrain finished, swapped in or not; drift waits out the cooldown
+    pub(super) last_retrain: Option<std::time::Instant>,
+    // Set when the collection is deleted, so background jobs still holding a handle do not write
+    // its files back
+    pub(super) dropped: bool,
+}
+
+/// Memory a loaded collection holds, as reported to the process-wide memory governor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub cache_bytes: usize, // Hot vectors, the metadata cache and the read view copies
+    pub index_bytes: usize, // Pointer index plus the vector index
+    pub mmap_resident_bytes: usize, // Data file pages currently in memory
+    pub wal_buffer_bytes: usize,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.cache_bytes + self.index_bytes + self.mmap_resident_bytes + self.wal_buffer_bytes
+    }
 }
 
 impl Collection {
@@ -32,8 +73,9 @@ impl Collection {
         }
     }
 
-    // Track operations to trigger checkpoints based on WAL config
-    pub(super) fn track_operation(&mut self) -> Result<()> {
+    // Track operations to trigger checkpoints based on WAL config. The write being tracked stands
+    // either way: a checkpoint that fails is logged and tried again after the next write.
+    pub(super) fn track_operation(&mut self) {
         let interval_due = if let Some(last) = self.checkpoint.last_checkpoint() {
             if let Some(interval) = self.config.wal.checkpoint_interval_secs {
                 let now = std::time::SystemTime::now()
@@ -49,8 +91,125 @@ impl Collection {
         };
 
         if self.checkpoint.should_checkpoint(&self.config.wal) || interval_due {
-            super::checkpoint::checkpoint(self)?;
-            self.checkpoint.reset_counter();
+            match super::checkpoint::checkpoint(self) {
+                Ok(()) => self.checkpoint.reset_counter(),
+                Err(error) => {
+                    tracing::warn!(path = %self.path, error = %error, "checkpoint_failed");
+                }
+            }
+        }
+    }
+
+    // Point `id` at a newly appended record; the record it replaces becomes dead
+    pub(super) fn set_pointer(&mut self, id: Uuid, pointer: EntryPointer) {
+        self.track_record_change(id);
+        self.live_bytes += self.record_store.stored_len(&pointer);
+        if let Some(old) = self.index.insert(id, pointer) {
+            self.live_bytes = self
+                .live_bytes
+                .saturating_sub(self.record_store.stored_len(&old));
+        }
+    }
+
+    pub(super) fn remove_pointer(&mut self, id: &Uuid) -> Option<EntryPointer> {
+        self.track_record_change(*id);
+        let old = self.index.remove(id)?;
+        self.live_bytes = self
+            .live_bytes
+            .saturating_sub(self.record_store.stored_len(&old));
+        Some(old)
+    }
+
+    fn track_record_change(&mut self, id: Uuid) {
+        self.write_generation += 1;
+        if let Some(changes) = self.record_changes.as_mut() {
+            changes.insert(id);
+        }
+    }
+
+    /// Live and dead bytes of the data file.
+    pub fn storage_usage(&self) -> super::StorageUsage {
+        let live_bytes = self.live_bytes;
+        super::StorageUsage {
+            live_bytes,
+            dead_bytes: self.record_store.record_bytes().saturating_sub(live_bytes),
+        }
+    }
+
+    pub(super) fn track_index_change(&mut self, id: Uuid) {
+        if let Some(changes) = self.index_changes.as_mut() {
+            changes.insert(id);
+        }
+    }
+
+    // Give readers a view slot, unless the config turns snapshot reads off
+    pub(super) fn start_views(&mut self) {
+        if self.config.parallelism.snapshot_reads {
+            self.view = Some(ViewState::default());
+        }
+    }
+
+    // Note that the vector or record of `id` changed, so the next read view picks it up
+    pub(super) fn stage_view(&mut self, id: Uuid) {
+        if let Some(view) = self.view.as_mut() {
+            view.stage(id);
+        }
+    }
+
+    // The vectors or the data file changed wholesale (compaction, cache rebuild); the next read
+    // view starts from scratch
+    pub(super) fn reset_view(&mut self) {
+        if let Some(view) = self.view.as_mut() {
+            view.reset();
+        }
+    }
+
+    // Called at the end of every write operation, failed ones included
+    pub(super) fn publish_view(&mut self) {
+        view::publish(self);
+    }
+
+    /// Where the collection publishes read views, if it keeps them. The slot can be read without
+    /// the collection lock.
+    pub fn view_slot(&self) -> Option<ViewSlot> {
+        self.view.as_ref().map(|view| view.slot().clone())
+    }
+
+    // What the read views hold beyond the collection's own vectors: their row maps, plus rows and
+    // maps the arena keeps only because a view may still read them
+    fn view_usage_bytes(&self) -> usize {
+        self.view.as_ref().map_or(0, |view| {
+            view.usage_bytes() + self.cache.vectors().held_bytes()
+        })
+    }
+
+    // Index type the Auto selector has outgrown the live index for, if any
+    pub fn index_migration_target(&self) -> Option<crate::index::IndexType> {
+        self.config
+            .index
+            .migration_target(self.vector_index.index_type(), self.count())
+    }
+
+    // Whether the index has drifted enough to retrain and the last retrain is past its cooldown
+    pub fn retrain_due(&self) -> bool {
+        let cooldown =
+            std::time::Duration::from_secs(self.config.maintenance.retrain_cooldown_secs);
+        self.vector_index.needs_retrain()
+            && self
+                .last_retrain
+                .is_none_or(|finished| finished.elapsed() >= cooldown)
+    }
+
+    /// Mark the collection as deleted. Call under the write lock before removing its files; an
+    /// off-lock index job that finishes afterwards gives up instead of installing its result.
+    pub fn mark_dropped(&mut self) {
+        self.dropped = true;
+    }
+
+    // Error for a background job that finds the collection deleted when it comes to swap
+    pub(super) fn ensure_not_dropped(&self) -> Result<()> {
+        if self.dropped {
+            return Err(crate::error::StorageError::CollectionNotFound(self.path.clone()).into());
         }
         Ok(())
     }
@@ -71,9 +230,22 @@ impl Collection {
         mmap_size
             + index_size
             + self.cache.memory_usage_bytes()
+            + self.view_usage_bytes()
             + self.vector_index.stats().memory_usage_bytes
     }
 
+    /// Resident memory by component. Unlike `memory_usage_bytes` this only counts data file pages
+    /// that are actually in memory.
+    pub fn memory_report(&self) -> MemoryReport {
+        let pointer_index = self.index.capacity() * std::mem::size_of::<(Uuid, EntryPointer)>();
+        MemoryReport {
+            cache_bytes: self.cache.memory_usage_bytes() + self.view_usage_bytes(),
+            index_bytes: pointer_index + self.vector_index.stats().memory_usage_bytes,
+            mmap_resident_bytes: self.record_store.resident_bytes(),
+            wal_buffer_bytes: self.checkpoint.wal.buffer_bytes(),
+        }
+    }
+
     pub fn vector_index(&self) -> &dyn VectorIndex {
         self.vector_index.as_ref()
     }
@@ -82,6 +254,44 @@ impl Collection {
         self.cache.memory_usage_bytes()
     }
 
+    /// Hot/cold split of the collection's vectors and how often reads found them in memory.
+    pub fn vector_tier_stats(&self) -> crate::cache::VectorTierStats {
+        self.cache.vector_tier_stats()
+    }
+
+    // Give the vectors whatever `memory.max_memory_per_collection` leaves after the indexes, the
+    // metadata cache and the read views, capped by the memory governor; vectors beyond that are
+    // read back from the arena file on demand.
+    pub(super) fn refresh_vector_budget(&mut self) {
+        let budget = self.config.memory.max_memory_per_collection.map(|limit| {
+            let index_size = self.index.capacity() * std::mem::size_of::<(Uuid, EntryPointer)>();
+            limit
+                .saturating_sub(index_size)
+                .saturating_sub(self.vector_index.stats().memory_usage_bytes)
+                .saturating_sub(self.cache.metadata_usage_bytes())
+                .saturating_sub(self.view_usage_bytes())
+        });
+        let budget = match (budget, self.vector_memory_cap) {
+            (Some(budget), Some(cap)) => Some(budget.min(cap)),
+            (budget, cap) => budget.or(cap),
+        };
+        self.cache.set_vector_budget(budget);
+    }
+
+    /// Keep at most `cap` bytes of vectors in memory on top of the collection's own budget,
+    /// evicting cold pages now if needed; None lifts the cap. Returns the hot vector bytes freed.
+    /// Has no effect unless the vector arena is memory-mapped.
+    pub fn cap_vector_memory(&mut self, cap: Option<usize>) -> usize {
+        let before = self.cache.vector_tier_stats().hot_bytes;
+        self.vector_memory_cap = cap;
+        self.refresh_vector_budget();
+        before.saturating_sub(self.cache.vector_tier_stats().hot_bytes)
+    }
+
+    pub fn vector_memory_cap(&self) -> Option<usize> {
+        self.vector_memory_cap
+    }
+
     pub fn metadata_cache_usage_bytes(&self) -> usize {
         self.cache.metadata_usage_bytes()
     }
@@ -90,20 +300,27 @@ impl Collection {
         self.cache.clear_metadata()
     }
 
-    pub fn clear_caches_for_rebuild(&mut self) {
-        self.cache.clear_all();
+    pub fn clear_caches_for_rebuild(&mut self) -> Result<()> {
+        self.cache.clear_all()
     }
 
     /// Fault frequently used files into the page cache to reduce cold-start latency.
     pub fn warm_page_cache(&self) {
         self.record_store.warm_page_cache();
-        let base = self.path.clone();
-        let _ = warm_file(&format!("{}.vecindex.db", base));
-        let _ = warm_file(&format!("{}.index.db", base));
-        let _ = warm_file(&get_wal_path(&base));
+        self.cache.warm_vectors();
+        // Only OS files go through the page cache
+        if !self.vfs.supports_mmap() {
+            return;
+        }
+        for path in checkpoint_files(&self.path).unwrap_or_default() {
+            let _ = warm_file(&path.to_string_lossy());
+        }
+        for segment in self.checkpoint.wal.segments() {
+            let _ = warm_file(&segment.path.to_string_lossy());
+        }
     }
 
-    pub fn vectors_view(&self) -> &HashMap<Uuid, Vec<f32>> {
+    pub fn vectors_view(&self) -> &VectorArena {
         self.cache.vectors()
     }
 
@@ -111,14 +328,43 @@ impl Collection {
         &self.cache
     }
 
-    pub fn metadata_view(&self) -> &HashMap<Uuid, crate::metadata::Metadata> {
-        self.cache.metadata()
+    pub fn metadata_reader(&self) -> &dyn MetadataReader {
+        self
+    }
+
+    /// Metadata of `id`, from the metadata cache or, on a miss, from its record in the data file.
+    pub fn metadata_of(&self, id: &Uuid) -> Result<Option<Arc<Metadata>>> {
+        let Some(pointer) = self.index.get(id) else {
+            return Ok(None);
+        };
+        if let Some(metadata) = self.cache.metadata(id, pointer.offset) {
+            return Ok(Some(metadata));
+        }
+        let entry = self.record_store.read_document(pointer)?;
+        let metadata = Arc::new(entry.metadata);
+        self.cache
+            .put_metadata(*id, pointer.offset, metadata.clone());
+        Ok(Some(metadata))
+    }
+
+    pub fn metadata_cache_stats(&self) -> MetadataCacheStats {
+        self.cache.metadata_stats()
+    }
+
+    pub fn result_cache_stats(&self) -> ResultCacheStats {
+        self.cache.results().stats()
     }
 
     pub fn config(&self) -> &crate::config::CollectionConfig {
         &self.config
     }
 
+    /// Durability point the latest write still has to reach. Wait on it after releasing the
+    /// collection lock so concurrent writers can share one group-commit fsync.
+    pub fn pending_wal_ack(&self) -> Option<crate::storage::wal::WalAck> {
+        self.checkpoint.wal.pending_ack()
+    }
+
     pub fn get_all(&self) -> Result<Vec<crate::storage::document::Document>> {
         let mut all_entries = Vec::new();
         for id in self.index.keys() {
@@ -140,6 +386,7 @@ impl Collection {
 
     /// Rebuild the vector index from on-disk data and persist it.
     pub fn rebuild_index(&mut self) -> Result<()> {
+        self.ensure_not_dropped()?;
         // Collect all vectors from storage
         let mut vectors: HashMap<Uuid, Vec<f32>> = HashMap::new();
 
@@ -151,14 +398,24 @@ impl Collection {
         // Build fresh index
         let mut new_index = self.config.index.create_index(self.index.len());
         let reader = HashMapVectorReader::new(&vectors);
-        for (id, vec) in &vectors {
-            new_index.insert(*id, vec, &reader);
-        }
+        let ids: Vec<Uuid> = vectors.keys().copied().collect();
+        new_index.insert_batch(&ids, &reader, &self.config.parallelism);
 
         // Swap and persist
-        self.vector_index = new_index;
+        self.vector_index = new_index.into();
         self.rebuild_vector_cache()?;
-        save_vector_index(self.path.as_str(), self.vector_index())?;
+        self.publish_view();
+        super::checkpoint::checkpoint(self)?;
         Ok(())
     }
 }
+
+impl MetadataReader for Collection {
+    // A record that cannot be read passes here; search re-reads it and reports the error
+    fn matches(&self, id: &Uuid, filter: &Filter) -> bool {
+        match self.metadata_of(id) {
+            Ok(Some(metadata)) => filter.matches(&metadata),
+            Ok(None) | Err(_) => true,
+        }
+    }
+}
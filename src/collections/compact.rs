@@ -1,70 +1,267 @@
 // Compaction logic for collections, including rewriting live documents and rebuilding indexes.
-//  takes a mutable reference to a `Collection` and performs compaction by creating a new temporary file, copying live documents to it, rebuilding the index and vector index, and then replacing the original file with the compacted version.
-use std::collections::HashMap;
+//  copies the live documents of a `Collection` into a new temporary file and builds a fresh index and vector index for them, then installs the result by replacing the original file with the compacted version.
+// Online compaction copies from a snapshot of the index with no lock held. Writes made meanwhile are recorded by the collection, and a short write lock copies them over before the files are swapped.
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use uuid::Uuid;
 
 use super::collection::Collection;
+use super::manager::CollectionHandle;
+use crate::config::CollectionConfig;
 use crate::error::Result;
-use crate::index::HashMapVectorReader;
+use crate::index::{HashMapVectorReader, VectorIndex};
 use crate::storage::document::Document;
-use crate::storage::persistence::{save_index, save_metadata, save_vector_index};
-use crate::storage::record_store::RecordStore;
+use crate::storage::persistence::EntryPointer;
+use crate::storage::record_store::{RecordReader, RecordStore};
+use crate::storage::vfs::{self, Vfs};
+
+/// Live and dead bytes of a collection's data file. Dead bytes are superseded records, deleted
+/// records and tombstones; compaction reclaims them.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StorageUsage {
+    pub live_bytes: u64,
+    pub dead_bytes: u64,
+}
+
+impl StorageUsage {
+    pub fn dead_ratio(&self) -> f64 {
+        let total = self.live_bytes + self.dead_bytes;
+        if total == 0 {
+            return 0.0;
+        }
+        self.dead_bytes as f64 / total as f64
+    }
+}
+
+/// Whether the collection's dead bytes have crossed the configured compaction thresholds.
+pub fn compaction_due(collection: &Collection) -> bool {
+    let config = collection.config.compaction;
+    if !config.enabled {
+        return false;
+    }
+    let usage = collection.storage_usage();
+    usage.dead_bytes >= config.dead_bytes
+        || (usage.dead_bytes >= config.min_dead_bytes && usage.dead_ratio() >= config.dead_ratio)
+}
 
 /// Compact a collection by rewriting live documents into a fresh file and rebuilding indexes.
 pub fn compact(collection: &mut Collection) -> Result<CompactStats> {
-    // 1. Get all live documents and their count before compaction
-    let original_entries = collection.index.len();
-    let docs: Vec<Document> = collection.get_all()?;
-
-    let temp_path = format!("{}.compact", collection.path);
-    match std::fs::remove_file(&temp_path) {
-        Ok(()) => {}
-        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
-        Err(error) => return Err(error.into()),
+    let snapshot = Snapshot::take(collection);
+    let compacted = copy_live(&snapshot, None, |pointer| {
+        collection.record_store.read_document(pointer)
+    })?;
+    install(collection, compacted)
+}
+
+/// Compact the collection behind `handle` while reads and writes continue, copying at most
+/// `max_bytes_per_sec` when set. Returns None when a compaction is already running.
+pub fn compact_online(
+    handle: &CollectionHandle,
+    max_bytes_per_sec: Option<u64>,
+) -> Result<Option<CompactStats>> {
+    // 1. Snapshot the index and start recording writes
+    let snapshot = {
+        let mut guard = handle.write();
+        if guard.record_changes.is_some() {
+            return Ok(None);
+        }
+        guard.record_changes = Some(HashSet::new());
+        Snapshot::take(&guard)
+    };
+
+    // 2. Copy the snapshotted records off-lock; appends never touch them
+    let copied = RecordReader::open_in(&*snapshot.vfs, &snapshot.path).and_then(|reader| {
+        copy_live(&snapshot, max_bytes_per_sec, |pointer| {
+            reader.read_document(pointer)
+        })
+    });
+
+    // 3. Copy the writes that landed during the copy, then swap
+    let mut guard = handle.write();
+    let changes = guard.record_changes.take().unwrap_or_default();
+    let mut compacted = copied?;
+    if let Err(error) = guard.ensure_not_dropped() {
+        // Installing now would bring the deleted data file back
+        drop(compacted.store);
+        vfs::remove_if_exists(&*guard.vfs, Path::new(&compacted.temp_path))?;
+        return Err(error);
+    }
+    compacted.replay(&guard, changes)?;
+    install(&mut guard, compacted).map(Some)
+}
+
+// What the copy works from, taken under the lock
+struct Snapshot {
+    path: String,
+    vfs: Arc<dyn Vfs>,
+    config: CollectionConfig,
+    pointers: Vec<EntryPointer>,
+    seq: u64,
+}
+
+impl Snapshot {
+    fn take(collection: &Collection) -> Self {
+        Self {
+            path: collection.path.clone(),
+            vfs: collection.vfs.clone(),
+            config: collection.config.clone(),
+            pointers: collection.index.values().cloned().collect(),
+            // Every live document is copied once, under a seq no later write can be below
+            seq: collection.checkpoint.wal.next_seq.saturating_sub(1),
+        }
+    }
+}
+
+// A compacted copy of the collection, written to `temp_path` but not yet in place
+struct Compacted {
+    store: RecordStore,
+    temp_path: String,
+    original_entries: usize,
+    index: HashMap<Uuid, EntryPointer>,
+    vector_index: Box<dyn VectorIndex>,
+}
+
+impl Compacted {
+    // Bring the copy up to date with the ids written since the snapshot
+    fn replay(&mut self, collection: &Collection, changes: HashSet<Uuid>) -> Result<()> {
+        let seq = collection.checkpoint.wal.next_seq.saturating_sub(1);
+        for id in changes {
+            self.vector_index.remove(&id);
+            match collection.index.get(&id) {
+                Some(pointer) => {
+                    let doc = collection.record_store.read_document(pointer)?;
+                    let bytes = RecordStore::encode_document(&doc)?;
+                    self.index.insert(id, self.store.append(&bytes, seq)?);
+                    self.vector_index
+                        .insert(id, &doc.get_vector(), &collection.cache);
+                }
+                None => {
+                    // The copy holds the deleted document, so a later scan must not revive it
+                    if self.index.remove(&id).is_some() {
+                        self.store.append_tombstone(&id, seq)?;
+                    }
+                }
+            }
+        }
+        Ok(())
     }
-    let mut temp_store = RecordStore::open(&temp_path, &collection.config, &HashMap::new())?;
-    let mut new_index = HashMap::with_capacity(docs.len());
-    let mut new_vectors = HashMap::with_capacity(docs.len());
-    let mut new_vector_index = collection.config.index.create_index(docs.len());
-    let mut new_metadata = collection.metadata.clone();
-    new_metadata.update_vector_count(0);
-
-    for doc in docs {
+}
+
+fn copy_live(
+    snapshot: &Snapshot,
+    max_bytes_per_sec: Option<u64>,
+    mut read: impl FnMut(&EntryPointer) -> Result<Document>,
+) -> Result<Compacted> {
+    // 1. Start a fresh record store next to the data file
+    let original_entries = snapshot.pointers.len();
+    let temp_path = format!("{}.compact", snapshot.path);
+    vfs::remove_if_exists(&*snapshot.vfs, Path::new(&temp_path))?;
+    let mut store = RecordStore::open_in(
+        &*snapshot.vfs,
+        &temp_path,
+        &snapshot.config,
+        &HashMap::new(),
+    )?;
+    let mut new_index = HashMap::with_capacity(original_entries);
+    let mut new_vectors = HashMap::with_capacity(original_entries);
+    let mut new_vector_index = snapshot.config.index.create_index(original_entries);
+
+    // 2. Copy live documents, pacing the writes when throttled
+    let mut throttle = max_bytes_per_sec.map(Throttle::new);
+    for pointer in &snapshot.pointers {
+        let doc = read(pointer)?;
         let id = doc.id;
         let vector = doc.get_vector();
         let bytes = RecordStore::encode_document(&doc)?;
-        let pointer = temp_store.append(&bytes)?;
-        new_metadata.set_dimensions(vector.len());
+        let pointer = store.append(&bytes, snapshot.seq)?;
+        if let Some(throttle) = throttle.as_mut() {
+            throttle.wrote(bytes.len() as u64);
+        }
         new_index.insert(id, pointer);
-        new_vectors.insert(id, vector.clone());
-        let reader = HashMapVectorReader::new(&new_vectors);
-        new_vector_index.insert(id, &vector, &reader);
+        new_vectors.insert(id, vector);
     }
-    new_metadata.update_vector_count(new_index.len());
-
-    temp_store.sync()?;
-    drop(temp_store);
-    std::fs::rename(&temp_path, &collection.path)?;
-
-    collection.record_store = RecordStore::open(&collection.path, &collection.config, &new_index)?;
-    collection.index = new_index;
-    collection.vector_index = new_vector_index;
-    collection.metadata = new_metadata;
-    collection.clear_caches_for_rebuild();
-    collection.rebuild_vector_cache()?;
-
-    // 4. Save the new index, vector index, and metadata to disk after compaction
-    save_index(&collection.path, &collection.index)?;
-    save_vector_index(&collection.path, collection.vector_index())?;
-    save_metadata(&collection.path, &collection.metadata)?;
-    // Rotate WAL to drop old entries after compaction
-    collection.checkpoint.wal.rotate()?;
 
-    Ok(CompactStats {
+    // 3. Build the vector index for the copied documents
+    let reader = HashMapVectorReader::new(&new_vectors);
+    let ids: Vec<_> = new_vectors.keys().copied().collect();
+    new_vector_index.insert_batch(&ids, &reader, &snapshot.config.parallelism);
+
+    Ok(Compacted {
+        store,
+        temp_path,
         original_entries,
+        index: new_index,
+        vector_index: new_vector_index,
+    })
+}
+
+fn install(collection: &mut Collection, compacted: Compacted) -> Result<CompactStats> {
+    compacted.store.sync()?;
+    drop(compacted.store);
+    collection
+        .vfs
+        .rename(Path::new(&compacted.temp_path), Path::new(&collection.path))?;
+
+    collection.record_store = RecordStore::open_in(
+        &*collection.vfs,
+        &collection.path,
+        &collection.config,
+        &compacted.index,
+    )?;
+    collection.live_bytes = collection.record_store.live_bytes(&compacted.index);
+    collection.index = compacted.index;
+    collection.vector_index = compacted.vector_index.into();
+    collection
+        .metadata
+        .update_vector_count(collection.index.len());
+    // The new vector index no longer needs the vectors of deleted documents
+    let index = &collection.index;
+    collection
+        .cache
+        .retain_vectors(|id| index.contains_key(id))?;
+    // Offsets in the new data file name other records than the cached ones did
+    collection.cache.renew_metadata();
+    collection.reset_view();
+    collection.publish_view();
+
+    // 4. Checkpoint the new index, vector index, and metadata, which also drops the WAL entries they cover
+    super::checkpoint::checkpoint(collection)?;
+
+    Ok(CompactStats {
+        original_entries: compacted.original_entries,
         compacted_entries: collection.index.len(),
     })
 }
 
+// Sleeps often enough to keep the copy at or below `bytes_per_sec`
+struct Throttle {
+    bytes_per_sec: u64,
+    started: Instant,
+    written: u64,
+}
+
+impl Throttle {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            started: Instant::now(),
+            written: 0,
+        }
+    }
+
+    fn wrote(&mut self, bytes: u64) {
+        self.written += bytes;
+        let due = Duration::from_secs_f64(self.written as f64 / self.bytes_per_sec as f64);
+        if let Some(ahead) = due.checked_sub(self.started.elapsed()) {
+            std::thread::sleep(ahead);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CompactStats {
     pub original_entries: usize,
@@ -26,8 +26,8 @@ pub fn find_duplicates(
 ) -> Result<Vec<DuplicateHit>> {
     let mut pairs = Vec::new();
     let vectors = collection.vectors_view();
-    let metadatas = collection.metadata_view();
-    let ids: Vec<Uuid> = vectors.keys().cloned().collect();
+    let metadatas = collection.metadata_reader();
+    let ids: Vec<Uuid> = vectors.ids().cloned().collect();
     let mode = collection.config.execution;
     let mut search_cfg = collection.config.search;
     if let Some(ef) = ef_override {
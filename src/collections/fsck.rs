@@ -0,0 +1,446 @@
+// Offline integrity checks and repair. Checking reads a collection's data file, checkpoint files and WAL without modifying any of them, so it is safe to run against a stopped server's data dir. Repair writes a new checkpoint generation from whatever is still consistent and then opens the collection once so the WAL is replayed on top.
+
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::checkpoint::load_wal_meta;
+use super::{Collection, CollectionOpenOptions};
+use crate::error::Result;
+use crate::index::{HashMapVectorReader, SerializableIndex, VectorIndex};
+use crate::storage::document::Document;
+use crate::storage::metadata::CollectionMetadata;
+use crate::storage::persistence::{
+    checkpoint_files, get_wal_path, load_manifest, manifest_path, write_checkpoint, EntryPointer,
+};
+use crate::storage::record_store::scan_records;
+use crate::storage::wal::Wal;
+
+/// One inconsistency found by [`check_collection`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblem {
+    /// A checkpoint file or the data file is missing or cannot be decoded.
+    Unreadable { file: PathBuf, reason: String },
+    /// An index entry that does not lead to a document with its id.
+    DanglingPointer {
+        id: Uuid,
+        offset: u64,
+        length: u32,
+        reason: String,
+    },
+    /// In the record index but not in the vector index.
+    MissingFromVectorIndex(Uuid),
+    /// In the vector index but not in the record index.
+    UnknownInVectorIndex(Uuid),
+    /// A document whose vector length differs from the collection's dimensions.
+    DimensionMismatch {
+        id: Uuid,
+        expected: usize,
+        found: usize,
+    },
+    /// A WAL segment with a torn or corrupt frame.
+    Wal {
+        segment: PathBuf,
+        offset: u64,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsckProblem::Unreadable { file, reason } => {
+                write!(f, "unreadable {}: {}", file.display(), reason)
+            }
+            FsckProblem::DanglingPointer {
+                id,
+                offset,
+                length,
+                reason,
+            } => write!(
+                f,
+                "dangling pointer for {id} (offset {offset}, length {length}): {reason}"
+            ),
+            FsckProblem::MissingFromVectorIndex(id) => {
+                write!(f, "{id} is missing from the vector index")
+            }
+            FsckProblem::UnknownInVectorIndex(id) => {
+                write!(
+                    f,
+                    "vector index holds {id}, which is not in the record index"
+                )
+            }
+            FsckProblem::DimensionMismatch {
+                id,
+                expected,
+                found,
+            } => write!(f, "{id} has {found} dimensions, collection has {expected}"),
+            FsckProblem::Wal {
+                segment,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "WAL segment {} is unreadable from offset {}: {}",
+                segment.display(),
+                offset,
+                reason
+            ),
+        }
+    }
+}
+
+/// Result of checking one collection.
+#[derive(Debug, Clone)]
+pub struct FsckReport {
+    pub collection: String,
+    pub documents: usize,
+    pub problems: Vec<FsckProblem>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// What [`repair_collection`] changed, and the check that followed it.
+#[derive(Debug, Clone)]
+pub struct RepairReport {
+    pub before: FsckReport,
+    pub after: FsckReport,
+    // The index could not be read and was rebuilt by scanning the data file
+    pub rebuilt_index: bool,
+    pub dropped_documents: usize,
+    // Damaged sealed WAL segments, and the segments after them, moved out of the log
+    pub quarantined_wal_segments: Vec<std::path::PathBuf>,
+}
+
+// The checkpoint state as far as it decodes.
+struct OnDisk {
+    name: String,
+    data: Option<memmap2::Mmap>,
+    index: Option<HashMap<Uuid, EntryPointer>>,
+    vector_index: Option<Box<dyn VectorIndex>>,
+    metadata: Option<CollectionMetadata>,
+    last_checkpoint_seq: u64,
+}
+
+impl OnDisk {
+    fn data(&self) -> &[u8] {
+        self.data.as_deref().unwrap_or(&[])
+    }
+}
+
+// Same encoding as `bincode::serialize`, with reads bounded by the bytes actually there.
+fn document_options(limit: usize) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit as u64)
+}
+
+fn read_document(data: &[u8], pointer: &EntryPointer) -> std::result::Result<Document, String> {
+    let start = pointer.offset as usize;
+    let end = start + pointer.length as usize;
+    if pointer.offset > data.len() as u64 || end > data.len() {
+        return Err(format!(
+            "past the end of the data file ({} bytes)",
+            data.len()
+        ));
+    }
+    document_options(pointer.length as usize)
+        .deserialize(&data[start..end])
+        .map_err(|e| format!("does not decode as a document: {e}"))
+}
+
+fn read_file<T: DeserializeOwned>(
+    path: &Path,
+    required: bool,
+    problems: &mut Vec<FsckProblem>,
+) -> Result<Option<T>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            if required {
+                problems.push(FsckProblem::Unreadable {
+                    file: path.to_path_buf(),
+                    reason: "file is missing".into(),
+                });
+            }
+            return Ok(None);
+        }
+        Err(error) => return Err(error.into()),
+    };
+    match bincode::deserialize(&bytes) {
+        Ok(value) => Ok(Some(value)),
+        Err(error) => {
+            problems.push(FsckProblem::Unreadable {
+                file: path.to_path_buf(),
+                reason: error.to_string(),
+            });
+            Ok(None)
+        }
+    }
+}
+
+fn read_on_disk(path: &str, problems: &mut Vec<FsckProblem>) -> Result<OnDisk> {
+    let name = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let data = match File::open(path) {
+        Ok(file) => {
+            let len = file.metadata()?.len() as usize;
+            // Mapping an empty file fails on some platforms, and there is nothing to read anyway
+            if len == 0 {
+                None
+            } else {
+                // The collection is offline, so nothing writes the file while it is mapped
+                Some(unsafe { memmap2::Mmap::map(&file)? })
+            }
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            problems.push(FsckProblem::Unreadable {
+                file: PathBuf::from(path),
+                reason: "data file is missing".into(),
+            });
+            None
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    let manifest = match load_manifest(path) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            problems.push(FsckProblem::Unreadable {
+                file: PathBuf::from(manifest_path(path)),
+                reason: error.to_string(),
+            });
+            None
+        }
+    };
+    // Legacy collections without a manifest may lack sidecars; the builder starts those empty.
+    let required = manifest.is_some();
+    let files = checkpoint_files(path).unwrap_or_default();
+    let (index, vector_index, metadata) = match files.as_slice() {
+        [index_file, vector_index_file, metadata_file] => {
+            let mut index = read_file(index_file, required, problems)?;
+            if index.is_none() && !required && !index_file.exists() {
+                index = Some(HashMap::new());
+            }
+            let vector_index =
+                read_file::<SerializableIndex>(vector_index_file, required, problems)?
+                    .map(SerializableIndex::to_trait_object);
+            let metadata = read_file(metadata_file, required, problems)?;
+            (index, vector_index, metadata)
+        }
+        _ => (None, None, None),
+    };
+    let last_checkpoint_seq = match &manifest {
+        Some(manifest) => manifest.last_checkpoint_seq,
+        None => load_wal_meta(path).unwrap_or(0),
+    };
+
+    Ok(OnDisk {
+        name,
+        data,
+        index,
+        vector_index,
+        metadata,
+        last_checkpoint_seq,
+    })
+}
+
+/// Check the collection stored at `path` without modifying any of its files.
+pub fn check_collection(path: &str) -> Result<FsckReport> {
+    let mut problems = Vec::new();
+    let disk = read_on_disk(path, &mut problems)?;
+    let index = disk.index.clone().unwrap_or_default();
+
+    // Every pointer has to decode to the document it is filed under.
+    let mut dimensions: HashMap<Uuid, usize> = HashMap::with_capacity(index.len());
+    let mut ids: Vec<&Uuid> = index.keys().collect();
+    ids.sort();
+    for id in ids {
+        let pointer = &index[id];
+        let reason = match read_document(disk.data(), pointer) {
+            Ok(document) if document.id == *id => {
+                dimensions.insert(*id, document.vector.len());
+                continue;
+            }
+            Ok(document) => format!("decodes as document {}", document.id),
+            Err(reason) => reason,
+        };
+        problems.push(FsckProblem::DanglingPointer {
+            id: *id,
+            offset: pointer.offset,
+            length: pointer.length,
+            reason,
+        });
+    }
+
+    if let Some(vector_index) = &disk.vector_index {
+        let indexed: HashSet<Uuid> = vector_index.ids().into_iter().collect();
+        let mut missing: Vec<Uuid> = index
+            .keys()
+            .filter(|id| !indexed.contains(id))
+            .copied()
+            .collect();
+        let mut unknown: Vec<Uuid> = indexed
+            .iter()
+            .filter(|id| !index.contains_key(id))
+            .copied()
+            .collect();
+        missing.sort();
+        unknown.sort();
+        problems.extend(missing.into_iter().map(FsckProblem::MissingFromVectorIndex));
+        problems.extend(unknown.into_iter().map(FsckProblem::UnknownInVectorIndex));
+    }
+
+    if let Some(expected) = expected_dimensions(disk.metadata.as_ref(), &dimensions) {
+        let mut mismatched: Vec<(Uuid, usize)> = dimensions
+            .iter()
+            .filter(|(_, found)| **found != expected)
+            .map(|(id, found)| (*id, *found))
+            .collect();
+        mismatched.sort();
+        problems.extend(
+            mismatched
+                .into_iter()
+                .map(|(id, found)| FsckProblem::DimensionMismatch {
+                    id,
+                    expected,
+                    found,
+                }),
+        );
+    }
+
+    for damaged in Wal::inspect(Path::new(&get_wal_path(path)))? {
+        problems.push(FsckProblem::Wal {
+            segment: damaged.segment,
+            offset: damaged.offset,
+            reason: damaged.reason,
+        });
+    }
+
+    Ok(FsckReport {
+        collection: disk.name,
+        documents: dimensions.len(),
+        problems,
+    })
+}
+
+// The collection's dimensions, or the most common vector length when the metadata has none.
+fn expected_dimensions(
+    metadata: Option<&CollectionMetadata>,
+    dimensions: &HashMap<Uuid, usize>,
+) -> Option<usize> {
+    if let Some(expected) = metadata.and_then(|metadata| metadata.dimensions) {
+        return Some(expected);
+    }
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for found in dimensions.values() {
+        *counts.entry(*found).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(found, count)| (*count, *found))
+        .map(|(found, _)| found)
+}
+
+/// Repair the collection stored at `path`: rebuild the index from the data file if it cannot be
+/// read, drop pointers that do not lead to a valid document and regenerate the vector index.
+/// The collection is then opened once with `options`, which replays the WAL, cuts off a torn
+/// tail and checkpoints.
+pub fn repair_collection(path: &str, options: CollectionOpenOptions) -> Result<RepairReport> {
+    let before = check_collection(path)?;
+    if before.is_clean() {
+        return Ok(RepairReport {
+            after: before.clone(),
+            before,
+            rebuilt_index: false,
+            dropped_documents: 0,
+            quarantined_wal_segments: Vec::new(),
+        });
+    }
+
+    let mut ignored = Vec::new();
+    let disk = read_on_disk(path, &mut ignored)?;
+    let rebuilt_index = disk.index.is_none();
+    let candidates = match &disk.index {
+        Some(index) => index.clone(),
+        None => scan_records(disk.data()).index,
+    };
+
+    let mut vectors: HashMap<Uuid, Vec<f32>> = HashMap::with_capacity(candidates.len());
+    let mut index = HashMap::with_capacity(candidates.len());
+    for (id, pointer) in candidates {
+        if let Ok(document) = read_document(disk.data(), &pointer) {
+            if document.id == id {
+                vectors.insert(id, document.vector);
+                index.insert(id, pointer);
+            }
+        }
+    }
+    let lengths: HashMap<Uuid, usize> = vectors.iter().map(|(id, v)| (*id, v.len())).collect();
+    let expected = expected_dimensions(disk.metadata.as_ref(), &lengths);
+    if let Some(expected) = expected {
+        vectors.retain(|_, vector| vector.len() == expected);
+        index.retain(|id, _| vectors.contains_key(id));
+    }
+    let dropped_documents = match &disk.index {
+        Some(original) => original.len() - index.len(),
+        None => 0,
+    };
+
+    let config = &options.config;
+    let mut vector_index = config.index.create_index(vectors.len());
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    vector_index.insert_batch(
+        &ids,
+        &HashMapVectorReader::new(&vectors),
+        &config.parallelism,
+    );
+    let mut metadata = disk
+        .metadata
+        .clone()
+        .unwrap_or_else(|| CollectionMetadata::new(disk.name.clone()));
+    if let (None, Some(expected)) = (metadata.dimensions, expected) {
+        metadata.set_dimensions(expected);
+    }
+    metadata.update_vector_count(index.len());
+
+    let last_checkpoint_seq = disk.last_checkpoint_seq;
+    // Unmap the data file before the collection reopens it
+    drop(disk);
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    write_checkpoint(
+        path,
+        last_checkpoint_seq,
+        timestamp,
+        &index,
+        vector_index.as_ref(),
+        &metadata,
+    )?;
+    // Opening refuses a damaged sealed segment, since later entries would replay without it
+    let quarantined_wal_segments =
+        Wal::quarantine_damaged(&*options.vfs, Path::new(&get_wal_path(path)))?;
+    drop(Collection::open_with_options(path, options)?);
+
+    Ok(RepairReport {
+        before,
+        after: check_collection(path)?,
+        rebuilt_index,
+        dropped_documents,
+        quarantined_wal_segments,
+    })
+}
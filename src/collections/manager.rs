@@ -1,9 +1,11 @@
-use dashmap::{mapref::one::Ref, DashMap};
+use dashmap::{mapref::entry::Entry, mapref::one::Ref, DashMap};
 use parking_lot::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
 
-use crate::collections::CollectionOpenOptions;
+use crate::collections::{CollectionOpenOptions, ReadView, ViewSlot};
 use crate::config::AppConfig;
 use crate::error::{Result, ServerError};
 use crate::metrics::LatencyTracker;
@@ -14,6 +16,13 @@ pub type CollectionHandle = Arc<RwLock<Collection>>;
 pub struct CollectionManager {
     collections: DashMap<String, CollectionHandle>,
     latency_trackers: DashMap<String, LatencyTracker>,
+    // Read view slots of the loaded collections that keep them, readable without the collection lock
+    views: DashMap<String, ViewSlot>,
+    // When each loaded collection was last handed out
+    last_access: DashMap<String, Instant>,
+    // Collections opened from disk and unloaded again since startup
+    opens: AtomicU64,
+    unloads: AtomicU64,
     data_dir: String,
     app_config: Arc<RwLock<AppConfig>>,
 }
@@ -23,6 +32,10 @@ impl CollectionManager {
         Self {
             collections: DashMap::new(),
             latency_trackers: DashMap::new(),
+            views: DashMap::new(),
+            last_access: DashMap::new(),
+            opens: AtomicU64::new(0),
+            unloads: AtomicU64::new(0),
             data_dir,
             app_config,
         }
@@ -30,6 +43,7 @@ impl CollectionManager {
 
     pub fn get_existing(&self, name: &str) -> Result<CollectionHandle> {
         if let Some(existing) = self.collections.get(name) {
+            self.touch(name);
             return Ok(existing.value().clone());
         }
 
@@ -43,6 +57,7 @@ impl CollectionManager {
 
     pub fn get_or_create(&self, name: &str) -> Result<CollectionHandle> {
         if let Some(existing) = self.collections.get(name) {
+            self.touch(name);
             return Ok(existing.value().clone());
         }
 
@@ -50,27 +65,134 @@ impl CollectionManager {
         self.open_and_register(name, &path)
     }
 
-    fn open_and_register(&self, name: &str, path: &str) -> Result<CollectionHandle> {
+    // Options collections are opened with, from the current config
+    pub fn open_options(&self) -> CollectionOpenOptions {
         let cfg = { self.app_config.read().clone() };
-        let collection = Collection::open_with_options(
-            path,
-            CollectionOpenOptions::from(cfg.to_collection_config()),
-        )?;
-        let handle = Arc::new(RwLock::new(collection));
+        CollectionOpenOptions::from(cfg.to_collection_config())
+    }
+
+    // Open the collection's files with the current config without registering the instance
+    pub fn open_unregistered(&self, name: &str) -> Result<Collection> {
+        Collection::open_with_options(&self.collection_path(name), self.open_options())
+    }
 
-        self.collections.insert(name.to_string(), handle.clone());
-        self.latency_trackers
-            .insert(name.to_string(), LatencyTracker::new());
-        self.warm_page_cache(handle.clone());
+    fn open_and_register(&self, name: &str, path: &str) -> Result<CollectionHandle> {
+        // Opening under the entry lock keeps concurrent first requests (e.g. for a collection that
+        // was just unloaded) from opening the same files twice
+        let handle = match self.collections.entry(name.to_string()) {
+            Entry::Occupied(existing) => existing.get().clone(),
+            Entry::Vacant(vacant) => {
+                let collection = Collection::open_with_options(path, self.open_options())?;
+                self.track_view(name, &collection);
+                let handle = Arc::new(RwLock::new(collection));
+                vacant.insert(handle.clone());
+                self.latency_trackers
+                    .insert(name.to_string(), LatencyTracker::new());
+                self.opens.fetch_add(1, Ordering::Relaxed);
+                self.warm_page_cache(handle.clone());
+                handle
+            }
+        };
+        self.touch(name);
 
         Ok(handle)
     }
 
     pub fn remove(&self, name: &str) -> Option<CollectionHandle> {
         self.latency_trackers.remove(name);
+        self.views.remove(name);
+        self.last_access.remove(name);
         self.collections.remove(name).map(|(_, handle)| handle)
     }
 
+    // Checkpoint a loaded collection and drop it from memory; the next request reopens it from
+    // disk. Returns false, leaving it loaded, when anything else holds a handle to it.
+    pub fn unload(&self, name: &str) -> Result<bool> {
+        let Some(handle) = self
+            .collections
+            .get(name)
+            .map(|entry| entry.value().clone())
+        else {
+            return Ok(false);
+        };
+        // The map and this function hold the only handles
+        if Arc::strong_count(&handle) > 2 {
+            return Ok(false);
+        }
+        // Checkpoint outside the map lock; holding the write lock keeps writes out until removal
+        let Some(mut guard) = handle.try_write() else {
+            return Ok(false);
+        };
+        guard.checkpoint()?;
+        guard.flush()?;
+        // A request that took a handle since the check above keeps the collection loaded. The side
+        // tables go while the entry is still locked, so a reopen can't lose its own.
+        let removed = self.collections.remove_if(name, |_, loaded| {
+            if !Arc::ptr_eq(loaded, &handle) || Arc::strong_count(loaded) > 2 {
+                return false;
+            }
+            self.latency_trackers.remove(name);
+            self.views.remove(name);
+            self.last_access.remove(name);
+            true
+        });
+        drop(guard);
+        if removed.is_none() {
+            return Ok(false);
+        }
+        self.unloads.fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    // Collections opened from disk since startup, reopens after an unload included
+    pub fn opens(&self) -> u64 {
+        self.opens.load(Ordering::Relaxed)
+    }
+
+    // Collections unloaded for being idle or to free memory since startup
+    pub fn unloads(&self) -> u64 {
+        self.unloads.load(Ordering::Relaxed)
+    }
+
+    // How long ago the collection was last handed out, if it is loaded
+    pub fn idle_for(&self, name: &str) -> Option<Duration> {
+        self.last_access
+            .get(name)
+            .map(|accessed| accessed.elapsed())
+    }
+
+    // Loaded collections, least recently used first
+    pub fn least_recently_used(&self) -> Vec<String> {
+        let mut collections: Vec<(String, Instant)> = self
+            .last_access
+            .iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
+        collections.sort_by_key(|(_, accessed)| *accessed);
+        collections.into_iter().map(|(name, _)| name).collect()
+    }
+
+    // Collections with a data file in the data dir, loaded or not, sorted by name
+    pub fn names_on_disk(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let entries = match std::fs::read_dir(&self.data_dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(error) => return Err(error.into()),
+        };
+        for entry in entries {
+            if let Some(name) = entry?
+                .file_name()
+                .to_str()
+                .and_then(collection_name_from_file)
+            {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
     pub fn contains_loaded(&self, name: &str) -> bool {
         self.collections.contains_key(name)
     }
@@ -90,11 +212,33 @@ impl CollectionManager {
             .collect()
     }
 
+    // Point readers at the read views of `collection`, which replaced the instance loaded as
+    // `name` (e.g. after a restore in place) or is about to be registered under it
+    pub fn track_view(&self, name: &str, collection: &Collection) {
+        match collection.view_slot() {
+            Some(slot) => {
+                self.views.insert(name.to_string(), slot);
+            }
+            None => {
+                self.views.remove(name);
+            }
+        }
+    }
+
+    // Latest read view of a loaded collection; None sends the caller to the collection lock
+    pub fn read_view(&self, name: &str) -> Option<Arc<ReadView>> {
+        self.views.get(name)?.load()
+    }
+
     pub fn tracker(&self, name: &str) -> Option<Ref<'_, String, LatencyTracker>> {
         self.latency_trackers.get(name)
     }
 
-    fn collection_path(&self, name: &str) -> String {
+    fn touch(&self, name: &str) {
+        self.last_access.insert(name.to_string(), Instant::now());
+    }
+
+    pub fn collection_path(&self, name: &str) -> String {
         format!("{}/{}.db", self.data_dir, name)
     }
 
@@ -107,3 +251,14 @@ impl CollectionManager {
         }
     }
 }
+
+/// The collection a data dir entry is the data file of, if it is one rather than a checkpoint,
+/// WAL, vector arena or other sidecar file. Collection names cannot contain dots, and every
+/// sidecar is named after the data file, so only `<name>.db` itself qualifies.
+pub fn collection_name_from_file(file_name: &str) -> Option<String> {
+    let name = file_name.strip_suffix(".db")?;
+    if name.is_empty() || name.contains('.') {
+        return None;
+    }
+    Some(name.to_string())
+}
@@ -0,0 +1,102 @@
+// Online index type migration. The new index is built from a snapshot of the live vectors with no
+// lock held, writes made meanwhile are recorded by the collection, and a short write lock replays
+// them onto the new index before it replaces `Collection.vector_index`.
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use super::manager::CollectionHandle;
+use crate::error::{IndexError, Result};
+use crate::index::{HashMapVectorReader, IndexType, VectorReader};
+
+// Stops the collection recording `index_changes` if an off-lock index build fails or panics
+// before its final write lock takes them, so the next build is not refused forever.
+pub(super) struct IndexChangesGuard<'a> {
+    handle: &'a CollectionHandle,
+    armed: bool,
+}
+
+impl<'a> IndexChangesGuard<'a> {
+    pub(super) fn new(handle: &'a CollectionHandle) -> Self {
+        Self {
+            handle,
+            armed: true,
+        }
+    }
+
+    // Take the recorded ids under the final write lock. An error if they are gone, which means the
+    // collection was replaced (restored, or unloaded and reopened) while the index was built.
+    pub(super) fn take(&mut self, collection: &mut super::Collection) -> Result<HashSet<Uuid>> {
+        self.armed = false;
+        collection.index_changes.take().ok_or_else(|| {
+            IndexError::BuildFailed(
+                "collection was replaced while its new index was built".to_string(),
+            )
+            .into()
+        })
+    }
+}
+
+impl Drop for IndexChangesGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            self.handle.write().index_changes = None;
+        }
+    }
+}
+
+/// Move the collection to the index type the Auto selector now picks for its size.
+/// Returns the new index type, or None when no migration is due or one is already running.
+pub fn migrate_index(handle: &CollectionHandle) -> Result<Option<IndexType>> {
+    // 1. Snapshot live vectors and start recording writes
+    let (snapshot, index_config, parallelism, target) = {
+        let mut guard = handle.write();
+        let Some(target) = guard.index_migration_target() else {
+            return Ok(None);
+        };
+        if guard.index_changes.is_some() {
+            return Ok(None);
+        }
+        // HNSW keeps deleted vectors cached for traversal, so filter by the live id map
+        let snapshot: HashMap<Uuid, Vec<f32>> = guard
+            .vectors_view()
+            .iter()
+            .filter(|(id, _)| guard.index.contains_key(id))
+            .map(|(id, vector)| (id, vector.to_vec()))
+            .collect();
+        guard.index_changes = Some(Default::default());
+        (
+            snapshot,
+            guard.config.index.clone(),
+            guard.config.parallelism,
+            target,
+        )
+    };
+
+    let mut recording = IndexChangesGuard::new(handle);
+
+    // 2. Build the new index off-lock
+    let mut new_index = index_config.create_index(snapshot.len());
+    let reader = HashMapVectorReader::new(&snapshot);
+    let ids: Vec<Uuid> = snapshot.keys().copied().collect();
+    new_index.insert_batch(&ids, &reader, &parallelism);
+    drop(snapshot);
+
+    // 3. Replay writes that landed during the build, then swap
+    let mut guard = handle.write();
+    let collection = &mut *guard;
+    let changes = recording.take(collection)?;
+    collection.ensure_not_dropped()?;
+    for id in changes {
+        new_index.remove(&id);
+        if collection.index.contains_key(&id) {
+            if let Some(vector) = collection.cache.get(&id) {
+                new_index.insert(id, vector, &collection.cache);
+            }
+        }
+    }
+    collection.vector_index = new_index.into();
+    collection.publish_view();
+    super::checkpoint::checkpoint(collection)?;
+    Ok(Some(target))
+}
@@ -4,25 +4,58 @@ mod checkpoint;
 mod collection;
 mod compact;
 mod dup;
+mod fsck;
 mod manager;
+mod migrate;
 mod operations;
+mod repair;
+mod retrain;
 mod search;
+mod snapshot;
+mod view;
 
 pub use builder::CollectionBuilder;
 pub use checkpoint::CheckpointManager;
-pub use collection::Collection;
-pub use compact::{compact, CompactStats};
+pub use collection::{Collection, MemoryReport};
+pub use compact::{compact, compact_online, compaction_due, CompactStats, StorageUsage};
 pub use dup::{find_duplicates, DuplicateHit};
-pub use manager::{CollectionHandle, CollectionManager};
-
-#[derive(Clone, Default)]
+pub use fsck::{check_collection, repair_collection, FsckProblem, FsckReport, RepairReport};
+pub use manager::{collection_name_from_file, CollectionHandle, CollectionManager};
+pub use migrate::migrate_index;
+pub use repair::repair_index;
+pub use retrain::retrain_index;
+pub use snapshot::{
+    create_snapshot, restore_snapshot, restore_to_point, PointInTimeRestore, RecoveryTarget,
+};
+pub use view::{ReadView, ViewSlot};
+
+#[derive(Clone)]
 pub struct CollectionOpenOptions {
     pub config: crate::config::CollectionConfig,
+    // File system the collection's files live in; the real one unless a test or an ephemeral
+    // collection asks for another
+    pub vfs: std::sync::Arc<dyn Vfs>,
+}
+
+impl CollectionOpenOptions {
+    pub fn with_vfs(mut self, vfs: std::sync::Arc<dyn Vfs>) -> Self {
+        self.vfs = vfs;
+        self
+    }
+}
+
+impl Default for CollectionOpenOptions {
+    fn default() -> Self {
+        Self::from(crate::config::CollectionConfig::default())
+    }
 }
 
 impl From<crate::config::CollectionConfig> for CollectionOpenOptions {
     fn from(config: crate::config::CollectionConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            vfs: crate::storage::vfs::os_vfs(),
+        }
     }
 }
 
@@ -31,7 +64,8 @@ use crate::metadata::Metadata;
 use crate::metrics::Metric;
 use crate::search::Hit;
 use crate::storage::document::Document;
-use std::collections::HashMap;
+use crate::storage::vector_arena::VectorArena;
+use crate::storage::vfs::Vfs;
 use uuid::Uuid;
 
 impl Collection {
@@ -48,31 +82,45 @@ impl Collection {
     }
 
     pub fn insert(&mut self, entry: Document) -> Result<Uuid> {
-        operations::insert(self, entry)
+        let result = operations::insert(self, entry);
+        self.publish_view();
+        result
     }
 
     pub fn insert_batch(&mut self, entries: Vec<Document>) -> Result<Vec<Uuid>> {
-        operations::insert_batch(self, entries)
+        let result = operations::insert_batch(self, entries);
+        self.publish_view();
+        result
     }
 
     pub fn upsert(&mut self, entry: Document) -> Result<Uuid> {
-        operations::upsert(self, entry)
+        let result = operations::upsert(self, entry);
+        self.publish_view();
+        result
     }
 
     pub fn delete(&mut self, id: &Uuid) -> Result<bool> {
-        operations::delete(self, id)
+        let result = operations::delete(self, id);
+        self.publish_view();
+        result
     }
 
     pub fn delete_batch(&mut self, ids: &[Uuid]) -> Result<usize> {
-        operations::delete_batch(self, ids)
+        let result = operations::delete_batch(self, ids);
+        self.publish_view();
+        result
     }
 
     pub fn update_metadata(&mut self, id: &Uuid, metadata: Metadata) -> Result<bool> {
-        operations::update_metadata(self, id, metadata)
+        let result = operations::update_metadata(self, id, metadata);
+        self.publish_view();
+        result
     }
 
     pub fn update_vector(&mut self, id: &Uuid, vector: Vec<f32>) -> Result<bool> {
-        operations::update_vector(self, id, vector)
+        let result = operations::update_vector(self, id, vector);
+        self.publish_view();
+        result
     }
 
     pub fn search(
@@ -85,6 +133,18 @@ impl Collection {
         search::search(self, query, k, metric, params)
     }
 
+    /// Like `search`, but repeated queries are answered from the result cache when
+    /// `cache.result_cache_size` is set. Also returns whether the hits came from the cache.
+    pub fn search_cached(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: Metric,
+        params: crate::search::SearchParams,
+    ) -> Result<(std::sync::Arc<Vec<Hit>>, bool)> {
+        search::search_cached(self, query, k, metric, params)
+    }
+
     pub fn search_batch(
         &self,
         queries: &[Vec<f32>],
@@ -94,7 +154,7 @@ impl Collection {
         search::search_batch(self, queries, k, metric)
     }
 
-    pub fn get_vectors(&self) -> &HashMap<Uuid, Vec<f32>> {
+    pub fn get_vectors(&self) -> &VectorArena {
         self.vectors_view()
     }
 
@@ -1,6 +1,8 @@
+use std::sync::Arc;
 use uuid::Uuid;
 
 use super::super::collection::Collection;
+use super::super::view;
 use super::limits;
 use super::read::get;
 use crate::error::Result;
@@ -24,11 +26,13 @@ pub fn update_metadata(storage: &mut Collection, id: &Uuid, metadata: Metadata)
         let bytes = RecordStore::encode_document(&entry)?;
 
         limits::enforce_single(storage, bytes.len())?;
-        let index_entry = storage.record_store.append(&bytes)?;
-        storage.index.insert(*id, index_entry);
-        storage.cache.put_metadata(*id, metadata);
+        let index_entry = storage.record_store.append(&bytes, wal_entry.seq())?;
+        let offset = index_entry.offset;
+        storage.set_pointer(*id, index_entry);
+        storage.cache.put_metadata(*id, offset, Arc::new(metadata));
+        storage.stage_view(*id);
         storage.metadata.update_vector_count(storage.index.len());
-        storage.track_operation()?;
+        storage.track_operation();
         Ok(true)
     } else {
         Ok(false)
@@ -58,14 +62,20 @@ pub fn update_vector(storage: &mut Collection, id: &Uuid, vector: Vec<f32>) -> R
         let bytes = RecordStore::encode_document(&entry)?;
         limits::enforce_single(storage, bytes.len())?;
 
-        let index_entry = storage.record_store.append(&bytes)?;
-        storage.index.insert(*id, index_entry);
-        storage.cache.put_vector(*id, vector.clone());
-        storage.cache.put_metadata(*id, entry.metadata.clone());
-        storage.vector_index.remove(id);
-        storage.vector_index.insert(*id, &vector, &storage.cache);
+        let index_entry = storage.record_store.append(&bytes, wal_entry.seq())?;
+        let offset = index_entry.offset;
+        storage.set_pointer(*id, index_entry);
+        storage.stage_view(*id);
+        storage.cache.put_vector(*id, &vector)?;
+        storage
+            .cache
+            .put_metadata(*id, offset, Arc::new(entry.metadata));
+        let vector_index = view::index_mut(&mut storage.vector_index);
+        vector_index.remove(id);
+        vector_index.insert(*id, &vector, &storage.cache);
+        storage.track_index_change(*id);
         storage.metadata.update_vector_count(storage.index.len());
-        storage.track_operation()?;
+        storage.track_operation();
         Ok(true)
     } else {
         Ok(false)
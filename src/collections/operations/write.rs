@@ -1,21 +1,37 @@
+use std::collections::HashSet;
+use std::sync::Arc;
 use uuid::Uuid;
 
 use super::super::collection::Collection;
+use super::super::view;
 use super::limits;
-use crate::error::Result;
+use crate::error::{PiramidError, Result};
 use crate::metadata::Metadata;
 use crate::storage::document::Document;
+use crate::storage::persistence::EntryPointer;
 use crate::storage::record_store::RecordStore;
 use crate::storage::wal::WalEntry;
 
-pub fn insert_internal(storage: &mut Collection, entry: Document) -> Result<Uuid> {
-    let id = entry.id;
-    let raw_vec = entry.get_vector();
+// Apply an insert logged under WAL sequence `seq`
+pub fn insert_internal(storage: &mut Collection, entry: Document, seq: u64) -> Result<Uuid> {
     let bytes = RecordStore::encode_document(&entry)?;
 
     limits::enforce_single(storage, bytes.len())?;
-    let index_entry = storage.record_store.append(&bytes)?;
-    storage.index.insert(id, index_entry.clone());
+    let index_entry = storage.record_store.append(&bytes, seq)?;
+    index_document(storage, entry, index_entry)
+}
+
+// Point the indexes at a document whose record is already in the data file
+fn index_document(
+    storage: &mut Collection,
+    entry: Document,
+    pointer: EntryPointer,
+) -> Result<Uuid> {
+    let id = entry.id;
+    let raw_vec = entry.get_vector();
+    let offset = pointer.offset;
+    storage.set_pointer(id, pointer);
+    storage.stage_view(id);
 
     storage.metadata.set_dimensions(raw_vec.len());
 
@@ -23,27 +39,45 @@ pub fn insert_internal(storage: &mut Collection, entry: Document) -> Result<Uuid
         crate::validation::validate_dimensions(&raw_vec, expected_dim)?;
     }
 
-    storage.cache.put_vector(id, raw_vec.clone());
-    storage.cache.put_metadata(id, entry.metadata.clone());
-    storage.vector_index.insert(id, &raw_vec, &storage.cache);
+    storage.cache.put_vector(id, &raw_vec)?;
+    storage
+        .cache
+        .put_metadata(id, offset, Arc::new(entry.metadata));
+    view::index_mut(&mut storage.vector_index).insert(id, &raw_vec, &storage.cache);
+    storage.track_index_change(id);
 
     storage.metadata.update_vector_count(storage.index.len());
 
     Ok(id)
 }
 
-pub fn delete_internal(storage: &mut Collection, id: &Uuid) {
-    storage.index.remove(id);
-    storage.vector_index.remove(id);
+pub fn delete_internal(storage: &mut Collection, id: &Uuid) -> Result<()> {
+    storage.remove_pointer(id);
+    view::index_mut(&mut storage.vector_index).remove(id);
+    storage.track_index_change(*id);
+    storage.stage_view(*id);
     if storage.vector_index.index_type() != crate::index::IndexType::Hnsw {
-        storage.cache.remove(id, true);
+        storage.cache.remove(id, true)?;
     } else {
-        storage.cache.remove(id, false);
+        storage.cache.remove(id, false)?;
     }
+    // HNSW keeps deleted vectors around for traversal until a background repair drops them
     storage.metadata.update_vector_count(storage.index.len());
+    Ok(())
+}
+
+// Take back the WAL entry of a write whose record the data file refused, so the write the caller
+// is told failed does not come back on replay
+fn abandon(storage: &mut Collection, seq: u64, error: PiramidError) -> PiramidError {
+    if let Err(retract_error) = storage.checkpoint.wal.retract(seq) {
+        tracing::warn!(path = %storage.path, error = %retract_error, "wal_retract_failed");
+    }
+    error
 }
 
 pub fn insert(storage: &mut Collection, entry: Document) -> Result<Uuid> {
+    let bytes = RecordStore::encode_document(&entry)?;
+    limits::enforce_single(storage, bytes.len())?;
     let vector = entry.get_vector();
     let mut wal_entry = WalEntry::Insert {
         id: entry.id,
@@ -54,57 +88,89 @@ pub fn insert(storage: &mut Collection, entry: Document) -> Result<Uuid> {
     };
     storage.checkpoint.wal.log(&mut wal_entry)?;
 
-    let id = insert_internal(storage, entry)?;
-    storage.track_operation()?;
+    let pointer = match storage.record_store.append(&bytes, wal_entry.seq()) {
+        Ok(pointer) => pointer,
+        Err(error) => return Err(abandon(storage, wal_entry.seq(), error)),
+    };
+    let id = index_document(storage, entry, pointer)?;
+    storage.track_operation();
     Ok(id)
 }
 
 pub fn insert_batch(storage: &mut Collection, mut entries: Vec<Document>) -> Result<Vec<Uuid>> {
     let mut ids = Vec::with_capacity(entries.len());
 
+    // Limits are checked before anything is logged, as for a single insert
+    let mut encoded = Vec::with_capacity(entries.len());
     for entry in &entries {
-        let vector = entry.get_vector();
-        let mut wal_entry = WalEntry::Insert {
+        encoded.push(RecordStore::encode_document(entry)?);
+    }
+    let total_bytes: u64 = encoded.iter().map(|bytes| bytes.len() as u64).sum();
+    let max_entry_bytes = encoded.iter().map(Vec::len).max();
+    limits::enforce_batch(storage, encoded.len(), total_bytes, max_entry_bytes)?;
+
+    let mut wal_entries: Vec<WalEntry> = entries
+        .iter()
+        .map(|entry| WalEntry::Insert {
             id: entry.id,
-            vector,
+            vector: entry.get_vector(),
             text: entry.text.clone(),
             metadata: entry.metadata.clone(),
             seq: 0,
-        };
-        storage.checkpoint.wal.log(&mut wal_entry)?;
-    }
+        })
+        .collect();
+    storage.checkpoint.wal.log_batch(&mut wal_entries)?;
 
-    let mut serialized: Vec<(Uuid, Vec<u8>)> = Vec::with_capacity(entries.len());
+    let mut serialized: Vec<(Uuid, u64, Vec<u8>)> = Vec::with_capacity(entries.len());
     let mut raw_vectors: Vec<(Uuid, Vec<f32>, Metadata)> = Vec::with_capacity(entries.len());
-    for entry in &mut entries {
+    for ((entry, wal_entry), bytes) in entries.iter_mut().zip(&wal_entries).zip(encoded) {
         let raw_vec = entry.get_vector();
-        let metadata = entry.metadata.clone();
-        let bytes = RecordStore::encode_document(entry)?;
-        serialized.push((entry.id, bytes));
-        raw_vectors.push((entry.id, raw_vec, metadata));
+        serialized.push((entry.id, wal_entry.seq(), bytes));
+        raw_vectors.push((entry.id, raw_vec, std::mem::take(&mut entry.metadata)));
     }
-    let total_bytes: u64 = serialized.iter().map(|(_, bytes)| bytes.len() as u64).sum();
-    let max_entry_bytes = serialized.iter().map(|(_, bytes)| bytes.len()).max();
-    limits::enforce_batch(storage, serialized.len(), total_bytes, max_entry_bytes)?;
-    let pointers = storage.record_store.append_batch(&serialized)?;
+    let pointers = match storage.record_store.append_batch(&serialized) {
+        Ok(pointers) => pointers,
+        Err(error) => match wal_entries.first() {
+            Some(first) => return Err(abandon(storage, first.seq(), error)),
+            None => return Err(error),
+        },
+    };
 
-    for ((id, _), pointer) in serialized.iter().zip(pointers) {
-        storage.index.insert(*id, pointer);
+    let mut offsets = Vec::with_capacity(pointers.len());
+    for ((id, _, _), pointer) in serialized.iter().zip(pointers) {
+        offsets.push(pointer.offset);
+        storage.set_pointer(*id, pointer);
+        storage.stage_view(*id);
         ids.push(*id);
     }
 
-    storage.track_operation()?;
-
-    for (id, vec_f32, metadata) in raw_vectors {
+    // Stage every valid vector in the cache, then index them together so HNSW can bulk-build.
+    let mut indexed_ids = Vec::with_capacity(raw_vectors.len());
+    let mut validation = Ok(());
+    for ((id, vec_f32, metadata), offset) in raw_vectors.into_iter().zip(offsets) {
         storage.metadata.set_dimensions(vec_f32.len());
         if let Some(expected_dim) = storage.metadata.dimensions {
-            crate::validation::validate_dimensions(&vec_f32, expected_dim)?;
+            validation = crate::validation::validate_dimensions(&vec_f32, expected_dim);
+            if validation.is_err() {
+                break;
+            }
         }
-        storage.cache.put_metadata(id, metadata);
-        storage.cache.put_vector(id, vec_f32.clone());
-        storage.vector_index.insert(id, &vec_f32, &storage.cache);
+        storage.cache.put_metadata(id, offset, Arc::new(metadata));
+        storage.cache.put_vector(id, &vec_f32)?;
+        indexed_ids.push(id);
+    }
+    view::index_mut(&mut storage.vector_index).insert_batch(
+        &indexed_ids,
+        &storage.cache,
+        &storage.config.parallelism,
+    );
+    for id in indexed_ids {
+        storage.track_index_change(id);
     }
     storage.metadata.update_vector_count(storage.index.len());
+    // Only now, since it may checkpoint, and the checkpoint has to see the batch indexed
+    storage.track_operation();
+    validation?;
 
     Ok(ids)
 }
@@ -126,12 +192,17 @@ pub fn upsert(storage: &mut Collection, entry: Document) -> Result<Uuid> {
         };
         storage.checkpoint.wal.log(&mut wal_entry)?;
 
-        delete_internal(storage, &id);
-        insert_internal(storage, entry)?;
-        storage.track_operation()?;
+        // Write the new record before dropping the old one, so a failed append leaves the old
+        // version readable
+        let pointer = match storage.record_store.append(&bytes, wal_entry.seq()) {
+            Ok(pointer) => pointer,
+            Err(error) => return Err(abandon(storage, wal_entry.seq(), error)),
+        };
+        delete_internal(storage, &id)?;
+        index_document(storage, entry, pointer)?;
+        storage.track_operation();
         Ok(id)
     } else {
-        limits::enforce_single(storage, bytes.len())?;
         insert(storage, entry)
     }
 }
@@ -141,8 +212,11 @@ pub fn delete(storage: &mut Collection, id: &Uuid) -> Result<bool> {
         let mut wal_entry = WalEntry::Delete { id: *id, seq: 0 };
         storage.checkpoint.wal.log(&mut wal_entry)?;
 
-        delete_internal(storage, id);
-        storage.track_operation()?;
+        if let Err(error) = storage.record_store.append_tombstone(id, wal_entry.seq()) {
+            return Err(abandon(storage, wal_entry.seq(), error));
+        }
+        delete_internal(storage, id)?;
+        storage.track_operation();
         Ok(true)
     } else {
         Ok(false)
@@ -150,25 +224,34 @@ pub fn delete(storage: &mut Collection, id: &Uuid) -> Result<bool> {
 }
 
 pub fn delete_batch(storage: &mut Collection, ids: &[Uuid]) -> Result<usize> {
-    let mut deleted_count = 0;
-
-    for id in ids {
-        if storage.index.contains_key(id) {
-            let mut wal_entry = WalEntry::Delete { id: *id, seq: 0 };
-            storage.checkpoint.wal.log(&mut wal_entry)?;
-        }
+    let mut seen = HashSet::with_capacity(ids.len());
+    let present: Vec<Uuid> = ids
+        .iter()
+        .filter(|id| storage.index.contains_key(id) && seen.insert(**id))
+        .copied()
+        .collect();
+    if present.is_empty() {
+        return Ok(0);
     }
 
-    for id in ids {
-        if storage.index.contains_key(id) {
-            delete_internal(storage, id);
-            deleted_count += 1;
-        }
-    }
+    let mut wal_entries: Vec<WalEntry> = present
+        .iter()
+        .map(|id| WalEntry::Delete { id: *id, seq: 0 })
+        .collect();
+    storage.checkpoint.wal.log_batch(&mut wal_entries)?;
 
-    if deleted_count > 0 {
-        storage.track_operation()?;
+    let tombstones: Vec<(Uuid, u64)> = present
+        .iter()
+        .zip(&wal_entries)
+        .map(|(id, wal_entry)| (*id, wal_entry.seq()))
+        .collect();
+    if let Err(error) = storage.record_store.append_tombstones(&tombstones) {
+        return Err(abandon(storage, tombstones[0].1, error));
+    }
+    for id in &present {
+        delete_internal(storage, id)?;
     }
+    storage.track_operation();
 
-    Ok(deleted_count)
+    Ok(present.len())
 }
@@ -0,0 +1,40 @@
+// Background index repair. Deletes only mark HNSW nodes as tombstones; once they pass the
+// configured share of the graph this job rewires their neighbours and drops them, a bounded batch
+// of nodes per write lock so writes and lock-bound reads get in between the steps.
+use parking_lot::RwLockWriteGuard;
+
+use super::manager::CollectionHandle;
+use crate::error::Result;
+
+/// Run the collection's pending index repair to completion in `maintenance.repair_batch` steps.
+/// Returns the number of entries dropped from the index, or None when no repair was due.
+pub fn repair_index(handle: &CollectionHandle) -> Result<Option<usize>> {
+    loop {
+        let mut guard = handle.write();
+        guard.ensure_not_dropped()?;
+        let config = guard.config.maintenance;
+        if !guard.vector_index.needs_repair(config.tombstone_ratio) {
+            return Ok(None);
+        }
+        let collection = &mut *guard;
+        let step = super::view::index_mut(&mut collection.vector_index)
+            .repair_step(&collection.cache, config.repair_batch.max(1));
+        let Some(removed) = step else {
+            // Hand the lock to waiting writers before the next step
+            RwLockWriteGuard::unlock_fair(guard);
+            continue;
+        };
+
+        // Deleted vectors stayed cached for traversal; nothing routes through them any more
+        let mut dropped = 0;
+        for id in removed {
+            if !collection.index.contains_key(&id) {
+                collection.cache.remove(&id, true)?;
+                collection.stage_view(id);
+                dropped += 1;
+            }
+        }
+        collection.publish_view();
+        return Ok(Some(dropped));
+    }
+}
@@ -0,0 +1,74 @@
+// Online index retraining. Like a migration, the new index is trained from a snapshot of the live
+// vectors with no lock held while the collection records the ids written meanwhile; a short write
+// lock replays them onto the retrained index before it replaces the live one.
+use std::collections::HashMap;
+use std::time::Instant;
+
+use uuid::Uuid;
+
+use super::manager::CollectionHandle;
+use crate::error::Result;
+use crate::index::{HashMapVectorReader, VectorReader};
+
+/// Retrain the collection's vector index (IVF k-means) without blocking readers or writers.
+/// Returns false when the index type has nothing to retrain, when another off-lock index build is
+/// running, or when `force` is unset and either the index is not drifted enough to need it or the
+/// retrained index does not cut the drift by `maintenance.retrain_min_improvement`.
+pub fn retrain_index(handle: &CollectionHandle, force: bool) -> Result<bool> {
+    // 1. Snapshot live vectors and start recording writes
+    let (snapshot, current) = {
+        let mut guard = handle.write();
+        if !force && !guard.retrain_due() {
+            return Ok(false);
+        }
+        if guard.index_changes.is_some() {
+            return Ok(false);
+        }
+        let snapshot: HashMap<Uuid, Vec<f32>> = guard
+            .vectors_view()
+            .iter()
+            .filter(|(id, _)| guard.index.contains_key(id))
+            .map(|(id, vector)| (id, vector.to_vec()))
+            .collect();
+        guard.index_changes = Some(Default::default());
+        (snapshot, guard.vector_index.clone())
+    };
+
+    let mut recording = super::migrate::IndexChangesGuard::new(handle);
+
+    // 2. Train off-lock against the snapshot
+    let reader = HashMapVectorReader::new(&snapshot);
+    let retrained = current.retrained(&reader);
+    let improved = match (&retrained, current.drift()) {
+        (Some(retrained), Some(before)) => retrained.drift().is_some_and(|after| {
+            let min_improvement = handle.read().config.maintenance.retrain_min_improvement;
+            after <= before * (1.0 - min_improvement)
+        }),
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+    drop(current);
+    drop(snapshot);
+
+    // 3. Replay writes that landed during training, then swap
+    let mut guard = handle.write();
+    let collection = &mut *guard;
+    let changes = recording.take(collection)?;
+    collection.ensure_not_dropped()?;
+    collection.last_retrain = Some(Instant::now());
+    let Some(mut retrained) = retrained.filter(|_| force || improved) else {
+        return Ok(false);
+    };
+    for id in changes {
+        retrained.remove(&id);
+        if collection.index.contains_key(&id) {
+            if let Some(vector) = collection.cache.get(&id) {
+                retrained.insert(id, vector, &collection.cache);
+            }
+        }
+    }
+    collection.vector_index = retrained.into();
+    collection.publish_view();
+    super::checkpoint::checkpoint(collection)?;
+    Ok(true)
+}
@@ -1,11 +1,49 @@
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::cache::{ResultCache, ResultKey};
+use crate::config::CollectionConfig;
+use crate::index::{MetadataReader, VectorIndex, VectorReader};
 use crate::metrics::Metric;
-use crate::search::Hit;
+use crate::search::{Hit, SearchSource};
+use crate::storage::document::Document;
 use crate::Result;
 
 use super::Collection;
 
-pub fn search(
-    collection: &Collection,
+impl SearchSource for Collection {
+    fn config(&self) -> &CollectionConfig {
+        &self.config
+    }
+
+    fn vector_index(&self) -> &dyn VectorIndex {
+        self.vector_index.as_ref()
+    }
+
+    fn vector_reader(&self) -> &dyn VectorReader {
+        &self.cache
+    }
+
+    fn metadata_reader(&self) -> &dyn MetadataReader {
+        self
+    }
+
+    fn document(&self, id: &Uuid) -> Result<Option<Document>> {
+        super::operations::get(self, id)
+    }
+
+    fn result_cache(&self) -> &ResultCache {
+        self.cache.results()
+    }
+
+    fn generation(&self) -> u64 {
+        self.write_generation
+    }
+}
+
+pub fn search<S: SearchSource + ?Sized>(
+    source: &S,
     query: &[f32],
     k: usize,
     metric: Metric,
@@ -13,26 +51,66 @@ pub fn search(
 ) -> Result<Vec<Hit>> {
     // If the execution mode in the search parameters is set to Auto, we override it with the collection's configured execution mode.
     if matches!(params.mode, crate::config::ExecutionMode::Auto) {
-        params.mode = collection.config().execution;
+        params.mode = source.config().execution;
     }
     // If the filter overfetch override is not set in the search parameters, we set it to the collection's configured filter overfetch value.
     if params.filter_overfetch_override.is_none() {
-        params.filter_overfetch_override = Some(collection.config.search.filter_overfetch);
+        params.filter_overfetch_override = Some(source.config().search.filter_overfetch);
+    }
+    crate::search::search_collection(source, query, k, metric, params)
+}
+
+// Answer repeated queries from the collection's result cache when it is enabled. Returns the hits
+// and whether they came from the cache.
+pub fn search_cached<S: SearchSource + ?Sized>(
+    source: &S,
+    query: &[f32],
+    k: usize,
+    metric: Metric,
+    params: crate::search::SearchParams,
+) -> Result<(Arc<Vec<Hit>>, bool)> {
+    let results = source.result_cache();
+    if !results.is_enabled() {
+        return Ok((Arc::new(search(source, query, k, metric, params)?), false));
+    }
+    let config = source.config();
+    // Key on the parameters as `search` resolves them, so equivalent requests share an entry
+    let mode = match params.mode {
+        crate::config::ExecutionMode::Auto => config.execution,
+        mode => mode,
+    };
+    let overfetch = params
+        .filter_overfetch_override
+        .unwrap_or(config.search.filter_overfetch);
+    let search_config = params.search_config_override.unwrap_or(config.search);
+    let key = ResultKey::new(
+        query,
+        k,
+        format!(
+            "{:?}|{:?}|{}|{:?}|{:?}",
+            metric, mode, overfetch, params.filter, search_config
+        ),
+    );
+    let generation = source.generation();
+    if let Some(hits) = results.get(&key, generation) {
+        return Ok((hits, true));
     }
-    crate::search::search_collection(collection, query, k, metric, params)
+    let hits = Arc::new(search(source, query, k, metric, params)?);
+    results.put(key, generation, hits.clone());
+    Ok((hits, false))
 }
 
-pub fn search_batch(
-    collection: &Collection,
+pub fn search_batch<S: SearchSource + ?Sized>(
+    source: &S,
     queries: &[Vec<f32>],
     k: usize,
     metric: Metric,
 ) -> Result<Vec<Vec<Hit>>> {
     let params = crate::search::SearchParams {
-        mode: collection.config().execution,
+        mode: source.config().execution,
         filter: None,
         filter_overfetch_override: None,
         search_config_override: None,
     };
-    crate::search::search_batch_collection(collection, queries, k, metric, params)
+    crate::search::search_batch_collection(source, queries, k, metric, params)
 }
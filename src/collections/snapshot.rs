@@ -0,0 +1,265 @@
+// Online snapshots, restore and point-in-time recovery. A snapshot checkpoints the collection and then archives its files while holding only a read lock, so searches keep running and writers wait just for the copy. Point-in-time recovery restores a snapshot and replays the WAL entries that follow it, up to a target.
+
+use parking_lot::RwLockWriteGuard;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::manager::CollectionHandle;
+use super::{Collection, CollectionBuilder, CollectionOpenOptions};
+use crate::error::{Result, ServerError};
+use crate::storage::persistence::{
+    checkpoint_files, load_manifest, manifest_path, remove_checkpoint_files_in,
+};
+use crate::storage::snapshot::{
+    extract_snapshot_in, write_snapshot, SnapshotDescriptor, SNAPSHOT_VERSION,
+};
+use crate::storage::vfs::{self, OsVfs, Vfs};
+use crate::storage::wal::{remove_wal_files_in, set_aside_archive_in, Wal, WalEntry, WalRecord};
+
+/// Checkpoint the collection and write a self-contained snapshot of it to `archive`.
+pub fn create_snapshot(
+    handle: &CollectionHandle,
+    collection: &str,
+    archive: &Path,
+) -> Result<SnapshotDescriptor> {
+    let mut guard = handle.write();
+    guard.checkpoint()?;
+    guard.flush()?;
+    // Nothing can write to the files until the read lock is released, so the copy is consistent.
+    let guard = RwLockWriteGuard::downgrade(guard);
+
+    let manifest = load_manifest(&guard.path)?.ok_or_else(|| {
+        crate::error::PiramidError::other("checkpoint did not produce a manifest")
+    })?;
+    let mut files = vec![
+        PathBuf::from(&guard.path),
+        PathBuf::from(manifest_path(&guard.path)),
+    ];
+    files.extend(checkpoint_files(&guard.path)?);
+    files.extend(
+        guard
+            .checkpoint
+            .wal
+            .segments()
+            .iter()
+            .map(|segment| segment.path.clone()),
+    );
+
+    let created_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    write_snapshot(
+        archive,
+        &guard.path,
+        SnapshotDescriptor {
+            version: SNAPSHOT_VERSION,
+            collection: collection.to_string(),
+            created_at,
+            last_checkpoint_seq: manifest.last_checkpoint_seq,
+            vector_count: guard.count(),
+            files: Vec::new(),
+        },
+        &files,
+    )
+}
+
+/// How far to replay the WAL on top of a restored snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryTarget {
+    /// Up to and including this sequence number.
+    Seq(u64),
+    /// Up to the last entry appended at or before this unix time in seconds. Segments written
+    /// before entries were timestamped only have checkpoint times to go by, so in those the
+    /// result is as fine-grained as the checkpoint interval.
+    Time(u64),
+}
+
+/// A collection brought back to a point in time.
+pub struct PointInTimeRestore {
+    pub collection: Collection,
+    pub descriptor: SnapshotDescriptor,
+    pub recovered_seq: u64,
+    pub replayed: usize,
+}
+
+/// Replace the files of the collection at `collection_path` with a snapshot. Nothing may use an
+/// open instance of the collection while this runs; reopen it afterwards. The snapshot is
+/// unpacked and verified in a staging directory first, so a corrupt archive leaves the existing
+/// collection untouched.
+pub fn restore_snapshot<R: Read>(reader: R, collection_path: &str) -> Result<SnapshotDescriptor> {
+    let staged = stage(&OsVfs, reader, collection_path)?;
+    commit(&OsVfs, &staged, collection_path)?;
+    Ok(staged.descriptor)
+}
+
+/// Restore a snapshot and replay the WAL of the snapshotted collection up to `target`. The WAL is
+/// read from that collection's live and archived segments in the same directory as
+/// `collection_path`, so entries purged by a checkpoint are only available with `wal.archive`
+/// enabled. The restored collection is opened, brought forward and checkpointed.
+pub fn restore_to_point<R: Read>(
+    reader: R,
+    collection_path: &str,
+    target: RecoveryTarget,
+    options: CollectionOpenOptions,
+) -> Result<PointInTimeRestore> {
+    let vfs = options.vfs.clone();
+    let staged = stage(&*vfs, reader, collection_path)?;
+    let plan = plan_replay(&*vfs, &staged, target);
+    let entries = match plan {
+        Ok(entries) => entries,
+        Err(error) => {
+            let _ = vfs.remove_dir_all(&staged.dir);
+            return Err(error);
+        }
+    };
+    commit(&*vfs, &staged, collection_path)?;
+
+    let mut collection = Collection::open_with_options(collection_path, options)?;
+    let recovered_seq = entries
+        .last()
+        .map(WalEntry::seq)
+        .unwrap_or(staged.descriptor.last_checkpoint_seq);
+    let replayed = entries.len();
+    CollectionBuilder::replay_wal(&mut collection, entries)?;
+    // The restored files end at the snapshot, so the WAL opened at that sequence; later writes and
+    // the checkpoint must come after what was replayed, or a scan rebuild would prefer the replay
+    let wal = &mut collection.checkpoint.wal;
+    wal.next_seq = wal.next_seq.max(recovered_seq + 1);
+    collection.rebuild_vector_cache()?;
+    collection.publish_view();
+    super::checkpoint::checkpoint(&mut collection)?;
+
+    Ok(PointInTimeRestore {
+        collection,
+        descriptor: staged.descriptor,
+        recovered_seq,
+        replayed,
+    })
+}
+
+struct Staged {
+    dir: PathBuf,
+    descriptor: SnapshotDescriptor,
+    // Directory holding the collection files
+    parent: PathBuf,
+}
+
+fn stage<R: Read>(vfs: &dyn Vfs, reader: R, collection_path: &str) -> Result<Staged> {
+    let path = Path::new(collection_path);
+    let parent = vfs::parent_dir(path);
+    let base = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let dir = parent.join(format!(".{}.restore", base));
+    if vfs.exists(&dir) {
+        vfs.remove_dir_all(&dir)?;
+    }
+    vfs.create_dir_all(&dir)?;
+    match extract_snapshot_in(vfs, reader, &dir, &base) {
+        Ok(descriptor) => Ok(Staged {
+            dir,
+            descriptor,
+            parent,
+        }),
+        Err(error) => {
+            let _ = vfs.remove_dir_all(&dir);
+            Err(error)
+        }
+    }
+}
+
+// The WAL entries to apply on top of the staged snapshot, checked to be gap-free.
+fn plan_replay(vfs: &dyn Vfs, staged: &Staged, target: RecoveryTarget) -> Result<Vec<WalEntry>> {
+    let start = staged.descriptor.last_checkpoint_seq;
+    let history_base = staged
+        .parent
+        .join(format!("{}.db.wal.db", staged.descriptor.collection));
+    let invalid = |message: String| -> crate::error::PiramidError {
+        ServerError::InvalidRequest(message).into()
+    };
+
+    let history = Wal::read_history_in(vfs, &history_base, start)?;
+    let end = match target {
+        RecoveryTarget::Seq(seq) if seq < start => {
+            return Err(invalid(format!(
+                "Snapshot covers up to seq {start}, which is past the recovery target {seq}"
+            )))
+        }
+        RecoveryTarget::Time(time) if staged.descriptor.created_at > time => {
+            return Err(invalid(format!(
+                "Snapshot was taken at {}, after the recovery target {time}",
+                staged.descriptor.created_at
+            )))
+        }
+        RecoveryTarget::Seq(seq) => seq,
+        RecoveryTarget::Time(time) => resolve_time(&history, start, time),
+    };
+
+    let entries: Vec<WalEntry> = history
+        .into_iter()
+        .map(|record| record.entry)
+        .filter(|entry| entry.seq() <= end)
+        .collect();
+    for (expected, entry) in (start + 1..).zip(&entries) {
+        if entry.seq() != expected {
+            return Err(invalid(format!(
+                "WAL history is missing seq {expected}; enable wal.archive to keep checkpointed segments"
+            )));
+        }
+    }
+    let reached = entries.last().map(WalEntry::seq).unwrap_or(start);
+    if reached < end {
+        return Err(invalid(format!(
+            "WAL history ends at seq {reached}, before the recovery target {end}"
+        )));
+    }
+    Ok(entries)
+}
+
+// The last seq appended at or before `time` (unix seconds). Timestamped entries decide on their own;
+// an untimed entry counts only once a later checkpoint at or before `time` vouches for it.
+fn resolve_time(history: &[WalRecord], start: u64, time: u64) -> u64 {
+    let limit = time.saturating_add(1).saturating_mul(1000);
+    let mut end = start;
+    for record in history {
+        let seq = record.entry.seq();
+        match (record.written_at, &record.entry) {
+            (Some(written_at), _) if written_at < limit => end = seq,
+            (Some(_), _) => break,
+            (None, WalEntry::Checkpoint { timestamp, .. }) if *timestamp <= time => end = seq,
+            (None, _) => {}
+        }
+    }
+    end
+}
+
+// Swap the staged files in for the current ones.
+fn commit(vfs: &dyn Vfs, staged: &Staged, collection_path: &str) -> Result<()> {
+    // Drop the current files, then move the restored ones in with the manifest last, so the
+    // generation it names is already in place when it appears.
+    vfs::remove_if_exists(vfs, Path::new(collection_path))?;
+    remove_checkpoint_files_in(vfs, collection_path)?;
+    let wal_base = PathBuf::from(format!("{}.wal.db", collection_path));
+    remove_wal_files_in(vfs, &wal_base)?;
+    // Sequence numbers restart from the snapshot, so the old archive would mix two histories.
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    set_aside_archive_in(vfs, &wal_base, &format!("before-restore-{millis}"))?;
+
+    let manifest = PathBuf::from(manifest_path(collection_path));
+    let mut restored = vfs.read_dir(&staged.dir)?;
+    restored.sort_by_key(|file| file.file_name() == manifest.file_name());
+    for file in restored {
+        if let Some(name) = file.file_name() {
+            vfs.rename(&file, &staged.parent.join(name))?;
+        }
+    }
+    vfs.remove_dir_all(&staged.dir)?;
+    vfs.sync_dir(&staged.parent)?;
+    Ok(())
+}
@@ -0,0 +1,396 @@
+// Immutable read views, so searches never wait on a collection's write lock.
+//
+// At the end of every write operation a collection publishes a `ReadView` into its `ViewSlot`: the
+// vector index, where every vector the index can reach and the record it belongs to live, and the
+// write generation, all behind `Arc`s. Searches load the latest view and run against it without
+// the collection lock, so a long write (a batch insert, an index rebuild) only delays when its
+// changes become visible.
+//
+// A view copies neither vectors nor records. It reads vectors from the collection's arena map,
+// which keeps every row a live view can reach (see `VectorArena::view`), through the same hot/cold
+// tier as the collection. It reads records from the data file with positional reads on a handle
+// every view shares; the file is append-only, so the records it points at stay put. Metadata comes
+// through the collection's metadata cache. What a view does hold, its id-to-row map, sits in shards
+// a write copies only when a view still holds the one it changes. The vector index keeps its tables
+// in shared chunks the same way (see `index::chunks`), so the first change after a publish copies
+// the chunk tables and the chunks it touches rather than the whole index.
+//
+// A collection only starts keeping views once a reader has asked its slot for one. Until then the
+// slot stays empty, readers fall back to the lock, and no rows are pinned for collections nobody
+// searches.
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::collection::Collection;
+use crate::cache::{MetadataCache, ResultCache, VectorTier};
+use crate::config::CollectionConfig;
+use crate::error::Result;
+use crate::index::{MetadataReader, VectorIndex, VectorReader};
+use crate::metadata::Metadata;
+use crate::metrics::Metric;
+use crate::search::{query::Filter, Hit, SearchParams, SearchSource};
+use crate::storage::document::Document;
+use crate::storage::persistence::EntryPointer;
+use crate::storage::record_store::RecordReader;
+use crate::storage::vector_arena::ArenaView;
+
+const SHARDS: usize = 64;
+
+#[derive(Clone)]
+struct ViewEntry {
+    row: u32,
+    // None for a deleted vector an HNSW graph still routes through until it is repaired
+    pointer: Option<EntryPointer>,
+}
+
+// Entries split into shards by id, so a change copies one shard rather than every entry
+#[derive(Clone)]
+struct Entries {
+    shards: Vec<Arc<HashMap<Uuid, ViewEntry>>>,
+    vectors: usize,
+    documents: usize,
+}
+
+impl Entries {
+    fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Arc::default()).collect(),
+            vectors: 0,
+            documents: 0,
+        }
+    }
+
+    fn shard_of(id: &Uuid) -> usize {
+        (id.as_u128() % SHARDS as u128) as usize
+    }
+
+    fn get(&self, id: &Uuid) -> Option<&ViewEntry> {
+        self.shards[Self::shard_of(id)].get(id)
+    }
+
+    fn set(&mut self, id: Uuid, entry: Option<ViewEntry>) {
+        let shard = Arc::make_mut(&mut self.shards[Self::shard_of(&id)]);
+        if let Some(entry) = &entry {
+            self.vectors += 1;
+            self.documents += usize::from(entry.pointer.is_some());
+        }
+        let old = match entry {
+            Some(entry) => shard.insert(id, entry),
+            None => shard.remove(&id),
+        };
+        if let Some(old) = old {
+            self.vectors -= 1;
+            self.documents -= usize::from(old.pointer.is_some());
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&Uuid, &ViewEntry)> + '_ {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    fn usage_bytes(&self) -> usize {
+        self.vectors * std::mem::size_of::<(Uuid, ViewEntry)>()
+    }
+}
+
+/// A collection as of the end of one write operation. Never changes once published.
+pub struct ReadView {
+    generation: u64,
+    config: CollectionConfig,
+    index: Arc<dyn VectorIndex>,
+    entries: Entries,
+    vectors: ArenaView,
+    tier: Option<Arc<VectorTier>>,
+    records: Arc<RecordReader>,
+    metadata: Arc<MetadataCache>,
+    results: Arc<ResultCache>,
+}
+
+impl ReadView {
+    /// Write generation of the collection when the view was published.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn config(&self) -> &CollectionConfig {
+        &self.config
+    }
+
+    pub fn count(&self) -> usize {
+        self.entries.documents
+    }
+
+    /// The document `id` as of this view, read from the data file.
+    pub fn get(&self, id: &Uuid) -> Result<Option<Document>> {
+        match self
+            .entries
+            .get(id)
+            .and_then(|entry| entry.pointer.as_ref())
+        {
+            Some(pointer) => self.records.read_document(pointer).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: Metric,
+        params: SearchParams,
+    ) -> Result<Vec<Hit>> {
+        super::search::search(self, query, k, metric, params)
+    }
+
+    /// Like `search`, answering repeated queries from the collection's result cache.
+    pub fn search_cached(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: Metric,
+        params: SearchParams,
+    ) -> Result<(Arc<Vec<Hit>>, bool)> {
+        super::search::search_cached(self, query, k, metric, params)
+    }
+
+    pub fn search_batch(
+        &self,
+        queries: &[Vec<f32>],
+        k: usize,
+        metric: Metric,
+    ) -> Result<Vec<Vec<Hit>>> {
+        super::search::search_batch(self, queries, k, metric)
+    }
+
+    fn vector(&self, row: u32) -> &[f32] {
+        if let (Some(tier), Some(map)) = (self.tier.as_ref(), self.vectors.map()) {
+            tier.touch(map, self.vectors.row_range(row));
+        }
+        self.vectors.vector_at(row)
+    }
+
+    // Metadata of the record at `pointer`, from the metadata cache or the data file
+    fn metadata_of(&self, id: &Uuid, pointer: &EntryPointer) -> Result<Arc<Metadata>> {
+        if let Some(metadata) = self.metadata.get(id, pointer.offset) {
+            return Ok(metadata);
+        }
+        let document = self.records.read_document(pointer)?;
+        let metadata = Arc::new(document.metadata);
+        self.metadata.put(*id, pointer.offset, metadata.clone());
+        Ok(metadata)
+    }
+}
+
+impl VectorReader for ReadView {
+    fn get(&self, id: &Uuid) -> Option<&[f32]> {
+        let row = self.entries.get(id)?.row;
+        Some(self.vector(row))
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (Uuid, &'a [f32])> + 'a> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(move |(id, entry)| (*id, self.vector(entry.row))),
+        )
+    }
+
+    fn len(&self) -> usize {
+        self.entries.vectors
+    }
+}
+
+impl MetadataReader for ReadView {
+    // A record that cannot be read passes here; search re-reads it and reports the error
+    fn matches(&self, id: &Uuid, filter: &Filter) -> bool {
+        let Some(pointer) = self
+            .entries
+            .get(id)
+            .and_then(|entry| entry.pointer.as_ref())
+        else {
+            return true;
+        };
+        match self.metadata_of(id, pointer) {
+            Ok(metadata) => filter.matches(&metadata),
+            Err(_) => true,
+        }
+    }
+}
+
+impl SearchSource for ReadView {
+    fn config(&self) -> &CollectionConfig {
+        &self.config
+    }
+
+    fn vector_index(&self) -> &dyn VectorIndex {
+        self.index.as_ref()
+    }
+
+    fn vector_reader(&self) -> &dyn VectorReader {
+        self
+    }
+
+    fn metadata_reader(&self) -> &dyn MetadataReader {
+        self
+    }
+
+    fn document(&self, id: &Uuid) -> Result<Option<Document>> {
+        self.get(id)
+    }
+
+    fn result_cache(&self) -> &ResultCache {
+        &self.results
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Where a collection publishes its read views. Clones share the slot.
+#[derive(Clone, Default)]
+pub struct ViewSlot {
+    inner: Arc<SlotInner>,
+}
+
+#[derive(Default)]
+struct SlotInner {
+    view: RwLock<Option<Arc<ReadView>>>,
+    // Set by the first reader that found no view; from then on every write publishes one
+    wanted: AtomicBool,
+}
+
+impl ViewSlot {
+    /// The latest published view. None until the collection has published one, in which case
+    /// the caller reads the collection under its lock and the next write publishes.
+    pub fn load(&self) -> Option<Arc<ReadView>> {
+        let view = self.inner.view.read().clone();
+        if view.is_none() {
+            self.inner.wanted.store(true, Ordering::Relaxed);
+        }
+        view
+    }
+
+    fn store(&self, view: ReadView) {
+        // The replaced view may be the last pin on retired rows or an old map; free it unlocked
+        let previous = self.inner.view.write().replace(Arc::new(view));
+        drop(previous);
+    }
+}
+
+// The collection's side of its views: the entries the next view will share, and the ids changed
+// since the last publish
+#[derive(Default)]
+pub(super) struct ViewState {
+    slot: ViewSlot,
+    // None until the first reader asks for a view; collections nobody searches keep no entries
+    entries: Option<Entries>,
+    // Read handle on the data file, opened with the entries
+    records: Option<Arc<RecordReader>>,
+    staged: HashSet<Uuid>,
+}
+
+impl ViewState {
+    pub fn slot(&self) -> &ViewSlot {
+        &self.slot
+    }
+
+    pub fn usage_bytes(&self) -> usize {
+        self.entries.as_ref().map_or(0, Entries::usage_bytes)
+    }
+
+    pub fn stage(&mut self, id: Uuid) {
+        if self.entries.is_some() {
+            self.staged.insert(id);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.entries = None;
+        self.records = None;
+        self.staged.clear();
+    }
+}
+
+// Where the collection's vectors and records are now. Reads no records.
+fn build(collection: &Collection) -> Entries {
+    let mut entries = Entries::new();
+    for (id, row) in collection.cache.vectors().rows() {
+        let pointer = collection.index.get(&id).cloned();
+        entries.set(id, Some(ViewEntry { row, pointer }));
+    }
+    entries
+}
+
+// Bring the entries up to date and, if a reader wants views, publish one of the collection as it is
+pub(super) fn publish(collection: &mut Collection) {
+    let Some(mut state) = collection.view.take() else {
+        return;
+    };
+    let building = state.entries.is_none();
+    if state.slot.inner.wanted.load(Ordering::Relaxed) {
+        apply(collection, &mut state);
+    }
+    let built = building && state.entries.is_some();
+    collection.view = Some(state);
+    // A build adds what the views hold all at once; later changes are picked up along with the
+    // indexes' growth at the next checkpoint
+    if built {
+        collection.refresh_vector_budget();
+    }
+}
+
+fn apply(collection: &mut Collection, state: &mut ViewState) {
+    let records = match state.records.clone() {
+        Some(records) => records,
+        None => match RecordReader::open_in(&*collection.vfs, &collection.path) {
+            Ok(reader) => state.records.insert(Arc::new(reader)).clone(),
+            Err(error) => {
+                // Readers keep using the lock; the next write tries again
+                tracing::warn!(path = %collection.path, error = %error, "read_view_build_failed");
+                return;
+            }
+        },
+    };
+    let entries = match state.entries.as_mut() {
+        Some(entries) => entries,
+        // A fresh build already holds everything staged before it
+        None => {
+            state.staged.clear();
+            state.entries.insert(build(collection))
+        }
+    };
+    for id in state.staged.drain() {
+        // Rows come from the arena, so the view sees exactly what the live index does
+        let entry = collection.cache.vectors().row_of(&id).map(|row| ViewEntry {
+            row,
+            pointer: collection.index.get(&id).cloned(),
+        });
+        entries.set(id, entry);
+    }
+
+    let view = ReadView {
+        generation: collection.write_generation,
+        config: collection.config.clone(),
+        index: collection.vector_index.clone(),
+        entries: entries.clone(),
+        vectors: collection.cache.view_vectors(),
+        tier: collection.cache.shared_tier(),
+        records,
+        metadata: collection.cache.shared_metadata(),
+        results: collection.cache.shared_results(),
+    };
+    state.slot.store(view);
+}
+
+// The vector index for a change, copied first if a published view still holds it. The copy shares
+// every chunk with the view until the change writes to one
+pub(super) fn index_mut(index: &mut Arc<dyn VectorIndex>) -> &mut dyn VectorIndex {
+    if Arc::get_mut(index).is_none() {
+        *index = Arc::from(index.clone_box());
+    }
+    Arc::get_mut(index).expect("a freshly copied index is unshared")
+}
@@ -3,9 +3,9 @@
 use serde::{Deserialize, Serialize};
 
 use super::{
-    CacheConfig, CollectionConfig, ExecutionMode, HardwareConfig, HardwareProfile, LimitsConfig,
-    LoggingConfig, MemoryConfig, ParallelismConfig, QuantizationConfig, QuantizationStage,
-    SearchConfig, WalConfig,
+    CacheConfig, CollectionConfig, CompactionConfig, ExecutionMode, HardwareConfig,
+    HardwareProfile, IdleConfig, LimitsConfig, LoggingConfig, MaintenanceConfig, MemoryConfig,
+    ParallelismConfig, QuantizationConfig, QuantizationStage, SearchConfig, WalConfig, WalSyncMode,
 };
 use crate::index::{AutoIndexConfig, IndexConfig};
 
@@ -25,6 +25,12 @@ pub struct AppConfig {
     pub cache: CacheConfig,
     #[serde(default)]
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub idle: IdleConfig,
 }
 
 impl Default for AppConfig {
@@ -41,6 +47,9 @@ impl Default for AppConfig {
             limits: LimitsConfig::default(),
             cache: CacheConfig::default(),
             logging: LoggingConfig::default(),
+            compaction: CompactionConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            idle: IdleConfig::default(),
         }
     }
 }
@@ -88,6 +97,19 @@ impl AppConfig {
         if let IndexConfig::Auto { auto, .. } = &self.index {
             validate_auto_index(auto)?;
         }
+        if let IndexConfig::IvfPq {
+            subquantizers,
+            rerank_factor,
+            ..
+        } = &self.index
+        {
+            if *subquantizers == 0 {
+                return Err("INDEX ivf_pq subquantizers must be > 0".into());
+            }
+            if *rerank_factor == 0 {
+                return Err("INDEX ivf_pq rerank_factor must be > 0".into());
+            }
+        }
         if self.quantization.level == crate::config::QuantizationLevel::None
             && self.quantization.stage != QuantizationStage::Disabled
         {
@@ -101,6 +123,24 @@ impl AppConfig {
         {
             return Err("QUANTIZATION enabled flags require a non-none level".into());
         }
+        if !(self.compaction.dead_ratio > 0.0 && self.compaction.dead_ratio <= 1.0) {
+            return Err("COMPACTION dead_ratio must be in (0.0, 1.0]".into());
+        }
+        if self.compaction.max_bytes_per_sec == Some(0) {
+            return Err("COMPACTION max_bytes_per_sec must be > 0 when set".into());
+        }
+        if !(self.maintenance.tombstone_ratio > 0.0 && self.maintenance.tombstone_ratio <= 1.0) {
+            return Err("MAINTENANCE tombstone_ratio must be in (0.0, 1.0]".into());
+        }
+        if self.maintenance.repair_batch == 0 {
+            return Err("MAINTENANCE repair_batch must be > 0".into());
+        }
+        if !(0.0..1.0).contains(&self.maintenance.retrain_min_improvement) {
+            return Err("MAINTENANCE retrain_min_improvement must be in [0.0, 1.0)".into());
+        }
+        if self.idle.check_interval_secs == 0 {
+            return Err("IDLE check_interval_secs must be > 0".into());
+        }
         if self.hardware.gpu_enabled && matches!(self.execution, ExecutionMode::Scalar) {
             return Err("HARDWARE gpu_enabled conflicts with scalar execution mode".into());
         }
@@ -120,6 +160,8 @@ impl AppConfig {
             limits: self.limits,
             cache: self.cache,
             logging: self.logging,
+            compaction: self.compaction,
+            maintenance: self.maintenance,
         }
     }
 
@@ -150,6 +192,17 @@ impl AppConfig {
                     mode: ExecutionMode::Auto,
                     search: self.search,
                 },
+                "ivf_pq" | "ivf-pq" | "ivfpq" => IndexConfig::IvfPq {
+                    num_clusters: 256,
+                    num_probes: 16,
+                    max_iterations: 20,
+                    subquantizers: 16,
+                    rerank: true,
+                    rerank_factor: 4,
+                    metric: crate::metrics::Metric::Cosine,
+                    mode: ExecutionMode::Auto,
+                    search: self.search,
+                },
                 _ => return Err(format!("Invalid INDEX_TYPE '{val}'")),
             };
         }
@@ -178,6 +231,20 @@ impl AppConfig {
                     parse_env::<usize>("INDEX_AUTO_IVF_MAX_ITERATIONS", &val)?;
                 changed = true;
             }
+            if let Ok(val) = std::env::var("INDEX_AUTO_IVF_PQ_MAX_VECTORS") {
+                auto.ivf_pq_max_vectors =
+                    Some(parse_env::<usize>("INDEX_AUTO_IVF_PQ_MAX_VECTORS", &val)?);
+                changed = true;
+            }
+            if let Ok(val) = std::env::var("INDEX_AUTO_IVF_PQ_SUBQUANTIZERS") {
+                auto.ivf_pq_subquantizers =
+                    parse_env::<usize>("INDEX_AUTO_IVF_PQ_SUBQUANTIZERS", &val)?;
+                changed = true;
+            }
+            if let Ok(val) = std::env::var("INDEX_AUTO_IVF_PQ_RERANK") {
+                auto.ivf_pq_rerank = parse_bool_env("INDEX_AUTO_IVF_PQ_RERANK", &val)?;
+                changed = true;
+            }
             if let Ok(val) = std::env::var("INDEX_AUTO_HNSW_M") {
                 auto.hnsw_m = parse_env::<usize>("INDEX_AUTO_HNSW_M", &val)?;
                 changed = true;
@@ -232,6 +299,25 @@ impl AppConfig {
             let secs = parse_env::<u64>("WAL_CHECKPOINT_INTERVAL_SECS", &val)?;
             self.wal.checkpoint_interval_secs = Some(secs.max(1));
         }
+        if let Ok(val) = std::env::var("WAL_SYNC_MODE") {
+            self.wal.sync_mode = match val.to_lowercase().as_str() {
+                "flush" => WalSyncMode::Flush,
+                "per_write" => WalSyncMode::PerWrite,
+                "group_commit" => WalSyncMode::GroupCommit,
+                "periodic" => WalSyncMode::Periodic,
+                _ => return Err(format!("Invalid WAL_SYNC_MODE '{val}'")),
+            };
+        }
+        if let Ok(val) = std::env::var("WAL_GROUP_COMMIT_WINDOW_MS") {
+            self.wal.group_commit_window_ms = parse_env::<u64>("WAL_GROUP_COMMIT_WINDOW_MS", &val)?;
+        }
+        if let Ok(val) = std::env::var("WAL_SYNC_INTERVAL_MS") {
+            let ms = parse_env::<u64>("WAL_SYNC_INTERVAL_MS", &val)?;
+            self.wal.sync_interval_ms = ms.max(1);
+        }
+        if let Ok(val) = std::env::var("WAL_ARCHIVE") {
+            self.wal.archive = parse_bool_env("WAL_ARCHIVE", &val)?;
+        }
 
         if let Ok(val) = std::env::var("MEMORY_USE_MMAP") {
             self.memory.use_mmap = parse_bool_env("MEMORY_USE_MMAP", &val)?;
@@ -244,6 +330,9 @@ impl AppConfig {
         if let Ok(val) = std::env::var("PARALLEL_SEARCH") {
             self.parallelism.parallel_search = parse_bool_env("PARALLEL_SEARCH", &val)?;
         }
+        if let Ok(val) = std::env::var("SNAPSHOT_READS") {
+            self.parallelism.snapshot_reads = parse_bool_env("SNAPSHOT_READS", &val)?;
+        }
         if let Ok(val) = std::env::var("NUM_THREADS") {
             let n = parse_env::<usize>("NUM_THREADS", &val)?;
             self.parallelism = self.parallelism.with_num_threads(n);
@@ -323,6 +412,47 @@ impl AppConfig {
         if let Ok(val) = std::env::var("CACHE_MAX_BYTES") {
             self.cache.max_bytes = Some(parse_env::<u64>("CACHE_MAX_BYTES", &val)?);
         }
+        if let Ok(val) = std::env::var("CACHE_RESULT_SIZE") {
+            self.cache.result_cache_size = parse_env::<usize>("CACHE_RESULT_SIZE", &val)?;
+        }
+        if let Ok(val) = std::env::var("CACHE_RESULT_TTL_SECONDS") {
+            self.cache.result_cache_ttl_seconds =
+                Some(parse_env::<u64>("CACHE_RESULT_TTL_SECONDS", &val)?);
+        }
+        if let Ok(val) = std::env::var("COMPACTION_ENABLED") {
+            self.compaction.enabled = parse_bool_env("COMPACTION_ENABLED", &val)?;
+        }
+        if let Ok(val) = std::env::var("COMPACTION_DEAD_RATIO") {
+            self.compaction.dead_ratio = parse_env::<f64>("COMPACTION_DEAD_RATIO", &val)?;
+        }
+        if let Ok(val) = std::env::var("COMPACTION_DEAD_BYTES") {
+            self.compaction.dead_bytes = parse_env::<u64>("COMPACTION_DEAD_BYTES", &val)?;
+        }
+        if let Ok(val) = std::env::var("COMPACTION_MAX_BYTES_PER_SEC") {
+            self.compaction.max_bytes_per_sec =
+                Some(parse_env::<u64>("COMPACTION_MAX_BYTES_PER_SEC", &val)?);
+        }
+        if let Ok(val) = std::env::var("MAINTENANCE_TOMBSTONE_RATIO") {
+            self.maintenance.tombstone_ratio =
+                parse_env::<f32>("MAINTENANCE_TOMBSTONE_RATIO", &val)?;
+        }
+        if let Ok(val) = std::env::var("MAINTENANCE_REPAIR_BATCH") {
+            self.maintenance.repair_batch = parse_env::<usize>("MAINTENANCE_REPAIR_BATCH", &val)?;
+        }
+        if let Ok(val) = std::env::var("MAINTENANCE_RETRAIN_COOLDOWN_SECS") {
+            self.maintenance.retrain_cooldown_secs =
+                parse_env::<u64>("MAINTENANCE_RETRAIN_COOLDOWN_SECS", &val)?;
+        }
+        if let Ok(val) = std::env::var("MAINTENANCE_RETRAIN_MIN_IMPROVEMENT") {
+            self.maintenance.retrain_min_improvement =
+                parse_env::<f32>("MAINTENANCE_RETRAIN_MIN_IMPROVEMENT", &val)?;
+        }
+        if let Ok(val) = std::env::var("IDLE_UNLOAD_AFTER_SECS") {
+            self.idle.unload_after_secs = Some(parse_env::<u64>("IDLE_UNLOAD_AFTER_SECS", &val)?);
+        }
+        if let Ok(val) = std::env::var("IDLE_CHECK_INTERVAL_SECS") {
+            self.idle.check_interval_secs = parse_env::<u64>("IDLE_CHECK_INTERVAL_SECS", &val)?;
+        }
         if let Ok(val) = std::env::var("LOG_LEVEL") {
             self.logging.level = parse_log_level(&val)?;
         }
@@ -412,6 +542,16 @@ fn validate_auto_index(auto: &AutoIndexConfig) -> Result<(), String> {
     if auto.ivf_max_iterations == 0 {
         return Err("INDEX auto ivf_max_iterations must be > 0".into());
     }
+    if let Some(ivf_pq_max_vectors) = auto.ivf_pq_max_vectors {
+        if ivf_pq_max_vectors <= auto.ivf_max_vectors {
+            return Err(
+                "INDEX auto ivf_pq_max_vectors must be greater than ivf_max_vectors".into(),
+            );
+        }
+    }
+    if auto.ivf_pq_subquantizers == 0 {
+        return Err("INDEX auto ivf_pq_subquantizers must be > 0".into());
+    }
     if auto.hnsw_m == 0 {
         return Err("INDEX auto hnsw_m must be > 0".into());
     }
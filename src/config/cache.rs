@@ -17,6 +17,14 @@ pub struct CacheConfig {
     // Maximum total collection cache bytes across loaded collections (None = unlimited)
     #[serde(default)]
     pub max_bytes: Option<u64>,
+
+    // Search results cached per collection (0 = result cache disabled)
+    #[serde(default)]
+    pub result_cache_size: usize,
+
+    // Time-to-live of cached search results in seconds (None = until the next write)
+    #[serde(default)]
+    pub result_cache_ttl_seconds: Option<u64>,
 }
 
 impl Default for CacheConfig {
@@ -26,6 +34,8 @@ impl Default for CacheConfig {
             max_size: 10_000,
             ttl_seconds: None,
             max_bytes: None,
+            result_cache_size: 0,
+            result_cache_ttl_seconds: None,
         }
     }
 }
@@ -38,6 +48,8 @@ impl CacheConfig {
             max_size: 0,
             ttl_seconds: None,
             max_bytes: Some(0),
+            result_cache_size: 0,
+            result_cache_ttl_seconds: None,
         }
     }
 
@@ -48,6 +60,8 @@ impl CacheConfig {
             max_size: size,
             ttl_seconds: None,
             max_bytes: None,
+            result_cache_size: 0,
+            result_cache_ttl_seconds: None,
         }
     }
 
@@ -58,6 +72,8 @@ impl CacheConfig {
             max_size: size,
             ttl_seconds: Some(ttl_seconds),
             max_bytes: None,
+            result_cache_size: 0,
+            result_cache_ttl_seconds: None,
         }
     }
 
@@ -65,4 +81,11 @@ impl CacheConfig {
         self.max_bytes = Some(max_bytes);
         self
     }
+
+    // Cache up to `size` search results per collection, optionally expiring them after a TTL
+    pub fn with_result_cache(mut self, size: usize, ttl_seconds: Option<u64>) -> Self {
+        self.result_cache_size = size;
+        self.result_cache_ttl_seconds = ttl_seconds;
+        self
+    }
 }
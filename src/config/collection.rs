@@ -48,6 +48,14 @@ pub struct CollectionConfig {
     // Logging controls for collection operations
     #[serde(default)]
     pub logging: LoggingConfig,
+
+    // Background compaction thresholds
+    #[serde(default)]
+    pub compaction: CompactionConfig,
+
+    // Background index clean-up (HNSW tombstone repair)
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
 }
 
 impl Default for CollectionConfig {
@@ -64,6 +72,8 @@ impl Default for CollectionConfig {
             limits: LimitsConfig::default(),
             cache: CacheConfig::default(),
             logging: LoggingConfig::default(),
+            compaction: CompactionConfig::default(),
+            maintenance: MaintenanceConfig::default(),
         }
     }
 }
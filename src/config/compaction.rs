@@ -0,0 +1,45 @@
+// Background compaction configuration
+
+use serde::{Deserialize, Serialize};
+
+// When the runtime compacts a collection's data file on its own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompactionConfig {
+    // Compact in the background after writes once a threshold is crossed
+    pub enabled: bool,
+
+    // Share of the data file taken by dead records (superseded or deleted) that triggers compaction
+    pub dead_ratio: f64,
+
+    // Dead bytes that trigger compaction regardless of the ratio
+    pub dead_bytes: u64,
+
+    // The ratio only applies once at least this many bytes are dead, so small files are left alone
+    pub min_dead_bytes: u64,
+
+    // Cap on the rate background compaction copies records at (None = unthrottled)
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig {
+            enabled: true,
+            dead_ratio: 0.5,
+            dead_bytes: 1024 * 1024 * 1024,
+            min_dead_bytes: 16 * 1024 * 1024,
+            max_bytes_per_sec: Some(64 * 1024 * 1024),
+        }
+    }
+}
+
+impl CompactionConfig {
+    // Never compact in the background
+    pub fn disabled() -> Self {
+        CompactionConfig {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Idle collection unloading configuration
+
+use serde::{Deserialize, Serialize};
+
+// When the runtime drops collections nobody is using; they reopen on the next request
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    // Checkpoint and unload collections not accessed for this long (None = keep them loaded)
+    pub unload_after_secs: Option<u64>,
+
+    // How often the background task looks for idle collections
+    pub check_interval_secs: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            unload_after_secs: None,
+            check_interval_secs: 60,
+        }
+    }
+}
+
+impl IdleConfig {
+    // Unload collections idle for `secs` seconds
+    pub fn unload_after(secs: u64) -> Self {
+        IdleConfig {
+            unload_after_secs: Some(secs),
+            ..Default::default()
+        }
+    }
+}
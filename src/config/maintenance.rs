@@ -0,0 +1,31 @@
+// Background index maintenance configuration
+
+use serde::{Deserialize, Serialize};
+
+// When and how much background index clean-up the runtime does on its own
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    // Share of HNSW nodes that are deleted-but-still-linked tombstones before a repair pass starts
+    pub tombstone_ratio: f32,
+
+    // Live nodes a repair step rewires under one write lock; writes get the lock between steps
+    pub repair_batch: usize,
+
+    // Seconds after a retrain before drift can schedule another one
+    pub retrain_cooldown_secs: u64,
+
+    // Share by which a background retrain must cut the drift (IVF list imbalance) to be swapped in
+    pub retrain_min_improvement: f32,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            tombstone_ratio: 0.2,
+            repair_batch: 1024,
+            retrain_cooldown_secs: 600,
+            retrain_min_improvement: 0.1,
+        }
+    }
+}
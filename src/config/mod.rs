@@ -3,10 +3,13 @@
 mod app;
 mod cache;
 mod collection;
+mod compaction;
 mod execution;
 mod hardware;
+mod idle;
 mod limits;
 mod logging;
+mod maintenance;
 mod memory;
 mod parallelism;
 mod quantization;
@@ -21,10 +24,13 @@ pub mod loader;
 pub use app::AppConfig;
 pub use cache::CacheConfig;
 pub use collection::CollectionConfig;
+pub use compaction::CompactionConfig;
 pub use execution::ExecutionMode;
 pub use hardware::{HardwareConfig, HardwareProfile};
+pub use idle::IdleConfig;
 pub use limits::LimitsConfig;
 pub use logging::{LogLevel, LoggingConfig};
+pub use maintenance::MaintenanceConfig;
 pub use memory::MemoryConfig;
 pub use parallelism::{ParallelismConfig, ParallelismMode};
 pub use quantization::{QuantizationConfig, QuantizationLevel, QuantizationStage};
@@ -32,4 +38,4 @@ pub use search::SearchConfig;
 pub use search_mode::{RangeSearchParams, SearchMode};
 pub use storage::StorageConfig;
 pub use tuning::{AdaptiveTuningConfig, QueryBudgetConfig};
-pub use wal::WalConfig;
+pub use wal::{WalConfig, WalSyncMode};
@@ -22,6 +22,14 @@ pub struct ParallelismConfig {
 
     // Enable parallel search (when applicable)
     pub parallel_search: bool,
+
+    // Run searches against published read views instead of under the collection lock
+    #[serde(default = "default_snapshot_reads")]
+    pub snapshot_reads: bool,
+}
+
+fn default_snapshot_reads() -> bool {
+    true
 }
 
 impl Default for ParallelismConfig {
@@ -29,6 +37,7 @@ impl Default for ParallelismConfig {
         ParallelismConfig {
             mode: ParallelismMode::Auto,
             parallel_search: true,
+            snapshot_reads: true,
         }
     }
 }
@@ -39,6 +48,7 @@ impl ParallelismConfig {
         ParallelismConfig {
             mode: ParallelismMode::SingleThreaded,
             parallel_search: false,
+            snapshot_reads: true,
         }
     }
 
@@ -47,6 +57,7 @@ impl ParallelismConfig {
         ParallelismConfig {
             mode: ParallelismMode::Fixed(num_threads),
             parallel_search: true,
+            snapshot_reads: true,
         }
     }
 
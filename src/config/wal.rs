@@ -1,6 +1,21 @@
 // in case of crashes or unexpected shutdowns, replay those changes during recovery to bring the collection back to a consistent state.
 use serde::{Deserialize, Serialize};
 
+// When a WAL append counts as durable, and therefore when a write is acknowledged.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WalSyncMode {
+    // Hand the entry to the OS page cache only; a power loss can drop recent writes
+    #[default]
+    Flush,
+    // fsync before every write returns
+    PerWrite,
+    // Writers wait up to `group_commit_window_ms` and share a single fsync
+    GroupCommit,
+    // fsync in the background every `sync_interval_ms`; writes are acknowledged before that
+    Periodic,
+}
+
 // WAL configuration
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct WalConfig {
@@ -17,8 +32,32 @@ pub struct WalConfig {
     // Maximum log file size in bytes before rotation
     pub max_log_size: usize,
 
-    // Sync to disk after every write, slower but safer
+    // Sync to disk after every write, slower but safer (same as `sync_mode: per_write`)
     pub sync_on_write: bool,
+
+    // Durability point for writes
+    #[serde(default)]
+    pub sync_mode: WalSyncMode,
+
+    // How long a group commit waits for other writers before syncing
+    #[serde(default = "default_group_commit_window_ms")]
+    pub group_commit_window_ms: u64,
+
+    // Background sync interval for periodic mode
+    #[serde(default = "default_sync_interval_ms")]
+    pub sync_interval_ms: u64,
+
+    // Move segments a checkpoint covers to `wal_archive/` instead of deleting them, for point-in-time recovery
+    #[serde(default)]
+    pub archive: bool,
+}
+
+fn default_group_commit_window_ms() -> u64 {
+    2
+}
+
+fn default_sync_interval_ms() -> u64 {
+    1000
 }
 
 impl Default for WalConfig {
@@ -29,6 +68,10 @@ impl Default for WalConfig {
             checkpoint_interval_secs: None,
             max_log_size: 100 * 1024 * 1024, // 100MB
             sync_on_write: false,
+            sync_mode: WalSyncMode::Flush,
+            group_commit_window_ms: default_group_commit_window_ms(),
+            sync_interval_ms: default_sync_interval_ms(),
+            archive: false,
         }
     }
 }
@@ -42,6 +85,10 @@ impl WalConfig {
             max_log_size: 0,
             sync_on_write: false,
             checkpoint_interval_secs: None,
+            sync_mode: WalSyncMode::Flush,
+            group_commit_window_ms: default_group_commit_window_ms(),
+            sync_interval_ms: default_sync_interval_ms(),
+            archive: false,
         }
     }
 
@@ -53,6 +100,10 @@ impl WalConfig {
             max_log_size: 50 * 1024 * 1024, // 50MB
             sync_on_write: true,
             checkpoint_interval_secs: Some(1),
+            sync_mode: WalSyncMode::PerWrite,
+            group_commit_window_ms: default_group_commit_window_ms(),
+            sync_interval_ms: default_sync_interval_ms(),
+            archive: false,
         }
     }
 
@@ -64,6 +115,27 @@ impl WalConfig {
             max_log_size: 500 * 1024 * 1024, // 500MB
             sync_on_write: false,
             checkpoint_interval_secs: None,
+            sync_mode: WalSyncMode::Flush,
+            group_commit_window_ms: default_group_commit_window_ms(),
+            sync_interval_ms: default_sync_interval_ms(),
+            archive: false,
+        }
+    }
+
+    // Group commit mode, durable acknowledgements with fsyncs shared across concurrent writers
+    pub fn group_commit() -> Self {
+        WalConfig {
+            sync_mode: WalSyncMode::GroupCommit,
+            ..Self::default()
+        }
+    }
+
+    // The configured sync mode, honouring the older `sync_on_write` flag
+    pub fn effective_sync_mode(&self) -> WalSyncMode {
+        if self.sync_on_write && self.sync_mode == WalSyncMode::Flush {
+            WalSyncMode::PerWrite
+        } else {
+            self.sync_mode
         }
     }
 }
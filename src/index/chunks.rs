@@ -0,0 +1,260 @@
+// Copy-on-write tables for index state that read views share.
+// A read view holds the vector index the collection last published, so the first change after a
+// publish cannot update it in place. Tables kept in fixed-size chunks behind `Arc`s make that copy
+// cheap: cloning copies one pointer per chunk, and a change copies only the chunk it lands in, and
+// only while a view still holds it. Each serializes exactly like the plain value it stands for, so
+// index files are unchanged.
+
+use serde::de::Deserializer;
+use serde::ser::{SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Entries per chunk: small enough that copying one is cheap, large enough that the chunk table
+// stays a small fraction of the entries
+const CHUNK_LEN: usize = 256;
+// Shards of a `ChunkedMap`; a change copies one
+const MAP_SHARDS: usize = 256;
+
+/// A `Vec` stored in shared chunks.
+#[derive(Clone)]
+pub(crate) struct ChunkedVec<T> {
+    chunks: Vec<Arc<Vec<T>>>,
+    len: usize,
+}
+
+impl<T> Default for ChunkedVec<T> {
+    fn default() -> Self {
+        Self {
+            chunks: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T: Clone> ChunkedVec<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        Some(&self.chunks[index / CHUNK_LEN][index % CHUNK_LEN])
+    }
+
+    // Copies the chunk first if a view still holds it
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let chunk = Arc::make_mut(&mut self.chunks[index / CHUNK_LEN]);
+        Some(&mut chunk[index % CHUNK_LEN])
+    }
+
+    pub fn push(&mut self, value: T) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < CHUNK_LEN => Arc::make_mut(chunk).push(value),
+            _ => {
+                let mut chunk = Vec::with_capacity(CHUNK_LEN);
+                chunk.push(value);
+                self.chunks.push(Arc::new(chunk));
+            }
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let chunk = self.chunks.last_mut()?;
+        let value = Arc::make_mut(chunk).pop();
+        if chunk.is_empty() {
+            self.chunks.pop();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn resize(&mut self, len: usize, value: T) {
+        while self.len > len {
+            self.pop();
+        }
+        while self.len < len {
+            self.push(value.clone());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+}
+
+impl<T: Clone> Index<usize> for ChunkedVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("chunked index out of bounds")
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for ChunkedVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("chunked index out of bounds")
+    }
+}
+
+impl<T: Clone> FromIterator<T> for ChunkedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut chunked = Self::new();
+        for value in iter {
+            chunked.push(value);
+        }
+        chunked
+    }
+}
+
+impl<T: Clone> From<Vec<T>> for ChunkedVec<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Clone + Serialize> Serialize for ChunkedVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len))?;
+        for value in self.iter() {
+            seq.serialize_element(value)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for ChunkedVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<T>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+/// A `HashMap` keyed by id, split into shared shards.
+#[derive(Clone)]
+pub(crate) struct ChunkedMap<V> {
+    shards: Vec<Arc<HashMap<Uuid, V>>>,
+    len: usize,
+}
+
+impl<V> Default for ChunkedMap<V> {
+    fn default() -> Self {
+        Self {
+            shards: (0..MAP_SHARDS).map(|_| Arc::default()).collect(),
+            len: 0,
+        }
+    }
+}
+
+impl<V: Clone> ChunkedMap<V> {
+    fn shard_of(id: &Uuid) -> usize {
+        (id.as_u128() % MAP_SHARDS as u128) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&V> {
+        self.shards[Self::shard_of(id)].get(id)
+    }
+
+    pub fn insert(&mut self, id: Uuid, value: V) -> Option<V> {
+        let old = Arc::make_mut(&mut self.shards[Self::shard_of(&id)]).insert(id, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<V> {
+        let shard = &mut self.shards[Self::shard_of(id)];
+        if !shard.contains_key(id) {
+            return None;
+        }
+        let old = Arc::make_mut(shard).remove(id);
+        self.len -= 1;
+        old
+    }
+
+    // Slots allocated across the shards, for memory accounting
+    pub fn capacity(&self) -> usize {
+        self.shards.iter().map(|shard| shard.capacity()).sum()
+    }
+}
+
+/// A value shared with read views until it is first changed. Writes go through `DerefMut`, which
+/// copies the value if a view still holds it.
+#[derive(Clone, Default)]
+pub(crate) struct Shared<T>(Arc<T>);
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Shared<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+impl<T: Serialize> Serialize for Shared<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Shared<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::from)
+    }
+}
+
+impl<A, T: FromIterator<A>> FromIterator<A> for Shared<T> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> Self {
+        T::from_iter(iter).into()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Shared<T>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.as_ref().into_iter()
+    }
+}
@@ -3,34 +3,46 @@
 // Best for: small collections, zero build time, 100% recall
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use uuid::Uuid;
 
 use super::config::FlatConfig;
 use crate::error::{IndexError, Result};
-use crate::index::traits::{IndexDetails, IndexStats, IndexType, VectorIndex, VectorReader};
+use crate::index::legacy::LegacyFlatIndex;
+use crate::index::traits::{
+    IndexDetails, IndexStats, IndexType, MetadataReader, VectorIndex, VectorReader,
+};
+use crate::index::IdMap;
 
 // Stores nothing except config, vectors are in main storage
 #[derive(Clone, Serialize, Deserialize)]
 pub struct FlatIndex {
     config: FlatConfig,
-    vector_ids: Vec<Uuid>, // Track which vectors we've seen
+    ids: IdMap, // Track which vectors we've seen
 }
 
 impl FlatIndex {
     pub fn new(config: FlatConfig) -> Self {
         FlatIndex {
             config,
-            vector_ids: Vec::new(),
+            ids: IdMap::new(),
         }
     }
 }
+
+impl From<LegacyFlatIndex> for FlatIndex {
+    fn from(legacy: LegacyFlatIndex) -> Self {
+        let mut index = FlatIndex::new(legacy.config);
+        for id in legacy.vector_ids {
+            index.ids.insert(id);
+        }
+        index
+    }
+}
+
 impl VectorIndex for FlatIndex {
     fn insert(&mut self, id: Uuid, _vector: &[f32], _vectors: &dyn VectorReader) {
         // Just track the ID - no indexing structure needed
-        if !self.vector_ids.contains(&id) {
-            self.vector_ids.push(id);
-        }
+        self.ids.insert(id);
     }
 
     // Search for nearest neighbors to the query vector. The filter and metadata parameters are also ignored in this simple implementation, but they could be used in a more advanced version to filter results based on metadata or other criteria.
@@ -41,15 +53,15 @@ impl VectorIndex for FlatIndex {
         vectors: &dyn VectorReader,
         _quality: crate::config::SearchConfig,
         _filter: Option<&crate::search::query::Filter>,
-        _metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+        _metadatas: &dyn MetadataReader,
     ) -> Result<Vec<Uuid>> {
-        let mut distances = Vec::with_capacity(self.vector_ids.len());
-        for id in &self.vector_ids {
-            let vec = vectors.get(id).ok_or_else(|| {
+        let mut distances = Vec::with_capacity(self.ids.len());
+        for (_, id) in self.ids.iter() {
+            let vec = vectors.get(&id).ok_or_else(|| {
                 IndexError::SearchFailed(format!("Flat index references missing vector {id}"))
             })?;
             let score = self.config.metric.calculate(query, vec, self.config.mode);
-            distances.push((*id, score));
+            distances.push((id, score));
         }
 
         // Sort by score (descending for similarity)
@@ -60,14 +72,18 @@ impl VectorIndex for FlatIndex {
     }
 
     fn remove(&mut self, id: &Uuid) {
-        self.vector_ids.retain(|vid| vid != id);
+        self.ids.release(id);
+    }
+
+    fn ids(&self) -> Vec<Uuid> {
+        self.ids.iter().map(|(_, id)| id).collect()
     }
 
     fn stats(&self) -> IndexStats {
         IndexStats {
             index_type: IndexType::Flat,
-            total_vectors: self.vector_ids.len(),
-            memory_usage_bytes: self.vector_ids.len() * std::mem::size_of::<Uuid>(),
+            total_vectors: self.ids.len(),
+            memory_usage_bytes: self.ids.memory_usage_bytes(),
             details: IndexDetails::Flat,
         }
     }
@@ -79,4 +95,8 @@ impl VectorIndex for FlatIndex {
     fn to_serializable(&self) -> crate::index::SerializableIndex {
         crate::index::SerializableIndex::Flat(self.clone())
     }
+
+    fn clone_box(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
 }
@@ -1,32 +1,38 @@
 use crate::metrics::Metric;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
 use std::collections::{BinaryHeap, HashMap, HashSet};
 use uuid::Uuid;
 
 use super::config::{HnswConfig, HnswStats};
 use crate::error::{IndexError, Result};
-use crate::index::VectorReader;
+use crate::index::legacy::LegacyHnswIndex;
+use crate::index::{ChunkedVec, IdMap, MetadataReader, Shared, VectorReader};
+
+// Nodes inserted one by one before the bulk path starts planning batches in parallel
+const BULK_SEED_NODES: usize = 256;
+// Upper bound on nodes planned against the same graph snapshot
+const BULK_MAX_BATCH: usize = 4096;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct HnswNode {
-    // connections[layer] = Vec of neighbor IDs at that layer
+    // connections[layer] = Vec of neighbor offsets at that layer
     // Layer 0 is at index 0
-    connections: Vec<Vec<Uuid>>,
+    connections: Vec<Vec<u32>>,
     // Marked true when deleted; we keep edges so traversal stays connected.
     tombstone: bool,
 }
 
 #[derive(Debug, Clone)]
 struct SearchCandidate {
-    id: Uuid,
+    id: u32,
     distance: f32,
 }
 
 struct SearchContext<'a> {
     vectors: &'a dyn VectorReader,
     filter: Option<&'a crate::search::query::Filter>,
-    metadatas: &'a HashMap<Uuid, crate::metadata::Metadata>,
+    metadatas: &'a dyn MetadataReader,
 }
 impl PartialEq for SearchCandidate {
     fn eq(&self, other: &Self) -> bool {
@@ -53,31 +59,112 @@ impl Ord for SearchCandidate {
 }
 
 // Main HNSW index structure
+// Nodes and edges are addressed by dense offsets from `ids`; UUIDs only appear at the API.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct HnswIndex {
     config: HnswConfig,
-    nodes: HashMap<Uuid, HnswNode>,
+    ids: IdMap,
+    // nodes[offset], None for a free offset. Chunked and shared per node, so a change after a read
+    // view was published copies the nodes it touches rather than the graph
+    nodes: ChunkedVec<Option<Shared<HnswNode>>>,
     max_level: isize,
-    start_node: Option<Uuid>,
+    start_node: Option<u32>,
+    // Cached tombstone count, recounted lazily after the index is loaded from disk
+    #[serde(skip)]
+    tombstones: Option<usize>,
+    // Repair pass spread over several `repair_step` calls, if one is under way
+    #[serde(skip)]
+    repair: Option<RepairPass>,
+}
+
+// Tombstones a stepwise repair is removing, and the next offset to rewire. Nodes added during the
+// pass never link to tombstones, so only the offsets present when it started need a visit.
+#[derive(Clone)]
+struct RepairPass {
+    dead: HashSet<u32>,
+    cursor: usize,
+    end: usize,
 }
 
 impl HnswIndex {
     pub fn new(config: HnswConfig) -> Self {
         HnswIndex {
             config,
-            nodes: HashMap::new(),
+            ids: IdMap::new(),
+            nodes: ChunkedVec::new(),
             max_level: -1,
             start_node: None,
+            tombstones: Some(0),
+            repair: None,
         }
     }
 
-    fn is_tombstone(&self, id: &Uuid) -> bool {
-        self.nodes.get(id).map(|n| n.tombstone).unwrap_or(false)
+    // Nodes that have not been deleted
+    pub fn live_ids(&self) -> Vec<Uuid> {
+        self.live_nodes()
+            .map(|(offset, _)| self.ids.id(offset))
+            .collect()
+    }
+
+    // Edges of a node at each layer, for live nodes and tombstones alike
+    pub fn neighbors(&self, id: &Uuid) -> Option<Vec<Vec<Uuid>>> {
+        let node = self.node(self.ids.offset(id)?)?;
+        let edges = node
+            .connections
+            .iter()
+            .map(|layer| layer.iter().map(|n| self.ids.id(*n)).collect())
+            .collect();
+        Some(edges)
     }
 
-    fn mark_tombstone(&mut self, id: &Uuid) {
-        if let Some(node) = self.nodes.get_mut(id) {
-            node.tombstone = true;
+    fn node(&self, offset: u32) -> Option<&HnswNode> {
+        self.nodes.get(offset as usize)?.as_deref()
+    }
+
+    fn node_mut(&mut self, offset: u32) -> Option<&mut HnswNode> {
+        self.nodes.get_mut(offset as usize)?.as_deref_mut()
+    }
+
+    // Every node in the graph, tombstones included
+    fn all_nodes(&self) -> impl Iterator<Item = (u32, &HnswNode)> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, node)| node.as_deref().map(|node| (offset as u32, node)))
+    }
+
+    fn live_nodes(&self) -> impl Iterator<Item = (u32, &HnswNode)> + '_ {
+        self.all_nodes().filter(|(_, node)| !node.tombstone)
+    }
+
+    // Stored vector of the node at `offset`
+    fn vector<'a>(&self, vectors: &'a dyn VectorReader, offset: u32) -> Option<&'a [f32]> {
+        vectors.get(&self.ids.id(offset))
+    }
+
+    fn is_tombstone(&self, offset: u32) -> bool {
+        self.node(offset).map(|n| n.tombstone).unwrap_or(false)
+    }
+
+    fn mark_tombstone(&mut self, offset: u32) {
+        // Count first, so a freshly loaded index knows its tombstones from the first delete on
+        let count = self.tombstone_count();
+        if self.node(offset).is_some_and(|node| !node.tombstone) {
+            if let Some(node) = self.node_mut(offset) {
+                node.tombstone = true;
+            }
+            self.tombstones = Some(count + 1);
+        }
+    }
+
+    fn tombstone_count(&mut self) -> usize {
+        match self.tombstones {
+            Some(count) => count,
+            None => {
+                let count = self.all_nodes().filter(|(_, n)| n.tombstone).count();
+                self.tombstones = Some(count);
+                count
+            }
         }
     }
     // higher layers have exponentially fewer nodes, so we want to assign layers
@@ -91,12 +178,6 @@ impl HnswIndex {
 
     // Insert a node with access to vector storage for distance calculations
     pub fn insert(&mut self, id: Uuid, vector: &[f32], vectors: &dyn VectorReader) {
-        let empty_meta: HashMap<Uuid, crate::metadata::Metadata> = HashMap::new();
-        let search_context = SearchContext {
-            vectors,
-            filter: None,
-            metadatas: &empty_meta,
-        };
         // in hnsw, we add nodes one at a time, connecting them to existing nodes
         // first, we need to create the node and determine its level
         // determine the layer for the new node
@@ -104,20 +185,110 @@ impl HnswIndex {
 
         // if first node, make it entry point and return
         if self.start_node.is_none() {
-            self.start_node = Some(id); // set entry point
-            self.max_level = layer as isize; // this makes sure max_level is always the highest level
-            let node = HnswNode {
-                connections: vec![Vec::new(); layer + 1], // this creates empty connections for each layer
-                tombstone: false,
-            }; // create the node
-            self.nodes.insert(id, node); // insert into the index
+            self.insert_entry_point(id, layer);
             return;
         }
 
-        // otherwise, we need to find the best entry point for each layer down to 0
+        let connections = self.plan_connections(vector, layer, vectors);
+        self.link_node(id, layer, connections, vectors);
+    }
+
+    // Number of graph slots in use, tombstones included
+    fn node_count(&self) -> usize {
+        self.ids.len()
+    }
+
+    // Bulk-build path used by rebuilds, compaction and batch inserts.
+    // Nodes are added in batches: the neighbour search for every node of a batch runs in parallel
+    // against the graph as it was before the batch (read-only), then the planned edges are linked
+    // sequentially. Batches never exceed half of the current graph so each new node still sees a
+    // well-connected graph, and a small seed is inserted one by one first.
+    pub fn insert_bulk(&mut self, ids: &[Uuid], vectors: &(dyn VectorReader + Sync)) {
+        use rayon::prelude::*;
+
+        let mut remaining = ids;
+        while !remaining.is_empty() && self.node_count() < BULK_SEED_NODES {
+            let id = remaining[0];
+            if let Some(vector) = vectors.get(&id) {
+                self.insert(id, vector, vectors);
+            }
+            remaining = &remaining[1..];
+        }
+
+        while !remaining.is_empty() {
+            let batch_len = (self.node_count() / 2).clamp(1, BULK_MAX_BATCH);
+            let (batch, rest) = remaining.split_at(batch_len.min(remaining.len()));
+            remaining = rest;
+
+            let this = &*self;
+            let plans: Vec<(Uuid, usize, Vec<Vec<u32>>)> = batch
+                .par_iter()
+                .filter_map(|id| {
+                    let vector = vectors.get(id)?;
+                    let layer = this.random_layer();
+                    Some((*id, layer, this.plan_connections(vector, layer, vectors)))
+                })
+                .collect();
+
+            for (id, layer, connections) in plans {
+                if self.start_node.is_none() {
+                    self.insert_entry_point(id, layer);
+                } else {
+                    self.link_node(id, layer, connections, vectors);
+                }
+            }
+        }
+    }
+
+    fn insert_entry_point(&mut self, id: Uuid, layer: usize) {
+        let node = HnswNode {
+            connections: vec![Vec::new(); layer + 1], // this creates empty connections for each layer
+            tombstone: false,
+        }; // create the node
+        let offset = self.put_node(id, node); // insert into the index
+        self.start_node = Some(offset); // set entry point
+        self.max_level = layer as isize; // this makes sure max_level is always the highest level
+    }
+
+    // Store `node` at the offset of `id`, replacing any node there, and return the offset
+    fn put_node(&mut self, id: Uuid, node: HnswNode) -> u32 {
+        let offset = self.ids.insert(id);
+        if self.nodes.len() <= offset as usize {
+            self.nodes.resize(offset as usize + 1, None);
+        }
+        // Re-inserting a deleted id (vector updates) revives its tombstone
+        if let Some(old) = self.nodes[offset as usize].replace(node.into()) {
+            if old.tombstone {
+                if let Some(count) = self.tombstones.as_mut() {
+                    *count = count.saturating_sub(1);
+                }
+            }
+        }
+        offset
+    }
+
+    // Find the neighbours a new node at `layer` should connect to. Read-only so batches of nodes
+    // can be planned in parallel.
+    fn plan_connections(
+        &self,
+        vector: &[f32],
+        layer: usize,
+        vectors: &dyn VectorReader,
+    ) -> Vec<Vec<u32>> {
+        let empty_meta: HashMap<Uuid, crate::metadata::Metadata> = HashMap::new();
+        let search_context = SearchContext {
+            vectors,
+            filter: None,
+            metadatas: &empty_meta,
+        };
+
+        // we need to find the best entry point for each layer down to 0
         // we do this by greedy search
         // start from the highest layer of the current entry point
-        let mut current_entry = vec![self.start_node.unwrap()];
+        let mut current_entry = match self.start_node {
+            Some(start) => vec![start],
+            None => return vec![Vec::new(); layer + 1],
+        };
 
         // Search from top layer down to target layer (layer + 1)
         for lc in ((layer as isize + 1)..=self.max_level).rev() {
@@ -125,7 +296,7 @@ impl HnswIndex {
                 self.search_layer(vector, &current_entry, 1, lc as usize, &search_context);
         }
 
-        // Insert and connect at each layer from target down to 0
+        // Collect connections at each layer from target down to 0
         // (keep pending connections to avoid partial writes before pruning).
         let mut pending_connections = vec![Vec::new(); layer + 1];
         for lc in (0..=layer).rev() {
@@ -142,45 +313,53 @@ impl HnswIndex {
             );
 
             // Select M best neighbors (or M_max for layer 0)
-            let m = if lc == 0 {
-                self.config.m_max
-            } else {
-                self.config.m
-            };
-            let neighbors = self.select_neighbors(&current_entry, m, vectors, vector);
-
-            // Add bidirectional connections
-            // we do this by adding edges in both directions between the new node and its neighbors
-            // at the current layer since HNSW uses undirected edges, undirectec edges mean that if node A
-            // is connected to node B, then node B is also connected to node A
-            for &neighbor_id in &neighbors {
-                // Add edge from new node to neighbor
-                if lc < pending_connections.len() {
-                    pending_connections[lc].push(neighbor_id);
-                }
+            let m = self.max_connections(lc);
+            pending_connections[lc] = self.select_neighbors(&current_entry, m, vectors, vector);
+        }
+        pending_connections
+    }
 
+    // Add the node with its planned connections and the reverse edges to its neighbours.
+    fn link_node(
+        &mut self,
+        id: Uuid,
+        layer: usize,
+        connections: Vec<Vec<u32>>,
+        vectors: &dyn VectorReader,
+    ) {
+        // The new node's offset goes into its neighbours' lists before the node itself exists
+        let offset = self.ids.insert(id);
+        // Add bidirectional connections
+        // we do this by adding edges in both directions between the new node and its neighbors
+        // at the current layer since HNSW uses undirected edges, undirectec edges mean that if node A
+        // is connected to node B, then node B is also connected to node A
+        for (lc, neighbors) in connections.iter().enumerate() {
+            let m = self.max_connections(lc);
+            for &neighbor_id in neighbors {
                 // Add edge from neighbor to new node
-                if let Some(neighbor) = self.nodes.get_mut(&neighbor_id) {
+                if let Some(neighbor) = self.node_mut(neighbor_id) {
                     // get mutable reference to neighbor why?
                     // because we want to modify it's connections
                     if lc < neighbor.connections.len() {
-                        neighbor.connections[lc].push(id);
+                        neighbor.connections[lc].push(offset);
 
                         // Prune connections if neighbor exceeds max why? because HNSW limits the
                         // number of connections per node to maintain efficiency
                         if neighbor.connections[lc].len() > m {
                             // Clone the connections and neighbor vector to avoid borrow issues
                             let neighbor_connections = neighbor.connections[lc].clone();
-                            let neighbor_vec = vectors.get(&neighbor_id).unwrap().to_vec();
+                            let Some(neighbor_vec) = self.vector(vectors, neighbor_id) else {
+                                continue;
+                            };
 
                             let pruned = self.select_neighbors(
                                 &neighbor_connections,
                                 m,
                                 vectors,
-                                &neighbor_vec,
+                                neighbor_vec,
                             );
 
-                            if let Some(neighbor) = self.nodes.get_mut(&neighbor_id) {
+                            if let Some(neighbor) = self.node_mut(neighbor_id) {
                                 if lc < neighbor.connections.len() {
                                     neighbor.connections[lc] = pruned;
                                 }
@@ -193,15 +372,23 @@ impl HnswIndex {
 
         // Create and insert the new node
         let new_node = HnswNode {
-            connections: pending_connections,
+            connections,
             tombstone: false,
         };
-        self.nodes.insert(id, new_node);
+        self.put_node(id, new_node);
 
         // Update entry point if this node is at a higher layer
         if layer as isize > self.max_level {
             self.max_level = layer as isize;
-            self.start_node = Some(id);
+            self.start_node = Some(offset);
+        }
+    }
+
+    fn max_connections(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m_max
+        } else {
+            self.config.m
         }
     }
 
@@ -215,16 +402,17 @@ impl HnswIndex {
         ef: usize,
         vectors: &dyn VectorReader,
         filter: Option<&crate::search::query::Filter>,
-        metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+        metadatas: &dyn MetadataReader,
     ) -> Result<Vec<Uuid>> {
         if self.start_node.is_none() {
             return Ok(Vec::new());
         }
 
         let ep = self.start_node.unwrap();
-        if vectors.get(&ep).is_none() {
+        if self.vector(vectors, ep).is_none() {
             return Err(IndexError::SearchFailed(format!(
-                "HNSW entry point {ep} is missing from vector storage"
+                "HNSW entry point {} is missing from vector storage",
+                self.ids.id(ep)
             ))
             .into());
         }
@@ -235,10 +423,16 @@ impl HnswIndex {
             filter,
             metadatas,
         };
+        // The descent only looks for a good entry into layer 0, so it ignores the filter
+        let descent_context = SearchContext {
+            vectors,
+            filter: None,
+            metadatas,
+        };
 
         // Search from top layer down to layer 1
         for lc in (1..=self.max_level as usize).rev() {
-            current_nearest = self.search_layer(query, &current_nearest, 1, lc, &search_context);
+            current_nearest = self.search_layer(query, &current_nearest, 1, lc, &descent_context);
         }
 
         // Search layer 0 with ef
@@ -247,45 +441,50 @@ impl HnswIndex {
         // Return top k
         let mut filtered: Vec<Uuid> = current_nearest
             .into_iter()
-            .filter(|id| !self.is_tombstone(id))
+            .filter(|offset| !self.is_tombstone(*offset))
+            .map(|offset| self.ids.id(offset))
             .collect();
         filtered.truncate(k);
         Ok(filtered)
     }
 
+    // Deleted nodes and nodes the filter rejects are still traversed, so the search can reach
+    // matching nodes behind them, but never returned
+    fn is_excluded(&self, offset: u32, context: &SearchContext<'_>) -> bool {
+        self.is_tombstone(offset)
+            || context
+                .filter
+                .is_some_and(|filter| !context.metadatas.matches(&self.ids.id(offset), filter))
+    }
+
     // Search within a specific layer - returns nearest neighbor IDs sorted by distance
     fn search_layer(
         &self,
         query: &[f32],
-        entry_points: &[Uuid],
+        entry_points: &[u32],
         num_closest: usize,
         level: usize,
         context: &SearchContext<'_>,
-    ) -> Vec<Uuid> {
+    ) -> Vec<u32> {
         let mut visited = HashSet::new();
         let mut candidates = BinaryHeap::new();
-        let mut nearest = BinaryHeap::new();
+        // SearchCandidate orders closest-first, so wrap in Reverse to make `nearest` a max-heap by
+        // distance whose top is the furthest kept result
+        let mut nearest: BinaryHeap<Reverse<SearchCandidate>> = BinaryHeap::new();
 
         // Initialize with entry points
         for &ep in entry_points {
-            if let Some(ep_vector) = context.vectors.get(&ep) {
-                if let Some(f) = context.filter {
-                    if let Some(md) = context.metadatas.get(&ep) {
-                        if !f.matches(md) {
-                            continue;
-                        }
-                    }
-                }
+            if let Some(ep_vector) = self.vector(context.vectors, ep) {
                 let dist = self.distance(query, ep_vector);
                 candidates.push(SearchCandidate {
                     id: ep,
                     distance: dist,
                 });
-                if !self.is_tombstone(&ep) {
-                    nearest.push(SearchCandidate {
+                if !self.is_excluded(ep, context) {
+                    nearest.push(Reverse(SearchCandidate {
                         id: ep,
                         distance: dist,
-                    });
+                    }));
                 }
                 visited.insert(ep);
             }
@@ -293,7 +492,10 @@ impl HnswIndex {
 
         // we track furthest distance by looking at the top of the nearest heap (since it's a
         // max-heap)
-        let mut furthest_distance = nearest.peek().map(|c| c.distance).unwrap_or(f32::INFINITY);
+        let mut furthest_distance = nearest
+            .peek()
+            .map(|c| c.0.distance)
+            .unwrap_or(f32::INFINITY);
 
         // Greedy search within the layer basically, we explore closest candidate first
         while let Some(candidate) = candidates.pop() {
@@ -302,23 +504,16 @@ impl HnswIndex {
             }
 
             // Explore neighbors at this level
-            if let Some(node) = self.nodes.get(&candidate.id) {
+            if let Some(node) = self.node(candidate.id) {
                 if level < node.connections.len() {
                     for &neighbor_id in &node.connections[level] {
                         if visited.insert(neighbor_id) {
                             // only proceed if not visited
                             // we need to calculate distance to this neighbor and decide if it should be added to candidates and nearest
-                            if let Some(neighbor_vector) = context.vectors.get(&neighbor_id) {
-                                // apply filter if provided
-                                if let Some(f) = context.filter {
-                                    if let Some(md) = context.metadatas.get(&neighbor_id) {
-                                        if !f.matches(md) {
-                                            continue;
-                                        }
-                                    }
-                                }
+                            if let Some(neighbor_vector) = self.vector(context.vectors, neighbor_id)
+                            {
                                 let dist = self.distance(query, neighbor_vector);
-                                let neighbor_dead = self.is_tombstone(&neighbor_id);
+                                let neighbor_excluded = self.is_excluded(neighbor_id, context);
 
                                 // If this neighbor is closer than the furthest in nearest, add it
                                 if dist < furthest_distance || nearest.len() < num_closest {
@@ -326,11 +521,11 @@ impl HnswIndex {
                                         id: neighbor_id,
                                         distance: dist,
                                     });
-                                    if !neighbor_dead {
-                                        nearest.push(SearchCandidate {
+                                    if !neighbor_excluded {
+                                        nearest.push(Reverse(SearchCandidate {
                                             id: neighbor_id,
                                             distance: dist,
-                                        });
+                                        }));
 
                                         if nearest.len() > num_closest {
                                             nearest.pop(); // remove furthest
@@ -339,7 +534,7 @@ impl HnswIndex {
                                         // Update furthest distance
                                         furthest_distance = nearest
                                             .peek()
-                                            .map(|c| c.distance)
+                                            .map(|c| c.0.distance)
                                             .unwrap_or(f32::INFINITY);
                                     }
                                 }
@@ -351,7 +546,7 @@ impl HnswIndex {
         }
 
         // Convert heap to sorted vector (closest first)
-        let mut result: Vec<_> = nearest.into_iter().collect();
+        let mut result: Vec<_> = nearest.into_iter().map(|c| c.0).collect();
         result.sort_by(|a, b| {
             a.distance
                 .partial_cmp(&b.distance)
@@ -366,11 +561,11 @@ impl HnswIndex {
     // Select M best neighbors using simple heuristic
     fn select_neighbors(
         &self,
-        candidates: &[Uuid],
+        candidates: &[u32],
         m: usize,
         vectors: &dyn VectorReader,
         query: &[f32],
-    ) -> Vec<Uuid> {
+    ) -> Vec<u32> {
         if candidates.len() <= m {
             return candidates.to_vec();
         }
@@ -379,10 +574,10 @@ impl HnswIndex {
         let mut distances: Vec<_> = candidates
             .iter()
             .filter_map(|&id| {
-                if self.is_tombstone(&id) {
+                if self.is_tombstone(id) {
                     return None;
                 }
-                vectors.get(&id).map(|vec| {
+                self.vector(vectors, id).map(|vec| {
                     let dist = self.distance(query, vec);
                     (id, dist)
                 })
@@ -397,9 +592,16 @@ impl HnswIndex {
     #[allow(dead_code)]
     fn get_neighbors_at_level(&self, node_id: &Uuid, level: usize) -> Vec<Uuid> {
         // get neighbors at the current level
-        if let Some(node) = self.nodes.get(node_id) {
+        if let Some(node) = self
+            .ids
+            .offset(node_id)
+            .and_then(|offset| self.node(offset))
+        {
             if level < node.connections.len() {
-                return node.connections[level].clone();
+                return node.connections[level]
+                    .iter()
+                    .map(|offset| self.ids.id(*offset))
+                    .collect();
             }
         }
         Vec::new()
@@ -418,28 +620,206 @@ impl HnswIndex {
 
     // Remove a node from the index
     pub fn remove(&mut self, id: &Uuid) {
-        if !self.nodes.contains_key(id) {
+        let Some(offset) = self.ids.offset(id) else {
             return;
-        }
-        self.mark_tombstone(id);
+        };
+        self.mark_tombstone(offset);
 
         // Update entry point if needed
-        if self.start_node == Some(*id) {
-            self.start_node = self
-                .nodes
-                .iter()
-                .find(|(_, n)| !n.tombstone)
-                .map(|(k, _)| *k);
+        if self.start_node == Some(offset) {
+            let start = self.live_nodes().next().map(|(k, _)| k);
+            self.start_node = start;
             self.max_level = self
-                .nodes
-                .values()
-                .filter(|n| !n.tombstone)
-                .map(|n| n.connections.len() as isize - 1)
+                .live_nodes()
+                .map(|(_, n)| n.connections.len() as isize - 1)
                 .max()
                 .unwrap_or(-1);
         }
     }
 
+    // Whether a repair pass is under way or tombstones make up `ratio` of the nodes
+    pub fn needs_repair(&self, ratio: f32) -> bool {
+        if self.repair.is_some() {
+            return true;
+        }
+        // Uncounted until the first delete after a load, and only deletes make repair due
+        let tombstones = self.tombstones.unwrap_or(0);
+        tombstones > 0 && (tombstones as f32) >= self.node_count() as f32 * ratio
+    }
+
+    // Reconnect live nodes that point at tombstones and drop the tombstones from the graph.
+    // For every live node with dead neighbours at a layer, the replacement candidates are its live
+    // neighbours plus the live nodes reachable through the dead ones, and the best M are kept.
+    // Returns the ids that were physically removed.
+    pub fn repair(&mut self, vectors: &dyn VectorReader) -> Vec<Uuid> {
+        self.repair = None;
+        loop {
+            if let Some(removed) = self.repair_step(vectors, usize::MAX) {
+                return removed;
+            }
+        }
+    }
+
+    // One bounded slice of a repair pass: rewires at most `budget` live nodes, starting a pass if
+    // none is under way. Returns the removed ids once the pass is done, None while work remains.
+    pub fn repair_step(&mut self, vectors: &dyn VectorReader, budget: usize) -> Option<Vec<Uuid>> {
+        let mut pass = match self.repair.take() {
+            Some(pass) => pass,
+            None => RepairPass {
+                dead: self
+                    .all_nodes()
+                    .filter(|(_, n)| n.tombstone)
+                    .map(|(offset, _)| offset)
+                    .collect(),
+                cursor: 0,
+                end: self.nodes.len(),
+            },
+        };
+        // Ids re-inserted since the pass started are live again and keep their edges
+        pass.dead.retain(|offset| self.is_tombstone(*offset));
+        if pass.dead.is_empty() {
+            return Some(Vec::new());
+        }
+
+        // Plan the rewiring of this slice against the graph as it is (tombstones still traversable)
+        let mut rewired: Vec<(u32, usize, Vec<u32>)> = Vec::new();
+        let mut visited = 0;
+        while pass.cursor < pass.end && visited < budget {
+            let offset = pass.cursor as u32;
+            pass.cursor += 1;
+            let Some(node) = self.node(offset) else {
+                continue;
+            };
+            let dead = &pass.dead;
+            if node.tombstone {
+                // Deleted after the pass started: search can still walk it, so it must not keep
+                // edges into offsets about to be freed. Not worth re-planning, just cut them.
+                if !dead.contains(&offset) {
+                    for (lc, neighbors) in node.connections.iter().enumerate() {
+                        if neighbors.iter().any(|n| dead.contains(n)) {
+                            let kept = neighbors.iter().filter(|n| !dead.contains(*n));
+                            rewired.push((offset, lc, kept.copied().collect()));
+                        }
+                    }
+                }
+                continue;
+            }
+            visited += 1;
+            for (lc, neighbors) in node.connections.iter().enumerate() {
+                if !neighbors.iter().any(|n| dead.contains(n)) {
+                    continue;
+                }
+                let m = self.max_connections(lc);
+                let candidates = self.repair_candidates(offset, lc, neighbors, dead);
+                let replacement = match self.vector(vectors, offset) {
+                    Some(vector) if candidates.is_empty() => {
+                        // Every path went through deleted nodes, so search the layer again
+                        let mut found = self.plan_connections(vector, lc, vectors).swap_remove(lc);
+                        found.retain(|n| *n != offset && !dead.contains(n));
+                        found
+                    }
+                    Some(vector) => self.select_neighbors(&candidates, m, vectors, vector),
+                    None => neighbors
+                        .iter()
+                        .filter(|n| !dead.contains(*n))
+                        .copied()
+                        .collect(),
+                };
+                rewired.push((offset, lc, replacement));
+            }
+        }
+
+        for (offset, lc, replacement) in rewired {
+            if let Some(node) = self.node_mut(offset) {
+                node.connections[lc] = replacement;
+            }
+        }
+        if pass.cursor < pass.end {
+            self.repair = Some(pass);
+            return None;
+        }
+
+        // No edge points at the dead nodes any more, so their offsets can be handed out again
+        let mut removed = Vec::with_capacity(pass.dead.len());
+        for offset in &pass.dead {
+            self.nodes[*offset as usize] = None;
+            let id = self.ids.id(*offset);
+            self.ids.release(&id);
+            removed.push(id);
+        }
+        // Deletes made during the pass stay tombstones until the next one
+        let remaining = self.tombstone_count().saturating_sub(removed.len());
+        self.tombstones = Some(remaining);
+
+        // Keep the entry point on the highest remaining layer
+        self.max_level = self
+            .all_nodes()
+            .map(|(_, n)| n.connections.len() as isize - 1)
+            .max()
+            .unwrap_or(-1);
+        let start_valid = self.start_node.is_some_and(|start| {
+            self.node(start)
+                .is_some_and(|n| n.connections.len() as isize - 1 == self.max_level)
+        });
+        if !start_valid {
+            let start = self
+                .all_nodes()
+                .find(|(_, n)| n.connections.len() as isize - 1 == self.max_level)
+                .map(|(offset, _)| offset);
+            self.start_node = start;
+        }
+
+        Some(removed)
+    }
+
+    // Live neighbours of `offset` at `level`, plus live nodes reached by walking through dead ones
+    fn repair_candidates(
+        &self,
+        offset: u32,
+        level: usize,
+        neighbors: &[u32],
+        dead: &HashSet<u32>,
+    ) -> Vec<u32> {
+        let mut candidates = Vec::new();
+        let mut seen: HashSet<u32> = HashSet::new();
+        seen.insert(offset);
+        let mut queue: Vec<u32> = Vec::new();
+
+        for &n in neighbors {
+            if !seen.insert(n) {
+                continue;
+            }
+            if dead.contains(&n) {
+                queue.push(n);
+            } else {
+                candidates.push(n);
+            }
+        }
+
+        while let Some(dead_id) = queue.pop() {
+            if candidates.len() >= self.config.ef_construction {
+                break;
+            }
+            let Some(node) = self.node(dead_id) else {
+                continue;
+            };
+            if level >= node.connections.len() {
+                continue;
+            }
+            for &n in &node.connections[level] {
+                if !seen.insert(n) {
+                    continue;
+                }
+                if dead.contains(&n) {
+                    queue.push(n);
+                } else {
+                    candidates.push(n);
+                }
+            }
+        }
+        candidates
+    }
+
     // Get statistics about the index
     pub fn stats(&self) -> HnswStats {
         let mut total_nodes = 0;
@@ -447,7 +827,7 @@ impl HnswIndex {
         let mut layer_sizes = vec![0; (self.max_level + 1) as usize];
         let mut total_connections = 0;
 
-        for node in self.nodes.values() {
+        for (_, node) in self.all_nodes() {
             if node.tombstone {
                 tombstones += 1;
             } else {
@@ -462,15 +842,16 @@ impl HnswIndex {
         }
 
         // calculate memory usage bytes
-        let memory_usage_bytes = self.nodes.len() * std::mem::size_of::<(Uuid, HnswNode)>()
+        let memory_usage_bytes = self.nodes.len() * std::mem::size_of::<Option<Shared<HnswNode>>>()
+            + self.ids.memory_usage_bytes()
             + self
-                .nodes
-                .values()
-                .map(|n| {
-                    n.connections
-                        .iter()
-                        .map(|c| c.len() * std::mem::size_of::<Uuid>())
-                        .sum::<usize>()
+                .all_nodes()
+                .map(|(_, n)| {
+                    std::mem::size_of::<HnswNode>()
+                        + n.connections
+                            .iter()
+                            .map(|c| c.len() * std::mem::size_of::<u32>())
+                            .sum::<usize>()
                 })
                 .sum::<usize>();
 
@@ -493,3 +874,38 @@ impl HnswIndex {
         self.config.ef_search
     }
 }
+
+// Older files keyed nodes and edges by UUID; assign offsets and translate the edges
+impl From<LegacyHnswIndex> for HnswIndex {
+    fn from(legacy: LegacyHnswIndex) -> Self {
+        let mut index = HnswIndex::new(legacy.config);
+        for id in legacy.nodes.keys() {
+            index.ids.insert(*id);
+        }
+        index.nodes.resize(index.ids.capacity(), None);
+        for (id, node) in legacy.nodes {
+            let connections = node
+                .connections
+                .into_iter()
+                .map(|layer| {
+                    layer
+                        .iter()
+                        .filter_map(|neighbor| index.ids.offset(neighbor))
+                        .collect()
+                })
+                .collect();
+            let offset = index.ids.insert(id);
+            index.nodes[offset as usize] = Some(
+                HnswNode {
+                    connections,
+                    tombstone: node.tombstone,
+                }
+                .into(),
+            );
+        }
+        index.max_level = legacy.max_level;
+        index.start_node = legacy.start_node.and_then(|id| index.ids.offset(&id));
+        index.tombstones = None;
+        index
+    }
+}
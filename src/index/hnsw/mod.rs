@@ -4,9 +4,10 @@ mod index;
 pub use config::{HnswConfig, HnswStats};
 pub use index::HnswIndex;
 
-use crate::index::traits::{IndexDetails, IndexStats, IndexType, VectorIndex, VectorReader};
+use crate::index::traits::{
+    IndexDetails, IndexStats, IndexType, MetadataReader, VectorIndex, VectorReader,
+};
 use crate::Result;
-use std::collections::HashMap;
 use uuid::Uuid;
 
 // we need a wrapper because HNSW has some specific parameters that affect search quality (ef_search) and we want to allow overriding them at search time without changing the index config
@@ -15,6 +16,24 @@ impl VectorIndex for HnswIndex {
         self.insert(id, vector, vectors);
     }
 
+    // Use the parallel bulk-build path unless the collection is configured single-threaded.
+    fn insert_batch(
+        &mut self,
+        ids: &[Uuid],
+        vectors: &(dyn VectorReader + Sync),
+        parallelism: &crate::config::ParallelismConfig,
+    ) {
+        if parallelism.num_threads() > 1 {
+            self.insert_bulk(ids, vectors);
+        } else {
+            for id in ids {
+                if let Some(vector) = vectors.get(id) {
+                    self.insert(*id, vector, vectors);
+                }
+            }
+        }
+    }
+
     // Search for nearest neighbors to the query vector with filters.
     fn search(
         &self,
@@ -23,7 +42,7 @@ impl VectorIndex for HnswIndex {
         vectors: &dyn VectorReader,
         quality: crate::config::SearchConfig,
         filter: Option<&crate::search::query::Filter>,
-        metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+        metadatas: &dyn MetadataReader,
     ) -> Result<Vec<Uuid>> {
         // Use quality.ef if provided, otherwise use configured ef_search
         let ef = quality.ef.unwrap_or_else(|| self.get_ef_search()).max(k);
@@ -34,6 +53,18 @@ impl VectorIndex for HnswIndex {
         self.remove(id);
     }
 
+    fn ids(&self) -> Vec<Uuid> {
+        self.live_ids()
+    }
+
+    fn needs_repair(&self, tombstone_ratio: f32) -> bool {
+        self.needs_repair(tombstone_ratio)
+    }
+
+    fn repair_step(&mut self, vectors: &dyn VectorReader, budget: usize) -> Option<Vec<Uuid>> {
+        self.repair_step(vectors, budget)
+    }
+
     // Get statistics about the HNSW index, including total nodes, max layer, layer sizes, average connections, and memory usage.
     fn stats(&self) -> IndexStats {
         let hnsw_stats = self.stats();
@@ -46,6 +77,7 @@ impl VectorIndex for HnswIndex {
                 max_layer: hnsw_stats.max_layer,
                 layer_sizes: hnsw_stats.layer_sizes,
                 avg_connections: hnsw_stats.avg_connections,
+                tombstones: hnsw_stats.tombstones,
             },
         }
     }
@@ -57,4 +89,8 @@ impl VectorIndex for HnswIndex {
     fn to_serializable(&self) -> crate::index::SerializableIndex {
         crate::index::SerializableIndex::Hnsw(self.clone())
     }
+
+    fn clone_box(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
 }
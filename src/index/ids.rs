@@ -0,0 +1,112 @@
+// Dense internal ids for the vectors an index holds.
+// Graph edges and inverted lists store u32 offsets (4 bytes) instead of UUIDs (16 bytes), and the
+// index keeps this one map to translate at its boundary. Released offsets are handed out again.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::chunks::{ChunkedMap, ChunkedVec};
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Uuid>", into = "Vec<Uuid>")]
+pub struct IdMap {
+    ids: ChunkedVec<Uuid>, // ids[offset] = vector id, nil for a released offset
+    offsets: ChunkedMap<u32>,
+    free: ChunkedVec<u32>,
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Number of ids currently mapped
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    // One past the highest offset handed out so far; per-offset tables are sized by this
+    pub fn capacity(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn offset(&self, id: &Uuid) -> Option<u32> {
+        self.offsets.get(id).copied()
+    }
+
+    pub fn id(&self, offset: u32) -> Uuid {
+        self.ids[offset as usize]
+    }
+
+    // Offset of `id`, assigning one if it has none yet
+    pub fn insert(&mut self, id: Uuid) -> u32 {
+        if let Some(offset) = self.offsets.get(&id) {
+            return *offset;
+        }
+        let offset = match self.free.pop() {
+            Some(offset) => {
+                self.ids[offset as usize] = id;
+                offset
+            }
+            None => {
+                self.ids.push(id);
+                (self.ids.len() - 1) as u32
+            }
+        };
+        self.offsets.insert(id, offset);
+        offset
+    }
+
+    // Forget `id`; its offset may be reused by the next insert
+    pub fn release(&mut self, id: &Uuid) -> Option<u32> {
+        let offset = self.offsets.remove(id)?;
+        self.ids[offset as usize] = Uuid::nil();
+        self.free.push(offset);
+        Some(offset)
+    }
+
+    // Mapped (offset, id) pairs in offset order
+    pub fn iter(&self) -> impl Iterator<Item = (u32, Uuid)> + '_ {
+        self.ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| !id.is_nil())
+            .map(|(offset, id)| (offset as u32, *id))
+    }
+
+    pub fn memory_usage_bytes(&self) -> usize {
+        self.ids.len() * std::mem::size_of::<Uuid>()
+            + self.offsets.capacity() * std::mem::size_of::<(Uuid, u32)>()
+            + self.free.len() * std::mem::size_of::<u32>()
+    }
+}
+
+// Only the offset table is persisted; the reverse map and free list are derived on load
+impl From<Vec<Uuid>> for IdMap {
+    fn from(ids: Vec<Uuid>) -> Self {
+        let mut offsets = ChunkedMap::default();
+        let mut free = ChunkedVec::new();
+        for (offset, id) in ids.iter().enumerate() {
+            if id.is_nil() {
+                free.push(offset as u32);
+            } else {
+                offsets.insert(*id, offset as u32);
+            }
+        }
+        Self {
+            ids: ids.into(),
+            offsets,
+            free,
+        }
+    }
+}
+
+impl From<IdMap> for Vec<Uuid> {
+    fn from(map: IdMap) -> Self {
+        map.ids.iter().copied().collect()
+    }
+}
@@ -2,22 +2,41 @@
 // O(√N) search complexity - much faster than brute force for large datasets
 
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use uuid::Uuid;
 
 use super::config::IvfConfig;
 use crate::error::{IndexError, Result};
-use crate::index::traits::{IndexDetails, IndexStats, IndexType, VectorIndex, VectorReader};
+use crate::index::legacy::LegacyIvfIndex;
+use crate::index::traits::{
+    IndexDetails, IndexStats, IndexType, MetadataReader, VectorIndex, VectorReader,
+};
+use crate::index::{ChunkedVec, IdMap, Shared};
+use crate::metrics::Metric;
+
+// Above this many vectors k-means trains on random mini-batches instead of the full set
+const MINI_BATCH_THRESHOLD: usize = 20_000;
+const MINI_BATCH_SIZE: usize = 4_096;
+// Largest list / mean list size that marks the clustering as drifted
+const RETRAIN_IMBALANCE_RATIO: f32 = 3.0;
+// Lists above SPLIT_FACTOR x mean are split, lists below MERGE_FACTOR x mean are merged away
+const SPLIT_FACTOR: f32 = 2.0;
+const MERGE_FACTOR: f32 = 0.1;
+// Power iterations used to find the axis an oversized list is split along
+const SPLIT_POWER_ITERATIONS: usize = 8;
+// vector_to_cluster entry of an offset that is pending or free
+const NO_CLUSTER: u32 = u32::MAX;
 
 // IVF index structure
 #[derive(Clone, Serialize, Deserialize)]
 pub struct IvfIndex {
     config: IvfConfig,
-    centroids: Vec<Vec<f32>>,                // Cluster centroids
-    inverted_lists: Vec<Vec<Uuid>>,          // vectors[cluster_id] = [vector_ids]
-    vector_to_cluster: HashMap<Uuid, usize>, // Track which cluster each vector belongs to
-    #[serde(default)]
-    pending_vectors: HashSet<Uuid>, // Vectors waiting for initial clustering
+    ids: IdMap,                            // Dense offsets of every indexed vector
+    centroids: Shared<Vec<Vec<f32>>>,      // Cluster centroids
+    inverted_lists: Vec<Shared<Vec<u32>>>, // vectors[cluster_id] = [vector offsets]
+    // vector_to_cluster[offset] = cluster, NO_CLUSTER if unassigned
+    vector_to_cluster: ChunkedVec<u32>,
+    pending_vectors: HashSet<u32>, // Vectors waiting for initial clustering
     dimensions: usize,
 }
 
@@ -28,49 +47,191 @@ impl IvfIndex {
     pub fn new(config: IvfConfig) -> Self {
         IvfIndex {
             config,
-            centroids: Vec::new(),
+            ids: IdMap::new(),
+            centroids: Shared::default(),
             inverted_lists: Vec::new(),
-            vector_to_cluster: HashMap::new(),
+            vector_to_cluster: ChunkedVec::new(),
             pending_vectors: HashSet::new(),
             dimensions: 0,
         }
     }
 
+    // Vectors assigned to a cluster (pending ones excluded)
+    fn assigned_count(&self) -> usize {
+        self.ids.len() - self.pending_vectors.len()
+    }
+
+    fn cluster_of(&self, offset: u32) -> Option<usize> {
+        match self.vector_to_cluster.get(offset as usize) {
+            Some(&cluster) if cluster != NO_CLUSTER => Some(cluster as usize),
+            _ => None,
+        }
+    }
+
+    fn assign(&mut self, offset: u32, cluster_id: usize) {
+        if self.vector_to_cluster.len() <= offset as usize {
+            self.vector_to_cluster
+                .resize(offset as usize + 1, NO_CLUSTER);
+        }
+        self.vector_to_cluster[offset as usize] = cluster_id as u32;
+    }
+
+    // Point vector_to_cluster at the lists as they now are
+    fn reassign_all(&mut self) {
+        self.vector_to_cluster = vec![NO_CLUSTER; self.ids.capacity()].into();
+        for (cluster_id, list) in self.inverted_lists.iter().enumerate() {
+            for offset in list {
+                self.vector_to_cluster[*offset as usize] = cluster_id as u32;
+            }
+        }
+    }
+
+    // Stored vector of the vector at `offset`
+    fn vector<'a>(&self, vectors: &'a dyn VectorReader, offset: u32) -> Option<&'a [f32]> {
+        vectors.get(&self.ids.id(offset))
+    }
+
     // Build clusters using k-means
     pub fn build_clusters(&mut self, vectors: &dyn VectorReader) {
         // building clusters is an offline process that can be done periodically as new vectors are
         // added
         // on high level, it works by:
-        // 1. Randomly initialize centroids from existing vectors
+        // 1. Seed centroids with k-means++ so they start spread out over the data
         // 2. Assign each vector to nearest centroid (forming clusters)
-        // 3. Update centroids by computing mean of assigned vectors
+        // 3. Update centroids by computing mean of assigned vectors (mini-batches for large sets)
         // 4. Repeat until convergence or max iterations
+        // 5. Merge near-empty lists and split oversized ones
         if vectors.is_empty() {
             return;
         }
 
-        let vector_list: Vec<(Uuid, Vec<f32>)> = vectors
+        // Clustering covers every vector the reader holds, so offsets are assigned afresh
+        self.ids = IdMap::new();
+        let vector_list: Vec<(u32, Vec<f32>)> = vectors
             .iter()
-            .map(|(id, vector)| (id, vector.to_vec()))
+            .map(|(id, vector)| (self.ids.insert(id), vector.to_vec()))
             .collect();
         if let Some((_, vector)) = vector_list.first() {
             self.dimensions = vector.len();
         }
 
         let num_clusters = self.config.num_clusters.min(vector_list.len());
+        self.centroids = self.seed_centroids(&vector_list, num_clusters).into();
+
+        if vector_list.len() > MINI_BATCH_THRESHOLD {
+            self.mini_batch_kmeans(&vector_list);
+        } else {
+            self.lloyd_kmeans(&vector_list);
+        }
+
+        // Build inverted lists
+        self.inverted_lists = vec![Shared::default(); num_clusters];
+        self.vector_to_cluster = vec![NO_CLUSTER; self.ids.capacity()].into();
+        self.pending_vectors.clear();
+
+        for (offset, vec) in &vector_list {
+            let cluster_id = self.find_nearest_centroid(vec);
+            self.inverted_lists[cluster_id].push(*offset);
+            self.assign(*offset, cluster_id);
+        }
+
+        self.rebalance(vectors);
+    }
+
+    // Build a freshly clustered copy of this index, leaving the current one untouched so it can
+    // keep serving searches while k-means runs.
+    pub fn retrained(&self, vectors: &dyn VectorReader) -> IvfIndex {
+        let mut index = IvfIndex::new(self.config.clone());
+        index.build_clusters(vectors);
+        index
+    }
+
+    // Whether lists have drifted far enough from balanced to warrant a retrain
+    pub fn needs_retrain(&self) -> bool {
+        if self.centroids.len() < 2 || self.assigned_count() < self.config.num_clusters {
+            return false;
+        }
+        self.imbalance_ratio() > RETRAIN_IMBALANCE_RATIO
+            || self.empty_lists() * 4 > self.inverted_lists.len()
+    }
 
-        self.centroids = vector_list
+    // Largest inverted list relative to the mean list size (1.0 = perfectly balanced)
+    pub fn imbalance_ratio(&self) -> f32 {
+        let total: usize = self.inverted_lists.iter().map(|l| l.len()).sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let mean = total as f32 / self.inverted_lists.len() as f32;
+        let largest = self
+            .inverted_lists
+            .iter()
+            .map(|l| l.len())
+            .max()
+            .unwrap_or(0);
+        largest as f32 / mean
+    }
+
+    pub fn empty_lists(&self) -> usize {
+        self.inverted_lists.iter().filter(|l| l.is_empty()).count()
+    }
+
+    // k-means++ seeding: each next centroid is drawn with probability proportional to its squared
+    // distance from the closest centroid picked so far. Large sets are seeded from a sample.
+    fn seed_centroids(&self, vector_list: &[(u32, Vec<f32>)], k: usize) -> Vec<Vec<f32>> {
+        use rand::seq::SliceRandom;
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let sample: Vec<&[f32]> = if vector_list.len() > MINI_BATCH_THRESHOLD {
+            vector_list
+                .choose_multiple(&mut rng, MINI_BATCH_THRESHOLD)
+                .map(|(_, v)| v.as_slice())
+                .collect()
+        } else {
+            vector_list.iter().map(|(_, v)| v.as_slice()).collect()
+        };
+        if sample.is_empty() || k == 0 {
+            return Vec::new();
+        }
+
+        let mut centroids = vec![sample[rng.gen_range(0..sample.len())].to_vec()];
+        let mut closest: Vec<f32> = sample
             .iter()
-            .take(num_clusters)
-            .map(|(_, v)| v.clone())
+            .map(|v| self.centroid_distance(v, &centroids[0]).powi(2))
             .collect();
 
-        // K-means iterations
+        while centroids.len() < k {
+            let total: f32 = closest.iter().sum();
+            let next = if total > 0.0 {
+                let mut target = rng.gen_range(0.0..total);
+                closest
+                    .iter()
+                    .position(|&d| {
+                        target -= d;
+                        target <= 0.0
+                    })
+                    .unwrap_or(sample.len() - 1)
+            } else {
+                // Every point already sits on a centroid
+                rng.gen_range(0..sample.len())
+            };
+            let centroid = sample[next].to_vec();
+            for (d, v) in closest.iter_mut().zip(&sample) {
+                *d = d.min(self.centroid_distance(v, &centroid).powi(2));
+            }
+            centroids.push(centroid);
+        }
+        centroids
+    }
+
+    // Full Lloyd iterations over every vector
+    fn lloyd_kmeans(&mut self, vector_list: &[(u32, Vec<f32>)]) {
+        let num_clusters = self.centroids.len();
         for _ in 0..self.config.max_iterations {
             // Assign each vector to nearest centroid
-            let mut clusters: Vec<Vec<(Uuid, Vec<f32>)>> = vec![Vec::new(); num_clusters];
+            let mut clusters: Vec<Vec<(u32, Vec<f32>)>> = vec![Vec::new(); num_clusters];
 
-            for (id, vec) in &vector_list {
+            for (id, vec) in vector_list {
                 let cluster_id = self.find_nearest_centroid(vec);
                 clusters[cluster_id].push((*id, vec.clone()));
             }
@@ -102,16 +263,186 @@ impl IvfIndex {
                 break;
             }
         }
+    }
 
-        // Build inverted lists
-        self.inverted_lists = vec![Vec::new(); num_clusters];
-        self.vector_to_cluster.clear();
-        self.pending_vectors.clear();
+    // Mini-batch k-means: each step pulls centroids towards a random batch with a per-centroid
+    // learning rate of 1/count, so cost per iteration does not grow with the collection.
+    fn mini_batch_kmeans(&mut self, vector_list: &[(u32, Vec<f32>)]) {
+        use rand::seq::SliceRandom;
 
-        for (id, vec) in &vector_list {
-            let cluster_id = self.find_nearest_centroid(vec);
-            self.inverted_lists[cluster_id].push(*id);
-            self.vector_to_cluster.insert(*id, cluster_id);
+        let mut rng = rand::thread_rng();
+        let mut counts = vec![0usize; self.centroids.len()];
+        let steps = self.config.max_iterations * vector_list.len().div_ceil(MINI_BATCH_SIZE);
+
+        for _ in 0..steps {
+            let batch: Vec<&(u32, Vec<f32>)> = vector_list
+                .choose_multiple(&mut rng, MINI_BATCH_SIZE)
+                .collect();
+            let assignments: Vec<usize> = batch
+                .iter()
+                .map(|(_, v)| self.find_nearest_centroid(v))
+                .collect();
+
+            for ((_, vector), cluster_id) in batch.into_iter().zip(assignments) {
+                counts[cluster_id] += 1;
+                let eta = 1.0 / counts[cluster_id] as f32;
+                for (c, x) in self.centroids[cluster_id].iter_mut().zip(vector) {
+                    *c += eta * (x - *c);
+                }
+            }
+        }
+    }
+
+    // Merge lists far below the mean size into their neighbours, then split oversized lists in
+    // half, folding the smallest list away first whenever the cluster budget is used up.
+    fn rebalance(&mut self, vectors: &dyn VectorReader) {
+        let total = self.assigned_count();
+        if total == 0 || self.centroids.len() < 2 {
+            return;
+        }
+
+        // Merge: drop centroids of tiny lists and move their vectors to the nearest survivor
+        let mean = total as f32 / self.centroids.len() as f32;
+        let keep: Vec<bool> = self
+            .inverted_lists
+            .iter()
+            .map(|l| l.len() as f32 >= mean * MERGE_FACTOR && !l.is_empty())
+            .collect();
+        self.merge_lists(&keep, vectors);
+
+        // Split: halve the largest list while it is oversized
+        for _ in 0..self.config.num_clusters {
+            let mean = total as f32 / self.centroids.len() as f32;
+            let Some(size) = self.inverted_lists.iter().map(|l| l.len()).max() else {
+                break;
+            };
+            if (size as f32) <= mean * SPLIT_FACTOR || size < 2 {
+                break;
+            }
+            if self.centroids.len() >= self.config.num_clusters {
+                let smallest = self
+                    .inverted_lists
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, l)| l.len())
+                    .map(|(i, _)| i);
+                let keep: Vec<bool> = (0..self.centroids.len())
+                    .map(|i| Some(i) != smallest)
+                    .collect();
+                self.merge_lists(&keep, vectors);
+            }
+            let largest = self
+                .inverted_lists
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, l)| l.len())
+                .map(|(i, _)| i);
+            if !largest.is_some_and(|i| self.split_list(i, vectors)) {
+                break;
+            }
+        }
+
+        self.reassign_all();
+    }
+
+    // Drop the lists not marked `keep` and reassign their vectors to the nearest remaining centroid
+    fn merge_lists(&mut self, keep: &[bool], vectors: &dyn VectorReader) {
+        if keep.iter().all(|k| *k) || !keep.iter().any(|k| *k) {
+            return;
+        }
+        let mut orphans = Vec::new();
+        let mut centroids = Vec::new();
+        let mut lists = Vec::new();
+        for ((centroid, list), keep) in self
+            .centroids
+            .drain(..)
+            .zip(self.inverted_lists.drain(..))
+            .zip(keep)
+        {
+            if *keep {
+                centroids.push(centroid);
+                lists.push(list);
+            } else {
+                orphans.extend(list.iter());
+            }
+        }
+        self.centroids = centroids.into();
+        self.inverted_lists = lists;
+        for offset in orphans {
+            if let Some(vector) = self.vector(vectors, offset) {
+                let cluster_id = self.find_nearest_centroid(vector);
+                self.inverted_lists[cluster_id].push(offset);
+            }
+        }
+    }
+
+    // Split a list in two at the median of its principal axis, so both halves are the same size.
+    // The axis comes from a few power iterations over the centred members.
+    fn split_list(&mut self, cluster_id: usize, vectors: &dyn VectorReader) -> bool {
+        use rand::Rng;
+
+        let members: Vec<(u32, Vec<f32>)> = self.inverted_lists[cluster_id]
+            .iter()
+            .filter_map(|offset| self.vector(vectors, *offset).map(|v| (*offset, v.to_vec())))
+            .collect();
+        if members.len() < 2 {
+            return false;
+        }
+        let mean = self.compute_centroid(&members);
+        let centred: Vec<Vec<f32>> = members
+            .iter()
+            .map(|(_, v)| v.iter().zip(&mean).map(|(x, m)| x - m).collect())
+            .collect();
+
+        let mut rng = rand::thread_rng();
+        let mut axis: Vec<f32> = (0..mean.len()).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        for _ in 0..SPLIT_POWER_ITERATIONS {
+            let mut next = vec![0.0f32; axis.len()];
+            for x in &centred {
+                let projection: f32 = x.iter().zip(&axis).map(|(a, b)| a * b).sum();
+                for (n, v) in next.iter_mut().zip(x) {
+                    *n += projection * v;
+                }
+            }
+            let norm = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm == 0.0 {
+                // Every member is identical, nothing to split
+                return false;
+            }
+            axis = next.into_iter().map(|v| v / norm).collect();
+        }
+
+        let mut order: Vec<(f32, usize)> = centred
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.iter().zip(&axis).map(|(a, b)| a * b).sum(), i))
+            .collect();
+        order.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        let (low, high) = order.split_at(order.len() / 2);
+        let half = |part: &[(f32, usize)]| -> Vec<(u32, Vec<f32>)> {
+            part.iter().map(|(_, i)| members[*i].clone()).collect()
+        };
+        let (first_half, second_half) = (half(low), half(high));
+
+        let (first, second) = (
+            self.compute_centroid(&first_half),
+            self.compute_centroid(&second_half),
+        );
+        self.centroids[cluster_id] = first;
+        self.inverted_lists[cluster_id] = first_half.into_iter().map(|(id, _)| id).collect();
+        self.centroids.push(second);
+        self.inverted_lists
+            .push(second_half.into_iter().map(|(id, _)| id).collect());
+        true
+    }
+
+    // Metric similarity turned into a non-negative distance for seeding and splitting
+    fn centroid_distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        let score = self.config.metric.calculate(a, b, self.config.mode);
+        match self.config.metric {
+            Metric::Euclidean if score > 0.0 => 1.0 / score - 1.0,
+            Metric::Euclidean => f32::MAX,
+            Metric::Cosine | Metric::DotProduct => (1.0 - score).max(0.0),
         }
     }
 
@@ -131,7 +462,7 @@ impl IvfIndex {
             .unwrap_or(0)
     }
 
-    fn compute_centroid(&self, cluster: &[(Uuid, Vec<f32>)]) -> Vec<f32> {
+    fn compute_centroid(&self, cluster: &[(u32, Vec<f32>)]) -> Vec<f32> {
         // Compute mean vector for the cluster by summing all vectors and dividing by count
         if cluster.is_empty() {
             return vec![0.0; self.dimensions];
@@ -156,13 +487,14 @@ impl IvfIndex {
 
 impl VectorIndex for IvfIndex {
     fn insert(&mut self, id: Uuid, vector: &[f32], vectors: &dyn VectorReader) {
-        if self.vector_to_cluster.contains_key(&id) || self.pending_vectors.contains(&id) {
+        if self.ids.offset(&id).is_some() {
             return;
         }
 
         // For online insertion, find nearest centroid and add to that cluster
         if self.centroids.is_empty() {
-            self.pending_vectors.insert(id);
+            let offset = self.ids.insert(id);
+            self.pending_vectors.insert(offset);
 
             // First insertion - need to build clusters
             if vectors.len() >= self.config.num_clusters {
@@ -177,8 +509,9 @@ impl VectorIndex for IvfIndex {
         if cluster_id >= self.inverted_lists.len() {
             return;
         }
-        self.inverted_lists[cluster_id].push(id);
-        self.vector_to_cluster.insert(id, cluster_id);
+        let offset = self.ids.insert(id);
+        self.inverted_lists[cluster_id].push(offset);
+        self.assign(offset, cluster_id);
     }
 
     fn search(
@@ -188,7 +521,7 @@ impl VectorIndex for IvfIndex {
         vectors: &dyn VectorReader,
         quality: crate::config::SearchConfig,
         _filter: Option<&crate::search::query::Filter>,
-        _metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+        _metadatas: &dyn MetadataReader,
     ) -> Result<Vec<Uuid>> {
         if self.centroids.is_empty() {
             return Err(IndexError::NotInitialized.into());
@@ -215,60 +548,90 @@ impl VectorIndex for IvfIndex {
         let nprobe = quality.nprobe.unwrap_or(self.config.num_probes);
 
         // Search top nprobe clusters
-        let mut candidates: Vec<(Uuid, f32)> = Vec::new();
+        let mut candidates: Vec<(u32, f32)> = Vec::new();
 
         for (cluster_id, _) in centroid_distances.iter().take(nprobe) {
-            if let Some(vector_ids) = self.inverted_lists.get(*cluster_id) {
-                for id in vector_ids {
-                    let vector = vectors.get(id).ok_or_else(|| {
+            if let Some(offsets) = self.inverted_lists.get(*cluster_id) {
+                for offset in offsets {
+                    let vector = self.vector(vectors, *offset).ok_or_else(|| {
                         IndexError::SearchFailed(format!(
-                            "IVF index references missing vector {id}"
+                            "IVF index references missing vector {}",
+                            self.ids.id(*offset)
                         ))
                     })?;
                     let score = self
                         .config
                         .metric
                         .calculate(query, vector, self.config.mode);
-                    candidates.push((*id, score));
+                    candidates.push((*offset, score));
                 }
             }
         }
 
         // Sort and return top k
         candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-        Ok(candidates.iter().take(k).map(|(id, _)| *id).collect())
+        Ok(candidates
+            .iter()
+            .take(k)
+            .map(|(offset, _)| self.ids.id(*offset))
+            .collect())
+    }
+
+    fn needs_retrain(&self) -> bool {
+        self.needs_retrain()
+    }
+
+    fn drift(&self) -> Option<f32> {
+        Some(self.imbalance_ratio())
+    }
+
+    fn retrained(&self, vectors: &dyn VectorReader) -> Option<Box<dyn VectorIndex>> {
+        Some(Box::new(self.retrained(vectors)))
     }
 
     fn remove(&mut self, id: &Uuid) {
-        self.pending_vectors.remove(id);
-        if let Some(cluster_id) = self.vector_to_cluster.remove(id) {
+        let Some(offset) = self.ids.offset(id) else {
+            return;
+        };
+        self.pending_vectors.remove(&offset);
+        if let Some(cluster_id) = self.cluster_of(offset) {
             if let Some(list) = self.inverted_lists.get_mut(cluster_id) {
-                list.retain(|vid| vid != id);
+                list.retain(|o| *o != offset);
             }
         }
+        if let Some(cluster) = self.vector_to_cluster.get_mut(offset as usize) {
+            *cluster = NO_CLUSTER;
+        }
+        self.ids.release(id);
+    }
+
+    fn ids(&self) -> Vec<Uuid> {
+        self.ids.iter().map(|(_, id)| id).collect()
     }
 
     fn stats(&self) -> IndexStats {
         let vectors_per_cluster = self.inverted_lists.iter().map(|list| list.len()).collect();
 
         let memory_usage = self.centroids.len() * self.dimensions * std::mem::size_of::<f32>()
-            + self.vector_to_cluster.len()
-                * (std::mem::size_of::<Uuid>() + std::mem::size_of::<usize>())
-            + self.pending_vectors.len() * std::mem::size_of::<Uuid>()
+            + self.ids.memory_usage_bytes()
+            + self.vector_to_cluster.len() * std::mem::size_of::<u32>()
+            + self.pending_vectors.len() * std::mem::size_of::<u32>()
             + self
                 .inverted_lists
                 .iter()
-                .map(|l| l.len() * std::mem::size_of::<Uuid>())
+                .map(|l| l.len() * std::mem::size_of::<u32>())
                 .sum::<usize>();
 
         IndexStats {
             index_type: IndexType::Ivf,
-            total_vectors: self.vector_to_cluster.len() + self.pending_vectors.len(),
+            total_vectors: self.ids.len(),
             memory_usage_bytes: memory_usage,
             details: IndexDetails::Ivf {
                 num_clusters: self.centroids.len(),
                 vectors_per_cluster,
                 centroids_computed: !self.centroids.is_empty(),
+                imbalance_ratio: self.imbalance_ratio(),
+                empty_lists: self.empty_lists(),
             },
         }
     }
@@ -280,4 +643,29 @@ impl VectorIndex for IvfIndex {
     fn to_serializable(&self) -> crate::index::SerializableIndex {
         crate::index::SerializableIndex::Ivf(self.clone())
     }
+
+    fn clone_box(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
+}
+
+// Older files keyed the lists by UUID; assign offsets and translate them
+impl From<LegacyIvfIndex> for IvfIndex {
+    fn from(legacy: LegacyIvfIndex) -> Self {
+        let mut index = IvfIndex::new(legacy.config);
+        index.centroids = legacy.centroids.into();
+        index.dimensions = legacy.dimensions;
+        index.inverted_lists = legacy
+            .inverted_lists
+            .into_iter()
+            .map(|list| list.into_iter().map(|id| index.ids.insert(id)).collect())
+            .collect();
+        index.pending_vectors = legacy
+            .pending_vectors
+            .into_iter()
+            .map(|id| index.ids.insert(id))
+            .collect();
+        index.reassign_all();
+        index
+    }
 }
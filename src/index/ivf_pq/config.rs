@@ -0,0 +1,59 @@
+// IVF-PQ index configuration
+
+use crate::config::ExecutionMode;
+use crate::metrics::Metric;
+use serde::{Deserialize, Serialize};
+
+// IVF-PQ index configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IvfPqConfig {
+    pub num_clusters: usize,   // Number of coarse clusters √N is a good default
+    pub num_probes: usize,     // Coarse clusters to scan, higher = better recall
+    pub max_iterations: usize, // K-means iterations for the coarse quantizer and PQ codebooks
+    pub subquantizers: usize,  // Number of PQ sub-spaces, each one encodes into a single byte
+    #[serde(default = "default_rerank")]
+    pub rerank: bool, // Re-score ADC candidates with the exact vectors
+    #[serde(default = "default_rerank_factor")]
+    pub rerank_factor: usize, // Candidates kept for the exact rerank, multiple of k
+    pub metric: Metric,
+    #[serde(default)]
+    pub mode: ExecutionMode,
+}
+
+// Implement default values for IvfPqConfig.
+impl Default for IvfPqConfig {
+    fn default() -> Self {
+        IvfPqConfig {
+            num_clusters: 100,
+            num_probes: 5,
+            max_iterations: 10,
+            subquantizers: 16,
+            rerank: default_rerank(),
+            rerank_factor: default_rerank_factor(),
+            metric: Metric::Cosine,
+            mode: ExecutionMode::default(),
+        }
+    }
+}
+
+impl IvfPqConfig {
+    // Auto-configure based on dataset size
+    pub fn auto(num_vectors: usize) -> Self {
+        let num_clusters = (num_vectors as f32).sqrt().max(10.0) as usize;
+        let num_probes = (num_clusters as f32 * 0.05).clamp(1.0, 16.0) as usize;
+
+        IvfPqConfig {
+            num_clusters,
+            num_probes,
+            ..IvfPqConfig::default()
+        }
+    }
+}
+
+fn default_rerank() -> bool {
+    true
+}
+
+fn default_rerank_factor() -> usize {
+    4
+}
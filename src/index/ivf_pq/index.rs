@@ -0,0 +1,554 @@
+// IVF-PQ composite index
+// Coarse k-means clustering like IVF, but every inverted list stores product-quantized residuals
+// (vector - centroid) instead of pointing back at full-precision vectors. Probed lists are scored
+// with asymmetric distance computation (ADC) against per-subspace lookup tables, and the best
+// candidates can optionally be re-scored with the exact vectors.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use super::config::IvfPqConfig;
+use crate::config::ExecutionMode;
+use crate::error::{IndexError, Result};
+use crate::index::legacy::LegacyIvfPqIndex;
+use crate::index::traits::{
+    IndexDetails, IndexStats, IndexType, MetadataReader, VectorIndex, VectorReader,
+};
+use crate::index::{ChunkedVec, IdMap, Shared};
+use crate::metrics::{dot_product, euclidean_distance_squared, Metric};
+
+// Each sub-space is encoded into one byte, so a codebook holds at most 256 entries.
+const PQ_CODEBOOK_SIZE: usize = 256;
+// vector_to_cluster entry of an offset that is pending or free
+const NO_CLUSTER: u32 = u32::MAX;
+
+// IVF-PQ index structure
+#[derive(Clone, Serialize, Deserialize)]
+pub struct IvfPqIndex {
+    config: IvfPqConfig,
+    ids: IdMap,                            // Dense offsets of every indexed vector
+    centroids: Shared<Vec<Vec<f32>>>,      // Coarse cluster centroids
+    codebooks: Shared<Vec<Vec<Vec<f32>>>>, // codebooks[subspace][code] = residual sub-vector
+    subspaces: Vec<(usize, usize)>,        // [start, end) dimension range of every subspace
+    inverted_lists: Vec<Shared<Vec<u32>>>, // vectors[cluster_id] = [vector offsets]
+    // PQ codes parallel to inverted_lists, subspaces.len() bytes per vector
+    list_codes: Vec<Shared<Vec<u8>>>,
+    // vector_to_cluster[offset] = cluster, NO_CLUSTER if unassigned
+    vector_to_cluster: ChunkedVec<u32>,
+    pending_vectors: HashSet<u32>, // Vectors waiting for initial training
+    dimensions: usize,
+}
+
+impl IvfPqIndex {
+    // IVF-PQ trades some accuracy for a much smaller footprint than IVF: a vector costs one
+    // byte per subspace in the inverted lists, and probing never touches the raw vectors unless
+    // rerank is enabled.
+
+    pub fn new(config: IvfPqConfig) -> Self {
+        IvfPqIndex {
+            config,
+            ids: IdMap::new(),
+            centroids: Shared::default(),
+            codebooks: Shared::default(),
+            subspaces: Vec::new(),
+            inverted_lists: Vec::new(),
+            list_codes: Vec::new(),
+            vector_to_cluster: ChunkedVec::new(),
+            pending_vectors: HashSet::new(),
+            dimensions: 0,
+        }
+    }
+
+    fn cluster_of(&self, offset: u32) -> Option<usize> {
+        match self.vector_to_cluster.get(offset as usize) {
+            Some(&cluster) if cluster != NO_CLUSTER => Some(cluster as usize),
+            _ => None,
+        }
+    }
+
+    fn assign(&mut self, offset: u32, cluster_id: usize) {
+        if self.vector_to_cluster.len() <= offset as usize {
+            self.vector_to_cluster
+                .resize(offset as usize + 1, NO_CLUSTER);
+        }
+        self.vector_to_cluster[offset as usize] = cluster_id as u32;
+    }
+
+    // Train the coarse quantizer and the PQ codebooks, then encode every vector
+    pub fn build_clusters(&mut self, vectors: &dyn VectorReader) {
+        // 1. Run k-means on the (normalized for cosine) vectors to get coarse centroids
+        // 2. Compute residuals against the nearest coarse centroid
+        // 3. Run k-means independently on every residual subspace to get the PQ codebooks
+        // 4. Encode each residual as one codebook index per subspace
+        if vectors.is_empty() {
+            return;
+        }
+
+        let vector_list: Vec<(Uuid, Vec<f32>)> = vectors
+            .iter()
+            .map(|(id, vector)| (id, self.prepare(vector)))
+            .collect();
+        let dimensions = vector_list[0].1.len();
+        if dimensions == 0 {
+            return;
+        }
+        // Training covers every vector the reader holds, so offsets are assigned afresh
+        self.ids = IdMap::new();
+        self.dimensions = dimensions;
+        self.subspaces = subspace_bounds(dimensions, self.config.subquantizers);
+
+        let metric = self.config.metric;
+        let mode = self.config.mode;
+        let points: Vec<&[f32]> = vector_list.iter().map(|(_, v)| v.as_slice()).collect();
+        let num_clusters = self.config.num_clusters.clamp(1, points.len());
+        self.centroids = train_kmeans(
+            &points,
+            num_clusters,
+            self.config.max_iterations,
+            |point, centroids| nearest_by_similarity(point, centroids, metric, mode),
+        )
+        .into();
+
+        let assigned: Vec<(Uuid, usize, Vec<f32>)> = vector_list
+            .iter()
+            .map(|(id, vector)| {
+                let cluster_id = self.find_nearest_centroid(vector);
+                (
+                    *id,
+                    cluster_id,
+                    residual(vector, &self.centroids[cluster_id]),
+                )
+            })
+            .collect();
+
+        self.codebooks = self
+            .subspaces
+            .iter()
+            .map(|&(start, end)| {
+                let sub_points: Vec<&[f32]> = assigned
+                    .iter()
+                    .map(|(_, _, residual)| &residual[start..end])
+                    .collect();
+                let codebook_size = PQ_CODEBOOK_SIZE.min(sub_points.len());
+                train_kmeans(
+                    &sub_points,
+                    codebook_size,
+                    self.config.max_iterations,
+                    |point, codebook| nearest_by_l2(point, codebook, mode),
+                )
+            })
+            .collect();
+
+        // Build inverted lists
+        self.inverted_lists = vec![Shared::default(); num_clusters];
+        self.list_codes = vec![Shared::default(); num_clusters];
+        self.vector_to_cluster.clear();
+        self.pending_vectors.clear();
+
+        for (id, cluster_id, residual) in &assigned {
+            let codes = self.encode(residual);
+            let offset = self.ids.insert(*id);
+            self.inverted_lists[*cluster_id].push(offset);
+            self.list_codes[*cluster_id].extend_from_slice(&codes);
+            self.assign(offset, *cluster_id);
+        }
+    }
+
+    // Cosine is served as an inner product over unit vectors, so normalize before
+    // training, encoding and querying.
+    fn prepare(&self, vector: &[f32]) -> Vec<f32> {
+        if self.config.metric != Metric::Cosine {
+            return vector.to_vec();
+        }
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm <= f32::EPSILON {
+            return vector.to_vec();
+        }
+        vector.iter().map(|v| v / norm).collect()
+    }
+
+    fn find_nearest_centroid(&self, vector: &[f32]) -> usize {
+        nearest_by_similarity(
+            vector,
+            &self.centroids,
+            self.config.metric,
+            self.config.mode,
+        )
+    }
+
+    fn encode(&self, residual: &[f32]) -> Vec<u8> {
+        self.subspaces
+            .iter()
+            .zip(&self.codebooks)
+            .map(|(&(start, end), codebook)| {
+                nearest_by_l2(&residual[start..end], codebook, self.config.mode) as u8
+            })
+            .collect()
+    }
+
+    // table[subspace][code] = squared L2 distance between the query residual block and the code
+    fn l2_table(&self, residual_query: &[f32]) -> Vec<Vec<f32>> {
+        self.subspaces
+            .iter()
+            .zip(&self.codebooks)
+            .map(|(&(start, end), codebook)| {
+                codebook
+                    .iter()
+                    .map(|code| {
+                        euclidean_distance_squared(
+                            &residual_query[start..end],
+                            code,
+                            self.config.mode,
+                        )
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // table[subspace][code] = inner product between the query block and the code
+    fn inner_product_table(&self, query: &[f32]) -> Vec<Vec<f32>> {
+        self.subspaces
+            .iter()
+            .zip(&self.codebooks)
+            .map(|(&(start, end), codebook)| {
+                codebook
+                    .iter()
+                    .map(|code| dot_product(&query[start..end], code, self.config.mode))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl VectorIndex for IvfPqIndex {
+    fn insert(&mut self, id: Uuid, vector: &[f32], vectors: &dyn VectorReader) {
+        if self.ids.offset(&id).is_some() {
+            return;
+        }
+
+        // Codebooks need training data, so buffer vectors until there are enough of them
+        if self.centroids.is_empty() {
+            let offset = self.ids.insert(id);
+            self.pending_vectors.insert(offset);
+
+            if vectors.len() >= self.config.num_clusters {
+                self.build_clusters(vectors);
+            }
+            return;
+        }
+
+        let vector = self.prepare(vector);
+        if vector.len() != self.dimensions {
+            return;
+        }
+        let cluster_id = self.find_nearest_centroid(&vector);
+        if cluster_id >= self.inverted_lists.len() {
+            return;
+        }
+        let codes = self.encode(&residual(&vector, &self.centroids[cluster_id]));
+        let offset = self.ids.insert(id);
+        self.inverted_lists[cluster_id].push(offset);
+        self.list_codes[cluster_id].extend_from_slice(&codes);
+        self.assign(offset, cluster_id);
+    }
+
+    fn search(
+        &self,
+        query: &[f32],
+        k: usize,
+        vectors: &dyn VectorReader,
+        quality: crate::config::SearchConfig,
+        _filter: Option<&crate::search::query::Filter>,
+        _metadatas: &dyn MetadataReader,
+    ) -> Result<Vec<Uuid>> {
+        if self.centroids.is_empty() {
+            return Err(IndexError::NotInitialized.into());
+        }
+
+        let prepared = self.prepare(query);
+        if prepared.len() != self.dimensions {
+            return Err(IndexError::SearchFailed(format!(
+                "IVF-PQ query has {} dimensions, index expects {}",
+                prepared.len(),
+                self.dimensions
+            ))
+            .into());
+        }
+
+        let metric = self.config.metric;
+        let mode = self.config.mode;
+
+        // Find nearest coarse centroids
+        let mut centroid_scores: Vec<(usize, f32)> = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(i, centroid)| (i, metric.calculate(&prepared, centroid, mode)))
+            .collect();
+        centroid_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Use quality.nprobe if provided, otherwise use configured num_probes
+        let nprobe = quality.nprobe.unwrap_or(self.config.num_probes);
+        let code_len = self.subspaces.len();
+
+        // The inner product table does not depend on the probed list, only the L2 one does
+        let inner_table = match metric {
+            Metric::Euclidean => None,
+            Metric::Cosine | Metric::DotProduct => Some(self.inner_product_table(&prepared)),
+        };
+
+        let mut candidates: Vec<(u32, f32)> = Vec::new();
+        for (cluster_id, _) in centroid_scores.iter().take(nprobe) {
+            let (Some(ids), Some(codes)) = (
+                self.inverted_lists.get(*cluster_id),
+                self.list_codes.get(*cluster_id),
+            ) else {
+                continue;
+            };
+            let centroid = &self.centroids[*cluster_id];
+
+            match &inner_table {
+                // <q, c + r> = <q, c> + sum over subspaces of <q_j, r_j>
+                Some(table) => {
+                    let base = dot_product(&prepared, centroid, mode);
+                    for (id, code) in ids.iter().zip(codes.chunks_exact(code_len)) {
+                        candidates.push((*id, base + adc_lookup(table, code)));
+                    }
+                }
+                // ||q - (c + r)||^2 = sum over subspaces of ||(q - c)_j - r_j||^2
+                None => {
+                    let table = self.l2_table(&residual(&prepared, centroid));
+                    for (id, code) in ids.iter().zip(codes.chunks_exact(code_len)) {
+                        let distance = adc_lookup(&table, code).max(0.0).sqrt();
+                        candidates.push((*id, 1.0 / (1.0 + distance)));
+                    }
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        if self.config.rerank {
+            // Re-score the best ADC candidates with the exact vectors to recover precision
+            candidates.truncate(k.saturating_mul(self.config.rerank_factor.max(1)));
+            for (offset, score) in candidates.iter_mut() {
+                let id = self.ids.id(*offset);
+                let vector = vectors.get(&id).ok_or_else(|| {
+                    IndexError::SearchFailed(format!("IVF-PQ index references missing vector {id}"))
+                })?;
+                *score = metric.calculate(query, vector, mode);
+            }
+            candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        }
+
+        Ok(candidates
+            .iter()
+            .take(k)
+            .map(|(offset, _)| self.ids.id(*offset))
+            .collect())
+    }
+
+    fn remove(&mut self, id: &Uuid) {
+        let Some(offset) = self.ids.release(id) else {
+            return;
+        };
+        self.pending_vectors.remove(&offset);
+        let Some(cluster_id) = self.cluster_of(offset) else {
+            return;
+        };
+        self.vector_to_cluster[offset as usize] = NO_CLUSTER;
+        let code_len = self.subspaces.len();
+        let (Some(list), Some(codes)) = (
+            self.inverted_lists.get_mut(cluster_id),
+            self.list_codes.get_mut(cluster_id),
+        ) else {
+            return;
+        };
+        if let Some(position) = list.iter().position(|o| *o == offset) {
+            // Mirror swap_remove on the codes so both lists stay aligned
+            list.swap_remove(position);
+            let last = codes.len() - code_len;
+            if position * code_len != last {
+                codes.copy_within(last.., position * code_len);
+            }
+            codes.truncate(last);
+        }
+    }
+
+    fn ids(&self) -> Vec<Uuid> {
+        self.ids.iter().map(|(_, id)| id).collect()
+    }
+
+    fn stats(&self) -> IndexStats {
+        let vectors_per_cluster = self.inverted_lists.iter().map(|list| list.len()).collect();
+
+        let memory_usage = self.centroids.len() * self.dimensions * std::mem::size_of::<f32>()
+            + self
+                .codebooks
+                .iter()
+                .flatten()
+                .map(|code| code.len() * std::mem::size_of::<f32>())
+                .sum::<usize>()
+            + self
+                .list_codes
+                .iter()
+                .map(|codes| codes.len())
+                .sum::<usize>()
+            + self
+                .inverted_lists
+                .iter()
+                .map(|l| l.len() * std::mem::size_of::<u32>())
+                .sum::<usize>()
+            + self.ids.memory_usage_bytes()
+            + self.vector_to_cluster.len() * std::mem::size_of::<u32>()
+            + self.pending_vectors.len() * std::mem::size_of::<u32>();
+
+        IndexStats {
+            index_type: IndexType::IvfPq,
+            total_vectors: self.ids.len(),
+            memory_usage_bytes: memory_usage,
+            details: IndexDetails::IvfPq {
+                num_clusters: self.centroids.len(),
+                vectors_per_cluster,
+                subquantizers: self.subspaces.len(),
+                centroids_computed: !self.centroids.is_empty(),
+                rerank: self.config.rerank,
+            },
+        }
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::IvfPq
+    }
+
+    fn to_serializable(&self) -> crate::index::SerializableIndex {
+        crate::index::SerializableIndex::IvfPq(self.clone())
+    }
+
+    fn clone_box(&self) -> Box<dyn VectorIndex> {
+        Box::new(self.clone())
+    }
+}
+
+// Older files keyed the lists by UUID; assign offsets and translate them
+impl From<LegacyIvfPqIndex> for IvfPqIndex {
+    fn from(legacy: LegacyIvfPqIndex) -> Self {
+        let mut index = IvfPqIndex::new(legacy.config);
+        index.centroids = legacy.centroids.into();
+        index.codebooks = legacy.codebooks.into();
+        index.subspaces = legacy.subspaces;
+        index.list_codes = legacy.list_codes.into_iter().map(Shared::from).collect();
+        index.dimensions = legacy.dimensions;
+        for (cluster_id, list) in legacy.inverted_lists.into_iter().enumerate() {
+            let offsets = list
+                .into_iter()
+                .map(|id| {
+                    let offset = index.ids.insert(id);
+                    index.assign(offset, cluster_id);
+                    offset
+                })
+                .collect();
+            index.inverted_lists.push(offsets);
+        }
+        for id in legacy.pending_vectors {
+            let offset = index.ids.insert(id);
+            index.pending_vectors.insert(offset);
+        }
+        index
+    }
+}
+
+// Split `dimensions` into at most `subquantizers` contiguous blocks of (nearly) equal size
+fn subspace_bounds(dimensions: usize, subquantizers: usize) -> Vec<(usize, usize)> {
+    let subquantizers = subquantizers.clamp(1, dimensions.max(1));
+    let block_len = dimensions.div_ceil(subquantizers).max(1);
+    (0..dimensions)
+        .step_by(block_len)
+        .map(|start| (start, (start + block_len).min(dimensions)))
+        .collect()
+}
+
+fn residual(vector: &[f32], centroid: &[f32]) -> Vec<f32> {
+    vector.iter().zip(centroid).map(|(v, c)| v - c).collect()
+}
+
+fn adc_lookup(table: &[Vec<f32>], code: &[u8]) -> f32 {
+    table
+        .iter()
+        .zip(code)
+        .map(|(row, &c)| row[c as usize])
+        .sum()
+}
+
+fn nearest_by_similarity(
+    vector: &[f32],
+    centroids: &[Vec<f32>],
+    metric: Metric,
+    mode: ExecutionMode,
+) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, centroid)| (i, metric.calculate(vector, centroid, mode)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn nearest_by_l2(vector: &[f32], centroids: &[Vec<f32>], mode: ExecutionMode) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, centroid)| (i, euclidean_distance_squared(vector, centroid, mode)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+// Lloyd's k-means. Initial centroids are spread evenly over the input instead of taking the
+// first k points, which matters for PQ codebooks trained on residuals of clustered data.
+fn train_kmeans<F>(points: &[&[f32]], k: usize, iterations: usize, nearest: F) -> Vec<Vec<f32>>
+where
+    F: Fn(&[f32], &[Vec<f32>]) -> usize,
+{
+    if points.is_empty() || k == 0 {
+        return Vec::new();
+    }
+    let dimensions = points[0].len();
+    let mut centroids: Vec<Vec<f32>> = (0..k)
+        .map(|i| points[i * points.len() / k].to_vec())
+        .collect();
+
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0f32; dimensions]; k];
+        let mut counts = vec![0usize; k];
+        for point in points {
+            let cluster_id = nearest(point, &centroids);
+            counts[cluster_id] += 1;
+            for (sum, value) in sums[cluster_id].iter_mut().zip(point.iter()) {
+                *sum += value;
+            }
+        }
+
+        let mut moved = false;
+        for (i, (sum, count)) in sums.into_iter().zip(counts).enumerate() {
+            // Keep the previous centroid for empty clusters
+            if count == 0 {
+                continue;
+            }
+            let centroid: Vec<f32> = sum.into_iter().map(|s| s / count as f32).collect();
+            if centroid != centroids[i] {
+                moved = true;
+            }
+            centroids[i] = centroid;
+        }
+
+        if !moved {
+            break;
+        }
+    }
+
+    centroids
+}
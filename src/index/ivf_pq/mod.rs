@@ -0,0 +1,7 @@
+// IVF-PQ index module
+
+mod config;
+mod index;
+
+pub use config::IvfPqConfig;
+pub use index::IvfPqIndex;
@@ -0,0 +1,54 @@
+// Index formats written before the indexes switched to dense internal ids.
+// Vector index files from older versions still deserialize into these, and each index type
+// converts its legacy form on load. The next checkpoint writes the current format.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use super::{FlatConfig, HnswConfig, IvfConfig, IvfPqConfig};
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyFlatIndex {
+    pub(crate) config: FlatConfig,
+    pub(crate) vector_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyHnswNode {
+    pub(crate) connections: Vec<Vec<Uuid>>,
+    pub(crate) tombstone: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyHnswIndex {
+    pub(crate) config: HnswConfig,
+    pub(crate) nodes: HashMap<Uuid, LegacyHnswNode>,
+    pub(crate) max_level: isize,
+    pub(crate) start_node: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyIvfIndex {
+    pub(crate) config: IvfConfig,
+    pub(crate) centroids: Vec<Vec<f32>>,
+    pub(crate) inverted_lists: Vec<Vec<Uuid>>,
+    pub(crate) vector_to_cluster: HashMap<Uuid, usize>,
+    #[serde(default)]
+    pub(crate) pending_vectors: HashSet<Uuid>,
+    pub(crate) dimensions: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LegacyIvfPqIndex {
+    pub(crate) config: IvfPqConfig,
+    pub(crate) centroids: Vec<Vec<f32>>,
+    pub(crate) codebooks: Vec<Vec<Vec<f32>>>,
+    pub(crate) subspaces: Vec<(usize, usize)>,
+    pub(crate) inverted_lists: Vec<Vec<Uuid>>,
+    pub(crate) list_codes: Vec<Vec<u8>>,
+    pub(crate) vector_to_cluster: HashMap<Uuid, usize>,
+    #[serde(default)]
+    pub(crate) pending_vectors: HashSet<Uuid>,
+    pub(crate) dimensions: usize,
+}
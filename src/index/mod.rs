@@ -1,19 +1,27 @@
-// Supports: HNSW, Flat, IVF
+// Supports: HNSW, Flat, IVF, IVF-PQ
 
+mod chunks;
 pub mod flat;
 pub mod hnsw;
+mod ids;
 pub mod ivf;
+pub mod ivf_pq;
+pub mod legacy;
 mod selector;
 mod traits;
 
+pub(crate) use chunks::{ChunkedVec, Shared};
+
 // Re-export trait and types
+pub use ids::IdMap;
 pub use selector::{AutoIndexConfig, IndexConfig};
 pub use traits::{
-    HashMapVectorReader, IndexDetails, IndexStats, IndexType, SerializableIndex, VectorIndex,
-    VectorReader,
+    HashMapVectorReader, IndexDetails, IndexStats, IndexType, MetadataReader, SerializableIndex,
+    VectorIndex, VectorReader,
 };
 
 // Re-export index implementations
 pub use flat::{FlatConfig, FlatIndex};
 pub use hnsw::{HnswConfig, HnswIndex, HnswStats};
 pub use ivf::{IvfConfig, IvfIndex};
+pub use ivf_pq::{IvfPqConfig, IvfPqIndex};
@@ -1,11 +1,13 @@
-//  a unified configuration interface for different types of vector indices (Flat, HNSW, IVF).
+//  a unified configuration interface for different types of vector indices (Flat, HNSW, IVF, IVF-PQ).
 use crate::config::ExecutionMode;
 use crate::config::SearchConfig;
 use crate::metrics::Metric;
 use serde::{Deserialize, Serialize};
 
 use super::traits::{IndexType, VectorIndex};
-use super::{FlatConfig, FlatIndex, HnswConfig, HnswIndex, IvfConfig, IvfIndex};
+use super::{
+    FlatConfig, FlatIndex, HnswConfig, HnswIndex, IvfConfig, IvfIndex, IvfPqConfig, IvfPqIndex,
+};
 
 // Unified index configuration
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -20,6 +22,13 @@ pub struct AutoIndexConfig {
     pub ivf_num_probes: Option<usize>,
     #[serde(default = "default_ivf_max_iterations")]
     pub ivf_max_iterations: usize,
+    // Upper bound for IVF-PQ between IVF and HNSW (None = skip IVF-PQ)
+    #[serde(default)]
+    pub ivf_pq_max_vectors: Option<usize>,
+    #[serde(default = "default_ivf_pq_subquantizers")]
+    pub ivf_pq_subquantizers: usize,
+    #[serde(default = "default_ivf_pq_rerank")]
+    pub ivf_pq_rerank: bool,
     #[serde(default = "default_hnsw_m")]
     pub hnsw_m: usize,
     #[serde(default = "default_hnsw_ef_construction")]
@@ -36,6 +45,9 @@ impl Default for AutoIndexConfig {
             ivf_num_clusters: None,
             ivf_num_probes: None,
             ivf_max_iterations: default_ivf_max_iterations(),
+            ivf_pq_max_vectors: None,
+            ivf_pq_subquantizers: default_ivf_pq_subquantizers(),
+            ivf_pq_rerank: default_ivf_pq_rerank(),
             hnsw_m: default_hnsw_m(),
             hnsw_ef_construction: default_hnsw_ef_construction(),
             hnsw_ef_search: default_hnsw_ef_search(),
@@ -89,6 +101,22 @@ pub enum IndexConfig {
         #[serde(default)]
         search: SearchConfig,
     },
+    // IVF index with product-quantized residuals
+    IvfPq {
+        num_clusters: usize,
+        num_probes: usize,
+        max_iterations: usize,
+        subquantizers: usize,
+        #[serde(default = "default_ivf_pq_rerank")]
+        rerank: bool,
+        #[serde(default = "default_ivf_pq_rerank_factor")]
+        rerank_factor: usize,
+        metric: Metric,
+        #[serde(default)]
+        mode: ExecutionMode,
+        #[serde(default)]
+        search: SearchConfig,
+    },
 }
 
 impl Default for IndexConfig {
@@ -112,6 +140,11 @@ impl IndexConfig {
                     IndexType::Flat
                 } else if num_vectors < auto.ivf_max_vectors {
                     IndexType::Ivf
+                } else if auto
+                    .ivf_pq_max_vectors
+                    .is_some_and(|max_vectors| num_vectors < max_vectors)
+                {
+                    IndexType::IvfPq
                 } else {
                     IndexType::Hnsw
                 }
@@ -119,7 +152,25 @@ impl IndexConfig {
             IndexConfig::Flat { .. } => IndexType::Flat,
             IndexConfig::Hnsw { .. } => IndexType::Hnsw,
             IndexConfig::Ivf { .. } => IndexType::Ivf,
+            IndexConfig::IvfPq { .. } => IndexType::IvfPq,
+        }
+    }
+
+    // Index type an Auto config should move to once the collection has grown past the live index.
+    // Only upgrades are reported so a collection hovering around a threshold does not flip back
+    // and forth between index types.
+    pub fn migration_target(&self, current: IndexType, num_vectors: usize) -> Option<IndexType> {
+        if !matches!(self, IndexConfig::Auto { .. }) {
+            return None;
         }
+        let scale = |index_type: IndexType| match index_type {
+            IndexType::Flat => 0,
+            IndexType::Ivf => 1,
+            IndexType::IvfPq => 2,
+            IndexType::Hnsw => 3,
+        };
+        let target = self.select_type(num_vectors);
+        (scale(target) > scale(current)).then_some(target)
     }
 
     // Create an index based on configuration and size
@@ -209,6 +260,49 @@ impl IndexConfig {
                 };
                 Box::new(IvfIndex::new(config))
             }
+            IndexType::IvfPq => {
+                let config = match self {
+                    IndexConfig::IvfPq {
+                        num_clusters,
+                        num_probes,
+                        max_iterations,
+                        subquantizers,
+                        rerank,
+                        rerank_factor,
+                        metric,
+                        mode,
+                        ..
+                    } => IvfPqConfig {
+                        num_clusters: *num_clusters,
+                        num_probes: *num_probes,
+                        max_iterations: *max_iterations,
+                        subquantizers: *subquantizers,
+                        rerank: *rerank,
+                        rerank_factor: *rerank_factor,
+                        metric: *metric,
+                        mode: *mode,
+                    },
+                    _ => {
+                        // Same sizing rules as the auto IVF path, plus the PQ settings from the auto config.
+                        let (metric, mode) = self.get_metric_and_simd();
+                        let auto = self.auto_config();
+                        let mut config = IvfPqConfig::auto(num_vectors);
+                        if let Some(num_clusters) = auto.ivf_num_clusters {
+                            config.num_clusters = num_clusters;
+                        }
+                        if let Some(num_probes) = auto.ivf_num_probes {
+                            config.num_probes = num_probes;
+                        }
+                        config.max_iterations = auto.ivf_max_iterations;
+                        config.subquantizers = auto.ivf_pq_subquantizers;
+                        config.rerank = auto.ivf_pq_rerank;
+                        config.metric = metric;
+                        config.mode = mode;
+                        config
+                    }
+                };
+                Box::new(IvfPqIndex::new(config))
+            }
         }
     }
 
@@ -219,6 +313,7 @@ impl IndexConfig {
             IndexConfig::Flat { metric, .. } => *metric,
             IndexConfig::Hnsw { metric, .. } => *metric,
             IndexConfig::Ivf { metric, .. } => *metric,
+            IndexConfig::IvfPq { metric, .. } => *metric,
         }
     }
 
@@ -232,6 +327,7 @@ impl IndexConfig {
             IndexConfig::Flat { metric, mode, .. } => (*metric, *mode),
             IndexConfig::Hnsw { metric, mode, .. } => (*metric, *mode),
             IndexConfig::Ivf { metric, mode, .. } => (*metric, *mode),
+            IndexConfig::IvfPq { metric, mode, .. } => (*metric, *mode),
         }
     }
 
@@ -241,6 +337,7 @@ impl IndexConfig {
             IndexConfig::Flat { search, .. } => *search,
             IndexConfig::Hnsw { search, .. } => *search,
             IndexConfig::Ivf { search, .. } => *search,
+            IndexConfig::IvfPq { search, .. } => *search,
         }
     }
 
@@ -264,6 +361,18 @@ fn default_ivf_max_iterations() -> usize {
     10
 }
 
+fn default_ivf_pq_subquantizers() -> usize {
+    16
+}
+
+fn default_ivf_pq_rerank() -> bool {
+    true
+}
+
+fn default_ivf_pq_rerank_factor() -> usize {
+    4
+}
+
 fn default_hnsw_m() -> usize {
     16
 }
@@ -1,7 +1,8 @@
 // All indexes (HNSW, Flat, IVF, etc.) implement this trait
 
-use crate::config::SearchConfig;
+use crate::config::{ParallelismConfig, SearchConfig};
 use crate::error::Result;
+use crate::index::{FlatIndex, HnswIndex, IvfIndex, IvfPqIndex};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -44,6 +45,18 @@ impl VectorReader for HashMapVectorReader<'_> {
     }
 }
 
+// Metadata lookups for filters applied while an index is traversed
+pub trait MetadataReader: Sync {
+    // Whether the metadata of `id` passes `filter`; ids without metadata pass
+    fn matches(&self, id: &Uuid, filter: &crate::search::query::Filter) -> bool;
+}
+
+impl MetadataReader for HashMap<Uuid, crate::metadata::Metadata> {
+    fn matches(&self, id: &Uuid, filter: &crate::search::query::Filter) -> bool {
+        self.get(id).is_none_or(|metadata| filter.matches(metadata))
+    }
+}
+
 pub trait VectorIndex: Send + Sync {
     // Insert a vector into the index
 
@@ -53,6 +66,25 @@ pub trait VectorIndex: Send + Sync {
     // * vectors - All vectors in the collection (for distance calculations)
     fn insert(&mut self, id: Uuid, vector: &[f32], vectors: &dyn VectorReader);
 
+    // Insert many vectors at once (rebuilds, compaction, batch inserts)
+
+    // # Arguments
+    // * ids - Vectors to index, each must be readable through `vectors`
+    // * vectors - All vectors in the collection (for distance calculations)
+    // * parallelism - Thread settings for indexes with a parallel build path
+    fn insert_batch(
+        &mut self,
+        ids: &[Uuid],
+        vectors: &(dyn VectorReader + Sync),
+        _parallelism: &ParallelismConfig,
+    ) {
+        for id in ids {
+            if let Some(vector) = vectors.get(id) {
+                self.insert(*id, vector, vectors);
+            }
+        }
+    }
+
     // Search for k nearest neighbors with custom quality settings
     // # Arguments
     // * query- Query vector
@@ -69,12 +101,45 @@ pub trait VectorIndex: Send + Sync {
         vectors: &dyn VectorReader,
         quality: SearchConfig,
         filter: Option<&crate::search::query::Filter>,
-        metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+        metadatas: &dyn MetadataReader,
     ) -> Result<Vec<Uuid>>;
 
+    // Whether the index has drifted enough (e.g. unbalanced IVF lists) to be worth retraining
+    fn needs_retrain(&self) -> bool {
+        false
+    }
+
+    // How far the index has drifted (IVF: largest list over the mean list size), for comparing a
+    // retrained copy against the live index. None for index types that do not drift.
+    fn drift(&self) -> Option<f32> {
+        None
+    }
+
+    // Build a retrained copy of the index without touching this one, so searches can keep using
+    // it meanwhile. None for index types with nothing to retrain.
+    fn retrained(&self, _vectors: &dyn VectorReader) -> Option<Box<dyn VectorIndex>> {
+        None
+    }
+
     // Remove a vector from the index
     fn remove(&mut self, id: &Uuid);
 
+    // Ids of the live vectors in the index (deleted HNSW nodes excluded)
+    fn ids(&self) -> Vec<Uuid>;
+
+    // Whether deletes have left enough behind (HNSW tombstones past `tombstone_ratio` of the
+    // nodes) for a clean-up pass, or one is already under way
+    fn needs_repair(&self, _tombstone_ratio: f32) -> bool {
+        false
+    }
+
+    // One bounded step of that clean-up, touching at most `budget` entries. Returns the ids that
+    // were physically dropped once the pass is done, so callers can release their vectors, and
+    // None while work remains.
+    fn repair_step(&mut self, _vectors: &dyn VectorReader, _budget: usize) -> Option<Vec<Uuid>> {
+        Some(Vec::new())
+    }
+
     // Get index statistics
     fn stats(&self) -> IndexStats;
 
@@ -83,6 +148,10 @@ pub trait VectorIndex: Send + Sync {
 
     // Convert the index into a serializable form for persistence
     fn to_serializable(&self) -> SerializableIndex;
+
+    // Copy the index, for a writer that has to change it while a read view still holds it. Indexes
+    // keep their large tables in shared chunks, so this copies pointers, not entries
+    fn clone_box(&self) -> Box<dyn VectorIndex>;
 }
 
 // Statistics about an index
@@ -103,11 +172,24 @@ pub enum IndexDetails {
         max_layer: isize,        // Maximum layer in the HNSW graph
         layer_sizes: Vec<usize>, // Number of nodes in each layer
         avg_connections: f32,    // Average number of connections per node
+        #[serde(default)]
+        tombstones: usize, // Deleted nodes still kept in the graph, awaiting repair
     },
     Ivf {
         num_clusters: usize,             // Number of clusters in the IVF index
         vectors_per_cluster: Vec<usize>, // Number of vectors assigned to each cluster
         centroids_computed: bool,        // Whether centroids have been computed for the clusters
+        #[serde(default)]
+        imbalance_ratio: f32, // Largest list size over the mean list size (1.0 = balanced)
+        #[serde(default)]
+        empty_lists: usize, // Clusters with no vectors assigned
+    },
+    IvfPq {
+        num_clusters: usize,             // Number of coarse clusters
+        vectors_per_cluster: Vec<usize>, // Number of encoded vectors in each inverted list
+        subquantizers: usize,            // PQ subspaces, i.e. code bytes per vector
+        centroids_computed: bool,        // Whether the coarse centroids and codebooks are trained
+        rerank: bool,                    // Whether ADC candidates are re-scored exactly
     },
 }
 
@@ -120,6 +202,8 @@ pub enum IndexType {
     Hnsw,
     // Inverted File Index - O(√N), best for 10k-1M vectors
     Ivf,
+    // Inverted File Index with product-quantized residuals - compressed IVF for very large sets
+    IvfPq,
 }
 
 // better readability in logs and stats
@@ -129,24 +213,37 @@ impl std::fmt::Display for IndexType {
             IndexType::Flat => write!(f, "Flat"),
             IndexType::Hnsw => write!(f, "HNSW"),
             IndexType::Ivf => write!(f, "IVF"),
+            IndexType::IvfPq => write!(f, "IVF-PQ"),
         }
     }
 }
 
 // Wrapper for persisting any index type
+// bincode tags variants by position, so the legacy variants keep the first four slots and files
+// written before dense ids still load.
 #[derive(Serialize, Deserialize)]
 pub enum SerializableIndex {
+    LegacyFlat(crate::index::legacy::LegacyFlatIndex),
+    LegacyHnsw(crate::index::legacy::LegacyHnswIndex),
+    LegacyIvf(crate::index::legacy::LegacyIvfIndex),
+    LegacyIvfPq(crate::index::legacy::LegacyIvfPqIndex),
     Flat(crate::index::flat::FlatIndex),
     Hnsw(crate::index::hnsw::HnswIndex),
     Ivf(crate::index::ivf::IvfIndex),
+    IvfPq(crate::index::ivf_pq::IvfPqIndex),
 }
 // Implement a method to convert the SerializableIndex back into a trait object for use to persist the index state and later restore it while still using the unified VectorIndex interface for operations.
 impl SerializableIndex {
     pub fn to_trait_object(self) -> Box<dyn VectorIndex> {
         match self {
+            SerializableIndex::LegacyFlat(idx) => Box::new(FlatIndex::from(idx)),
+            SerializableIndex::LegacyHnsw(idx) => Box::new(HnswIndex::from(idx)),
+            SerializableIndex::LegacyIvf(idx) => Box::new(IvfIndex::from(idx)),
+            SerializableIndex::LegacyIvfPq(idx) => Box::new(IvfPqIndex::from(idx)),
             SerializableIndex::Flat(idx) => Box::new(idx),
             SerializableIndex::Hnsw(idx) => Box::new(idx),
             SerializableIndex::Ivf(idx) => Box::new(idx),
+            SerializableIndex::IvfPq(idx) => Box::new(idx),
         }
     }
 }
@@ -25,7 +25,7 @@ pub use embeddings::{EmbeddingConfig, EmbeddingError, EmbeddingProvider};
 pub use error::{ErrorContext, PiramidError, Result};
 pub use index::{
     FlatConfig, FlatIndex, HashMapVectorReader, HnswConfig, HnswIndex, IndexConfig, IndexStats,
-    IndexType, IvfConfig, IvfIndex, VectorIndex, VectorReader,
+    IndexType, IvfConfig, IvfIndex, IvfPqConfig, IvfPqIndex, VectorIndex, VectorReader,
 };
 pub use metadata::{metadata, Metadata, MetadataValue};
 pub use metrics::Metric;
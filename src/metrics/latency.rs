@@ -21,6 +21,8 @@ pub struct LatencyTracker {
     update_count: Arc<AtomicU64>,
     lock_read_count: Arc<AtomicU64>,
     lock_write_count: Arc<AtomicU64>,
+    // Reads served from a published read view, also counted as (near zero) lock reads
+    snapshot_read_count: Arc<AtomicU64>,
 }
 
 impl Default for LatencyTracker {
@@ -44,6 +46,7 @@ impl LatencyTracker {
             update_count: Arc::new(AtomicU64::new(0)),
             lock_read_count: Arc::new(AtomicU64::new(0)),
             lock_write_count: Arc::new(AtomicU64::new(0)),
+            snapshot_read_count: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -81,6 +84,12 @@ impl LatencyTracker {
         self.update_moving_average(&self.lock_read_latency_us, us, &self.lock_read_count);
     }
 
+    // A read that loaded a read view instead of taking the collection lock
+    pub fn record_snapshot_read(&self, duration: Duration) {
+        self.snapshot_read_count.fetch_add(1, Ordering::Relaxed);
+        self.record_lock_read(duration);
+    }
+
     pub fn record_lock_write(&self, duration: Duration) {
         self.lock_write_count.fetch_add(1, Ordering::Relaxed);
         let us = duration.as_micros() as u64;
@@ -136,6 +145,10 @@ impl LatencyTracker {
         }
     }
 
+    pub fn snapshot_reads(&self) -> u64 {
+        self.snapshot_read_count.load(Ordering::Relaxed)
+    }
+
     pub fn avg_lock_write_latency_ms(&self) -> Option<f32> {
         let us = self.lock_write_latency_us.load(Ordering::Relaxed);
         if us > 0 {
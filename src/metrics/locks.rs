@@ -8,6 +8,12 @@ pub fn record_lock_read(tracker: Option<&LatencyTracker>, start: Instant) {
     }
 }
 
+pub fn record_snapshot_read(tracker: Option<&LatencyTracker>, start: Instant) {
+    if let Some(tracker) = tracker {
+        tracker.record_snapshot_read(start.elapsed());
+    }
+}
+
 pub fn record_lock_write(tracker: Option<&LatencyTracker>, start: Instant) {
     if let Some(tracker) = tracker {
         tracker.record_lock_write(start.elapsed());
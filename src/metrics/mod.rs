@@ -10,7 +10,7 @@ pub use crate::compute::{
 };
 pub use embed::{EmbedMetrics, EmbedMetricsSnapshot};
 pub use latency::{time_operation, time_operation_sync, LatencyTracker};
-pub use locks::{record_lock_read, record_lock_write};
+pub use locks::{record_lock_read, record_lock_write, record_snapshot_read};
 
 use crate::config::ExecutionMode;
 
@@ -0,0 +1,30 @@
+// Background unloading of idle collections.
+//
+// With thousands of small collections, keeping every one that was ever opened in memory does not
+// scale. This task wakes every `idle.check_interval_secs` and unloads the collections that have
+// gone unused for `idle.unload_after_secs`; the next request for one reopens it from disk. Both
+// settings are read on every pass, so a config reload applies without a restart.
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use super::SharedState;
+
+/// Start the idle unloader on the current tokio runtime. It stops once the server shuts down.
+pub fn spawn_idle_unloader(state: SharedState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let interval = state.app_config.read().idle.check_interval_secs.max(1);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            if state.shutting_down.load(Ordering::Relaxed) {
+                break;
+            }
+            let pass_state = state.clone();
+            // Unloading checkpoints, which is blocking file I/O
+            if let Err(error) =
+                tokio::task::spawn_blocking(move || pass_state.unload_idle_collections()).await
+            {
+                tracing::warn!(error = %error, "idle_unload_pass_failed");
+            }
+        }
+    })
+}
@@ -0,0 +1,57 @@
+// Exclusive ownership of a data directory.
+//
+// The server and the offline subcommands that write collection files (`snapshot create|restore`,
+// `fsck --repair`) take the same advisory lock on `<data_dir>/.piramid.lock`, so a CLI run cannot
+// checkpoint or replace files under a live server. The lock is released when the process exits,
+// even if it is killed, so a stale lock file never blocks a restart.
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use crate::error::{PiramidError, Result};
+
+pub const DATA_DIR_LOCK_FILE: &str = ".piramid.lock";
+
+/// Held for as long as the data directory is in use; dropping it releases the lock.
+#[derive(Debug)]
+pub struct DataDirLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl DataDirLock {
+    /// Take the data directory's lock, failing at once if another process (or another
+    /// `DataDirLock` in this one) holds it.
+    pub fn acquire(data_dir: impl AsRef<Path>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(DATA_DIR_LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::fd::AsRawFd;
+            let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+            if rc != 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == std::io::ErrorKind::WouldBlock {
+                    return Err(PiramidError::other(format!(
+                        "data directory {} is in use by another piramid process; \
+                         stop the server or use the HTTP API",
+                        data_dir.display()
+                    )));
+                }
+                return Err(error.into());
+            }
+        }
+
+        Ok(DataDirLock { _file: file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,223 @@
+// Process-wide memory accounting.
+//
+// Every loaded collection reports what it holds (caches, indexes, resident data file pages, WAL
+// buffers) and the governor sums the reports against `hardware.memory_budget_bytes`. Over budget
+// it frees memory in three steps, stopping as soon as the total fits: unload idle collections,
+// least recently used first; evict cold vectors by capping how much of each collection's vector
+// arena stays in memory; and finally flag the process so writes fail with a 503 until memory is
+// freed, rather than growing until the kernel kills it.
+//
+// Collections locked by a request are skipped and keep their last report, so the governor can run
+// from a write path that still holds its own collection's lock.
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::collections::{CollectionManager, MemoryReport};
+
+#[derive(Default)]
+pub struct MemoryGovernor {
+    reports: DashMap<String, MemoryReport>,
+    exhausted: AtomicBool,
+    unloads: AtomicU64,
+    evicted_bytes: AtomicU64,
+}
+
+/// Memory held by all loaded collections against the process budget.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryUsage {
+    pub budget_bytes: Option<u64>,
+    pub total: MemoryReport,
+    /// Writes are being rejected until memory is freed.
+    pub exhausted: bool,
+    /// Collections unloaded to stay within the budget.
+    pub unloads: u64,
+    /// Vector bytes evicted to stay within the budget.
+    pub evicted_vector_bytes: u64,
+}
+
+impl MemoryGovernor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    pub fn usage(&self, budget_bytes: Option<u64>) -> MemoryUsage {
+        MemoryUsage {
+            budget_bytes,
+            total: self.total(),
+            exhausted: self.is_exhausted(),
+            unloads: self.unloads.load(Ordering::Relaxed),
+            evicted_vector_bytes: self.evicted_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Collect fresh reports from every loaded collection that isn't locked and return the total.
+    pub fn refresh(&self, manager: &CollectionManager) -> MemoryReport {
+        let loaded = manager.loaded_collections();
+        self.reports
+            .retain(|name, _| loaded.iter().any(|(loaded, _)| loaded == name));
+        for (name, handle) in loaded {
+            if let Some(guard) = handle.try_read() {
+                self.reports.insert(name, guard.memory_report());
+            }
+        }
+        self.total()
+    }
+
+    /// Bring the loaded collections back under `budget_bytes`, if set. `busy` names collections
+    /// that must stay loaded, e.g. ones with a running index job.
+    pub fn enforce(
+        &self,
+        manager: &CollectionManager,
+        budget_bytes: Option<u64>,
+        busy: impl Fn(&str) -> bool,
+    ) {
+        let Some(budget) = budget_bytes.map(|budget| budget as usize) else {
+            self.exhausted.store(false, Ordering::Relaxed);
+            self.lift_vector_caps(manager, usize::MAX);
+            return;
+        };
+        let mut total = self.refresh(manager).total_bytes();
+        if total <= budget {
+            self.exhausted.store(false, Ordering::Relaxed);
+            // Give capped collections back headroom below the high watermark
+            let low_watermark = budget - budget / 4;
+            if total < low_watermark {
+                self.lift_vector_caps(manager, low_watermark - total);
+            }
+            return;
+        }
+        tracing::warn!(
+            total_bytes = total,
+            budget_bytes = budget,
+            "memory_budget_exceeded"
+        );
+
+        for name in manager.least_recently_used() {
+            if total <= budget {
+                break;
+            }
+            if busy(&name) {
+                continue;
+            }
+            match manager.unload(&name) {
+                Ok(true) => {
+                    let freed = self
+                        .reports
+                        .remove(&name)
+                        .map_or(0, |(_, report)| report.total_bytes());
+                    total = total.saturating_sub(freed);
+                    self.unloads.fetch_add(1, Ordering::Relaxed);
+                    tracing::info!(
+                        collection = %name,
+                        freed_bytes = freed,
+                        total_bytes = total,
+                        "collection_unloaded_for_memory"
+                    );
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    tracing::warn!(collection = %name, error = %error, "collection_unload_failed");
+                }
+            }
+        }
+
+        if total > budget {
+            total = total.saturating_sub(self.evict_vectors(manager, total - budget));
+        }
+
+        let exhausted = total > budget;
+        if exhausted {
+            tracing::warn!(
+                total_bytes = total,
+                budget_bytes = budget,
+                "memory_budget_exhausted_rejecting_writes"
+            );
+        }
+        self.exhausted.store(exhausted, Ordering::Relaxed);
+    }
+
+    // Cap the in-memory vectors of the collections holding the most until `excess` bytes are
+    // freed; returns the bytes freed.
+    fn evict_vectors(&self, manager: &CollectionManager, excess: usize) -> usize {
+        let mut collections: Vec<_> = manager
+            .loaded_collections()
+            .into_iter()
+            .filter_map(|(name, handle)| {
+                let hot_bytes = handle.try_read()?.vector_tier_stats().hot_bytes;
+                Some((name, handle, hot_bytes))
+            })
+            .collect();
+        collections.sort_by_key(|collection| std::cmp::Reverse(collection.2));
+
+        let mut freed = 0;
+        for (name, handle, hot_bytes) in collections {
+            if freed >= excess || hot_bytes == 0 {
+                break;
+            }
+            let Some(mut guard) = handle.try_write() else {
+                continue;
+            };
+            let cap = hot_bytes.saturating_sub(excess - freed);
+            let released = guard.cap_vector_memory(Some(cap));
+            freed += released;
+            self.evicted_bytes
+                .fetch_add(released as u64, Ordering::Relaxed);
+            self.reports.insert(name.clone(), guard.memory_report());
+            tracing::debug!(
+                collection = %name,
+                vector_cap_bytes = cap,
+                freed_bytes = released,
+                "vector_memory_capped"
+            );
+        }
+        freed
+    }
+
+    // Share `headroom` bytes between the collections whose vectors were capped, dropping caps
+    // that would now cover all of a collection's vectors.
+    fn lift_vector_caps(&self, manager: &CollectionManager, headroom: usize) {
+        let capped: Vec<_> = manager
+            .loaded_collections()
+            .into_iter()
+            .filter(|(_, handle)| {
+                handle
+                    .try_read()
+                    .is_some_and(|guard| guard.vector_memory_cap().is_some())
+            })
+            .collect();
+        if capped.is_empty() {
+            return;
+        }
+        let share = headroom / capped.len();
+        for (_, handle) in capped {
+            let Some(mut guard) = handle.try_write() else {
+                continue;
+            };
+            let Some(cap) = guard.vector_memory_cap() else {
+                continue;
+            };
+            let stats = guard.vector_tier_stats();
+            let raised = cap.saturating_add(share);
+            if raised >= stats.hot_bytes + stats.cold_bytes {
+                guard.cap_vector_memory(None);
+            } else {
+                guard.cap_vector_memory(Some(raised));
+            }
+        }
+    }
+
+    fn total(&self) -> MemoryReport {
+        self.reports
+            .iter()
+            .fold(MemoryReport::default(), |total, report| MemoryReport {
+                cache_bytes: total.cache_bytes + report.cache_bytes,
+                index_bytes: total.index_bytes + report.index_bytes,
+                mmap_resident_bytes: total.mmap_resident_bytes + report.mmap_resident_bytes,
+                wal_buffer_bytes: total.wal_buffer_bytes + report.wal_buffer_bytes,
+            })
+    }
+}
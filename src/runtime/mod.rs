@@ -1,3 +1,9 @@
+pub mod idle;
+pub mod lock;
+pub mod memory;
 pub mod state;
 
-pub use state::{AppState, RebuildJobStatus, RebuildState, SharedState};
+pub use idle::spawn_idle_unloader;
+pub use lock::DataDirLock;
+pub use memory::{MemoryGovernor, MemoryUsage};
+pub use state::{AppState, IndexJob, RebuildJobStatus, RebuildState, SharedState};
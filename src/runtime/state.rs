@@ -13,6 +13,8 @@ use crate::embeddings::Embedder;
 use crate::error::{Result, ServerError};
 use crate::metrics::EmbedMetrics;
 
+use super::memory::{MemoryGovernor, MemoryUsage};
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum RebuildState {
     Running,
@@ -20,9 +22,32 @@ pub enum RebuildState {
     Failed,
 }
 
+// Which kind of index job a RebuildJobStatus tracks
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IndexJob {
+    Rebuild,
+    Retrain,
+    Migrate(crate::index::IndexType), // Online move to the index type the Auto selector picked
+    Compact,                          // Background compaction of the data file
+    Repair,                           // Stepwise removal of HNSW tombstones
+}
+
+impl IndexJob {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexJob::Rebuild => "rebuild",
+            IndexJob::Retrain => "retrain",
+            IndexJob::Migrate(_) => "migrate",
+            IndexJob::Compact => "compact",
+            IndexJob::Repair => "repair",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RebuildJobStatus {
     pub status: RebuildState, // Current status of the rebuild job (Running, Completed, Failed)
+    pub kind: IndexJob,       // Rebuild, retrain or migration
     pub started_at: u64, // Timestamp when the rebuild job started (in seconds since UNIX epoch)
     pub finished_at: Option<u64>, // Optional timestamp when the rebuild job finished (in seconds since UNIX epoch)
     pub error: Option<String>,    // Optional error message if the rebuild job failed
@@ -46,6 +71,7 @@ pub struct AppState {
     pub config_last_reload: Arc<AtomicU64>, // Timestamp of last config reload for cache invalidation
     pub disk_min_free_bytes: Option<u64>,
     pub disk_readonly_on_low_space: bool,
+    pub memory: MemoryGovernor, // Process-wide memory accounting against hardware.memory_budget_bytes
 }
 
 impl AppState {
@@ -78,6 +104,7 @@ impl AppState {
             )),
             disk_min_free_bytes,
             disk_readonly_on_low_space,
+            memory: MemoryGovernor::new(),
         })
     }
 
@@ -110,6 +137,7 @@ impl AppState {
             )),
             disk_min_free_bytes,
             disk_readonly_on_low_space,
+            memory: MemoryGovernor::new(),
         })
     }
 
@@ -180,7 +208,18 @@ impl AppState {
         }
     }
 
+    // Frees memory first if the last write was rejected for lack of it, so this may checkpoint and
+    // unload collections; async callers run it on a blocking thread.
     pub fn ensure_write_allowed(&self) -> Result<()> {
+        if self.memory.is_exhausted() {
+            // Memory may have been freed since the last write was rejected
+            self.enforce_memory_budget();
+        }
+        self.check_write_allowed()
+    }
+
+    // The checks of `ensure_write_allowed`, without trying to free memory
+    pub fn check_write_allowed(&self) -> Result<()> {
         if self.shutting_down.load(Ordering::Relaxed) {
             return Err(ServerError::ServiceUnavailable("Server is shutting down".into()).into());
         }
@@ -205,10 +244,84 @@ impl AppState {
                 }
             }
         }
+        if self.memory.is_exhausted() {
+            return Err(ServerError::ServiceUnavailable(
+                "Memory limit reached; write operations disabled until memory is freed".into(),
+            )
+            .into());
+        }
         Ok(())
     }
 
+    // Unload idle collections, evict cold vectors and, failing that, block writes while the loaded
+    // collections exceed hardware.memory_budget_bytes.
+    pub fn enforce_memory_budget(&self) {
+        let budget = self.app_config.read().hardware.memory_budget_bytes;
+        self.memory
+            .enforce(&self.collection_manager, budget, |name| {
+                self.rebuild_jobs
+                    .get(name)
+                    .is_some_and(|job| job.status == RebuildState::Running)
+            });
+    }
+
+    // Checkpoint and unload collections nobody has used for idle.unload_after_secs. Collections
+    // with a running index job, or held by a request, stay loaded. Returns the names unloaded.
+    pub fn unload_idle_collections(&self) -> Vec<String> {
+        let Some(after) = self.app_config.read().idle.unload_after_secs else {
+            return Vec::new();
+        };
+        let after = std::time::Duration::from_secs(after);
+        let mut unloaded = Vec::new();
+        for name in self.collection_manager.least_recently_used() {
+            let Some(idle) = self.collection_manager.idle_for(&name) else {
+                continue;
+            };
+            // Oldest first, so the rest were used more recently
+            if idle < after {
+                break;
+            }
+            let busy = self
+                .rebuild_jobs
+                .get(&name)
+                .is_some_and(|job| job.status == RebuildState::Running);
+            if busy {
+                continue;
+            }
+            match self.collection_manager.unload(&name) {
+                Ok(true) => {
+                    tracing::info!(
+                        collection = %name,
+                        idle_secs = idle.as_secs(),
+                        "idle_collection_unloaded"
+                    );
+                    unloaded.push(name);
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    tracing::warn!(collection = %name, error = %error, "collection_unload_failed");
+                }
+            }
+        }
+        unloaded
+    }
+
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory.refresh(&self.collection_manager);
+        self.memory
+            .usage(self.app_config.read().hardware.memory_budget_bytes)
+    }
+
+    // Called after writes: keep the caches within cache.max_bytes, then the whole process within
+    // its memory budget. May checkpoint and unload collections, so call it with no collection
+    // locked and, from async code, on a blocking thread.
     pub fn enforce_cache_budget(&self) {
+        self.evict_metadata_over_cache_budget();
+        self.enforce_memory_budget();
+    }
+
+    // Collections locked by the caller (or anyone else) are skipped rather than waited on.
+    fn evict_metadata_over_cache_budget(&self) {
         let cache_config = self.current_config().cache;
         if !cache_config.enabled {
             return;
@@ -221,11 +334,14 @@ impl AppState {
         let mut total: u64 = 0;
         let mut collections = Vec::new();
         for (name, storage) in self.collection_manager.loaded_collections() {
-            let guard = storage.read();
+            let Some(guard) = storage.try_read() else {
+                continue;
+            };
             let cache_bytes = guard.cache_usage_bytes();
             let metadata_bytes = guard.metadata_cache_usage_bytes();
+            drop(guard);
             total = total.saturating_add(cache_bytes as u64);
-            collections.push((name, storage.clone(), metadata_bytes));
+            collections.push((name, storage, metadata_bytes));
         }
 
         if total > max_bytes {
@@ -240,7 +356,9 @@ impl AppState {
                 if total <= max_bytes || metadata_bytes == 0 {
                     break;
                 }
-                let mut guard = storage.write();
+                let Some(mut guard) = storage.try_write() else {
+                    continue;
+                };
                 let freed = guard.clear_metadata_cache() as u64;
                 total = total.saturating_sub(freed);
                 tracing::debug!(
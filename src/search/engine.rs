@@ -1,13 +1,15 @@
 // Unified search engine for collections.
 // Wraps vector index search + scoring and optional metadata filtering.
 
-use crate::collections::Collection;
-use crate::config::ExecutionMode;
+use uuid::Uuid;
+
+use crate::cache::ResultCache;
+use crate::config::{CollectionConfig, ExecutionMode};
 use crate::error::Result;
+use crate::index::{MetadataReader, VectorIndex, VectorReader};
 use crate::metrics::Metric;
 use crate::search::{query::Filter, utils::sort_and_truncate, Hit};
-use std::collections::HashMap;
-use uuid::Uuid;
+use crate::storage::document::Document;
 
 // Parameters for a search request.
 #[derive(Debug, Clone, Copy)]
@@ -29,18 +31,30 @@ impl Default for SearchParams<'_> {
     }
 }
 
-fn search_collection_with_maps(
-    storage: &Collection,
+// What a search reads from: a collection, or a read view it published
+pub trait SearchSource: Sync {
+    fn config(&self) -> &CollectionConfig;
+    fn vector_index(&self) -> &dyn VectorIndex;
+    fn vector_reader(&self) -> &dyn VectorReader;
+    fn metadata_reader(&self) -> &dyn MetadataReader;
+    fn document(&self, id: &Uuid) -> Result<Option<Document>>;
+    // Cache the source's results go in, keyed on the write generation its data is at
+    fn result_cache(&self) -> &ResultCache;
+    fn generation(&self) -> u64;
+}
+
+fn search_collection_with_maps<S: SearchSource + ?Sized>(
+    storage: &S,
     query: &[f32],
     k: usize,
     metric: Metric,
     params: SearchParams<'_>,
-    metadatas: &HashMap<Uuid, crate::metadata::Metadata>,
+    metadatas: &dyn MetadataReader,
 ) -> Result<Vec<Hit>> {
     // 1. Determine effective search config and overfetch factor
     let effective_search = params
         .search_config_override
-        .unwrap_or(storage.config.search);
+        .unwrap_or(storage.config().search);
 
     // 2. Calculate overfetch factor based on filter presence and configuration. If a filter is applied, we need to overfetch more results from the vector index to ensure that after filtering we still have enough results to return. The overfetch factor is determined by the search configuration's filter_overfetch parameter, which specifies how many times more results to fetch compared to k when a filter is applied. If no filter is present, we can just fetch k results directly.
     let base_overfetch = effective_search.filter_overfetch.max(1);
@@ -73,7 +87,7 @@ fn search_collection_with_maps(
 
     // 5. For each candidate ID returned by the vector index search, retrieve the corresponding vector and metadata from storage, calculate the similarity score using the specified metric, and construct a Hit object that includes the ID, score, text, vector, and metadata. This step involves looking up each candidate ID in the storage to get the full information needed to return to the caller. The similarity score is calculated using the configured metric (e.g., cosine similarity), which takes into account the query vector and the candidate vector.
     for id in neighbor_ids {
-        let entry = storage.get(&id)?.ok_or_else(|| {
+        let entry = storage.document(&id)?.ok_or_else(|| {
             crate::error::IndexError::SearchFailed(format!("index returned missing document {id}"))
         })?;
         let vec = entry.try_get_vector()?;
@@ -97,26 +111,26 @@ fn search_collection_with_maps(
     }
 }
 
-pub fn search_collection(
-    storage: &Collection,
+pub fn search_collection<S: SearchSource + ?Sized>(
+    storage: &S,
     query: &[f32],
     k: usize,
     metric: Metric,
     params: SearchParams<'_>,
 ) -> Result<Vec<Hit>> {
     // Get vectors and metadatas from storage to pass to the search function. This allows us to perform the search using the vector index while also having access to the metadata for filtering and constructing the Hit objects. The search_collection_with_maps function is then called with these maps to perform the actual search and return the results.
-    let metadatas = storage.metadata_view();
+    let metadatas = storage.metadata_reader();
     search_collection_with_maps(storage, query, k, metric, params, metadatas)
 }
 
-pub fn search_batch_collection(
-    storage: &Collection,
+pub fn search_batch_collection<S: SearchSource + ?Sized>(
+    storage: &S,
     queries: &[Vec<f32>],
     k: usize,
     metric: Metric,
     params: SearchParams<'_>,
 ) -> Result<Vec<Vec<Hit>>> {
-    let metadatas = storage.metadata_view();
+    let metadatas = storage.metadata_reader();
 
     if storage.config().parallelism.parallel_search {
         use rayon::prelude::*; // If parallel search is enabled in the configuration, we use Rayon to perform the searches for each query in parallel. This can significantly speed up batch searches when there are multiple queries and the underlying hardware supports parallel execution. Each query is processed independently, and the results are collected into a vector of vectors of hits, where each inner vector corresponds to the results for a single query.
@@ -11,6 +11,6 @@ mod types;
 pub mod utils;
 
 pub use crate::metrics::Metric;
-pub use engine::{search_batch_collection, search_collection, SearchParams};
+pub use engine::{search_batch_collection, search_collection, SearchParams, SearchSource};
 pub use query::{Filter, FilterCondition};
 pub use types::Hit;
@@ -56,6 +56,13 @@ pub async fn rebuild_index(
     collection::rebuild_index(&state, collection).map(Json)
 }
 
+pub async fn retrain_index(
+    State(state): State<SharedState>,
+    Path(collection): Path<String>,
+) -> Result<Json<RebuildIndexResponse>> {
+    collection::retrain_index(&state, collection).map(Json)
+}
+
 pub async fn find_duplicates(
     State(state): State<SharedState>,
     Path(collection): Path<String>,
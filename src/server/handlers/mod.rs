@@ -4,6 +4,7 @@ pub mod config;
 pub mod embeddings;
 pub mod health;
 pub mod ready;
+pub mod snapshots;
 pub mod vectors;
 pub mod version;
 
@@ -13,5 +14,6 @@ pub use config::*;
 pub use embeddings::*;
 pub use health::*;
 pub use ready::*;
+pub use snapshots::*;
 pub use vectors::*;
 pub use version::*;
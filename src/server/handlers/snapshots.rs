@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Json, Response},
+};
+use tokio::io::AsyncWriteExt;
+use tower_http::services::ServeFile;
+
+use crate::collections::RecoveryTarget;
+use crate::error::{Result, ServerError};
+use crate::runtime::SharedState;
+use crate::server::types::*;
+use crate::services::snapshot::{self, RestoreSource};
+
+pub async fn create_snapshot(
+    State(state): State<SharedState>,
+    Path(collection): Path<String>,
+) -> Result<Json<SnapshotInfo>> {
+    // Checkpointing and archiving do blocking file I/O
+    tokio::task::spawn_blocking(move || snapshot::create_snapshot(&state, collection))
+        .await
+        .map_err(|e| ServerError::Internal(format!("snapshot task failed: {e}")))?
+        .map(Json)
+}
+
+pub async fn list_snapshots(
+    State(state): State<SharedState>,
+    Path(collection): Path<String>,
+) -> Result<Json<SnapshotsResponse>> {
+    snapshot::list_snapshots(&state, collection).map(Json)
+}
+
+// The archive is streamed from disk (with range support) rather than read into memory
+pub async fn download_snapshot(
+    State(state): State<SharedState>,
+    Path((collection, snapshot_id)): Path<(String, String)>,
+    request: Request,
+) -> Result<Response> {
+    let path = snapshot::snapshot_file(&state, &collection, &snapshot_id)?;
+    let mut response = ServeFile::new(path).try_call(request).await?.map(Body::new);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-tar"),
+    );
+    let disposition = format!("attachment; filename=\"{}.snapshot\"", snapshot_id);
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    Ok(response.into_response())
+}
+
+pub async fn delete_snapshot(
+    State(state): State<SharedState>,
+    Path((collection, snapshot_id)): Path<(String, String)>,
+) -> Result<Json<DeleteResponse>> {
+    snapshot::delete_snapshot(&state, collection, snapshot_id).map(Json)
+}
+
+// A JSON body names a stored snapshot and optionally a recovery point; any other body is an
+// uploaded archive, restored as it is
+pub async fn restore_collection(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<RestoreResponse>> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let mut upload = None;
+    let (source, target) = if is_json {
+        let body = axum::body::to_bytes(body, RESTORE_REQUEST_LIMIT)
+            .await
+            .map_err(|e| ServerError::InvalidRequest(format!("Invalid restore request: {e}")))?;
+        let req: RestoreRequest = serde_json::from_slice(&body)
+            .map_err(|e| ServerError::InvalidRequest(format!("Invalid restore request: {e}")))?;
+        let target = match (req.until_seq, req.until_time) {
+            (Some(_), Some(_)) => {
+                return Err(ServerError::InvalidRequest(
+                    "Set at most one of until_seq and until_time".into(),
+                )
+                .into())
+            }
+            (Some(seq), None) => Some(RecoveryTarget::Seq(seq)),
+            (None, Some(time)) => Some(RecoveryTarget::Time(time)),
+            (None, None) => None,
+        };
+        let source = RestoreSource::Stored {
+            collection: req.collection.unwrap_or_else(|| name.clone()),
+            snapshot: req.snapshot,
+        };
+        (source, target)
+    } else {
+        let file = spool_upload(&state, body).await?;
+        let source = RestoreSource::File(file.0.clone());
+        upload = Some(file);
+        (source, None)
+    };
+
+    let restored = tokio::task::spawn_blocking(move || {
+        snapshot::restore_collection(&state, name, source, target)
+    })
+    .await
+    .map_err(|e| ServerError::Internal(format!("restore task failed: {e}")))?;
+    drop(upload);
+    restored.map(Json)
+}
+
+// A JSON restore request is a few names and numbers
+const RESTORE_REQUEST_LIMIT: usize = 64 * 1024;
+
+// An uploaded archive spooled to disk; removed once the restore is done, or when the request is
+// dropped part way through the upload
+struct SpooledUpload(PathBuf);
+
+impl Drop for SpooledUpload {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Stream the request body into a file under the snapshot directory, one frame at a time
+async fn spool_upload(state: &SharedState, mut body: Body) -> Result<SpooledUpload> {
+    let path = snapshot::upload_file(state);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let upload = SpooledUpload(path);
+    let mut file = tokio::fs::File::create(&upload.0).await?;
+    let mut len = 0usize;
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        let frame = frame
+            .map_err(|e| ServerError::InvalidRequest(format!("Failed to read upload: {e}")))?;
+        if let Ok(data) = frame.into_data() {
+            len += data.len();
+            file.write_all(&data).await?;
+        }
+    }
+    file.flush().await?;
+    if len == 0 {
+        return Err(ServerError::InvalidRequest(
+            "Expected a snapshot archive or a JSON restore request".into(),
+        )
+        .into());
+    }
+    Ok(upload)
+}
@@ -15,7 +15,9 @@ pub async fn insert_vector(
     Path(collection): Path<String>,
     Json(req): Json<InsertRequest>,
 ) -> Result<Json<InsertResultsResponse>> {
-    vector::insert_vector(&state, collection, req).map(Json)
+    vector::insert_vector(&state, collection, req)
+        .await
+        .map(Json)
 }
 
 pub async fn get_vector(
@@ -37,7 +39,9 @@ pub async fn delete_vector(
     State(state): State<SharedState>,
     Path((collection, id)): Path<(String, String)>,
 ) -> Result<Json<DeleteResultsResponse>> {
-    vector::delete_vector(&state, collection, id).map(Json)
+    vector::delete_vector(&state, collection, id)
+        .await
+        .map(Json)
 }
 
 pub async fn delete_vectors(
@@ -45,7 +49,9 @@ pub async fn delete_vectors(
     Path(collection): Path<String>,
     Json(req): Json<DeleteVectorsRequest>,
 ) -> Result<Json<DeleteResultsResponse>> {
-    vector::delete_vectors(&state, collection, req).map(Json)
+    vector::delete_vectors(&state, collection, req)
+        .await
+        .map(Json)
 }
 
 pub async fn search_vectors(
@@ -62,7 +68,9 @@ pub async fn upsert_vector(
     Path(collection): Path<String>,
     Json(req): Json<UpsertRequest>,
 ) -> Result<Json<UpsertResponse>> {
-    vector::upsert_vector(&state, collection, req).map(Json)
+    vector::upsert_vector(&state, collection, req)
+        .await
+        .map(Json)
 }
 
 pub async fn range_search_vectors(
@@ -45,6 +45,10 @@ fn api_router(state: SharedState) -> Router<SharedState> {
             "/collections/{collection}/index/rebuild/status",
             get(handlers::rebuild_index_status),
         )
+        .route(
+            "/collections/{collection}/index/retrain",
+            post(handlers::retrain_index),
+        )
         .route(
             "/collections/{collection}/compact",
             post(handlers::compact_collection),
@@ -53,6 +57,27 @@ fn api_router(state: SharedState) -> Router<SharedState> {
             "/collections/{collection}/duplicates",
             post(handlers::find_duplicates),
         )
+        // Snapshots and restore
+        .route(
+            "/collections/{collection}/snapshots",
+            post(handlers::create_snapshot),
+        )
+        .route(
+            "/collections/{collection}/snapshots",
+            get(handlers::list_snapshots),
+        )
+        .route(
+            "/collections/{collection}/snapshots/{snapshot}",
+            get(handlers::download_snapshot),
+        )
+        .route(
+            "/collections/{collection}/snapshots/{snapshot}",
+            delete(handlers::delete_snapshot),
+        )
+        .route(
+            "/collections/{collection}/restore",
+            post(handlers::restore_collection),
+        )
         // Config hot reload/status
         .route("/config", get(handlers::config_status))
         .route("/config/reload", post(handlers::reload_config))
@@ -9,11 +9,14 @@ pub struct HealthResponse {
 #[derive(Serialize)]
 pub struct MetricsResponse {
     pub total_collections: usize,
+    pub collections_opened: u64, // Opened from disk since startup, reopens included
+    pub collections_unloaded: u64, // Unloaded when idle or to free memory since startup
     pub total_vectors: usize,
     pub collections: Vec<CollectionMetrics>,
     pub app_config: crate::config::AppConfig,
     pub wal_stats: Vec<WalStats>,
     pub embedding: EmbeddingMetricsResponse,
+    pub memory: MemoryMetricsResponse,
 }
 
 #[derive(Serialize)]
@@ -26,9 +29,26 @@ pub struct CollectionMetrics {
     pub search_latency_ms: Option<f32>,
     pub lock_read_ms: Option<f32>,
     pub lock_write_ms: Option<f32>,
+    pub snapshot_reads: u64, // Reads served from a published read view without the collection lock
     pub search_overfetch: Option<usize>,
     pub hnsw_ef_search: Option<usize>,
     pub ivf_nprobe: Option<usize>,
+    pub live_bytes: u64, // Data file bytes held by current records
+    pub dead_bytes: u64, // Data file bytes compaction would reclaim
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_budget_bytes: Option<usize>, // Memory the hot vectors may take
+    pub hot_vector_bytes: usize, // Vector bytes held in memory
+    pub cold_vector_bytes: usize, // Vector bytes left in the arena file
+    pub vector_hits: u64, // Vector reads served from memory
+    pub vector_misses: u64, // Vector reads that faulted pages in from disk
+    pub vector_evictions: u64, // Pages dropped to stay within the budget
+    pub metadata_cache_entries: usize,
+    pub metadata_cache_hits: u64,
+    pub metadata_cache_misses: u64, // Lookups that read the record store instead
+    pub metadata_cache_evictions: u64, // Entries pushed out by max_size or expired by ttl_seconds
+    pub result_cache_entries: usize,
+    pub result_cache_hits: u64,
+    pub result_cache_misses: u64, // Searches that ran, including ones whose entry was stale
 }
 
 #[derive(Serialize)]
@@ -37,6 +57,14 @@ pub struct WalStats {
     pub last_checkpoint: Option<u64>,
     pub checkpoint_age_secs: Option<u64>,
     pub wal_size_bytes: Option<u64>,
+    pub segments: Vec<WalSegmentStats>,
+}
+
+#[derive(Serialize)]
+pub struct WalSegmentStats {
+    pub file: String,
+    pub first_seq: u64,
+    pub size_bytes: u64,
 }
 
 #[derive(Serialize)]
@@ -48,6 +76,20 @@ pub struct EmbeddingMetricsResponse {
     pub avg_latency_ms: Option<f32>,
 }
 
+#[derive(Serialize)]
+pub struct MemoryMetricsResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_bytes: Option<u64>, // hardware.memory_budget_bytes
+    pub total_bytes: usize,
+    pub cache_bytes: usize,
+    pub index_bytes: usize,
+    pub mmap_resident_bytes: usize,
+    pub wal_buffer_bytes: usize,
+    pub writes_blocked: bool, // Writes fail with 503 until memory is freed
+    pub unloaded_collections: u64,
+    pub evicted_vector_bytes: u64,
+}
+
 #[derive(Serialize)]
 pub struct ConfigStatusResponse {
     pub app_config: crate::config::AppConfig,
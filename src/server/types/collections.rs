@@ -69,6 +69,9 @@ pub struct RebuildIndexResponse {
 #[derive(Serialize)]
 pub struct RebuildIndexStatusResponse {
     pub status: String,
+    pub job: String, // rebuild, retrain or migrate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_index: Option<String>, // Index type a migration is moving to
     #[serde(skip_serializing_if = "Option::is_none")]
     pub started_at: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -4,6 +4,7 @@ pub mod common;
 pub mod embeddings;
 pub mod range;
 pub mod search;
+pub mod snapshots;
 pub mod vectors;
 
 pub use admin::*;
@@ -11,4 +12,5 @@ pub use collections::*;
 pub use common::*;
 pub use embeddings::*;
 pub use search::*;
+pub use snapshots::*;
 pub use vectors::*;
@@ -38,6 +38,8 @@ pub struct SearchResponse {
     pub results: Vec<HitResponse>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub latency_ms: Option<f32>,
+    // Whether the results came from the collection's result cache
+    pub cache_hit: bool,
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub collection: String,
+    pub created_at: u64,
+    pub size_bytes: u64,
+    pub vector_count: usize,
+    pub last_checkpoint_seq: u64,
+}
+
+#[derive(Serialize)]
+pub struct SnapshotsResponse {
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
+// Restore from a snapshot already stored on the server. An uploaded archive is sent as the raw
+// request body instead.
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    pub snapshot: String,
+    #[serde(default)]
+    pub collection: Option<String>, // Collection the snapshot was taken of, defaults to the target
+    // Point-in-time recovery: replay the source collection's WAL up to a seq or a unix time
+    #[serde(default)]
+    pub until_seq: Option<u64>,
+    #[serde(default)]
+    pub until_time: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct RestoreResponse {
+    pub name: String,
+    pub source_collection: String,
+    pub snapshot_created_at: u64,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovered_seq: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f32>,
+}
@@ -70,16 +70,17 @@ pub fn metrics(state: &SharedState) -> Result<MetricsResponse> {
         let count = collection_guard.count();
         let index_type = collection_guard.vector_index().index_type().to_string();
         let memory_usage_bytes = collection_guard.memory_usage_bytes();
-        let (insert_latency_ms, search_latency_ms, lock_read_ms, lock_write_ms) =
+        let (insert_latency_ms, search_latency_ms, lock_read_ms, lock_write_ms, snapshot_reads) =
             if let Some(tracker) = state.collection_manager.tracker(&collection_name) {
                 (
                     tracker.avg_insert_latency_ms(),
                     tracker.avg_search_latency_ms(),
                     tracker.avg_lock_read_latency_ms(),
                     tracker.avg_lock_write_latency_ms(),
+                    tracker.snapshot_reads(),
                 )
             } else {
-                (None, None, None, None)
+                (None, None, None, None, 0)
             };
 
         total_vectors += count;
@@ -95,9 +96,16 @@ pub fn metrics(state: &SharedState) -> Result<MetricsResponse> {
             } => (Some(search.filter_overfetch), Some(*ef_search), None),
             crate::index::IndexConfig::Ivf {
                 num_probes, search, ..
+            }
+            | crate::index::IndexConfig::IvfPq {
+                num_probes, search, ..
             } => (Some(search.filter_overfetch), None, Some(*num_probes)),
         };
 
+        let storage_usage = collection_guard.storage_usage();
+        let vector_tier = collection_guard.vector_tier_stats();
+        let metadata_cache = collection_guard.metadata_cache_stats();
+        let result_cache = collection_guard.result_cache_stats();
         collection_metrics.push(CollectionMetrics {
             name: collection_name,
             vector_count: count,
@@ -107,12 +115,39 @@ pub fn metrics(state: &SharedState) -> Result<MetricsResponse> {
             search_latency_ms,
             lock_read_ms,
             lock_write_ms,
+            snapshot_reads,
             search_overfetch,
             hnsw_ef_search,
             ivf_nprobe,
+            live_bytes: storage_usage.live_bytes,
+            dead_bytes: storage_usage.dead_bytes,
+            vector_budget_bytes: vector_tier.budget_bytes,
+            hot_vector_bytes: vector_tier.hot_bytes,
+            cold_vector_bytes: vector_tier.cold_bytes,
+            vector_hits: vector_tier.hits,
+            vector_misses: vector_tier.misses,
+            vector_evictions: vector_tier.evictions,
+            metadata_cache_entries: metadata_cache.entries,
+            metadata_cache_hits: metadata_cache.hits,
+            metadata_cache_misses: metadata_cache.misses,
+            metadata_cache_evictions: metadata_cache.evictions + metadata_cache.expirations,
+            result_cache_entries: result_cache.entries,
+            result_cache_hits: result_cache.hits,
+            result_cache_misses: result_cache.misses,
         });
 
-        let wal_size = optional_file_size(&format!("{}.wal.db", collection_guard.path))?;
+        let wal = &collection_guard.checkpoint.wal;
+        let segments: Vec<WalSegmentStats> = wal
+            .segments()
+            .iter()
+            .map(|segment| WalSegmentStats {
+                file: segment.path.display().to_string(),
+                first_seq: segment.first_seq,
+                size_bytes: wal.segment_bytes(segment),
+            })
+            .collect();
+        let wal_size =
+            (!segments.is_empty()).then(|| segments.iter().map(|segment| segment.size_bytes).sum());
         let checkpoint_age_secs = collection_guard
             .checkpoint
             .last_checkpoint()
@@ -122,12 +157,16 @@ pub fn metrics(state: &SharedState) -> Result<MetricsResponse> {
             last_checkpoint: collection_guard.checkpoint.last_checkpoint(),
             checkpoint_age_secs,
             wal_size_bytes: wal_size,
+            segments,
         });
     }
 
     let embed_metrics = state.embed_metrics.snapshot();
+    let memory = state.memory_usage();
     Ok(MetricsResponse {
         total_collections: state.collection_manager.len(),
+        collections_opened: state.collection_manager.opens(),
+        collections_unloaded: state.collection_manager.unloads(),
         total_vectors,
         collections: collection_metrics,
         app_config: state.current_config(),
@@ -138,6 +177,17 @@ pub fn metrics(state: &SharedState) -> Result<MetricsResponse> {
             total_tokens: embed_metrics.total_tokens,
             avg_latency_ms: embed_metrics.avg_latency_ms,
         },
+        memory: MemoryMetricsResponse {
+            budget_bytes: memory.budget_bytes,
+            total_bytes: memory.total.total_bytes(),
+            cache_bytes: memory.total.cache_bytes,
+            index_bytes: memory.total.index_bytes,
+            mmap_resident_bytes: memory.total.mmap_resident_bytes,
+            wal_buffer_bytes: memory.total.wal_buffer_bytes,
+            writes_blocked: memory.exhausted,
+            unloaded_collections: memory.unloads,
+            evicted_vector_bytes: memory.evicted_vector_bytes,
+        },
     })
 }
 
@@ -160,7 +210,8 @@ pub fn readyz(state: &SharedState) -> Result<ReadyzResponse> {
         let last_checkpoint = collection_guard.checkpoint.last_checkpoint();
         let checkpoint_age_secs =
             last_checkpoint.and_then(|timestamp| current_unix_secs().ok()?.checked_sub(timestamp));
-        let wal_size_bytes = optional_file_size(&format!("{}.wal.db", collection_guard.path))?;
+        let wal = &collection_guard.checkpoint.wal;
+        let wal_size_bytes = (!wal.segments().is_empty()).then(|| wal.size_bytes());
 
         collections.push(CollectionHealth {
             name,
@@ -223,14 +274,6 @@ pub fn readyz(state: &SharedState) -> Result<ReadyzResponse> {
     })
 }
 
-fn optional_file_size(path: &str) -> Result<Option<u64>> {
-    match std::fs::metadata(path) {
-        Ok(metadata) => Ok(Some(metadata.len())),
-        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
-        Err(error) => Err(error.into()),
-    }
-}
-
 fn current_unix_secs() -> Result<u64> {
     Ok(SystemTime::now()
         .duration_since(UNIX_EPOCH)
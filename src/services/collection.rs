@@ -1,10 +1,13 @@
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use crate::collections::CollectionHandle;
 use crate::error::{Result, ServerError};
+use crate::index::IndexType;
 use crate::metrics::record_lock_read;
-use crate::runtime::{RebuildJobStatus, RebuildState, SharedState};
+use crate::runtime::{IndexJob, RebuildJobStatus, RebuildState, SharedState};
 use crate::server::types::*;
 use crate::validation;
+use crate::Collection;
 
 fn ensure_available(state: &SharedState) -> Result<()> {
     if state
@@ -27,17 +30,35 @@ fn collection_info(name: String, collection: &crate::Collection) -> CollectionIn
     }
 }
 
+// Every collection in the data dir. Loaded ones report their live state; the rest report what
+// their last checkpoint recorded, without being opened.
 pub fn list_collections(state: &SharedState) -> Result<CollectionsResponse> {
     ensure_available(state)?;
 
+    let manager = &state.collection_manager;
+    let mut loaded: std::collections::HashMap<String, CollectionHandle> =
+        manager.loaded_collections().into_iter().collect();
+    let mut names: std::collections::BTreeSet<String> =
+        manager.names_on_disk()?.into_iter().collect();
+    names.extend(loaded.keys().cloned());
+
     let mut collections = Vec::new();
-    for (name, collection_handle) in state.collection_manager.loaded_collections() {
+    for name in names {
+        let Some(collection_handle) = loaded.remove(&name) else {
+            let path = manager.collection_path(&name);
+            let meta = crate::storage::persistence::load_checkpoint_metadata(&path)?;
+            collections.push(CollectionInfo {
+                name,
+                count: meta.as_ref().map_or(0, |meta| meta.vector_count),
+                created_at: meta.as_ref().map(|meta| meta.created_at),
+                updated_at: meta.as_ref().map(|meta| meta.updated_at),
+                dimensions: meta.and_then(|meta| meta.dimensions),
+            });
+            continue;
+        };
         let lock_start = Instant::now();
         let collection_guard = collection_handle.read();
-        record_lock_read(
-            state.collection_manager.tracker(&name).as_deref(),
-            lock_start,
-        );
+        record_lock_read(manager.tracker(&name).as_deref(), lock_start);
         collections.push(collection_info(name, &collection_guard));
     }
 
@@ -74,30 +95,29 @@ pub fn get_collection(state: &SharedState, collection: String) -> Result<Collect
     Ok(collection_info(collection, &collection_guard))
 }
 
+// Delete a collection's files whether or not it is loaded; idle or memory-pressure unloads leave
+// collections on disk only.
 pub fn delete_collection(state: &SharedState, collection: String) -> Result<DeleteResponse> {
     ensure_available(state)?;
-
-    let existed = state.collection_manager.remove(&collection).is_some();
+    validation::validate_collection_name(&collection)?;
+
+    let path = state.collection_manager.collection_path(&collection);
+    let removed = state.collection_manager.remove(&collection);
+    let existed = removed.is_some() || std::path::Path::new(&path).exists();
+    if let Some(handle) = removed {
+        // Index jobs still holding the handle check this before they write anything back
+        handle.write().mark_dropped();
+    }
     if existed {
-        for suffix in [
-            "",
-            ".index.db",
-            ".metadata.db",
-            ".vecindex.db",
-            ".wal.db",
-            ".wal.meta",
-        ] {
-            let path = if suffix.is_empty() {
-                format!("{}/{}.db", state.data_dir, collection)
-            } else {
-                format!("{}/{}.db{}", state.data_dir, collection, suffix)
-            };
-            match std::fs::remove_file(&path) {
-                Ok(()) => {}
-                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
-                Err(error) => return Err(error.into()),
-            }
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
         }
+        crate::storage::persistence::remove_checkpoint_files(&path)?;
+        let wal_base = format!("{}.wal.db", path);
+        crate::storage::wal::remove_wal_files(std::path::Path::new(&wal_base))?;
+        crate::storage::wal::remove_wal_archive(std::path::Path::new(&wal_base))?;
     }
 
     Ok(DeleteResponse {
@@ -146,6 +166,132 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
     ensure_available(state)?;
 
     let collection_handle = state.get_existing_collection(&collection)?;
+    spawn_index_job(
+        state,
+        collection,
+        collection_handle,
+        IndexJob::Rebuild,
+        |handle| handle.write().rebuild_index(),
+    );
+
+    Ok(RebuildIndexResponse {
+        success: true,
+        latency_ms: None,
+    })
+}
+
+// Manual retrain (IVF k-means plus list rebalancing), tracked like a rebuild job
+pub fn retrain_index(state: &SharedState, collection: String) -> Result<RebuildIndexResponse> {
+    ensure_available(state)?;
+
+    let collection_handle = state.get_existing_collection(&collection)?;
+    spawn_index_job(
+        state,
+        collection,
+        collection_handle,
+        IndexJob::Retrain,
+        |handle| crate::collections::retrain_index(handle, true).map(|_| ()),
+    );
+
+    Ok(RebuildIndexResponse {
+        success: true,
+        latency_ms: None,
+    })
+}
+
+// Background index work a write can make due
+pub enum IndexMaintenance {
+    // The Auto selector has outgrown the live index type
+    Migrate(IndexType),
+    // The live index reports drift (unbalanced IVF lists)
+    Retrain,
+    // Dead records in the data file crossed the compaction thresholds
+    Compact,
+    // Deleted HNSW nodes crossed the tombstone ratio
+    Repair,
+}
+
+pub fn index_maintenance_due(collection: &Collection) -> Option<IndexMaintenance> {
+    if let Some(target) = collection.index_migration_target() {
+        return Some(IndexMaintenance::Migrate(target));
+    }
+    // Compaction builds a fresh index, which retrains it too
+    if crate::collections::compaction_due(collection) {
+        return Some(IndexMaintenance::Compact);
+    }
+    if collection.retrain_due() {
+        return Some(IndexMaintenance::Retrain);
+    }
+    let tombstone_ratio = collection.config.maintenance.tombstone_ratio;
+    collection
+        .vector_index()
+        .needs_repair(tombstone_ratio)
+        .then_some(IndexMaintenance::Repair)
+}
+
+// Start due index maintenance in the background, unless an index job for the collection is
+// already running. Call after the collection write lock has been released.
+pub fn schedule_index_maintenance(
+    state: &SharedState,
+    collection: &str,
+    handle: &CollectionHandle,
+    maintenance: IndexMaintenance,
+) {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return;
+    }
+    let running = state
+        .rebuild_jobs
+        .get(collection)
+        .is_some_and(|job| job.status == RebuildState::Running);
+    if running {
+        return;
+    }
+    match maintenance {
+        IndexMaintenance::Migrate(target) => spawn_index_job(
+            state,
+            collection.to_string(),
+            handle.clone(),
+            IndexJob::Migrate(target),
+            |handle| crate::collections::migrate_index(handle).map(|_| ()),
+        ),
+        IndexMaintenance::Retrain => spawn_index_job(
+            state,
+            collection.to_string(),
+            handle.clone(),
+            IndexJob::Retrain,
+            |handle| crate::collections::retrain_index(handle, false).map(|_| ()),
+        ),
+        IndexMaintenance::Compact => spawn_index_job(
+            state,
+            collection.to_string(),
+            handle.clone(),
+            IndexJob::Compact,
+            |handle| {
+                let throttle = handle.read().config.compaction.max_bytes_per_sec;
+                crate::collections::compact_online(handle, throttle).map(|_| ())
+            },
+        ),
+        IndexMaintenance::Repair => spawn_index_job(
+            state,
+            collection.to_string(),
+            handle.clone(),
+            IndexJob::Repair,
+            |handle| crate::collections::repair_index(handle).map(|_| ()),
+        ),
+    }
+}
+
+// Run an index job on the blocking pool and record its progress in `rebuild_jobs`
+fn spawn_index_job<F>(
+    state: &SharedState,
+    collection: String,
+    collection_handle: CollectionHandle,
+    kind: IndexJob,
+    job: F,
+) where
+    F: FnOnce(&CollectionHandle) -> Result<()> + Send + 'static,
+{
     let started_at = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -154,6 +300,7 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
         collection.clone(),
         RebuildJobStatus {
             status: RebuildState::Running,
+            kind,
             started_at,
             finished_at: None,
             error: None,
@@ -161,19 +308,18 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
         },
     );
 
-    let collection_name = collection.clone();
-    let collection_handle_clone = collection_handle.clone();
+    let collection_name = collection;
     let jobs = state.rebuild_jobs.clone();
 
     tokio::task::spawn_blocking(move || {
-        let mut collection_guard = collection_handle_clone.write();
         let start = Instant::now();
-        if let Err(e) = collection_guard.rebuild_index() {
+        if let Err(e) = job(&collection_handle) {
             tracing::error!(
                 target: "piramid::indexing",
                 collection=%collection_name,
                 error=%e,
-                "index_rebuild_failed"
+                "index_{}_failed",
+                kind.name()
             );
             let finished = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -183,6 +329,7 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
                 collection_name.clone(),
                 RebuildJobStatus {
                     status: RebuildState::Failed,
+                    kind,
                     started_at,
                     finished_at: Some(finished),
                     error: Some(e.to_string()),
@@ -194,7 +341,8 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
                 target: "piramid::indexing",
                 collection=%collection_name,
                 elapsed_ms = start.elapsed().as_millis(),
-                "index_rebuild_complete"
+                "index_{}_complete",
+                kind.name()
             );
             let finished = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -204,6 +352,7 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
                 collection_name.clone(),
                 RebuildJobStatus {
                     status: RebuildState::Completed,
+                    kind,
                     started_at,
                     finished_at: Some(finished),
                     error: None,
@@ -212,11 +361,6 @@ pub fn rebuild_index(state: &SharedState, collection: String) -> Result<RebuildI
             );
         }
     });
-
-    Ok(RebuildIndexResponse {
-        success: true,
-        latency_ms: None,
-    })
 }
 
 pub fn find_duplicates(
@@ -261,9 +405,10 @@ pub fn compact_collection(state: &SharedState, collection: String) -> Result<Reb
     ensure_available(state)?;
 
     let collection_handle = state.get_existing_collection(&collection)?;
-    let mut collection_guard = collection_handle.write();
     let start = Instant::now();
-    let stats = crate::collections::compact(&mut collection_guard)?;
+    let stats = crate::collections::compact_online(&collection_handle, None)?.ok_or_else(|| {
+        ServerError::AlreadyExists("Compaction is already running for this collection".into())
+    })?;
     let duration = start.elapsed();
     tracing::info!(
         target: "piramid::indexing",
@@ -297,6 +442,11 @@ pub fn rebuild_index_status(
     };
     Ok(RebuildIndexStatusResponse {
         status: status.to_string(),
+        job: job.kind.name().to_string(),
+        target_index: match job.kind {
+            IndexJob::Migrate(target) => Some(target.to_string()),
+            _ => None,
+        },
         started_at: Some(job.started_at),
         finished_at: job.finished_at,
         elapsed_ms: job.elapsed_ms.map(|ms| ms as f32),
@@ -2,12 +2,15 @@ use std::collections::HashMap;
 use std::time::Instant;
 
 use crate::error::{Result, ServerError};
-use crate::metrics::{record_lock_read, record_lock_write};
+use crate::metrics::record_lock_write;
 use crate::runtime::SharedState;
 use crate::server::helpers::{json_to_metadata, EMBEDDING_NOT_CONFIGURED};
 use crate::server::request_id::RequestId;
 use crate::server::types::*;
-use crate::services::search::{apply_search_overrides, hit_to_response, parse_metric};
+use crate::services::search::{
+    apply_search_overrides, hit_to_response, parse_metric, SearchTarget,
+};
+use crate::services::vector::{enforce_cache_budget, ensure_write_allowed, wait_durable};
 use crate::Document;
 
 fn ensure_available(state: &SharedState) -> Result<()> {
@@ -26,7 +29,7 @@ pub async fn embed_text(
     req: EmbedRequest,
 ) -> Result<EmbedResultsResponse> {
     ensure_available(state)?;
-    state.ensure_write_allowed()?;
+    ensure_write_allowed(state).await?;
 
     let collection_handle = state.get_or_create_collection(&collection)?;
     let embedder = state
@@ -47,20 +50,23 @@ pub async fn embed_text(
             let response = embedder.embed(&text).await?;
             let embed_duration = start.elapsed();
 
-            let lock_start = Instant::now();
-            let mut collection_guard = collection_handle.write();
-            record_lock_write(
-                state.collection_manager.tracker(&collection).as_deref(),
-                lock_start,
-            );
-
             let entry = Document::with_metadata(
                 response.embedding.clone(),
                 text,
                 json_to_metadata(req.metadata),
             );
-            let id = collection_guard.insert(entry)?;
-            state.enforce_cache_budget();
+            let (id, wal_ack) = {
+                let lock_start = Instant::now();
+                let mut collection_guard = collection_handle.write();
+                record_lock_write(
+                    state.collection_manager.tracker(&collection).as_deref(),
+                    lock_start,
+                );
+                let id = collection_guard.insert(entry)?;
+                (id, collection_guard.pending_wal_ack())
+            };
+            wait_durable(wal_ack).await?;
+            enforce_cache_budget(state).await;
             state
                 .embed_metrics
                 .record(1, 1, response.tokens.unwrap_or(0) as u64, embed_duration);
@@ -107,16 +113,19 @@ pub async fn embed_text(
                 ));
             }
 
-            let lock_start = Instant::now();
-            let mut collection_guard = collection_handle.write();
-            record_lock_write(
-                state.collection_manager.tracker(&collection).as_deref(),
-                lock_start,
-            );
-
-            let insert_ids = collection_guard.insert_batch(entries)?;
+            let (insert_ids, wal_ack) = {
+                let lock_start = Instant::now();
+                let mut collection_guard = collection_handle.write();
+                record_lock_write(
+                    state.collection_manager.tracker(&collection).as_deref(),
+                    lock_start,
+                );
+                let insert_ids = collection_guard.insert_batch(entries)?;
+                (insert_ids, collection_guard.pending_wal_ack())
+            };
+            wait_durable(wal_ack).await?;
             ids.extend(insert_ids.into_iter().map(|id| id.to_string()));
-            state.enforce_cache_budget();
+            enforce_cache_budget(state).await;
             state
                 .embed_metrics
                 .record(1, ids.len() as u64, total_tokens as u64, start.elapsed());
@@ -168,32 +177,22 @@ pub async fn search_by_text(
         .record(1, 1, response.tokens.unwrap_or(0) as u64, embed_duration);
 
     let metric = parse_metric(req.metric)?;
-    let base_search = {
-        let collection_guard = collection_handle.read();
-        collection_guard.config().search
-    };
+    let target = SearchTarget::acquire(state, &collection, &collection_handle);
     let effective_search = apply_search_overrides(
-        base_search,
+        target.config().search,
         req.ef,
         req.nprobe,
         req.overfetch,
         req.preset.clone(),
     )?;
 
-    let lock_start = Instant::now();
-    let collection_guard = collection_handle.read();
-    record_lock_read(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
-
     let start = Instant::now();
-    let results = collection_guard.search(
+    let (results, cache_hit) = target.search_cached(
         &response.embedding,
         req.k,
         metric,
         crate::SearchParams {
-            mode: collection_guard.config().execution,
+            mode: target.config().execution,
             filter: None,
             filter_overfetch_override: req.overfetch,
             search_config_override: Some(effective_search),
@@ -214,7 +213,8 @@ pub async fn search_by_text(
     }
 
     Ok(SearchResponse {
-        results: results.into_iter().map(hit_to_response).collect(),
+        results: results.iter().cloned().map(hit_to_response).collect(),
         latency_ms: Some(duration.as_millis() as f32),
+        cache_hit,
     })
 }
@@ -2,4 +2,5 @@ pub mod admin;
 pub mod collection;
 pub mod embedding;
 pub mod search;
+pub mod snapshot;
 pub mod vector;
@@ -1,8 +1,64 @@
-use crate::config::SearchConfig;
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::RwLockReadGuard;
+
+use crate::collections::{CollectionHandle, ReadView};
+use crate::config::{CollectionConfig, SearchConfig};
 use crate::error::{Result, ServerError};
-use crate::metrics::Metric;
-use crate::search::Hit;
+use crate::metrics::{record_lock_read, record_snapshot_read, Metric};
+use crate::runtime::SharedState;
+use crate::search::{Hit, SearchParams, SearchSource};
 use crate::server::{helpers::metadata_to_json, types::HitResponse};
+use crate::Collection;
+
+// What a search request reads: the collection's latest read view, or the collection itself under
+// its read lock when no view has been published yet
+pub enum SearchTarget<'a> {
+    View(Arc<ReadView>),
+    Locked(RwLockReadGuard<'a, Collection>),
+}
+
+impl<'a> SearchTarget<'a> {
+    pub fn acquire(state: &SharedState, name: &str, handle: &'a CollectionHandle) -> Self {
+        let start = Instant::now();
+        let manager = &state.collection_manager;
+        if let Some(view) = manager.read_view(name) {
+            record_snapshot_read(manager.tracker(name).as_deref(), start);
+            return SearchTarget::View(view);
+        }
+        let guard = handle.read();
+        record_lock_read(manager.tracker(name).as_deref(), start);
+        SearchTarget::Locked(guard)
+    }
+
+    pub fn config(&self) -> &CollectionConfig {
+        match self {
+            SearchTarget::View(view) => view.config(),
+            SearchTarget::Locked(guard) => guard.config(),
+        }
+    }
+
+    pub fn source(&self) -> &dyn SearchSource {
+        match self {
+            SearchTarget::View(view) => view.as_ref(),
+            SearchTarget::Locked(guard) => &**guard,
+        }
+    }
+
+    pub fn search_cached(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: Metric,
+        params: SearchParams,
+    ) -> Result<(Arc<Vec<Hit>>, bool)> {
+        match self {
+            SearchTarget::View(view) => view.search_cached(query, k, metric, params),
+            SearchTarget::Locked(guard) => guard.search_cached(query, k, metric, params),
+        }
+    }
+}
 
 pub fn parse_metric(metric: Option<String>) -> Result<Metric> {
     match metric.as_deref() {
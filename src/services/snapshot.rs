@@ -0,0 +1,264 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::collections::{Collection, CollectionOpenOptions, RecoveryTarget};
+use crate::error::{PiramidError, Result, ServerError, StorageError};
+use crate::runtime::SharedState;
+use crate::server::types::*;
+use crate::storage::snapshot::{read_snapshot_descriptor, SnapshotDescriptor, SNAPSHOT_EXTENSION};
+use crate::validation;
+
+fn ensure_available(state: &SharedState) -> Result<()> {
+    if state
+        .shutting_down
+        .load(std::sync::atomic::Ordering::Relaxed)
+    {
+        return Err(ServerError::ServiceUnavailable("Server is shutting down".to_string()).into());
+    }
+    Ok(())
+}
+
+// Where a restore reads the archive from
+pub enum RestoreSource {
+    // A snapshot stored on the server under `collection`
+    Stored {
+        collection: String,
+        snapshot: String,
+    },
+    // An archive file on the local filesystem: an upload spooled to disk, or a CLI argument
+    File(PathBuf),
+}
+
+// Snapshots live next to the collections, one directory per collection
+pub fn snapshot_dir(state: &SharedState, collection: &str) -> PathBuf {
+    Path::new(&state.data_dir)
+        .join("snapshots")
+        .join(collection)
+}
+
+// Where an uploaded archive is spooled before it is restored. Uploads are streamed to disk rather
+// than buffered, so an archive can be larger than memory.
+pub fn upload_file(state: &SharedState) -> PathBuf {
+    Path::new(&state.data_dir)
+        .join("snapshots")
+        .join(".uploads")
+        .join(format!("{}.snapshot", uuid::Uuid::new_v4()))
+}
+
+fn snapshot_info(id: String, path: &Path, descriptor: SnapshotDescriptor) -> SnapshotInfo {
+    SnapshotInfo {
+        id,
+        collection: descriptor.collection,
+        created_at: descriptor.created_at,
+        size_bytes: std::fs::metadata(path).map(|meta| meta.len()).unwrap_or(0),
+        vector_count: descriptor.vector_count,
+        last_checkpoint_seq: descriptor.last_checkpoint_seq,
+    }
+}
+
+pub fn create_snapshot(state: &SharedState, collection: String) -> Result<SnapshotInfo> {
+    state.ensure_write_allowed()?;
+    validation::validate_collection_name(&collection)?;
+
+    let collection_handle = state.get_existing_collection(&collection)?;
+    let dir = snapshot_dir(state, &collection);
+    // Ids sort in creation order; bump on the rare same-millisecond collision
+    let mut millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let (id, path) = loop {
+        let id = format!("{}-{}", collection, millis);
+        let path = dir.join(format!("{}.{}", id, SNAPSHOT_EXTENSION));
+        if !path.exists() {
+            break (id, path);
+        }
+        millis += 1;
+    };
+
+    let start = Instant::now();
+    let descriptor = crate::collections::create_snapshot(&collection_handle, &collection, &path)?;
+    tracing::info!(
+        collection=%collection,
+        snapshot=%id,
+        vectors=descriptor.vector_count,
+        elapsed_ms=start.elapsed().as_millis(),
+        "snapshot_created"
+    );
+    Ok(snapshot_info(id, &path, descriptor))
+}
+
+pub fn list_snapshots(state: &SharedState, collection: String) -> Result<SnapshotsResponse> {
+    ensure_available(state)?;
+    validation::validate_collection_name(&collection)?;
+
+    let entries = match std::fs::read_dir(snapshot_dir(state, &collection)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(SnapshotsResponse {
+                snapshots: Vec::new(),
+            })
+        }
+        Err(error) => return Err(error.into()),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SNAPSHOT_EXTENSION) {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        match read_snapshot_descriptor(&path) {
+            Ok(descriptor) => snapshots.push(snapshot_info(id.to_string(), &path, descriptor)),
+            Err(error) => tracing::warn!(
+                snapshot=%path.display(),
+                error=%error,
+                "snapshot_unreadable"
+            ),
+        }
+    }
+    snapshots.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+    Ok(SnapshotsResponse { snapshots })
+}
+
+// Path of a stored snapshot archive, for downloads
+pub fn snapshot_file(state: &SharedState, collection: &str, snapshot: &str) -> Result<PathBuf> {
+    ensure_available(state)?;
+    validation::validate_collection_name(collection)?;
+    validation::validate_snapshot_id(snapshot)?;
+
+    let path = snapshot_dir(state, collection).join(format!("{}.{}", snapshot, SNAPSHOT_EXTENSION));
+    if !path.exists() {
+        return Err(ServerError::NotFound("Snapshot not found".into()).into());
+    }
+    Ok(path)
+}
+
+pub fn delete_snapshot(
+    state: &SharedState,
+    collection: String,
+    snapshot: String,
+) -> Result<DeleteResponse> {
+    let path = match snapshot_file(state, &collection, &snapshot) {
+        Ok(path) => path,
+        Err(PiramidError::Server(ServerError::NotFound(_))) => {
+            return Ok(DeleteResponse {
+                deleted: false,
+                latency_ms: None,
+            })
+        }
+        Err(error) => return Err(error),
+    };
+    std::fs::remove_file(path)?;
+    Ok(DeleteResponse {
+        deleted: true,
+        latency_ms: None,
+    })
+}
+
+// Replace collection `name` with the contents of a snapshot, optionally brought forward to a
+// recovery point by replaying the snapshotted collection's WAL. A loaded collection is restored
+// under its write lock and swapped in place, so requests already holding its handle see the
+// restored data.
+pub fn restore_collection(
+    state: &SharedState,
+    name: String,
+    source: RestoreSource,
+    target: Option<RecoveryTarget>,
+) -> Result<RestoreResponse> {
+    state.ensure_write_allowed()?;
+    validation::validate_collection_name(&name)?;
+
+    let start = Instant::now();
+    let path = format!("{}/{}.db", state.data_dir, name);
+    let options = state.collection_manager.open_options();
+    let restore = |path: &str| -> Result<Restored> {
+        let restored = match &source {
+            RestoreSource::Stored {
+                collection,
+                snapshot,
+            } => {
+                let archive = snapshot_file(state, collection, snapshot)?;
+                restore_from(std::fs::File::open(archive)?, path, target, options)
+            }
+            RestoreSource::File(archive) => {
+                restore_from(std::fs::File::open(archive)?, path, target, options)
+            }
+        };
+        // A damaged or foreign archive is the caller's mistake, not a server fault
+        restored.map_err(|error| match error {
+            PiramidError::Storage(StorageError::CorruptedData(message)) => {
+                ServerError::InvalidRequest(message).into()
+            }
+            other => other,
+        })
+    };
+
+    let (restored, count) = if state.collection_manager.contains_loaded(&name) {
+        let handle = state.get_existing_collection(&name)?;
+        let mut guard = handle.write();
+        let mut restored = restore(&path)?;
+        *guard = match restored.collection.take() {
+            Some(collection) => collection,
+            None => state.collection_manager.open_unregistered(&name)?,
+        };
+        state.collection_manager.track_view(&name, &guard);
+        (restored, guard.count())
+    } else {
+        let mut restored = restore(&path)?;
+        // Close the recovered instance before the manager opens the files again
+        drop(restored.collection.take());
+        let handle = state.get_existing_collection(&name)?;
+        let count = handle.read().count();
+        (restored, count)
+    };
+
+    tracing::info!(
+        collection=%name,
+        source=%restored.descriptor.collection,
+        vectors=count,
+        recovered_seq=?restored.recovered_seq,
+        elapsed_ms=start.elapsed().as_millis(),
+        "snapshot_restored"
+    );
+    Ok(RestoreResponse {
+        name,
+        source_collection: restored.descriptor.collection,
+        snapshot_created_at: restored.descriptor.created_at,
+        count,
+        recovered_seq: restored.recovered_seq,
+        latency_ms: Some(start.elapsed().as_millis() as f32),
+    })
+}
+
+struct Restored {
+    descriptor: SnapshotDescriptor,
+    recovered_seq: Option<u64>,
+    collection: Option<Collection>,
+}
+
+fn restore_from<R: Read>(
+    reader: R,
+    path: &str,
+    target: Option<RecoveryTarget>,
+    options: CollectionOpenOptions,
+) -> Result<Restored> {
+    match target {
+        Some(target) => {
+            let restored = crate::collections::restore_to_point(reader, path, target, options)?;
+            Ok(Restored {
+                descriptor: restored.descriptor,
+                recovered_seq: Some(restored.recovered_seq),
+                collection: Some(restored.collection),
+            })
+        }
+        None => Ok(Restored {
+            descriptor: crate::collections::restore_snapshot(reader, path)?,
+            recovered_seq: None,
+            collection: None,
+        }),
+    }
+}
@@ -10,7 +10,11 @@ use crate::server::helpers::{json_to_metadata, metadata_to_json, VECTOR_NOT_FOUN
 use crate::server::request_id::RequestId;
 use crate::server::types::range::RangeSearchRequest;
 use crate::server::types::*;
-use crate::services::search::{apply_search_overrides, hit_to_response, parse_metric};
+use crate::services::collection::{index_maintenance_due, schedule_index_maintenance};
+use crate::services::search::{
+    apply_search_overrides, hit_to_response, parse_metric, SearchTarget,
+};
+use crate::storage::wal::WalAck;
 use crate::validation;
 use crate::Document;
 
@@ -26,6 +30,37 @@ fn ensure_available(state: &SharedState) -> Result<()> {
     Ok(())
 }
 
+// Writes are acknowledged only once the WAL reaches the configured durability point. Group commit
+// waits block, so keep them off the async worker threads.
+pub(crate) async fn wait_durable(ack: Option<WalAck>) -> Result<()> {
+    match ack {
+        Some(ack) => tokio::task::spawn_blocking(move || ack.wait())
+            .await
+            .map_err(|e| crate::error::PiramidError::other(e.to_string()))?,
+        None => Ok(()),
+    }
+}
+
+// Budget enforcement can checkpoint and unload other collections, so like the WAL wait it runs on
+// a blocking thread, after the request has released its collection lock.
+pub(crate) async fn enforce_cache_budget(state: &SharedState) {
+    let state = state.clone();
+    if let Err(error) = tokio::task::spawn_blocking(move || state.enforce_cache_budget()).await {
+        tracing::warn!(error = %error, "cache_budget_enforcement_failed");
+    }
+}
+
+// `AppState::ensure_write_allowed`, moved to a blocking thread when it has memory to free
+pub(crate) async fn ensure_write_allowed(state: &SharedState) -> Result<()> {
+    if !state.memory.is_exhausted() {
+        return state.check_write_allowed();
+    }
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || state.ensure_write_allowed())
+        .await
+        .map_err(|e| crate::error::PiramidError::other(e.to_string()))?
+}
+
 fn build_single_entry(mut req: InsertRequest) -> Result<Document> {
     let text = req.text.clone().ok_or_else(|| {
         ServerError::InvalidRequest("text is required for single insert".to_string())
@@ -90,13 +125,13 @@ fn build_batch_entries(mut req: InsertRequest) -> Result<Vec<Document>> {
     Ok(entries)
 }
 
-pub fn insert_vector(
+pub async fn insert_vector(
     state: &SharedState,
     collection: String,
     mut req: InsertRequest,
 ) -> Result<InsertResultsResponse> {
     ensure_available(state)?;
-    state.ensure_write_allowed()?;
+    ensure_write_allowed(state).await?;
     validation::validate_collection_name(&collection)?;
 
     let collection_handle = state.get_or_create_collection(&collection)?;
@@ -108,60 +143,71 @@ pub fn insert_vector(
         "insert_request"
     );
 
-    let lock_start = Instant::now();
-    let mut collection_guard = collection_handle.write();
-    record_lock_write(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
-
-    let response = match (req.vector.take(), req.vectors.take()) {
-        (Some(vector), None) => {
-            req.vector = Some(vector);
-            let entry = build_single_entry(req)?;
-            let start = Instant::now();
-            let id = collection_guard.insert(entry)?;
-            let duration = start.elapsed();
+    let (response, maintenance, wal_ack) = {
+        let lock_start = Instant::now();
+        let mut collection_guard = collection_handle.write();
+        record_lock_write(
+            state.collection_manager.tracker(&collection).as_deref(),
+            lock_start,
+        );
 
-            if let Some(tracker) = state.collection_manager.tracker(&collection) {
-                tracker.record_insert(duration);
+        let response = match (req.vector.take(), req.vectors.take()) {
+            (Some(vector), None) => {
+                req.vector = Some(vector);
+                let entry = build_single_entry(req)?;
+                let start = Instant::now();
+                let id = collection_guard.insert(entry)?;
+                let duration = start.elapsed();
+
+                if let Some(tracker) = state.collection_manager.tracker(&collection) {
+                    tracker.record_insert(duration);
+                }
+
+                InsertResultsResponse::Single(InsertResponse {
+                    id: id.to_string(),
+                    latency_ms: Some(duration.as_millis() as f32),
+                })
             }
-            state.enforce_cache_budget();
-
-            InsertResultsResponse::Single(InsertResponse {
-                id: id.to_string(),
-                latency_ms: Some(duration.as_millis() as f32),
-            })
-        }
-        (None, Some(vectors)) => {
-            req.vectors = Some(vectors);
-            let count = req.texts.as_ref().map(|texts| texts.len()).unwrap_or(0);
-            let entries = build_batch_entries(req)?;
-            let start = Instant::now();
-            let ids = collection_guard.insert_batch(entries)?;
-            let duration = start.elapsed();
-
-            if let Some(tracker) = state.collection_manager.tracker(&collection) {
-                tracker.record_insert(duration);
+            (None, Some(vectors)) => {
+                req.vectors = Some(vectors);
+                let count = req.texts.as_ref().map(|texts| texts.len()).unwrap_or(0);
+                let entries = build_batch_entries(req)?;
+                let start = Instant::now();
+                let ids = collection_guard.insert_batch(entries)?;
+                let duration = start.elapsed();
+
+                if let Some(tracker) = state.collection_manager.tracker(&collection) {
+                    tracker.record_insert(duration);
+                }
+
+                InsertResultsResponse::Multi(MultiInsertResponse {
+                    ids: ids.into_iter().map(|id| id.to_string()).collect(),
+                    count,
+                    latency_ms: Some(duration.as_millis() as f32),
+                })
+            }
+            (Some(_), Some(_)) => {
+                return Err(ServerError::InvalidRequest(
+                    "Provide either vector or vectors, not both".to_string(),
+                )
+                .into())
             }
-            state.enforce_cache_budget();
+            (None, None) => {
+                return Err(ServerError::InvalidRequest("No vectors provided".to_string()).into())
+            }
+        };
 
-            InsertResultsResponse::Multi(MultiInsertResponse {
-                ids: ids.into_iter().map(|id| id.to_string()).collect(),
-                count,
-                latency_ms: Some(duration.as_millis() as f32),
-            })
-        }
-        (Some(_), Some(_)) => {
-            return Err(ServerError::InvalidRequest(
-                "Provide either vector or vectors, not both".to_string(),
-            )
-            .into())
-        }
-        (None, None) => {
-            return Err(ServerError::InvalidRequest("No vectors provided".to_string()).into())
-        }
+        (
+            response,
+            index_maintenance_due(&collection_guard),
+            collection_guard.pending_wal_ack(),
+        )
     };
+    wait_durable(wal_ack).await?;
+    enforce_cache_budget(state).await;
+    if let Some(maintenance) = maintenance {
+        schedule_index_maintenance(state, &collection, &collection_handle, maintenance);
+    }
 
     Ok(response)
 }
@@ -222,28 +268,39 @@ pub fn list_vectors(
         .collect()
 }
 
-pub fn delete_vector(
+pub async fn delete_vector(
     state: &SharedState,
     collection: String,
     id: String,
 ) -> Result<DeleteResultsResponse> {
     ensure_available(state)?;
-    state.ensure_write_allowed()?;
+    ensure_write_allowed(state).await?;
 
     let collection_handle = state.get_existing_collection(&collection)?;
     let uuid = Uuid::parse_str(&id)
         .map_err(|_| ServerError::InvalidRequest("Invalid UUID".to_string()))?;
 
-    let lock_start = Instant::now();
-    let mut collection_guard = collection_handle.write();
-    record_lock_write(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
+    let (deleted, duration, maintenance, wal_ack) = {
+        let lock_start = Instant::now();
+        let mut collection_guard = collection_handle.write();
+        record_lock_write(
+            state.collection_manager.tracker(&collection).as_deref(),
+            lock_start,
+        );
 
-    let start = Instant::now();
-    let deleted = collection_guard.delete(&uuid)?;
-    let duration = start.elapsed();
+        let start = Instant::now();
+        let deleted = collection_guard.delete(&uuid)?;
+        (
+            deleted,
+            start.elapsed(),
+            index_maintenance_due(&collection_guard),
+            collection_guard.pending_wal_ack(),
+        )
+    };
+    wait_durable(wal_ack).await?;
+    if let Some(maintenance) = maintenance {
+        schedule_index_maintenance(state, &collection, &collection_handle, maintenance);
+    }
 
     if let Some(tracker) = state.collection_manager.tracker(&collection) {
         tracker.record_delete(duration);
@@ -255,13 +312,13 @@ pub fn delete_vector(
     }))
 }
 
-pub fn delete_vectors(
+pub async fn delete_vectors(
     state: &SharedState,
     collection: String,
     req: DeleteVectorsRequest,
 ) -> Result<DeleteResultsResponse> {
     ensure_available(state)?;
-    state.ensure_write_allowed()?;
+    ensure_write_allowed(state).await?;
     validation::validate_collection_name(&collection)?;
     validation::validate_batch_size(req.ids.len(), MAX_BATCH_SIZE, "Delete")?;
 
@@ -273,16 +330,27 @@ pub fn delete_vectors(
         uuids.push(uuid);
     }
 
-    let lock_start = Instant::now();
-    let mut collection_guard = collection_handle.write();
-    record_lock_write(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
+    let (deleted_count, duration, maintenance, wal_ack) = {
+        let lock_start = Instant::now();
+        let mut collection_guard = collection_handle.write();
+        record_lock_write(
+            state.collection_manager.tracker(&collection).as_deref(),
+            lock_start,
+        );
 
-    let start = Instant::now();
-    let deleted_count = collection_guard.delete_batch(&uuids)?;
-    let duration = start.elapsed();
+        let start = Instant::now();
+        let deleted_count = collection_guard.delete_batch(&uuids)?;
+        (
+            deleted_count,
+            start.elapsed(),
+            index_maintenance_due(&collection_guard),
+            collection_guard.pending_wal_ack(),
+        )
+    };
+    wait_durable(wal_ack).await?;
+    if let Some(maintenance) = maintenance {
+        schedule_index_maintenance(state, &collection, &collection_handle, maintenance);
+    }
 
     if let Some(tracker) = state.collection_manager.tracker(&collection) {
         tracker.record_delete(duration);
@@ -304,12 +372,7 @@ pub fn search_vectors(
     validation::validate_collection_name(&collection)?;
 
     let collection_handle = state.get_existing_collection(&collection)?;
-    let lock_start = Instant::now();
-    let collection_guard = collection_handle.read();
-    record_lock_read(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
+    let target = SearchTarget::acquire(state, &collection, &collection_handle);
 
     let SearchRequest {
         vector,
@@ -322,24 +385,19 @@ pub fn search_vectors(
         preset,
     } = req;
     let metric = parse_metric(metric)?;
-    let effective_search = apply_search_overrides(
-        collection_guard.config().search,
-        ef,
-        nprobe,
-        overfetch,
-        preset,
-    )?;
+    let effective_search =
+        apply_search_overrides(target.config().search, ef, nprobe, overfetch, preset)?;
 
     match (vector, vectors) {
         (Some(vector), None) => {
             validation::validate_vector(&vector)?;
             let start = Instant::now();
-            let results = collection_guard.search(
+            let (results, cache_hit) = target.search_cached(
                 &vector,
                 k,
                 metric,
                 crate::SearchParams {
-                    mode: collection_guard.config().execution,
+                    mode: target.config().execution,
                     filter: None,
                     filter_overfetch_override: overfetch,
                     search_config_override: Some(effective_search),
@@ -360,8 +418,9 @@ pub fn search_vectors(
             }
 
             Ok(SearchResultsResponse::Single(SearchResponse {
-                results: results.into_iter().map(hit_to_response).collect(),
+                results: results.iter().cloned().map(hit_to_response).collect(),
                 latency_ms: Some(duration.as_millis() as f32),
+                cache_hit,
             }))
         }
         (None, Some(queries)) => {
@@ -370,13 +429,13 @@ pub fn search_vectors(
 
             let start = Instant::now();
             let params = crate::SearchParams {
-                mode: collection_guard.config().execution,
+                mode: target.config().execution,
                 filter: None,
                 filter_overfetch_override: overfetch,
                 search_config_override: Some(effective_search),
             };
             let batch_results = crate::search::search_batch_collection(
-                &collection_guard,
+                target.source(),
                 &queries,
                 k,
                 metric,
@@ -414,13 +473,13 @@ pub fn search_vectors(
     }
 }
 
-pub fn upsert_vector(
+pub async fn upsert_vector(
     state: &SharedState,
     collection: String,
     mut req: UpsertRequest,
 ) -> Result<UpsertResponse> {
     ensure_available(state)?;
-    state.ensure_write_allowed()?;
+    ensure_write_allowed(state).await?;
     validation::validate_collection_name(&collection)?;
     validation::validate_text(&req.text)?;
     validation::validate_vector(&req.vector)?;
@@ -430,41 +489,57 @@ pub fn upsert_vector(
     }
 
     let collection_handle = state.get_or_create_collection(&collection)?;
-    let lock_start = Instant::now();
-    let mut collection_guard = collection_handle.write();
-    record_lock_write(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
-
-    let id = if let Some(id) = req.id {
-        Uuid::parse_str(&id).map_err(|_| ServerError::InvalidRequest("Invalid UUID".to_string()))?
-    } else {
-        Uuid::new_v4()
-    };
-    let exists = collection_guard.get(&id)?.is_some();
-    let mut entry = Document::with_metadata(req.vector, req.text, json_to_metadata(req.metadata));
-    entry.id = id;
-
-    let start = Instant::now();
-    collection_guard.upsert(entry)?;
-    let duration = start.elapsed();
+    let (id, exists, duration, maintenance, wal_ack) = {
+        let lock_start = Instant::now();
+        let mut collection_guard = collection_handle.write();
+        record_lock_write(
+            state.collection_manager.tracker(&collection).as_deref(),
+            lock_start,
+        );
 
-    if let Some(tracker) = state.collection_manager.tracker(&collection) {
-        if exists {
-            tracker.record_update(duration);
+        let id = if let Some(id) = req.id {
+            Uuid::parse_str(&id)
+                .map_err(|_| ServerError::InvalidRequest("Invalid UUID".to_string()))?
         } else {
-            tracker.record_insert(duration);
+            Uuid::new_v4()
+        };
+        let exists = collection_guard.get(&id)?.is_some();
+        let mut entry =
+            Document::with_metadata(req.vector, req.text, json_to_metadata(req.metadata));
+        entry.id = id;
+
+        let start = Instant::now();
+        collection_guard.upsert(entry)?;
+        let duration = start.elapsed();
+
+        if let Some(tracker) = state.collection_manager.tracker(&collection) {
+            if exists {
+                tracker.record_update(duration);
+            } else {
+                tracker.record_insert(duration);
+            }
         }
+        tracing::info!(
+            target: "piramid::writes",
+            collection=%collection,
+            id=%id,
+            created=!exists,
+            "upsert_request"
+        );
+
+        (
+            id,
+            exists,
+            duration,
+            index_maintenance_due(&collection_guard),
+            collection_guard.pending_wal_ack(),
+        )
+    };
+    wait_durable(wal_ack).await?;
+    enforce_cache_budget(state).await;
+    if let Some(maintenance) = maintenance {
+        schedule_index_maintenance(state, &collection, &collection_handle, maintenance);
     }
-    state.enforce_cache_budget();
-    tracing::info!(
-        target: "piramid::writes",
-        collection=%collection,
-        id=%id,
-        created=!exists,
-        "upsert_request"
-    );
 
     Ok(UpsertResponse {
         id: id.to_string(),
@@ -484,34 +559,28 @@ pub fn range_search_vectors(
     validation::validate_vector(&req.vector)?;
 
     let collection_handle = state.get_existing_collection(&collection)?;
-    let lock_start = Instant::now();
-    let collection_guard = collection_handle.read();
-    record_lock_read(
-        state.collection_manager.tracker(&collection).as_deref(),
-        lock_start,
-    );
+    let target = SearchTarget::acquire(state, &collection, &collection_handle);
 
     let metric = parse_metric(req.metric)?;
     let effective_search = apply_search_overrides(
-        collection_guard.config().search,
+        target.config().search,
         req.ef,
         req.nprobe,
         req.overfetch,
         req.preset,
     )?;
     let start = Instant::now();
-    let mut results = collection_guard.search(
+    let (results, cache_hit) = target.search_cached(
         &req.vector,
         req.k,
         metric,
         crate::SearchParams {
-            mode: collection_guard.config().execution,
+            mode: target.config().execution,
             filter: None,
             filter_overfetch_override: req.overfetch,
             search_config_override: Some(effective_search),
         },
     )?;
-    results.retain(|hit| hit.score >= req.min_score);
     let duration = start.elapsed();
     if duration.as_millis() > state.slow_query_ms {
         tracing::warn!(
@@ -527,7 +596,13 @@ pub fn range_search_vectors(
     }
 
     Ok(SearchResponse {
-        results: results.into_iter().map(hit_to_response).collect(),
+        results: results
+            .iter()
+            .filter(|hit| hit.score >= req.min_score)
+            .cloned()
+            .map(hit_to_response)
+            .collect(),
         latency_ms: Some(duration.as_millis() as f32),
+        cache_hit,
     })
 }
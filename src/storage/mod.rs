@@ -1,4 +1,4 @@
-// Storage module - handles records, write-ahead logging, metadata sidecars, and mmap persistence.
+// Storage module - handles records, write-ahead logging, metadata sidecars, snapshot archives, mmap persistence, and the file-system backends (VFS) they are stored through.
 
 pub mod collection {
     pub use crate::collections::{
@@ -11,6 +11,9 @@ pub mod document;
 pub mod metadata;
 pub mod persistence;
 pub mod record_store;
+pub mod snapshot;
+pub mod vector_arena;
+pub mod vfs;
 pub mod wal;
 pub use crate::collections::Collection;
 pub use document::Document;
@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::error::{Result, StorageError};
+use crate::storage::vfs::{OsVfs, Vfs};
 
 //  maps UUID to location in mmap file
 // This is just file storage metadata
@@ -28,17 +29,17 @@ pub fn save_index(path: &str, index: &HashMap<Uuid, EntryPointer>) -> Result<()>
 }
 
 pub fn load_index(path: &str) -> Result<HashMap<Uuid, EntryPointer>> {
+    load_index_in(&OsVfs, path)
+}
+
+pub(super) fn load_index_in(vfs: &dyn Vfs, path: &str) -> Result<HashMap<Uuid, EntryPointer>> {
     let index_path = format!("{}.index.db", path);
 
-    let mut index_file = match std::fs::File::open(&index_path) {
-        Ok(file) => file,
+    let index_data = match vfs.read(std::path::Path::new(&index_path)) {
+        Ok(data) => data,
         Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
         Err(error) => return Err(error.into()),
     };
-
-    use std::io::Read;
-    let mut index_data = Vec::new();
-    index_file.read_to_end(&mut index_data)?;
     bincode::deserialize(&index_data).map_err(|e| {
         StorageError::CorruptedIndex(format!("failed to decode {index_path}: {e}")).into()
     })
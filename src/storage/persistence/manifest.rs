@@ -0,0 +1,344 @@
+// Checkpoint generations. Every checkpoint writes the index, vector index and metadata into new generation-numbered files, then atomically replaces a small manifest that names them. A crash at any point leaves the previous manifest, and therefore one complete generation, in place.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::index::EntryPointer;
+use crate::error::{PiramidError, Result, StorageError};
+use crate::index::{SerializableIndex, VectorIndex};
+use crate::storage::metadata::SCHEMA_VERSION;
+use crate::storage::vfs::{self, FileOptions, OsVfs, Vfs};
+use crate::storage::CollectionMetadata;
+
+const MANIFEST_VERSION: u32 = 1;
+
+// File kinds that make up one generation, with the suffix used for legacy un-versioned files.
+const INDEX_KIND: &str = "index.db";
+const VECTOR_INDEX_KIND: &str = "vecindex.db";
+const METADATA_KIND: &str = "metadata.db";
+const KINDS: [&str; 3] = [INDEX_KIND, VECTOR_INDEX_KIND, METADATA_KIND];
+
+/// Points at the files of the current checkpoint generation. File names are relative to the
+/// collection's directory so a collection can be moved or archived as a whole.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub generation: u64,
+    pub last_checkpoint_seq: u64,
+    pub timestamp: u64,
+    pub index_file: String,
+    pub vector_index_file: String,
+    pub metadata_file: String,
+}
+
+/// The state restored from the current generation (or from legacy sidecar files).
+pub struct LoadedCheckpoint {
+    pub index: HashMap<Uuid, EntryPointer>,
+    // Set when the index file is missing or does not decode; `index` is then empty and has to be
+    // rebuilt from the data file
+    pub index_error: Option<String>,
+    pub vector_index: Option<Box<dyn VectorIndex>>,
+    pub metadata: Option<CollectionMetadata>,
+    pub manifest: Option<Manifest>,
+}
+
+impl Manifest {
+    /// The same manifest for a collection whose file names start with `to` instead of `from`.
+    pub fn rebased(&self, from: &str, to: &str) -> Self {
+        let rebase = |name: &str| match name.strip_prefix(from) {
+            Some(suffix) => format!("{to}{suffix}"),
+            None => name.to_string(),
+        };
+        Self {
+            index_file: rebase(&self.index_file),
+            vector_index_file: rebase(&self.vector_index_file),
+            metadata_file: rebase(&self.metadata_file),
+            ..self.clone()
+        }
+    }
+}
+
+pub fn manifest_path(collection_path: &str) -> String {
+    format!("{}.manifest", collection_path)
+}
+
+fn generation_file(collection_path: &str, kind: &str, generation: u64) -> String {
+    format!("{}.{}.{:020}", collection_path, kind, generation)
+}
+
+fn collection_dir(collection_path: &str) -> PathBuf {
+    vfs::parent_dir(Path::new(collection_path))
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn resolve(collection_path: &str, name: &str) -> PathBuf {
+    collection_dir(collection_path).join(name)
+}
+
+pub fn load_manifest(collection_path: &str) -> Result<Option<Manifest>> {
+    load_manifest_in(&OsVfs, collection_path)
+}
+
+pub fn load_manifest_in(vfs: &dyn Vfs, collection_path: &str) -> Result<Option<Manifest>> {
+    let data = match vfs.read(Path::new(&manifest_path(collection_path))) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let manifest: Manifest = serde_json::from_slice(&data).map_err(|e| {
+        StorageError::CorruptedData(format!("failed to decode checkpoint manifest: {e}"))
+    })?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(StorageError::CorruptedData(format!(
+            "Unsupported manifest version {}, expected {}",
+            manifest.version, MANIFEST_VERSION
+        ))
+        .into());
+    }
+    Ok(Some(manifest))
+}
+
+/// Files holding the current checkpoint, whether versioned or legacy.
+pub fn checkpoint_files(collection_path: &str) -> Result<Vec<PathBuf>> {
+    checkpoint_files_in(&OsVfs, collection_path)
+}
+
+pub fn checkpoint_files_in(vfs: &dyn Vfs, collection_path: &str) -> Result<Vec<PathBuf>> {
+    Ok(match load_manifest_in(vfs, collection_path)? {
+        Some(manifest) => vec![
+            resolve(collection_path, &manifest.index_file),
+            resolve(collection_path, &manifest.vector_index_file),
+            resolve(collection_path, &manifest.metadata_file),
+        ],
+        None => KINDS
+            .iter()
+            .map(|kind| PathBuf::from(format!("{}.{}", collection_path, kind)))
+            .collect(),
+    })
+}
+
+/// Load the generation named by the manifest. Collections written before manifests existed fall
+/// back to the un-versioned sidecar files.
+pub fn load_checkpoint(collection_path: &str) -> Result<LoadedCheckpoint> {
+    load_checkpoint_in(&OsVfs, collection_path)
+}
+
+pub fn load_checkpoint_in(vfs: &dyn Vfs, collection_path: &str) -> Result<LoadedCheckpoint> {
+    let Some(manifest) = load_manifest_in(vfs, collection_path)? else {
+        let (index, index_error) = match super::index::load_index_in(vfs, collection_path) {
+            Ok(index) => (index, None),
+            Err(PiramidError::Storage(StorageError::CorruptedIndex(reason))) => {
+                (HashMap::new(), Some(reason))
+            }
+            Err(error) => return Err(error),
+        };
+        return Ok(LoadedCheckpoint {
+            index,
+            index_error,
+            vector_index: super::vector_index::load_vector_index_in(vfs, collection_path)?,
+            metadata: super::metadata::load_metadata_in(vfs, collection_path)?,
+            manifest: None,
+        });
+    };
+
+    let index_path = resolve(collection_path, &manifest.index_file);
+    let (index, index_error) = match vfs.read(&index_path) {
+        Ok(bytes) => match bincode::deserialize(&bytes) {
+            Ok(index) => (index, None),
+            Err(e) => (
+                HashMap::new(),
+                Some(format!("failed to decode {}: {e}", index_path.display())),
+            ),
+        },
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => (
+            HashMap::new(),
+            Some(format!("{} is missing", index_path.display())),
+        ),
+        Err(error) => return Err(error.into()),
+    };
+    let vector_index: SerializableIndex =
+        bincode::deserialize(&vfs.read(&resolve(collection_path, &manifest.vector_index_file))?)?;
+    let metadata: CollectionMetadata =
+        bincode::deserialize(&vfs.read(&resolve(collection_path, &manifest.metadata_file))?)
+            .map_err(|e| {
+                PiramidError::Storage(StorageError::CorruptedData(format!(
+                    "Failed to read metadata: {e}"
+                )))
+            })?;
+    if metadata.schema_version != SCHEMA_VERSION {
+        return Err(PiramidError::Storage(StorageError::CorruptedData(format!(
+            "Schema version mismatch: expected {}, found {}",
+            SCHEMA_VERSION, metadata.schema_version
+        ))));
+    }
+
+    remove_stale_generations(vfs, collection_path, manifest.generation)?;
+    Ok(LoadedCheckpoint {
+        index,
+        index_error,
+        vector_index: Some(vector_index.to_trait_object()),
+        metadata: Some(metadata),
+        manifest: Some(manifest),
+    })
+}
+
+/// Only the collection metadata of the current generation, for reporting on a collection without
+/// opening it. Nothing is modified.
+pub fn load_checkpoint_metadata(collection_path: &str) -> Result<Option<CollectionMetadata>> {
+    let Some(manifest) = load_manifest(collection_path)? else {
+        return super::metadata::load_metadata(collection_path);
+    };
+    let bytes = OsVfs.read(&resolve(collection_path, &manifest.metadata_file))?;
+    let metadata = bincode::deserialize(&bytes).map_err(|e| {
+        PiramidError::Storage(StorageError::CorruptedData(format!(
+            "Failed to read metadata: {e}"
+        )))
+    })?;
+    Ok(Some(metadata))
+}
+
+/// Write a new checkpoint generation and switch the manifest to it. Each file is written to a
+/// temporary name, fsynced and renamed before the manifest is replaced the same way, so readers
+/// only ever see the old generation or the complete new one.
+pub fn write_checkpoint(
+    collection_path: &str,
+    last_checkpoint_seq: u64,
+    timestamp: u64,
+    index: &HashMap<Uuid, EntryPointer>,
+    vector_index: &dyn VectorIndex,
+    metadata: &CollectionMetadata,
+) -> Result<Manifest> {
+    write_checkpoint_in(
+        &OsVfs,
+        collection_path,
+        last_checkpoint_seq,
+        timestamp,
+        index,
+        vector_index,
+        metadata,
+    )
+}
+
+pub fn write_checkpoint_in(
+    vfs: &dyn Vfs,
+    collection_path: &str,
+    last_checkpoint_seq: u64,
+    timestamp: u64,
+    index: &HashMap<Uuid, EntryPointer>,
+    vector_index: &dyn VectorIndex,
+    metadata: &CollectionMetadata,
+) -> Result<Manifest> {
+    let generation = load_manifest_in(vfs, collection_path)?
+        .map(|manifest| manifest.generation + 1)
+        .unwrap_or(1);
+
+    let index_file = generation_file(collection_path, INDEX_KIND, generation);
+    let vector_index_file = generation_file(collection_path, VECTOR_INDEX_KIND, generation);
+    let metadata_file = generation_file(collection_path, METADATA_KIND, generation);
+    write_durable(vfs, Path::new(&index_file), &bincode::serialize(index)?)?;
+    write_durable(
+        vfs,
+        Path::new(&vector_index_file),
+        &bincode::serialize(&vector_index.to_serializable())?,
+    )?;
+    write_durable(
+        vfs,
+        Path::new(&metadata_file),
+        &bincode::serialize(metadata)?,
+    )?;
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        generation,
+        last_checkpoint_seq,
+        timestamp,
+        index_file: file_name(&index_file),
+        vector_index_file: file_name(&vector_index_file),
+        metadata_file: file_name(&metadata_file),
+    };
+    write_durable(
+        vfs,
+        Path::new(&manifest_path(collection_path)),
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    vfs.sync_dir(&collection_dir(collection_path))?;
+
+    // The new generation is live; older generations and legacy sidecars are now garbage.
+    remove_stale_generations(vfs, collection_path, generation)?;
+    for path in KINDS
+        .iter()
+        .map(|kind| format!("{}.{}", collection_path, kind))
+        .chain(std::iter::once(format!("{}.wal.meta", collection_path)))
+    {
+        vfs::remove_if_exists(vfs, Path::new(&path))?;
+    }
+    Ok(manifest)
+}
+
+/// Delete the manifest and every checkpoint file of a collection.
+pub fn remove_checkpoint_files(collection_path: &str) -> Result<()> {
+    remove_checkpoint_files_in(&OsVfs, collection_path)
+}
+
+pub fn remove_checkpoint_files_in(vfs: &dyn Vfs, collection_path: &str) -> Result<()> {
+    remove_stale_generations(vfs, collection_path, u64::MAX)?;
+    let arena = crate::storage::vector_arena::arena_path(collection_path);
+    let files = KINDS
+        .iter()
+        .map(|kind| format!("{}.{}", collection_path, kind))
+        .chain([
+            manifest_path(collection_path),
+            arena,
+            format!("{}.wal.meta", collection_path),
+        ]);
+    for path in files {
+        vfs::remove_if_exists(vfs, Path::new(&path))?;
+    }
+    Ok(())
+}
+
+// Remove generation files (and leftover temporaries) other than `keep`.
+fn remove_stale_generations(vfs: &dyn Vfs, collection_path: &str, keep: u64) -> Result<()> {
+    let base = file_name(collection_path);
+    let entries = match vfs.read_dir(&collection_dir(collection_path)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    for entry in entries {
+        let name = file_name(&entry.to_string_lossy());
+        let stale = KINDS.iter().any(|kind| {
+            let Some(suffix) = name.strip_prefix(&format!("{}.{}.", base, kind)) else {
+                return false;
+            };
+            let digits = suffix.strip_suffix(".tmp").unwrap_or(suffix);
+            match digits.parse::<u64>() {
+                Ok(generation) => generation != keep || suffix.ends_with(".tmp"),
+                Err(_) => false,
+            }
+        }) || name == format!("{}.manifest.tmp", base);
+        if stale {
+            vfs::remove_if_exists(vfs, &entry)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_durable(vfs: &dyn Vfs, path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut file = vfs.open(&tmp, FileOptions::truncate())?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    vfs.rename(&tmp, path)?;
+    Ok(())
+}
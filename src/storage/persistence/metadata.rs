@@ -3,6 +3,7 @@
 use crate::error::PiramidError;
 use crate::error::Result;
 use crate::storage::metadata::SCHEMA_VERSION;
+use crate::storage::vfs::{OsVfs, Vfs};
 use crate::storage::CollectionMetadata;
 use std::fs;
 use std::path::Path;
@@ -22,13 +23,20 @@ pub fn save_metadata(collection_path: &str, metadata: &CollectionMetadata) -> Re
 
 // Load collection metadata from disk
 pub fn load_metadata(collection_path: &str) -> Result<Option<CollectionMetadata>> {
+    load_metadata_in(&OsVfs, collection_path)
+}
+
+pub(super) fn load_metadata_in(
+    vfs: &dyn Vfs,
+    collection_path: &str,
+) -> Result<Option<CollectionMetadata>> {
     let metadata_path = get_metadata_path(collection_path);
 
-    if !Path::new(&metadata_path).exists() {
+    if !vfs.exists(Path::new(&metadata_path)) {
         return Ok(None);
     }
 
-    let bytes = fs::read(metadata_path)?;
+    let bytes = vfs.read(Path::new(&metadata_path))?;
     let metadata: CollectionMetadata = bincode::deserialize(&bytes).map_err(|e| {
         PiramidError::Storage(crate::error::storage::StorageError::CorruptedData(format!(
             "Failed to read metadata: {e}"
@@ -3,10 +3,11 @@
 use memmap2::{MmapMut, MmapOptions};
 use std::fs::File;
 
-use crate::error::Result;
+use crate::error::{Result, StorageError};
+use crate::storage::vfs::VfsFile;
 
-pub fn ensure_file_size(file: &File, min_size: u64) -> Result<()> {
-    let current_size = file.metadata()?.len();
+pub fn ensure_file_size(file: &dyn VfsFile, min_size: u64) -> Result<()> {
+    let current_size = file.file_len()?;
     if current_size < min_size {
         file.set_len(min_size)?;
     }
@@ -40,18 +41,21 @@ pub fn warm_mmap(mmap: &MmapMut) {
 
 pub fn grow_mmap_if_needed(
     mmap: &mut Option<MmapMut>,
-    file: &File,
+    file: &dyn VfsFile,
     required_size: u64,
 ) -> Result<()> {
     let current_size = mmap
         .as_ref()
         .map(|mmap| mmap.len() as u64)
-        .unwrap_or_else(|| file.metadata().map(|meta| meta.len()).unwrap_or(0));
+        .unwrap_or_else(|| file.file_len().unwrap_or(0));
     if required_size > current_size {
         let new_size = required_size.saturating_mul(2);
         if mmap.is_some() {
             drop(mmap.take());
             file.set_len(new_size)?;
+            let file = file.as_file().ok_or_else(|| {
+                StorageError::WriteFailed("memory map over a non-OS file".to_string())
+            })?;
             *mmap = Some(create_mmap(file)?);
         } else {
             file.set_len(new_size)?;
@@ -61,3 +65,70 @@ pub fn grow_mmap_if_needed(
     // memory map without any changes.
     Ok(())
 }
+
+/// Size of a virtual memory page, the granularity of `release_mmap_range`.
+pub fn page_size() -> usize {
+    static PAGE_SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        #[cfg(target_family = "unix")]
+        {
+            let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            if size > 0 {
+                return size as usize;
+            }
+        }
+        4096
+    })
+}
+
+/// Drop the pages of a file-backed map in `offset..offset + len` from this process. Their
+/// contents stay in the file and are faulted back in on the next access. Best effort: the range is
+/// rounded to whole pages and errors are ignored.
+pub fn release_mmap_range(mmap: &MmapMut, offset: usize, len: usize) {
+    #[cfg(target_family = "unix")]
+    {
+        let page = page_size();
+        let start = offset / page * page;
+        let end = (offset + len).min(mmap.len());
+        if end <= start {
+            return;
+        }
+        // SAFETY: callers only pass shared file mappings. Dropping their pages does not discard
+        // data, it only makes the next access fault the page in again, so slices into the map
+        // stay valid.
+        let _ = unsafe {
+            mmap.unchecked_advise_range(memmap2::UncheckedAdvice::DontNeed, start, end - start)
+        };
+    }
+    #[cfg(not(target_family = "unix"))]
+    {
+        let _ = (mmap, offset, len);
+    }
+}
+
+/// Bytes of `mmap` currently resident in memory. Falls back to the full length where residency
+/// can't be queried.
+pub fn resident_mmap_bytes(mmap: &MmapMut) -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        let page = page_size();
+        let pages = mmap.len().div_ceil(page);
+        if pages == 0 {
+            return 0;
+        }
+        let mut residency = vec![0u8; pages];
+        // SAFETY: the map starts page aligned and `residency` has one byte per page of it.
+        let rc = unsafe {
+            libc::mincore(
+                mmap.as_ptr() as *mut libc::c_void,
+                mmap.len(),
+                residency.as_mut_ptr(),
+            )
+        };
+        if rc == 0 {
+            let resident = residency.iter().filter(|flags| **flags & 1 != 0).count();
+            return (resident * page).min(mmap.len());
+        }
+    }
+    mmap.len()
+}
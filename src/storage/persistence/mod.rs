@@ -1,9 +1,19 @@
 mod index;
+mod manifest;
 mod metadata;
 mod mmap;
 mod vector_index;
 
 pub use index::{get_wal_path, load_index, save_index, EntryPointer};
+pub use manifest::{
+    checkpoint_files, checkpoint_files_in, load_checkpoint, load_checkpoint_in,
+    load_checkpoint_metadata, load_manifest, load_manifest_in, manifest_path,
+    remove_checkpoint_files, remove_checkpoint_files_in, write_checkpoint, write_checkpoint_in,
+    LoadedCheckpoint, Manifest,
+};
 pub use metadata::{load_metadata, save_metadata};
-pub use mmap::{create_mmap, ensure_file_size, grow_mmap_if_needed, warm_mmap};
+pub use mmap::{
+    create_mmap, ensure_file_size, grow_mmap_if_needed, page_size, release_mmap_range,
+    resident_mmap_bytes, warm_mmap,
+};
 pub use vector_index::{load_vector_index, save_vector_index, warm_file};
@@ -2,6 +2,7 @@
 
 use crate::error::Result;
 use crate::index::{SerializableIndex, VectorIndex};
+use crate::storage::vfs::{OsVfs, Vfs};
 use std::fs;
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -40,16 +41,23 @@ pub fn warm_file(path: &str) -> Result<()> {
 }
 // Load index from disk
 pub fn load_vector_index(collection_path: &str) -> Result<Option<Box<dyn VectorIndex>>> {
+    load_vector_index_in(&OsVfs, collection_path)
+}
+
+pub(super) fn load_vector_index_in(
+    vfs: &dyn Vfs,
+    collection_path: &str,
+) -> Result<Option<Box<dyn VectorIndex>>> {
     // construct the expected file path for the index based on the collection path.
     // If the file exists, we read the bytes from the file and deserialize them into a SerializableIndex enum. convert the SerializableIndex into a Box<dyn VectorIndex> trait object and return it wrapped in Some.
     // If the file does not exist, we return Ok(None) to indicate that there is no existing index to load.
     let index_path = get_index_file_path(collection_path);
 
-    if !Path::new(&index_path).exists() {
+    if !vfs.exists(Path::new(&index_path)) {
         return Ok(None);
     }
 
-    let bytes = fs::read(index_path)?;
+    let bytes = vfs.read(Path::new(&index_path))?;
     let serializable: SerializableIndex = bincode::deserialize(&bytes)?;
     Ok(Some(serializable.to_trait_object()))
 }
@@ -1,18 +1,248 @@
+// Append-only data file holding the collection's documents. Files written by this version start with an 8-byte header (magic `PDAT` + u32 LE version) followed by one frame per record:
+// [magic "PREC"][flags: u8][seq: u64 LE][payload_len: u32 LE][crc32: u32 LE][payload]
+// The payload is a bincode `Document`, or the 16 id bytes of a deleted document when the tombstone flag is set. The CRC covers the flags, seq, length and payload. Index pointers address the payload only, so reads do not depend on the framing. Frames make the index rebuildable: a sequential scan keeps the highest-seq record of every id and drops ids whose last record is a tombstone. Version 1 files (bare bincode documents back to back) are still read and appended to until compaction rewrites them.
+
 use memmap2::MmapMut;
-use std::fs::{File, OpenOptions};
+use std::collections::HashMap;
 use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use uuid::Uuid;
 
 use crate::config::CollectionConfig;
 use crate::error::{Result, StorageError};
 use crate::storage::document::Document;
 use crate::storage::persistence::{
-    create_mmap, ensure_file_size, grow_mmap_if_needed, warm_mmap, EntryPointer,
+    create_mmap, ensure_file_size, grow_mmap_if_needed, resident_mmap_bytes, warm_mmap,
+    EntryPointer,
 };
+use crate::storage::vfs::{parent_dir, FileOptions, OsVfs, Vfs, VfsFile};
+
+pub const LEGACY_RECORD_VERSION: u32 = 1;
+pub const RECORD_VERSION: u32 = 2;
+
+const FILE_MAGIC: &[u8; 4] = b"PDAT";
+const FILE_HEADER_LEN: u64 = 8;
+const FRAME_MAGIC: &[u8; 4] = b"PREC";
+const FRAME_HEADER_LEN: usize = 21;
+const TOMBSTONE: u8 = 1;
+
+/// Result of rebuilding the pointer index from the data file.
+#[derive(Debug, Default)]
+pub struct RecordScan {
+    pub index: HashMap<Uuid, EntryPointer>,
+    // Valid frames read, tombstones included
+    pub records: usize,
+    pub tombstones: usize,
+    // Bytes between valid frames that failed their checksum and were skipped
+    pub skipped_bytes: u64,
+    pub last_seq: u64,
+    // End of the last valid record
+    pub end_offset: u64,
+}
+
+struct FrameHeader {
+    flags: u8,
+    seq: u64,
+    len: u32,
+    crc: u32,
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn parse_frame_header(bytes: &[u8]) -> Option<FrameHeader> {
+    if bytes.len() < FRAME_HEADER_LEN || &bytes[..4] != FRAME_MAGIC {
+        return None;
+    }
+    let mut seq = [0u8; 8];
+    seq.copy_from_slice(&bytes[5..13]);
+    Some(FrameHeader {
+        flags: bytes[4],
+        seq: u64::from_le_bytes(seq),
+        len: read_u32(&bytes[13..17]),
+        crc: read_u32(&bytes[17..21]),
+    })
+}
+
+fn frame_crc(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header[4..17]);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+fn encode_frame(flags: u8, seq: u64, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(FRAME_MAGIC);
+    frame.push(flags);
+    frame.extend_from_slice(&seq.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    let crc = frame_crc(&frame, payload);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// The valid frame starting at `offset` of `data`, if there is one.
+fn frame_in(data: &[u8], offset: usize) -> Option<(FrameHeader, &[u8])> {
+    let header = parse_frame_header(data.get(offset..offset + FRAME_HEADER_LEN)?)?;
+    let start = offset + FRAME_HEADER_LEN;
+    let payload = data.get(start..start + header.len as usize)?;
+    if frame_crc(&data[offset..start], payload) != header.crc {
+        return None;
+    }
+    Some((header, payload))
+}
+
+/// Rebuild the pointer index from the contents of a data file, framed or not.
+pub fn scan_records(data: &[u8]) -> RecordScan {
+    if data.starts_with(FILE_MAGIC) {
+        scan_frames(data)
+    } else {
+        scan_legacy(data)
+    }
+}
+
+fn scan_frames(data: &[u8]) -> RecordScan {
+    let mut scan = RecordScan::default();
+    let mut latest: HashMap<Uuid, (u64, Option<EntryPointer>)> = HashMap::new();
+    let mut offset = FILE_HEADER_LEN as usize;
+    let mut gap_start: Option<usize> = None;
+    while offset + FRAME_HEADER_LEN <= data.len() {
+        let Some((header, payload)) = frame_in(data, offset) else {
+            // Resynchronise on the next frame magic; a torn or zeroed tail simply runs out.
+            gap_start.get_or_insert(offset);
+            match data[offset + 1..]
+                .windows(FRAME_MAGIC.len())
+                .position(|window| window == FRAME_MAGIC)
+            {
+                Some(position) => {
+                    offset += 1 + position;
+                    continue;
+                }
+                None => break,
+            }
+        };
+        if let Some(start) = gap_start.take() {
+            scan.skipped_bytes += (offset - start) as u64;
+        }
+
+        let payload_offset = offset + FRAME_HEADER_LEN;
+        let record = if header.flags & TOMBSTONE != 0 {
+            scan.tombstones += 1;
+            Uuid::from_slice(payload).ok().map(|id| (id, None))
+        } else {
+            bincode::deserialize::<Document>(payload)
+                .ok()
+                .map(|document| {
+                    let pointer = EntryPointer::new(payload_offset as u64, header.len);
+                    (document.id, Some(pointer))
+                })
+        };
+        if let Some((id, pointer)) = record {
+            scan.records += 1;
+            // Later frames win ties, e.g. the copies compaction writes under one seq.
+            let current = latest.get(&id).map(|(seq, _)| *seq);
+            if current.is_none_or(|seq| header.seq >= seq) {
+                latest.insert(id, (header.seq, pointer));
+            }
+        }
+        scan.last_seq = scan.last_seq.max(header.seq);
+        offset = payload_offset + header.len as usize;
+        scan.end_offset = offset as u64;
+    }
+
+    scan.index = latest
+        .into_iter()
+        .filter_map(|(id, (_, pointer))| pointer.map(|pointer| (id, pointer)))
+        .collect();
+    scan
+}
+
+// Version 1 files carry no lengths, checksums or deletes: decode documents back to back until one
+// fails, letting later copies of an id win. Documents deleted since the last compaction come back.
+fn scan_legacy(data: &[u8]) -> RecordScan {
+    use bincode::Options;
+
+    let mut scan = RecordScan::default();
+    let mut offset = 0usize;
+    while offset < data.len() {
+        let mut rest = &data[offset..];
+        let decoded = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(rest.len() as u64)
+            .deserialize_from::<_, Document>(&mut rest);
+        let Ok(document) = decoded else {
+            break;
+        };
+        let length = data.len() - offset - rest.len();
+        if document.id.is_nil() || length == 0 {
+            break;
+        }
+        scan.records += 1;
+        scan.index
+            .insert(document.id, EntryPointer::new(offset as u64, length as u32));
+        offset += length;
+        scan.end_offset = offset as u64;
+    }
+    scan
+}
+
+fn decode_document(bytes: Result<Vec<u8>>, pointer: &EntryPointer) -> Result<Document> {
+    let bytes = bytes.map_err(|e| {
+        StorageError::ReadFailed(format!(
+            "failed to read document at offset {} length {}: {e}",
+            pointer.offset, pointer.length
+        ))
+    })?;
+    bincode::deserialize(&bytes).map_err(|e| {
+        StorageError::CorruptedData(format!(
+            "failed to decode document at offset {} length {}: {e}",
+            pointer.offset, pointer.length
+        ))
+        .into()
+    })
+}
+
+/// Read-only handle on a data file, used to read records without the collection lock. Records
+/// are never rewritten in place, so pointers stay valid while the store keeps appending. Reads are
+/// positional, so any number of threads can share one reader.
+pub struct RecordReader {
+    data_file: Box<dyn VfsFile>,
+}
+
+impl RecordReader {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::open_in(&OsVfs, path)
+    }
+
+    pub fn open_in(vfs: &dyn Vfs, path: &str) -> Result<Self> {
+        Ok(Self {
+            data_file: vfs.open(Path::new(path), FileOptions::read_only())?,
+        })
+    }
+
+    pub fn read_document(&self, pointer: &EntryPointer) -> Result<Document> {
+        let mut buffer = vec![0u8; pointer.length as usize];
+        let read = self
+            .data_file
+            .read_exact_at(&mut buffer, pointer.offset)
+            .map(|_| buffer)
+            .map_err(Into::into);
+        decode_document(read, pointer)
+    }
+}
 
 pub struct RecordStore {
-    data_file: File,
+    data_file: Box<dyn VfsFile>,
     mmap: Option<MmapMut>,
     append_cursor: u64,
+    // False for version 1 files, which keep their unframed layout until compaction
+    framed: bool,
+    // Highest seq among the frames past the checkpointed index
+    last_seq: u64,
 }
 
 impl RecordStore {
@@ -21,87 +251,199 @@ impl RecordStore {
         config: &CollectionConfig,
         index: &std::collections::HashMap<uuid::Uuid, EntryPointer>,
     ) -> Result<Self> {
-        let data_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(path)?;
+        Self::open_in(&OsVfs, path, config, index)
+    }
+
+    /// Open the data file at `path` of `vfs`. It is memory-mapped only if the config asks for it
+    /// and the backend supports it.
+    pub fn open_in(
+        vfs: &dyn Vfs,
+        path: &str,
+        config: &CollectionConfig,
+        index: &std::collections::HashMap<uuid::Uuid, EntryPointer>,
+    ) -> Result<Self> {
+        let created = !vfs.exists(Path::new(path));
+        let data_file = vfs.open(Path::new(path), FileOptions::read_write())?;
+        if created {
+            vfs.sync_dir(&parent_dir(Path::new(path)))?;
+        }
 
         let initial_size = initial_size(config);
-        ensure_file_size(&data_file, initial_size)?;
+        ensure_file_size(&*data_file, initial_size)?;
 
-        let mmap = if config.memory.use_mmap {
-            Some(create_mmap(&data_file)?)
-        } else {
-            None
+        let mmap = match data_file.as_file() {
+            Some(file) if config.memory.use_mmap => Some(create_mmap(file)?),
+            _ => None,
         };
 
-        Ok(Self {
+        let mut store = Self {
             data_file,
             mmap,
             append_cursor: next_append_offset(index),
-        })
+            framed: true,
+            last_seq: 0,
+        };
+        let header = store.read_range(0, FILE_HEADER_LEN as usize)?;
+        if header.starts_with(FILE_MAGIC) {
+            store.append_cursor = store.append_cursor.max(FILE_HEADER_LEN);
+            store.skip_tail_frames()?;
+        } else if index.is_empty() && header.iter().all(|byte| *byte == 0) {
+            // A new (zero-filled) file gets the current format.
+            let mut header = FILE_MAGIC.to_vec();
+            header.extend_from_slice(&RECORD_VERSION.to_le_bytes());
+            store.write_at(0, &header)?;
+            store.append_cursor = FILE_HEADER_LEN;
+        } else {
+            store.framed = false;
+        }
+        Ok(store)
     }
 
-    pub fn append(&mut self, bytes: &[u8]) -> Result<EntryPointer> {
+    /// Append one document (already encoded) written under WAL sequence `seq`.
+    pub fn append(&mut self, bytes: &[u8], seq: u64) -> Result<EntryPointer> {
+        if !self.framed {
+            let offset = self.append_cursor;
+            self.write_record(bytes)?;
+            return Ok(EntryPointer::new(offset, bytes.len() as u32));
+        }
         let offset = self.append_cursor;
-        let required_size = offset + bytes.len() as u64;
-        grow_mmap_if_needed(&mut self.mmap, &self.data_file, required_size)?;
-        self.write_at(offset, bytes)?;
-        self.append_cursor = required_size;
-        Ok(EntryPointer::new(offset, bytes.len() as u32))
+        self.write_record(&encode_frame(0, seq, bytes))?;
+        self.last_seq = self.last_seq.max(seq);
+        Ok(EntryPointer::new(
+            offset + FRAME_HEADER_LEN as u64,
+            bytes.len() as u32,
+        ))
+    }
+
+    /// Record that `id` was deleted at `seq`, so a rebuild by scan does not bring it back.
+    /// Version 1 files have no way to store this.
+    pub fn append_tombstone(&mut self, id: &Uuid, seq: u64) -> Result<()> {
+        if self.framed {
+            self.write_record(&encode_frame(TOMBSTONE, seq, id.as_bytes()))?;
+            self.last_seq = self.last_seq.max(seq);
+        }
+        Ok(())
+    }
+
+    /// Record the deletes of a batch, each `id` with its WAL sequence, in one write.
+    pub fn append_tombstones(&mut self, entries: &[(Uuid, u64)]) -> Result<()> {
+        if !self.framed || entries.is_empty() {
+            return Ok(());
+        }
+        let mut buffer = Vec::with_capacity(entries.len() * (FRAME_HEADER_LEN + 16));
+        for (id, seq) in entries {
+            buffer.extend_from_slice(&encode_frame(TOMBSTONE, *seq, id.as_bytes()));
+        }
+        self.write_record(&buffer)?;
+        let last = entries.iter().map(|(_, seq)| *seq).max();
+        self.last_seq = self.last_seq.max(last.unwrap_or(0));
+        Ok(())
     }
 
     pub fn encode_document(document: &Document) -> Result<Vec<u8>> {
         Ok(bincode::serialize(document)?)
     }
 
-    pub fn append_batch(&mut self, entries: &[(uuid::Uuid, Vec<u8>)]) -> Result<Vec<EntryPointer>> {
-        let total_bytes: u64 = entries.iter().map(|(_, bytes)| bytes.len() as u64).sum();
-        let required_size = self.append_cursor + total_bytes;
-        grow_mmap_if_needed(&mut self.mmap, &self.data_file, required_size)?;
-
+    /// Append documents with their WAL sequences in one write.
+    pub fn append_batch(
+        &mut self,
+        entries: &[(uuid::Uuid, u64, Vec<u8>)],
+    ) -> Result<Vec<EntryPointer>> {
+        let overhead = if self.framed { FRAME_HEADER_LEN } else { 0 };
+        let mut buffer = Vec::with_capacity(
+            entries
+                .iter()
+                .map(|(_, _, bytes)| bytes.len() + overhead)
+                .sum(),
+        );
         let mut pointers = Vec::with_capacity(entries.len());
-        for (_, bytes) in entries {
-            let offset = self.append_cursor;
-            self.write_at(offset, bytes)?;
-            self.append_cursor += bytes.len() as u64;
-            pointers.push(EntryPointer::new(offset, bytes.len() as u32));
+        for (_, seq, bytes) in entries {
+            let offset = self.append_cursor + buffer.len() as u64;
+            if self.framed {
+                buffer.extend_from_slice(&encode_frame(0, *seq, bytes));
+            } else {
+                buffer.extend_from_slice(bytes);
+            }
+            pointers.push(EntryPointer::new(
+                offset + overhead as u64,
+                bytes.len() as u32,
+            ));
+        }
+        self.write_record(&buffer)?;
+        if self.framed {
+            let last = entries.iter().map(|(_, seq, _)| *seq).max();
+            self.last_seq = self.last_seq.max(last.unwrap_or(0));
         }
         Ok(pointers)
     }
 
     pub fn read_document(&self, pointer: &EntryPointer) -> Result<Document> {
-        let bytes = self.read_bytes(pointer).map_err(|e| {
-            StorageError::ReadFailed(format!(
-                "failed to read document at offset {} length {}: {e}",
-                pointer.offset, pointer.length
-            ))
-        })?;
-        bincode::deserialize(&bytes).map_err(|e| {
-            StorageError::CorruptedData(format!(
-                "failed to decode document at offset {} length {}: {e}",
-                pointer.offset, pointer.length
-            ))
-            .into()
-        })
+        decode_document(self.read_bytes(pointer), pointer)
+    }
+
+    /// Rebuild the pointer index by scanning the whole file. Appends continue after the last
+    /// record the scan found.
+    pub fn rebuild_index(&mut self) -> Result<RecordScan> {
+        let scan = match self.mmap.as_ref() {
+            Some(mmap) => scan_records(&mmap[..]),
+            None => {
+                let mut data = Vec::new();
+                let mut file = self.data_file.try_clone()?;
+                file.seek(SeekFrom::Start(0))?;
+                file.read_to_end(&mut data)?;
+                scan_records(&data)
+            }
+        };
+        self.append_cursor = self.append_cursor.max(scan.end_offset);
+        self.last_seq = self.last_seq.max(scan.last_seq);
+        Ok(scan)
+    }
+
+    /// On-disk format version of the data file.
+    pub fn version(&self) -> u32 {
+        if self.framed {
+            RECORD_VERSION
+        } else {
+            LEGACY_RECORD_VERSION
+        }
+    }
+
+    /// Highest WAL sequence written to the file since the checkpointed index, or by this instance.
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
     }
 
     pub fn used_bytes(&self) -> u64 {
         self.append_cursor
     }
 
+    /// Bytes the record behind `pointer` takes up in the file, framing included.
+    pub fn stored_len(&self, pointer: &EntryPointer) -> u64 {
+        let framing = if self.framed { FRAME_HEADER_LEN } else { 0 };
+        pointer.length as u64 + framing as u64
+    }
+
+    /// Bytes taken by records, live or not.
+    pub fn record_bytes(&self) -> u64 {
+        let header = if self.framed { FILE_HEADER_LEN } else { 0 };
+        self.append_cursor.saturating_sub(header)
+    }
+
+    /// Bytes taken by the records `index` points at.
+    pub fn live_bytes(&self, index: &HashMap<Uuid, EntryPointer>) -> u64 {
+        index.values().map(|pointer| self.stored_len(pointer)).sum()
+    }
+
     pub fn mapped_len(&self) -> usize {
         self.mmap
             .as_ref()
             .map(|mmap| mmap.len())
-            .unwrap_or_else(|| {
-                self.data_file
-                    .metadata()
-                    .map(|meta| meta.len() as usize)
-                    .unwrap_or(0)
-            })
+            .unwrap_or_else(|| self.data_file.file_len().unwrap_or(0) as usize)
+    }
+
+    // Bytes of the data file mapping currently in memory; reads without mmap hold none
+    pub fn resident_bytes(&self) -> usize {
+        self.mmap.as_ref().map_or(0, resident_mmap_bytes)
     }
 
     pub fn warm_page_cache(&self) {
@@ -118,6 +460,35 @@ impl RecordStore {
         Ok(())
     }
 
+    // Frames appended after the checkpoint the index came from (including tombstones, which the
+    // index never points at) must not be overwritten, so appends resume after the last valid one.
+    fn skip_tail_frames(&mut self) -> Result<()> {
+        loop {
+            let header = self.read_range(self.append_cursor, FRAME_HEADER_LEN)?;
+            let Some(frame) = parse_frame_header(&header) else {
+                return Ok(());
+            };
+            let payload = self.read_range(
+                self.append_cursor + FRAME_HEADER_LEN as u64,
+                frame.len as usize,
+            )?;
+            if payload.len() != frame.len as usize || frame_crc(&header, &payload) != frame.crc {
+                return Ok(());
+            }
+            self.last_seq = self.last_seq.max(frame.seq);
+            self.append_cursor += (FRAME_HEADER_LEN + payload.len()) as u64;
+        }
+    }
+
+    fn write_record(&mut self, bytes: &[u8]) -> Result<()> {
+        let offset = self.append_cursor;
+        let required_size = offset + bytes.len() as u64;
+        grow_mmap_if_needed(&mut self.mmap, &*self.data_file, required_size)?;
+        self.write_at(offset, bytes)?;
+        self.append_cursor = required_size;
+        Ok(())
+    }
+
     fn read_bytes(&self, pointer: &EntryPointer) -> Result<Vec<u8>> {
         let offset = pointer.offset as usize;
         let length = pointer.length as usize;
@@ -127,10 +498,22 @@ impl RecordStore {
             }
         }
 
-        let mut file = self.data_file.try_clone()?;
         let mut buffer = vec![0u8; length];
-        file.seek(SeekFrom::Start(pointer.offset))?;
-        file.read_exact(&mut buffer)?;
+        self.data_file.read_exact_at(&mut buffer, pointer.offset)?;
+        Ok(buffer)
+    }
+
+    // Up to `length` bytes at `offset`, fewer if the file ends first.
+    fn read_range(&self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        if let Some(mmap) = self.mmap.as_ref() {
+            let start = (offset as usize).min(mmap.len());
+            let end = start.saturating_add(length).min(mmap.len());
+            return Ok(mmap[start..end].to_vec());
+        }
+        let mut file = self.data_file.try_clone()?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = Vec::with_capacity(length.min(1 << 20));
+        file.take(length as u64).read_to_end(&mut buffer)?;
         Ok(buffer)
     }
 
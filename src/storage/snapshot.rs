@@ -0,0 +1,276 @@
+// Collection snapshot archives. A snapshot is a tar file that starts with a `snapshot.json` descriptor, followed by the collection's data file, checkpoint manifest, generation files and WAL segments. Names inside the archive use the neutral base `collection.db` instead of the collection's own file name, so a snapshot can be restored under any name. Every file is listed in the descriptor with its size and CRC32, and restore checks both.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, StorageError};
+use crate::storage::persistence::{load_manifest_in, manifest_path};
+use crate::storage::vfs::{FileOptions, OsVfs, Vfs};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+const DESCRIPTOR_NAME: &str = "snapshot.json";
+const ARCHIVE_BASE: &str = "collection.db";
+
+/// One collection file stored in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub name: String,
+    pub size_bytes: u64,
+    pub crc32: u32,
+}
+
+/// What a snapshot contains. Written as the first entry of the archive so it can be read without
+/// unpacking the rest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDescriptor {
+    pub version: u32,
+    pub collection: String,
+    pub created_at: u64,
+    pub last_checkpoint_seq: u64,
+    pub vector_count: usize,
+    pub files: Vec<SnapshotFile>,
+}
+
+fn corrupted(message: impl Into<String>) -> StorageError {
+    StorageError::CorruptedData(format!("invalid snapshot: {}", message.into()))
+}
+
+// Errors from parsing the tar stream itself mean the archive is damaged, not that the disk failed.
+fn malformed(error: std::io::Error) -> StorageError {
+    corrupted(format!("unreadable archive: {error}"))
+}
+
+fn base_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Name a collection file gets inside the archive, e.g. `docs.db.wal.db.00000000000000000007`
+// becomes `collection.db.wal.db.00000000000000000007`.
+fn archive_name(collection_path: &str, file: &Path) -> Result<String> {
+    let base = base_name(Path::new(collection_path));
+    let name = base_name(file);
+    let suffix = name.strip_prefix(&base).ok_or_else(|| {
+        corrupted(format!(
+            "{} does not belong to collection {}",
+            file.display(),
+            collection_path
+        ))
+    })?;
+    Ok(format!("{ARCHIVE_BASE}{suffix}"))
+}
+
+// The manifest names generation files, so it is stored and restored with its names rebased.
+fn rebased_manifest(vfs: &dyn Vfs, collection_path: &str, from: &str, to: &str) -> Result<Vec<u8>> {
+    let manifest = load_manifest_in(vfs, collection_path)?
+        .ok_or_else(|| corrupted("collection has no checkpoint manifest"))?;
+    Ok(serde_json::to_vec_pretty(&manifest.rebased(from, to))?)
+}
+
+fn file_crc32(path: &Path) -> Result<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finalize())
+}
+
+fn append_entry<W: Write, R: Read>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    size: u64,
+    data: R,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Archive `files` of the collection stored at `collection_path` into `archive`. The archive is
+/// written to a temporary name and renamed once complete. The caller must keep the files stable
+/// (for example by holding the collection lock) until this returns.
+pub fn write_snapshot(
+    archive: &Path,
+    collection_path: &str,
+    mut descriptor: SnapshotDescriptor,
+    files: &[PathBuf],
+) -> Result<SnapshotDescriptor> {
+    let manifest_name = base_name(Path::new(&manifest_path(collection_path)));
+    let source_base = base_name(Path::new(collection_path));
+    let manifest = rebased_manifest(&OsVfs, collection_path, &source_base, ARCHIVE_BASE)?;
+
+    descriptor.files.clear();
+    for file in files {
+        let (size_bytes, crc32) = if base_name(file) == manifest_name {
+            (manifest.len() as u64, crc32fast::hash(&manifest))
+        } else {
+            (fs::metadata(file)?.len(), file_crc32(file)?)
+        };
+        descriptor.files.push(SnapshotFile {
+            name: archive_name(collection_path, file)?,
+            size_bytes,
+            crc32,
+        });
+    }
+
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = PathBuf::from(format!("{}.tmp", archive.display()));
+    let result = (|| -> Result<()> {
+        let mut builder = tar::Builder::new(File::create(&tmp)?);
+        let encoded = serde_json::to_vec_pretty(&descriptor)?;
+        append_entry(
+            &mut builder,
+            DESCRIPTOR_NAME,
+            encoded.len() as u64,
+            &encoded[..],
+        )?;
+        for (file, entry) in files.iter().zip(&descriptor.files) {
+            if base_name(file) == manifest_name {
+                append_entry(&mut builder, &entry.name, entry.size_bytes, &manifest[..])?;
+            } else {
+                // Read exactly the size that was checksummed.
+                let reader = File::open(file)?.take(entry.size_bytes);
+                append_entry(&mut builder, &entry.name, entry.size_bytes, reader)?;
+            }
+        }
+        let file = builder.into_inner()?;
+        file.sync_all()?;
+        Ok(())
+    })();
+    if let Err(error) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(error);
+    }
+    fs::rename(&tmp, archive)?;
+    Ok(descriptor)
+}
+
+fn decode_descriptor<R: Read>(mut entry: tar::Entry<'_, R>) -> Result<SnapshotDescriptor> {
+    if entry.path().map_err(malformed)?.to_string_lossy() != DESCRIPTOR_NAME {
+        return Err(corrupted(format!("first entry is not {DESCRIPTOR_NAME}")).into());
+    }
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes).map_err(malformed)?;
+    let descriptor: SnapshotDescriptor =
+        serde_json::from_slice(&bytes).map_err(|e| corrupted(e.to_string()))?;
+    if descriptor.version != SNAPSHOT_VERSION {
+        return Err(corrupted(format!(
+            "unsupported version {}, expected {}",
+            descriptor.version, SNAPSHOT_VERSION
+        ))
+        .into());
+    }
+    Ok(descriptor)
+}
+
+/// Read only the descriptor of a snapshot archive.
+pub fn read_snapshot_descriptor(archive: &Path) -> Result<SnapshotDescriptor> {
+    let mut archive = tar::Archive::new(BufReader::new(File::open(archive)?));
+    let entry = archive
+        .entries()
+        .map_err(malformed)?
+        .next()
+        .ok_or_else(|| corrupted("archive is empty"))?
+        .map_err(malformed)?;
+    decode_descriptor(entry)
+}
+
+/// Unpack a snapshot into `dest_dir`, naming its files after `collection_base` (the file name of
+/// the collection being restored, e.g. `docs.db`). Every file is checked against the descriptor.
+pub fn extract_snapshot<R: Read>(
+    reader: R,
+    dest_dir: &Path,
+    collection_base: &str,
+) -> Result<SnapshotDescriptor> {
+    extract_snapshot_in(&OsVfs, reader, dest_dir, collection_base)
+}
+
+/// `extract_snapshot` into a directory of `vfs`.
+pub fn extract_snapshot_in<R: Read>(
+    vfs: &dyn Vfs,
+    reader: R,
+    dest_dir: &Path,
+    collection_base: &str,
+) -> Result<SnapshotDescriptor> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries().map_err(malformed)?;
+    let descriptor = decode_descriptor(
+        entries
+            .next()
+            .ok_or_else(|| corrupted("archive is empty"))?
+            .map_err(malformed)?,
+    )?;
+
+    let mut seen = Vec::new();
+    for entry in entries {
+        let mut entry = entry.map_err(malformed)?;
+        let name = entry
+            .path()
+            .map_err(malformed)?
+            .to_string_lossy()
+            .into_owned();
+        // Only plain file names of this collection, so an archive cannot write elsewhere.
+        let suffix = name
+            .strip_prefix(ARCHIVE_BASE)
+            .filter(|suffix| !suffix.contains(['/', '\\']))
+            .ok_or_else(|| corrupted(format!("unexpected entry {name}")))?;
+        let expected = descriptor
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .ok_or_else(|| corrupted(format!("{name} is not listed in the descriptor")))?;
+
+        let target = dest_dir.join(format!("{collection_base}{suffix}"));
+        let mut bytes_written = 0u64;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut out = vfs.open(&target, FileOptions::truncate())?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = entry.read(&mut buf).map_err(malformed)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            out.write_all(&buf[..read])?;
+            bytes_written += read as u64;
+        }
+        out.sync_all()?;
+        if bytes_written != expected.size_bytes || hasher.finalize() != expected.crc32 {
+            return Err(corrupted(format!("{name} does not match its checksum")).into());
+        }
+        seen.push(name);
+    }
+
+    if let Some(missing) = descriptor
+        .files
+        .iter()
+        .find(|file| !seen.contains(&file.name))
+    {
+        return Err(corrupted(format!("{} is missing", missing.name)).into());
+    }
+
+    // Point the restored manifest at the restored generation files.
+    let manifest = dest_dir.join(format!("{collection_base}.manifest"));
+    if vfs.exists(&manifest) {
+        let path = dest_dir.join(collection_base);
+        let rebased =
+            rebased_manifest(vfs, &path.to_string_lossy(), ARCHIVE_BASE, collection_base)?;
+        vfs.write(&manifest, &rebased)?;
+    }
+    Ok(descriptor)
+}
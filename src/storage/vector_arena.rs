@@ -0,0 +1,655 @@
+// Dense vector arena: every vector of a collection in one memory-mapped file of fixed-size rows.
+//
+// Layout: a 64-byte header (`PVEC`, version u32, dimensions u32, flags u32, generation u64,
+// seq u64, rows u64), then `rows` rows of 16 id bytes followed by `dimensions` f32 components in
+// little-endian order. A nil id marks a free row, which later vectors reuse. Readers get slices
+// straight into the map, so lookups neither copy nor decode.
+//
+// The file is a cache of the vectors in the data file, valid for one checkpoint. A checkpoint
+// flushes the rows and then marks the header clean with its generation and seq. The first change
+// after that durably clears the mark before touching any row, so after a crash the arena is
+// rebuilt instead of trusted. The file is opened, sized, synced and removed through the
+// collection's VFS; only its rows are read and written through the map.
+//
+// Read views share the map instead of copying rows. While a view is alive, a changed or removed
+// vector moves to a fresh row and its old row is retired rather than overwritten; retired rows
+// are reused once every view that could reach them is gone. A map replaced by growth or a clear
+// stays alive for as long as views still read it.
+use memmap2::{MmapMut, MmapOptions};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, OnceLock, Weak};
+use uuid::Uuid;
+
+use crate::error::{Result, StorageError};
+use crate::storage::persistence::{create_mmap, release_mmap_range, warm_mmap};
+use crate::storage::vfs::{self, os_vfs, FileOptions, Vfs, VfsFile};
+
+// Rows are handed out as `&[f32]` without conversion.
+const _: () = assert!(cfg!(target_endian = "little"));
+
+const MAGIC: &[u8; 4] = b"PVEC";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 64;
+const ID_LEN: usize = 16;
+const CLEAN: u32 = 1;
+
+pub fn arena_path(collection_path: &str) -> String {
+    format!("{}.vectors.db", collection_path)
+}
+
+/// One mapping of the arena, shared by the arena and the read views published from it.
+pub struct ArenaMap {
+    map: MmapMut,
+    // Taken from the map once, so writes through a shared map keep the mutable provenance
+    ptr: *mut u8,
+    file_backed: bool,
+}
+
+// SAFETY: the pointer targets the map this struct owns. The arena only writes bytes no view can
+// reach (fresh rows, retired rows' ids, the header), so shared reads never race a write.
+unsafe impl Send for ArenaMap {}
+unsafe impl Sync for ArenaMap {}
+
+impl ArenaMap {
+    fn new(mut map: MmapMut, file_backed: bool) -> Self {
+        let ptr = map.as_mut_ptr();
+        Self {
+            map,
+            ptr,
+            file_backed,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Drop the pages covering `range` from memory; the next read faults them back in from the
+    /// file. Does nothing for an in-memory map, whose pages hold the only copy.
+    pub fn release(&self, range: Range<usize>) {
+        if self.file_backed {
+            release_mmap_range(&self.map, range.start, range.len());
+        }
+    }
+
+    fn bytes(&self, range: Range<usize>) -> &[u8] {
+        &self.map[range]
+    }
+
+    fn vector(&self, start: usize, dimensions: usize) -> &[f32] {
+        assert!(start + dimensions * 4 <= self.map.len());
+        // SAFETY: the map is page-aligned and the header and rows are multiples of 4 bytes, so
+        // `start` is f32-aligned; the row lies within the map, and f32 is little-endian here.
+        unsafe { std::slice::from_raw_parts(self.ptr.add(start).cast::<f32>(), dimensions) }
+    }
+
+    // Only the arena calls this, and only for bytes no published view reads
+    fn write(&self, offset: usize, bytes: &[u8]) {
+        assert!(offset + bytes.len() <= self.map.len());
+        // SAFETY: the range lies within the map, and views never read it (see `Send`/`Sync`).
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr.add(offset), bytes.len()) }
+    }
+}
+
+// Held by every read view. A pin keeps the pins of later views alive, so once the pin that was
+// current when a row was retired is gone, so is every view that could still reach the row.
+#[derive(Default)]
+struct ArenaPin {
+    next: OnceLock<Arc<ArenaPin>>,
+}
+
+impl Drop for ArenaPin {
+    // Unlink the chain iteratively; a long-lived view can keep thousands of later pins alive
+    fn drop(&mut self) {
+        let mut next = self.next.take();
+        while let Some(pin) = next {
+            next = Arc::into_inner(pin).and_then(|mut pin| pin.next.take());
+        }
+    }
+}
+
+/// The arena's rows as a read view sees them: rows it can reach are neither overwritten nor
+/// reused until it is dropped.
+#[derive(Clone)]
+pub struct ArenaView {
+    map: Option<Arc<ArenaMap>>,
+    dimensions: usize,
+    _pin: Arc<ArenaPin>,
+}
+
+impl ArenaView {
+    pub fn map(&self) -> Option<&ArenaMap> {
+        self.map.as_deref()
+    }
+
+    pub fn vector_at(&self, row: u32) -> &[f32] {
+        let map = self.map.as_ref().expect("arena rows are mapped");
+        map.vector(row_offset(row, self.dimensions) + ID_LEN, self.dimensions)
+    }
+
+    /// Byte range of `row` within the map.
+    pub fn row_range(&self, row: u32) -> Range<usize> {
+        let start = row_offset(row, self.dimensions);
+        start..start + stride(self.dimensions)
+    }
+}
+
+pub struct VectorArena {
+    // None for an arena that only lives in memory
+    path: Option<String>,
+    vfs: Option<Arc<dyn Vfs>>,
+    file: Option<Box<dyn VfsFile>>,
+    mmap: Option<Arc<ArenaMap>>,
+    dimensions: usize,
+    rows: HashMap<Uuid, u32>,
+    row_ids: Vec<Uuid>,
+    free: Vec<u32>,
+    // Whether the header on disk still carries the clean mark of the last checkpoint
+    clean: bool,
+    // Pin of the latest view handed out
+    pin: Weak<ArenaPin>,
+    // Rows given up while views were alive, with the pin that was current at the time
+    retired: Vec<(Weak<ArenaPin>, Vec<u32>)>,
+    // In-memory maps replaced while views still read them
+    detached: Vec<Weak<ArenaMap>>,
+    remaps: u64,
+}
+
+impl VectorArena {
+    /// An arena backed by anonymous memory, for collections that do not use mmap.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            vfs: None,
+            file: None,
+            mmap: None,
+            dimensions: 0,
+            rows: HashMap::new(),
+            row_ids: Vec::new(),
+            free: Vec::new(),
+            clean: false,
+            pin: Weak::new(),
+            retired: Vec::new(),
+            detached: Vec::new(),
+            remaps: 0,
+        }
+    }
+
+    /// Open the arena file at `path`. Its rows are kept only when the header was marked clean by
+    /// the checkpoint `(generation, seq)` the collection is opening from; otherwise the file is
+    /// started afresh. Returns the arena and whether its rows were kept.
+    pub fn open(path: &str, checkpoint: Option<(u64, u64)>) -> Result<(Self, bool)> {
+        Self::open_in(os_vfs(), path, checkpoint)
+    }
+
+    /// Like `open`, with the file in `vfs`, which must support mmap.
+    pub fn open_in(
+        vfs: Arc<dyn Vfs>,
+        path: &str,
+        checkpoint: Option<(u64, u64)>,
+    ) -> Result<(Self, bool)> {
+        if let Some(arena) = Self::open_clean(&vfs, path, checkpoint)? {
+            return Ok((arena, true));
+        }
+        let (file, mmap) = create_file(&*vfs, path)?;
+        let mut arena = Self {
+            path: Some(path.to_string()),
+            vfs: Some(vfs),
+            mmap: Some(Arc::new(mmap)),
+            file: Some(file),
+            ..Self::in_memory()
+        };
+        arena.write_header(0, 0, 0);
+        Ok((arena, false))
+    }
+
+    fn open_clean(
+        vfs: &Arc<dyn Vfs>,
+        path: &str,
+        checkpoint: Option<(u64, u64)>,
+    ) -> Result<Option<Self>> {
+        let Some((generation, seq)) = checkpoint else {
+            return Ok(None);
+        };
+        let file = match vfs.open(Path::new(path), FileOptions::existing()) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        if file.file_len()? < HEADER_LEN as u64 {
+            return Ok(None);
+        }
+        let mmap = map_file(&*file)?;
+        let header = &mmap[..HEADER_LEN];
+        let trusted = &header[..4] == MAGIC
+            && read_u32(&header[4..8]) == VERSION
+            && read_u32(&header[12..16]) == CLEAN
+            && read_u64(&header[16..24]) == generation
+            && read_u64(&header[24..32]) == seq;
+        if !trusted {
+            return Ok(None);
+        }
+        let dimensions = read_u32(&header[8..12]) as usize;
+        let row_count = read_u64(&header[32..40]) as usize;
+        if HEADER_LEN + row_count * stride(dimensions) > mmap.len() {
+            return Ok(None);
+        }
+
+        let mut arena = Self {
+            path: Some(path.to_string()),
+            vfs: Some(vfs.clone()),
+            file: Some(file),
+            dimensions,
+            rows: HashMap::with_capacity(row_count),
+            row_ids: Vec::with_capacity(row_count),
+            clean: true,
+            ..Self::in_memory()
+        };
+        for row in 0..row_count {
+            let start = row_offset(row as u32, dimensions);
+            let id = Uuid::from_slice(&mmap[start..start + ID_LEN])
+                .map_err(|e| StorageError::CorruptedData(format!("bad arena row id: {e}")))?;
+            if id.is_nil() {
+                arena.free.push(row as u32);
+            } else {
+                arena.rows.insert(id, row as u32);
+            }
+            arena.row_ids.push(id);
+        }
+        arena.mmap = Some(Arc::new(ArenaMap::new(mmap, true)));
+        Ok(Some(arena))
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn contains_key(&self, id: &Uuid) -> bool {
+        self.rows.contains_key(id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &Uuid> + '_ {
+        self.rows.keys()
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&[f32]> {
+        let row = *self.rows.get(id)?;
+        Some(self.row(row))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, &[f32])> + '_ {
+        self.rows.iter().map(|(id, row)| (*id, self.row(*row)))
+    }
+
+    /// Row holding the vector of `id`.
+    pub fn row_of(&self, id: &Uuid) -> Option<u32> {
+        self.rows.get(id).copied()
+    }
+
+    /// Every id with its row.
+    pub fn rows(&self) -> impl Iterator<Item = (Uuid, u32)> + '_ {
+        self.rows.iter().map(|(id, row)| (*id, *row))
+    }
+
+    pub fn vector_at(&self, row: u32) -> &[f32] {
+        self.row(row)
+    }
+
+    /// Byte range of `row` within the map.
+    pub fn row_range(&self, row: u32) -> Range<usize> {
+        let start = self.row_offset(row);
+        start..start + self.stride()
+    }
+
+    pub fn map(&self) -> Option<&ArenaMap> {
+        self.mmap.as_deref()
+    }
+
+    pub fn mapped_len(&self) -> usize {
+        self.mmap.as_ref().map_or(0, |mmap| mmap.len())
+    }
+
+    /// Number of times the rows moved to a new map, through growth or a clear.
+    pub fn remaps(&self) -> u64 {
+        self.remaps
+    }
+
+    /// Whether the rows live in a file, so pages can be dropped from memory and read back later.
+    pub fn is_file_backed(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Drop the pages covering `range` from memory; the next read faults them back in from the
+    /// file. Does nothing for an in-memory arena, whose pages hold the only copy.
+    pub fn release(&self, range: Range<usize>) {
+        if let Some(mmap) = self.mmap.as_ref() {
+            mmap.release(range);
+        }
+    }
+
+    /// Bytes held by rows in use.
+    pub fn usage_bytes(&self) -> usize {
+        self.rows.len() * (self.stride() + std::mem::size_of::<(Uuid, u32)>())
+    }
+
+    /// Bytes kept only for read views: retired rows and in-memory maps replaced under them.
+    pub fn held_bytes(&self) -> usize {
+        let retired: usize = self.retired.iter().map(|(_, rows)| rows.len()).sum();
+        let detached: usize = self
+            .detached
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|map| map.len())
+            .sum();
+        retired * self.stride() + detached
+    }
+
+    /// Share the current rows with a read view. Until the view is dropped, no row it can reach
+    /// is overwritten or handed to another vector.
+    pub fn view(&mut self) -> ArenaView {
+        let pin = Arc::new(ArenaPin::default());
+        if let Some(previous) = self.pin.upgrade() {
+            let _ = previous.next.set(pin.clone());
+        }
+        self.pin = Arc::downgrade(&pin);
+        ArenaView {
+            map: self.mmap.clone(),
+            dimensions: self.dimensions,
+            _pin: pin,
+        }
+    }
+
+    /// Store `vector` for `id`. Its row is overwritten in place unless a view may still read it,
+    /// in which case the vector moves to a new row.
+    pub fn put(&mut self, id: Uuid, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dimensions {
+            if !self.rows.is_empty() {
+                return Err(StorageError::CorruptedData(format!(
+                    "vector has {} dimensions, the arena holds {}",
+                    vector.len(),
+                    self.dimensions
+                ))
+                .into());
+            }
+            // The first vector (again) fixes the row size
+            self.clear()?;
+            self.dimensions = vector.len();
+        }
+        self.mark_dirty()?;
+        self.reclaim();
+        let row = match self.rows.get(&id).copied() {
+            Some(row) if !self.pinned() => row,
+            existing => {
+                let row = self.allocate()?;
+                if let Some(old) = existing {
+                    self.retire(old);
+                }
+                self.rows.insert(id, row);
+                self.row_ids[row as usize] = id;
+                row
+            }
+        };
+        let mut bytes = Vec::with_capacity(self.stride());
+        bytes.extend_from_slice(id.as_bytes());
+        for value in vector {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        self.map_ref().write(self.row_offset(row), &bytes);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Result<()> {
+        let Some(row) = self.rows.get(id).copied() else {
+            return Ok(());
+        };
+        self.mark_dirty()?;
+        self.reclaim();
+        self.rows.remove(id);
+        if self.pinned() {
+            self.retire(row);
+        } else {
+            self.row_ids[row as usize] = Uuid::nil();
+            self.free.push(row);
+            self.map_ref().write(self.row_offset(row), &[0; ID_LEN]);
+        }
+        Ok(())
+    }
+
+    /// Free the rows of ids that fail `keep`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Uuid) -> bool) -> Result<()> {
+        let dropped: Vec<Uuid> = self.rows.keys().filter(|id| !keep(id)).copied().collect();
+        for id in dropped {
+            self.remove(&id)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.mark_dirty()?;
+        if self.pinned() {
+            self.detach()?;
+        }
+        self.rows.clear();
+        self.row_ids.clear();
+        self.free.clear();
+        self.retired.clear();
+        self.dimensions = 0;
+        Ok(())
+    }
+
+    /// Flush every row and the file's size, then mark the header clean for checkpoint
+    /// `(generation, seq)`.
+    pub fn sync(&mut self, generation: u64, seq: u64) -> Result<()> {
+        let Some(file) = self.file.as_ref() else {
+            return Ok(());
+        };
+        if let Some(mmap) = self.mmap.as_ref() {
+            mmap.map.flush()?;
+        }
+        file.sync_all()?;
+        self.write_header(CLEAN, generation, seq);
+        if let Some(mmap) = self.mmap.as_ref() {
+            mmap.map.flush_range(0, HEADER_LEN)?;
+        }
+        self.clean = true;
+        Ok(())
+    }
+
+    pub fn warm(&self) {
+        if let Some(mmap) = self.mmap.as_ref() {
+            warm_mmap(&mmap.map);
+        }
+    }
+
+    // Durably drop the clean mark before the first change after a checkpoint.
+    fn mark_dirty(&mut self) -> Result<()> {
+        if !self.clean {
+            return Ok(());
+        }
+        if let Some(mmap) = self.mmap.as_ref() {
+            mmap.write(12, &0u32.to_le_bytes());
+            mmap.map.flush_range(0, HEADER_LEN)?;
+        }
+        self.clean = false;
+        Ok(())
+    }
+
+    fn write_header(&mut self, flags: u32, generation: u64, seq: u64) {
+        let Some(mmap) = self.mmap.as_ref() else {
+            return;
+        };
+        let mut header = [0u8; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..12].copy_from_slice(&(self.dimensions as u32).to_le_bytes());
+        header[12..16].copy_from_slice(&flags.to_le_bytes());
+        header[16..24].copy_from_slice(&generation.to_le_bytes());
+        header[24..32].copy_from_slice(&seq.to_le_bytes());
+        header[32..40].copy_from_slice(&(self.row_ids.len() as u64).to_le_bytes());
+        mmap.write(0, &header);
+    }
+
+    // Whether a view handed out earlier may still be reading rows
+    fn pinned(&self) -> bool {
+        self.pin.strong_count() > 0
+    }
+
+    // Give up a row a view may still read. Its id is cleared, which views never look at, so a
+    // reopened arena does not find the id twice; its vector stays until the row is reclaimed.
+    fn retire(&mut self, row: u32) {
+        self.row_ids[row as usize] = Uuid::nil();
+        self.map_ref().write(self.row_offset(row), &[0; ID_LEN]);
+        match self.retired.last_mut() {
+            Some((pin, rows)) if Weak::ptr_eq(pin, &self.pin) => rows.push(row),
+            _ => self.retired.push((self.pin.clone(), vec![row])),
+        }
+    }
+
+    // Hand back the retired rows no view can reach any more
+    fn reclaim(&mut self) {
+        let free = &mut self.free;
+        self.retired.retain_mut(|(pin, rows)| {
+            if pin.strong_count() > 0 {
+                return true;
+            }
+            free.append(rows);
+            false
+        });
+        self.detached.retain(|map| map.strong_count() > 0);
+    }
+
+    fn allocate(&mut self) -> Result<u32> {
+        if let Some(row) = self.free.pop() {
+            return Ok(row);
+        }
+        self.reserve_row()?;
+        self.row_ids.push(Uuid::nil());
+        Ok((self.row_ids.len() - 1) as u32)
+    }
+
+    // Make room for one more row at the end. The grown map replaces the current one; views keep
+    // reading the map they were given.
+    fn reserve_row(&mut self) -> Result<()> {
+        let required = self.row_offset(self.row_ids.len() as u32) + self.stride();
+        if required <= self.mapped_len() {
+            return Ok(());
+        }
+        let grown = match self.file.as_ref() {
+            Some(file) => {
+                file.set_len(required as u64 * 2)?;
+                ArenaMap::new(map_file(&**file)?, true)
+            }
+            None => {
+                let mut grown = MmapOptions::new().len(required * 2).map_anon()?;
+                if let Some(old) = self.mmap.as_ref() {
+                    grown[..old.len()].copy_from_slice(old.bytes(0..old.len()));
+                }
+                ArenaMap::new(grown, false)
+            }
+        };
+        self.replace_map(Some(Arc::new(grown)));
+        Ok(())
+    }
+
+    // Move to an empty map, leaving the current one to the views still reading it. A file-backed
+    // arena starts a new file; the old one is unlinked and lives on in those views' maps.
+    fn detach(&mut self) -> Result<()> {
+        let fresh = match (self.vfs.as_deref(), self.path.as_deref()) {
+            (Some(vfs), Some(path)) => {
+                let (file, mmap) = create_file(vfs, path)?;
+                self.file = Some(file);
+                Some(Arc::new(mmap))
+            }
+            _ => None,
+        };
+        self.replace_map(fresh);
+        self.write_header(0, 0, 0);
+        Ok(())
+    }
+
+    fn replace_map(&mut self, map: Option<Arc<ArenaMap>>) {
+        if let Some(old) = std::mem::replace(&mut self.mmap, map) {
+            // A file map shares its pages with its successor; only in-memory copies add up
+            if !old.file_backed && Arc::strong_count(&old) > 1 {
+                self.detached.push(Arc::downgrade(&old));
+            }
+        }
+        self.remaps += 1;
+    }
+
+    fn map_ref(&self) -> &ArenaMap {
+        self.mmap.as_ref().expect("arena rows are mapped")
+    }
+
+    fn stride(&self) -> usize {
+        stride(self.dimensions)
+    }
+
+    fn row_offset(&self, row: u32) -> usize {
+        row_offset(row, self.dimensions)
+    }
+
+    fn row(&self, row: u32) -> &[f32] {
+        self.map_ref()
+            .vector(self.row_offset(row) + ID_LEN, self.dimensions)
+    }
+}
+
+// A fresh arena file of just a header. Unlink rather than truncate, so an instance or view still
+// mapping the old file is unaffected.
+fn create_file(vfs: &dyn Vfs, path: &str) -> Result<(Box<dyn VfsFile>, ArenaMap)> {
+    let path = Path::new(path);
+    vfs::remove_if_exists(vfs, path)?;
+    let options = FileOptions {
+        truncate: true,
+        ..FileOptions::read_write()
+    };
+    let file = vfs.open(path, options)?;
+    file.set_len(HEADER_LEN as u64)?;
+    let mmap = ArenaMap::new(map_file(&*file)?, true);
+    Ok((file, mmap))
+}
+
+// The arena reads and writes its rows only through the map; the VFS handle owns the file
+fn map_file(file: &dyn VfsFile) -> Result<MmapMut> {
+    match file.as_file() {
+        Some(file) => create_mmap(file),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the vector arena needs a file system that supports mmap",
+        )
+        .into()),
+    }
+}
+
+fn stride(dimensions: usize) -> usize {
+    ID_LEN + dimensions * 4
+}
+
+fn row_offset(row: u32, dimensions: usize) -> usize {
+    HEADER_LEN + row as usize * stride(dimensions)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buf)
+}
@@ -0,0 +1,234 @@
+// Fault injection for recovery tests. `FaultVfs` wraps another backend and fails it on cue: a
+// crash part-way through a write, a failed fsync, or a full disk. After a crash or a failed fsync
+// every later change fails too, as if the process had died; a full disk only refuses writes.
+use parking_lot::Mutex;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{FileOptions, Vfs, VfsFile};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Die after `n` more bytes are written; the write that crosses the limit is torn.
+    CrashAfterBytes(u64),
+    /// Fail the fsync after `n` more successful ones, then die.
+    FailSync(u64),
+    /// Run out of space after `n` more bytes; later writes fail with `StorageFull`.
+    NoSpaceAfterBytes(u64),
+}
+
+#[derive(Default)]
+struct FaultState {
+    fault: Option<Fault>,
+    written: u64,
+    syncs: u64,
+    dead: bool,
+    full: bool,
+}
+
+impl FaultState {
+    fn alive(&self) -> io::Result<()> {
+        if self.dead {
+            return Err(io::Error::other("simulated crash"));
+        }
+        Ok(())
+    }
+
+    // How much of a `wanted`-byte write goes through
+    fn admit(&mut self, wanted: usize) -> io::Result<usize> {
+        self.alive()?;
+        if self.full {
+            return Err(no_space());
+        }
+        let limit = match self.fault {
+            Some(Fault::CrashAfterBytes(limit)) | Some(Fault::NoSpaceAfterBytes(limit)) => limit,
+            _ => return Ok(wanted),
+        };
+        let remaining = limit.saturating_sub(self.written);
+        if remaining == 0 && wanted > 0 {
+            return Err(match self.fault {
+                Some(Fault::NoSpaceAfterBytes(_)) => {
+                    self.full = true;
+                    no_space()
+                }
+                _ => {
+                    self.dead = true;
+                    io::Error::other("simulated crash")
+                }
+            });
+        }
+        let admitted = wanted.min(remaining as usize);
+        self.written += admitted as u64;
+        Ok(admitted)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.alive()?;
+        if let Some(Fault::FailSync(after)) = self.fault {
+            if self.syncs == after {
+                self.dead = true;
+                return Err(io::Error::other("simulated fsync failure"));
+            }
+        }
+        self.syncs += 1;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct FaultVfs {
+    inner: Arc<dyn Vfs>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl FaultVfs {
+    pub fn new(inner: Arc<dyn Vfs>) -> Self {
+        Self {
+            inner,
+            state: Arc::default(),
+        }
+    }
+
+    /// Arm `fault`, counting bytes and fsyncs from now. Replaces any earlier fault.
+    pub fn inject(&self, fault: Fault) {
+        *self.state.lock() = FaultState {
+            fault: Some(fault),
+            ..FaultState::default()
+        };
+    }
+
+    /// Disarm the fault and bring the file system back to life.
+    pub fn clear(&self) {
+        *self.state.lock() = FaultState::default();
+    }
+
+    /// Whether a `NoSpaceAfterBytes` fault has fired.
+    pub fn is_full(&self) -> bool {
+        self.state.lock().full
+    }
+
+    /// Whether the armed fault has fired.
+    pub fn tripped(&self) -> bool {
+        let state = self.state.lock();
+        state.dead || state.full
+    }
+}
+
+impl Vfs for FaultVfs {
+    fn open(&self, path: &Path, options: FileOptions) -> io::Result<Box<dyn VfsFile>> {
+        if options.write || options.append || options.create || options.truncate {
+            self.state.lock().alive()?;
+        }
+        Ok(Box::new(FaultFile {
+            inner: self.inner.open(path, options)?,
+            state: self.state.clone(),
+        }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.remove_file(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        self.inner.file_len(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.remove_dir_all(path)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.inner.read_dir(dir)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        self.state.lock().sync()?;
+        self.inner.sync_dir(dir)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+}
+
+struct FaultFile {
+    inner: Box<dyn VfsFile>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl Read for FaultFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for FaultFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let admitted = self.state.lock().admit(buf.len())?;
+        self.inner.write(&buf[..admitted])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.flush()
+    }
+}
+
+impl Seek for FaultFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl VfsFile for FaultFile {
+    fn file_len(&self) -> io::Result<u64> {
+        self.inner.file_len()
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.state.lock().alive()?;
+        self.inner.set_len(len)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.state.lock().sync()?;
+        self.inner.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.state.lock().sync()?;
+        self.inner.sync_data()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.inner.read_exact_at(buf, offset)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(FaultFile {
+            inner: self.inner.try_clone()?,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+fn no_space() -> io::Error {
+    io::Error::new(io::ErrorKind::StorageFull, "simulated full disk")
+}
@@ -0,0 +1,344 @@
+// Files kept in process memory. Clones share the same files, so a collection can be closed and
+// reopened from the same `MemoryVfs`. Directories are implicit: a directory exists once a file
+// has been created inside it or `create_dir_all` named it.
+//
+// With crash simulation on, the file system also tracks what would survive a power loss: a file's
+// contents as of its last fsync, and the names in a directory as of the directory's last fsync.
+// `simulate_crash` drops everything else, except that writes made since the last fsync may have
+// partly reached the disk.
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use super::{FileOptions, Vfs, VfsFile};
+
+#[derive(Default)]
+struct Inode {
+    data: Vec<u8>,
+    // Contents as of the last fsync; only kept with crash simulation on
+    synced: Vec<u8>,
+}
+
+type FileData = Arc<Mutex<Inode>>;
+
+#[derive(Default)]
+struct MemoryFs {
+    files: HashMap<PathBuf, FileData>,
+    dirs: HashSet<PathBuf>,
+    // Names as of the last fsync of their directory, with crash simulation on
+    durable: Option<HashMap<PathBuf, FileData>>,
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryVfs {
+    fs: Arc<Mutex<MemoryFs>>,
+}
+
+impl MemoryVfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A file system that tracks what has been fsynced, for `simulate_crash`.
+    pub fn with_crash_simulation() -> Self {
+        let vfs = Self::default();
+        vfs.fs.lock().durable = Some(HashMap::new());
+        vfs
+    }
+
+    /// Paths of every file, sorted.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<_> = self.fs.lock().files.keys().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Total bytes held by all files.
+    pub fn size_bytes(&self) -> u64 {
+        self.fs
+            .lock()
+            .files
+            .values()
+            .map(|inode| inode.lock().data.len() as u64)
+            .sum()
+    }
+
+    /// Reset the file system to what a power loss would leave: the names of the last directory
+    /// fsyncs, and the contents of the last file fsyncs plus a prefix (chosen by `seed`) of what
+    /// was written to each file since. Handles opened before the crash are not invalidated, so
+    /// everything using the file system should be dropped first. Does nothing without crash
+    /// simulation.
+    pub fn simulate_crash(&self, seed: u64) {
+        let mut fs = self.fs.lock();
+        let Some(durable) = fs.durable.clone() else {
+            return;
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut paths: Vec<_> = durable.keys().cloned().collect();
+        paths.sort();
+        for path in &paths {
+            let mut inode = durable[path].lock();
+            let survived = torn_write(&inode.data, &inode.synced, &mut rng);
+            inode.data = survived.clone();
+            inode.synced = survived;
+        }
+        fs.files = durable;
+    }
+
+    fn data(&self, path: &Path) -> io::Result<FileData> {
+        self.fs
+            .lock()
+            .files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+impl Vfs for MemoryVfs {
+    fn open(&self, path: &Path, options: FileOptions) -> io::Result<Box<dyn VfsFile>> {
+        let path = normalize(path);
+        let mut fs = self.fs.lock();
+        let data = match fs.files.get(&path) {
+            Some(data) => data.clone(),
+            None if options.create => {
+                let data = FileData::default();
+                fs.files.insert(path.clone(), data.clone());
+                data
+            }
+            None => return Err(not_found(&path)),
+        };
+        if options.truncate {
+            data.lock().data.clear();
+        }
+        Ok(Box::new(MemoryFile {
+            data,
+            pos: 0,
+            append: options.append,
+            track: fs.durable.is_some(),
+        }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut fs = self.fs.lock();
+        let data = fs
+            .files
+            .remove(&normalize(from))
+            .ok_or_else(|| not_found(from))?;
+        fs.files.insert(normalize(to), data);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.fs
+            .lock()
+            .files
+            .remove(&normalize(path))
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        let fs = self.fs.lock();
+        fs.files.contains_key(&path)
+            || fs.dirs.contains(&path)
+            || fs.files.keys().any(|file| file.starts_with(&path))
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        Ok(self.data(path)?.lock().data.len() as u64)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.fs.lock().dirs.insert(normalize(path));
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut fs = self.fs.lock();
+        if !fs.dirs.contains(&path) && !fs.files.keys().any(|file| file.starts_with(&path)) {
+            return Err(not_found(&path));
+        }
+        fs.files.retain(|file, _| !file.starts_with(&path));
+        fs.dirs.retain(|dir| !dir.starts_with(&path));
+        Ok(())
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir = normalize(dir);
+        let fs = self.fs.lock();
+        let entries: Vec<_> = fs
+            .files
+            .keys()
+            .filter(|file| file.parent() == Some(dir.as_path()))
+            .cloned()
+            .collect();
+        if entries.is_empty() && !dir.as_os_str().is_empty() && !fs.dirs.contains(&dir) {
+            return Err(not_found(&dir));
+        }
+        Ok(entries)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        let mut fs = self.fs.lock();
+        let in_dir: Vec<_> = fs
+            .files
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(dir.as_path()))
+            .map(|(path, data)| (path.clone(), data.clone()))
+            .collect();
+        if let Some(durable) = fs.durable.as_mut() {
+            durable.retain(|path, _| path.parent() != Some(dir.as_path()));
+            durable.extend(in_dir);
+        }
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.data(path)?.lock().data.clone())
+    }
+}
+
+struct MemoryFile {
+    data: FileData,
+    pos: u64,
+    append: bool,
+    track: bool,
+}
+
+impl MemoryFile {
+    fn sync(&self) {
+        if self.track {
+            let mut inode = self.data.lock();
+            inode.synced = inode.data.clone();
+        }
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inode = self.data.lock();
+        let data = &inode.data;
+        let start = (self.pos as usize).min(data.len());
+        let count = buf.len().min(data.len() - start);
+        buf[..count].copy_from_slice(&data[start..start + count]);
+        self.pos += count as u64;
+        Ok(count)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inode = self.data.lock();
+        let data = &mut inode.data;
+        if self.append {
+            self.pos = data.len() as u64;
+        }
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => (self.data.lock().data.len() as u64).checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = target.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+impl VfsFile for MemoryFile {
+    fn file_len(&self) -> io::Result<u64> {
+        Ok(self.data.lock().data.len() as u64)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.data.lock().data.resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.sync();
+        Ok(())
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.sync();
+        Ok(())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let inode = self.data.lock();
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|start| inode.data.get(start..start.checked_add(buf.len())?))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>> {
+        Ok(Box::new(MemoryFile {
+            data: self.data.clone(),
+            pos: self.pos,
+            append: self.append,
+            track: self.track,
+        }))
+    }
+}
+
+// What of `data` survives a crash when `synced` was last fsynced: the writes since then landed
+// in file order up to a random cut.
+fn torn_write(data: &[u8], synced: &[u8], rng: &mut StdRng) -> Vec<u8> {
+    let unchanged = data
+        .iter()
+        .zip(synced)
+        .take_while(|(now, then)| now == then)
+        .count();
+    if unchanged == data.len() && data.len() == synced.len() {
+        return synced.to_vec();
+    }
+    let cut = rng.gen_range(unchanged..=data.len());
+    let mut survived = data[..cut].to_vec();
+    if synced.len() > cut {
+        survived.extend_from_slice(&synced[cut..]);
+    }
+    survived
+}
+
+// Drop `.` components so `./a/b` and `a/b` name the same file.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found", path.display()),
+    )
+}
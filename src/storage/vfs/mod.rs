@@ -0,0 +1,150 @@
+// Storage backends. Everything a collection keeps on disk (data file, WAL segments, checkpoint
+// generations, manifest) is read and written through a `Vfs`, a small file-system interface:
+// open files, whole-file reads and writes, atomic rename, removal, directory listing and fsync.
+// `OsVfs` is the real file system; `MemoryVfs` keeps files in process memory, for ephemeral
+// collections and tests that should not touch disk, and can simulate a power loss. `FaultVfs`
+// wraps either to inject crashes, failed fsyncs and full disks.
+//
+// Memory maps need a real file, so only a backend that reports `supports_mmap` gets a mapped data
+// file and vector arena. Collections on other backends read and write the data file through the
+// file handle and keep their vectors in anonymous memory.
+mod fault;
+mod memory;
+mod os;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+pub use fault::{Fault, FaultVfs};
+pub use memory::MemoryVfs;
+pub use os::OsVfs;
+
+/// How to open a file, mirroring `std::fs::OpenOptions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileOptions {
+    pub read: bool,
+    pub write: bool,
+    pub append: bool,
+    pub create: bool,
+    pub truncate: bool,
+}
+
+impl FileOptions {
+    pub fn read_only() -> Self {
+        Self {
+            read: true,
+            ..Default::default()
+        }
+    }
+
+    // Read and write, creating the file if it is missing and keeping its contents otherwise
+    pub fn read_write() -> Self {
+        Self {
+            read: true,
+            write: true,
+            create: true,
+            ..Default::default()
+        }
+    }
+
+    // Read and write an existing file
+    pub fn existing() -> Self {
+        Self {
+            read: true,
+            write: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn append() -> Self {
+        Self {
+            append: true,
+            create: true,
+            ..Default::default()
+        }
+    }
+
+    // Start the file over, creating it if needed
+    pub fn truncate() -> Self {
+        Self {
+            read: true,
+            write: true,
+            create: true,
+            truncate: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// An open file of a `Vfs`.
+pub trait VfsFile: Read + Write + Seek + Send + Sync {
+    fn file_len(&self) -> io::Result<u64>;
+    fn set_len(&self, len: u64) -> io::Result<()>;
+    fn sync_all(&self) -> io::Result<()>;
+    fn sync_data(&self) -> io::Result<()>;
+    /// Fill `buf` from `offset` without moving the cursor, so shared handles can read concurrently.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+    /// A second handle on the same file.
+    fn try_clone(&self) -> io::Result<Box<dyn VfsFile>>;
+    /// The underlying OS file, for backends whose files can be memory-mapped.
+    fn as_file(&self) -> Option<&File> {
+        None
+    }
+}
+
+/// A file system collections can be stored in.
+pub trait Vfs: Send + Sync {
+    fn open(&self, path: &Path, options: FileOptions) -> io::Result<Box<dyn VfsFile>>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    fn file_len(&self, path: &Path) -> io::Result<u64>;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Remove `path` and everything under it.
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Paths of the files directly inside `dir`, in no particular order.
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+    /// Make renames and removals in `dir` durable.
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+
+    /// Whether files are OS files that can be memory-mapped.
+    fn supports_mmap(&self) -> bool {
+        false
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = self.open(path, FileOptions::read_only())?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let mut file = self.open(path, FileOptions::truncate())?;
+        file.write_all(bytes)
+    }
+}
+
+/// The process-wide handle on the real file system.
+pub fn os_vfs() -> Arc<dyn Vfs> {
+    static OS: OnceLock<Arc<dyn Vfs>> = OnceLock::new();
+    OS.get_or_init(|| Arc::new(OsVfs)).clone()
+}
+
+/// Remove `path`, treating a missing file as already removed.
+pub fn remove_if_exists(vfs: &dyn Vfs, path: &Path) -> io::Result<()> {
+    match vfs.remove_file(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Directory `path` lives in; "." for a bare file name.
+pub fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
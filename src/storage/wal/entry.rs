@@ -29,3 +29,31 @@ pub enum WalEntry {
         seq: u64,
     },
 }
+
+impl WalEntry {
+    pub fn seq(&self) -> u64 {
+        match self {
+            WalEntry::Insert { seq, .. }
+            | WalEntry::Update { seq, .. }
+            | WalEntry::Delete { seq, .. }
+            | WalEntry::Checkpoint { seq, .. } => *seq,
+        }
+    }
+
+    pub(super) fn set_seq(&mut self, value: u64) {
+        match self {
+            WalEntry::Insert { seq, .. }
+            | WalEntry::Update { seq, .. }
+            | WalEntry::Delete { seq, .. }
+            | WalEntry::Checkpoint { seq, .. } => *seq = value,
+        }
+    }
+}
+
+/// A WAL entry as read back from a segment, with the unix time in milliseconds it was appended
+/// at. Segments written before format version 3 did not record it.
+#[derive(Debug, Clone)]
+pub struct WalRecord {
+    pub entry: WalEntry,
+    pub written_at: Option<u64>,
+}
@@ -0,0 +1,208 @@
+// On-disk encoding of WAL segments. Version 2 and 3 segments start with the magic bytes and a little-endian version, then a sequence of frames:
+// [payload_len: u32 LE][crc32(payload): u32 LE][payload]
+// A version 3 payload is [written_at: u64 LE unix millis][bincode(WalEntry)]; a version 2 payload is the bare bincode entry.
+// Version 1 files start with a JSON header line, followed by one JSON-serialized entry per line.
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use super::entry::{WalEntry, WalRecord};
+use crate::error::Result;
+use crate::storage::vfs::{FileOptions, Vfs};
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct WalHeader {
+    version: u32,
+}
+
+pub(super) const LEGACY_WAL_VERSION: u32 = 1;
+pub(super) const UNTIMED_WAL_VERSION: u32 = 2;
+pub(super) const WAL_VERSION: u32 = 3;
+
+const WAL_MAGIC: &[u8; 4] = b"PWAL";
+const HEADER_LEN: usize = 8;
+const FRAME_HEADER_LEN: usize = 8;
+const TIMESTAMP_LEN: usize = 8;
+// Any length past this is treated as a corrupt length prefix rather than a real entry.
+const MAX_FRAME_LEN: usize = 256 * 1024 * 1024;
+
+// Entries decoded from one segment, and where decoding stopped if the tail was torn or corrupt.
+#[derive(Default)]
+pub(super) struct Decoded {
+    pub records: Vec<WalRecord>,
+    pub version: u32,
+    pub torn: Option<(u64, String)>,
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Decoded> {
+    if bytes.starts_with(WAL_MAGIC) {
+        decode_frames(bytes)
+    } else {
+        decode_lines(bytes)
+    }
+}
+
+pub(super) fn write_header(writer: &mut impl Write) -> Result<()> {
+    writer.write_all(WAL_MAGIC)?;
+    writer.write_all(&WAL_VERSION.to_le_bytes())?;
+    Ok(())
+}
+
+// Peek at the start of an existing WAL to tell the binary format from the JSON-lines one.
+pub(super) fn detect_version(vfs: &dyn Vfs, path: &Path) -> Result<Option<u32>> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    vfs.open(path, FileOptions::read_only())?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut bytes)?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    let magic_len = bytes.len().min(WAL_MAGIC.len());
+    if bytes[..magic_len] == WAL_MAGIC[..magic_len] {
+        if bytes.len() < HEADER_LEN {
+            // Torn header: the file never held a frame, so start it over.
+            vfs.write(path, &[])?;
+            return Ok(None);
+        }
+        return Ok(Some(read_u32(&bytes[4..HEADER_LEN])));
+    }
+    Ok(Some(LEGACY_WAL_VERSION))
+}
+
+// Frame an entry for a segment of `version`; only version 3 frames keep `written_at` (unix millis).
+pub(super) fn encode_frame(entry: &WalEntry, version: u32, written_at: u64) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    if version >= WAL_VERSION {
+        payload.extend_from_slice(&written_at.to_le_bytes());
+    }
+    bincode::serialize_into(&mut payload, entry)?;
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn decode_frames(bytes: &[u8]) -> Result<Decoded> {
+    let mut replay = Decoded {
+        version: WAL_VERSION,
+        ..Decoded::default()
+    };
+    if bytes.len() < HEADER_LEN {
+        return Ok(replay);
+    }
+    let version = read_u32(&bytes[4..HEADER_LEN]);
+    if version != WAL_VERSION && version != UNTIMED_WAL_VERSION {
+        return Err(crate::error::PiramidError::other(format!(
+            "Unsupported WAL version {}, expected {}",
+            version, WAL_VERSION
+        )));
+    }
+    replay.version = version;
+
+    let mut offset = HEADER_LEN;
+    while offset < bytes.len() {
+        let remaining = &bytes[offset..];
+        let reason = if remaining.len() < FRAME_HEADER_LEN {
+            Some("torn frame header".to_string())
+        } else {
+            let len = read_u32(&remaining[0..4]) as usize;
+            let crc = read_u32(&remaining[4..8]);
+            if len > MAX_FRAME_LEN {
+                Some(format!("frame length {} exceeds limit", len))
+            } else if remaining.len() < FRAME_HEADER_LEN + len {
+                Some("torn frame payload".to_string())
+            } else {
+                let payload = &remaining[FRAME_HEADER_LEN..FRAME_HEADER_LEN + len];
+                if crc32fast::hash(payload) != crc {
+                    Some("checksum mismatch".to_string())
+                } else {
+                    match decode_payload(payload, version) {
+                        Ok(record) => {
+                            replay.records.push(record);
+                            offset += FRAME_HEADER_LEN + len;
+                            None
+                        }
+                        Err(e) => Some(format!("undecodable frame: {}", e)),
+                    }
+                }
+            }
+        };
+        if let Some(reason) = reason {
+            replay.torn = Some((offset as u64, reason));
+            break;
+        }
+    }
+    Ok(replay)
+}
+
+fn decode_payload(payload: &[u8], version: u32) -> std::result::Result<WalRecord, String> {
+    let (written_at, entry) = if version >= WAL_VERSION {
+        if payload.len() < TIMESTAMP_LEN {
+            return Err("missing timestamp".to_string());
+        }
+        let (stamp, entry) = payload.split_at(TIMESTAMP_LEN);
+        let stamp = u64::from_le_bytes(stamp.try_into().unwrap_or_default());
+        (Some(stamp), entry)
+    } else {
+        (None, payload)
+    };
+    let entry = bincode::deserialize::<WalEntry>(entry).map_err(|e| e.to_string())?;
+    Ok(WalRecord { entry, written_at })
+}
+
+// Version 1 replay. A malformed line can only come from a torn append, so everything from it onwards is dropped.
+fn decode_lines(bytes: &[u8]) -> Result<Decoded> {
+    let mut replay = Decoded {
+        version: LEGACY_WAL_VERSION,
+        ..Decoded::default()
+    };
+
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let end = bytes[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|pos| offset + pos);
+        let line = &bytes[offset..end.unwrap_or(bytes.len())];
+        let next = end.map(|pos| pos + 1).unwrap_or(bytes.len());
+        if line.is_empty() {
+            offset = next;
+            continue;
+        }
+
+        // Skip header if present (and validate version)
+        if let Ok(header) = serde_json::from_slice::<WalHeader>(line) {
+            if header.version != LEGACY_WAL_VERSION {
+                return Err(crate::error::PiramidError::other(format!(
+                    "Unsupported WAL version {}, expected {}",
+                    header.version, LEGACY_WAL_VERSION
+                )));
+            }
+            offset = next;
+            continue;
+        }
+
+        // A final line without its newline was cut off mid-write, even if it happens to parse.
+        let parsed = match end {
+            Some(_) => serde_json::from_slice::<WalEntry>(line).map_err(|e| e.to_string()),
+            None => Err("torn line".to_string()),
+        };
+        match parsed {
+            Ok(entry) => replay.records.push(WalRecord {
+                entry,
+                written_at: None,
+            }),
+            Err(reason) => {
+                replay.torn = Some((offset as u64, reason));
+                break;
+            }
+        }
+        offset = next;
+    }
+    Ok(replay)
+}
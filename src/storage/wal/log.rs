@@ -1,35 +1,103 @@
-//  This module provides a simple JSON-based WAL that supports appending entries, replaying entries from a certain sequence number, and checkpointing. The WAL is designed to be durable and efficient, with support for rotation to prevent unbounded growth.
-
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::path::PathBuf;
-
-use super::entry::WalEntry;
-use crate::error::Result;
-// The WAL file starts with a header line containing the version number, followed by one JSON-serialized entry per line. Each entry includes a sequence number (seq) that is assigned when the entry is logged. The replay method reads the WAL file and returns all entries with a sequence number greater than a specified minimum sequence number (min_seq). The log method appends a new entry to the WAL file, automatically assigning it the next sequence number. The checkpoint method logs a special checkpoint entry that can be used to indicate a consistent state of the collection, allowing older entries to be safely discarded after checkpointing. The rotate method allows for rotating the WAL file by closing the current one and starting a new, empty file, which is typically done after checkpointing to prevent the WAL from growing indefinitely.
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct WalHeader {
-    version: u32,
+//  This module provides the collection write-ahead log. Entries are appended to the newest of a list of segment files; a segment is closed once it reaches `max_log_size` and a new one is started. Version 3 segments store one length-prefixed, CRC32-checksummed bincode frame per entry, stamped with the time it was appended, so a crash mid-append can be detected and cut off instead of failing recovery. Older segments (version 2 frames without the time, or version 1 with one JSON entry per line) are still read and appended to in their own format until the next rotation.
+
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::entry::{WalEntry, WalRecord};
+use super::format::{self, LEGACY_WAL_VERSION, WAL_VERSION};
+use super::segment::{
+    archive_segment, list_archived_segments_in, list_segments_in, segment_path, WalSegment,
+};
+use super::sync::{WalAck, WalSync};
+use crate::config::{WalConfig, WalSyncMode};
+use crate::error::{Result, StorageError};
+use crate::storage::vfs::{self, os_vfs, FileOptions, OsVfs, Vfs, VfsFile};
+
+/// Where replay stopped reading the WAL and how much was cut off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalTruncation {
+    pub segment: PathBuf,
+    pub offset: u64,
+    pub dropped_bytes: u64,
+    pub reason: String,
 }
 
-const WAL_VERSION: u32 = 1;
+/// The entries recovered from the WAL plus any torn or corrupt tail that was discarded.
+#[derive(Debug, Clone, Default)]
+pub struct WalReplay {
+    pub entries: Vec<WalEntry>,
+    pub version: u32,
+    pub truncated: Option<WalTruncation>,
+}
 
 pub struct Wal {
-    file: Option<BufWriter<File>>,
+    file: Option<BufWriter<Box<dyn VfsFile>>>,
+    vfs: Arc<dyn Vfs>,
     path: PathBuf,
+    // Oldest first; the last segment is the one being appended to.
+    segments: Vec<WalSegment>,
+    active_bytes: u64,
+    // Zero disables size-based rotation.
+    max_segment_bytes: u64,
     pub next_seq: u64,
+    // Format of the active segment; an older file keeps its format until it is rotated.
+    segment_version: u32,
+    // Archive segments instead of deleting them once a checkpoint covers them.
+    archive: bool,
+    sync: WalSync,
+    // Set when a failed append could not be rolled back; writes are refused until reopened.
+    failed: bool,
+    // First sequence and start offset of the last append (one entry or a batch) to the active
+    // segment, while it can still be taken back.
+    last_append: Option<(u64, u64)>,
 }
 
 impl Wal {
     /// Create a WAL writer starting at the provided sequence.
     pub fn new(path: PathBuf, next_seq: u64) -> Result<Self> {
-        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Self::open(path, next_seq, &WalConfig::default())
+    }
+
+    /// Create a WAL writer that syncs and rotates according to `config`.
+    pub fn open(path: PathBuf, next_seq: u64, config: &WalConfig) -> Result<Self> {
+        Self::open_in(os_vfs(), path, next_seq, config)
+    }
+
+    /// Create a WAL writer whose segments live in `vfs`.
+    pub fn open_in(
+        vfs: Arc<dyn Vfs>,
+        path: PathBuf,
+        next_seq: u64,
+        config: &WalConfig,
+    ) -> Result<Self> {
+        let mut segments = list_segments_in(&*vfs, &path)?;
+        if segments.is_empty() {
+            segments.push(WalSegment {
+                path: segment_path(&path, next_seq),
+                first_seq: next_seq,
+            });
+        }
         let mut wal = Wal {
-            file: Some(BufWriter::new(file)),
+            file: None,
+            vfs,
             path,
+            segments,
+            active_bytes: 0,
+            max_segment_bytes: config.max_log_size as u64,
             next_seq,
+            segment_version: WAL_VERSION,
+            archive: config.archive,
+            sync: WalSync::new(
+                config.effective_sync_mode(),
+                Duration::from_millis(config.group_commit_window_ms),
+                Duration::from_millis(config.sync_interval_ms.max(1)),
+            ),
+            failed: false,
+            last_append: None,
         };
-        wal.ensure_header()?;
+        wal.open_active()?;
         Ok(wal)
     }
 
@@ -37,68 +105,195 @@ impl Wal {
     pub fn disabled(path: PathBuf, next_seq: u64) -> Result<Self> {
         Ok(Wal {
             file: None,
+            vfs: os_vfs(),
             path,
+            segments: Vec::new(),
+            active_bytes: 0,
+            max_segment_bytes: 0,
             next_seq,
+            segment_version: WAL_VERSION,
+            archive: false,
+            sync: WalSync::new(WalSyncMode::Flush, Duration::ZERO, Duration::ZERO),
+            failed: false,
+            last_append: None,
         })
     }
 
     /// Replay entries with seq greater than `min_seq`.
-    pub fn replay(&self, min_seq: u64) -> Result<Vec<WalEntry>> {
+    pub fn replay(&mut self, min_seq: u64) -> Result<Vec<WalEntry>> {
+        Ok(self.recover(min_seq)?.entries)
+    }
+
+    /// Replay entries with seq greater than `min_seq` across all segments. A torn or corrupt frame
+    /// in the active (last) segment is where a crash cut an append short: the segment is truncated
+    /// there so appends resume right after the last good entry. The same damage in a sealed
+    /// segment is an error, since later segments hold entries written after it; `fsck --repair`
+    /// quarantines it (see `quarantine_damaged`). Damage the checkpoint already covers is ignored.
+    pub fn recover(&mut self, min_seq: u64) -> Result<WalReplay> {
         if self.file.is_none() {
-            return Ok(Vec::new());
+            return Ok(WalReplay::default());
         }
+        self.flush()?;
 
-        let file = File::open(&self.path)?;
-        let reader = BufReader::new(file);
-        let mut entries = Vec::new();
+        let mut replay = WalReplay::default();
+        let last = self.segments.len().saturating_sub(1);
+        for idx in 0..self.segments.len() {
+            let segment = self.segments[idx].clone();
+            let bytes = self.vfs.read(&segment.path)?;
+            let decoded = format::decode(&bytes)?;
+            replay
+                .entries
+                .extend(decoded.records.into_iter().map(|record| record.entry));
+            replay.version = decoded.version;
 
-        for line in reader.lines() {
-            let line = line?;
-            if line.is_empty() {
+            let Some((offset, reason)) = decoded.torn else {
                 continue;
-            }
-            // Skip header if present (and validate version)
-            if let Ok(header) = serde_json::from_str::<WalHeader>(&line) {
-                if header.version != WAL_VERSION {
-                    return Err(crate::error::PiramidError::other(format!(
-                        "Unsupported WAL version {}, expected {}",
-                        header.version, WAL_VERSION
-                    )));
-                }
-                continue;
-            }
-            let entry: WalEntry = serde_json::from_str(&line)?;
-            let entry_seq = match &entry {
-                WalEntry::Insert { seq, .. }
-                | WalEntry::Update { seq, .. }
-                | WalEntry::Delete { seq, .. }
-                | WalEntry::Checkpoint { seq, .. } => *seq,
             };
-            if entry_seq <= min_seq {
-                continue;
+            if idx < last {
+                // Everything in a sealed segment is below the next segment's first sequence
+                if self.segments[idx + 1].first_seq <= min_seq + 1 {
+                    continue;
+                }
+                return Err(StorageError::CorruptedData(format!(
+                    "sealed WAL segment {} is unreadable from offset {offset}: {reason}",
+                    segment.path.display()
+                ))
+                .into());
             }
-            entries.push(entry);
+            let dropped_bytes = bytes.len() as u64 - offset;
+            let file = self.vfs.open(&segment.path, FileOptions::existing())?;
+            file.set_len(offset)?;
+            file.sync_all()?;
+            self.open_active()?;
+            replay.truncated = Some(WalTruncation {
+                segment: segment.path,
+                offset,
+                dropped_bytes,
+                reason,
+            });
         }
 
-        Ok(entries)
+        // Never hand out a sequence that is already in the log.
+        if let Some(last) = replay.entries.iter().map(WalEntry::seq).max() {
+            self.next_seq = self.next_seq.max(last + 1);
+        }
+        replay.entries.retain(|entry| entry.seq() > min_seq);
+        Ok(replay)
     }
 
-    // Log a new WAL entry. This method assigns the next sequence number to the entry, serializes it to JSON, and appends it to the WAL file. If the WAL is disabled (file is None), it simply increments the sequence number without writing anything.
+    // Log a new WAL entry. This method assigns the next sequence number to the entry, frames it, and appends it to the active segment, first rolling over to a new segment if the active one has reached the size limit, so the last entry always sits in the active segment. If the WAL is disabled (file is None), it simply increments the sequence number without writing anything. In per-write mode the entry is fsynced before returning; group commit callers wait on `pending_ack` after releasing the collection lock. An append that fails leaves nothing behind, so an entry the caller was told failed is never replayed.
     pub fn log(&mut self, entry: &mut WalEntry) -> Result<()> {
-        match entry {
-            WalEntry::Insert { seq, .. }
-            | WalEntry::Update { seq, .. }
-            | WalEntry::Delete { seq, .. }
-            | WalEntry::Checkpoint { seq, .. } => {
-                *seq = self.next_seq;
+        self.log_batch(std::slice::from_mut(entry))
+    }
+
+    /// Log `entries` under consecutive sequence numbers in a single append, which `retract` with
+    /// the first one's sequence takes back as a whole. A batch never spans segments.
+    pub fn log_batch(&mut self, entries: &mut [WalEntry]) -> Result<()> {
+        if self.failed {
+            return Err(crate::error::PiramidError::other(format!(
+                "WAL {} could not undo a failed append; reopen the collection",
+                self.path.display()
+            )));
+        }
+        if self.file.is_none() {
+            for entry in entries {
+                entry.set_seq(self.next_seq);
+                self.next_seq += 1;
             }
+            return Ok(());
         }
-        if let Some(file) = &mut self.file {
-            let json = serde_json::to_string(entry)?;
-            writeln!(file, "{}", json)?;
-            file.flush()?;
+        if entries.is_empty() {
+            return Ok(());
         }
-        self.next_seq += 1;
+        if self.max_segment_bytes > 0 && self.active_bytes >= self.max_segment_bytes {
+            let rolled = self.roll();
+            if self.file.is_none() {
+                self.failed = true;
+            }
+            rolled?;
+        }
+        let Some(file) = &mut self.file else {
+            return Err(crate::error::PiramidError::other("WAL segment is not open"));
+        };
+
+        let written_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut bytes = Vec::new();
+        for (seq, entry) in (self.next_seq..).zip(entries.iter_mut()) {
+            entry.set_seq(seq);
+            if self.segment_version == LEGACY_WAL_VERSION {
+                bytes.extend_from_slice(&serde_json::to_vec(entry)?);
+                bytes.push(b'\n');
+            } else {
+                bytes.extend_from_slice(&format::encode_frame(
+                    entry,
+                    self.segment_version,
+                    written_at,
+                )?);
+            }
+        }
+        let last = self.next_seq + entries.len() as u64 - 1;
+        let per_write = self.sync.mode() == WalSyncMode::PerWrite;
+        if let Err(error) = append(file, &bytes, per_write) {
+            self.discard_failed_append();
+            return Err(error);
+        }
+        self.sync.appended(last, per_write);
+        self.last_append = Some((self.next_seq, self.active_bytes));
+        self.active_bytes += bytes.len() as u64;
+        self.next_seq = last + 1;
+        Ok(())
+    }
+
+    // Take back a frame whose append failed part way, e.g. on a full disk. The unwritten tail is
+    // dropped from the buffer instead of being flushed ahead of the next entry, and the segment is
+    // cut back to where the frame started. Appends reopen at the end of the file, so the same
+    // handle carries on from there.
+    fn discard_failed_append(&mut self) {
+        let Some(writer) = self.file.take() else {
+            return;
+        };
+        let (file, _unwritten) = writer.into_parts();
+        match file.set_len(self.active_bytes) {
+            Ok(()) => self.file = Some(BufWriter::new(file)),
+            Err(error) => {
+                tracing::warn!(error = %error, path = %self.path.display(), "wal_append_rollback_failed");
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Take back the last append, whose first entry was logged as `seq`, when the writes it
+    /// records could not be applied. The segment is cut back to where the append started and
+    /// synced, and `seq` is handed out again, so none of its entries is replayed. An append that
+    /// is no longer the last one in the active segment cannot be taken back; the WAL then refuses
+    /// writes until it is reopened.
+    pub fn retract(&mut self, seq: u64) -> Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+        let start = match self.last_append.take() {
+            Some((first, start)) if first == seq => start,
+            _ => {
+                self.failed = true;
+                return Err(crate::error::PiramidError::other(format!(
+                    "WAL {} cannot take back entry {seq}",
+                    self.path.display()
+                )));
+            }
+        };
+        let truncated = file
+            .flush()
+            .and_then(|_| file.get_ref().set_len(start))
+            .and_then(|_| file.get_ref().sync_data());
+        if let Err(error) = truncated {
+            self.failed = true;
+            return Err(error.into());
+        }
+        self.sync.retracted(seq);
+        self.active_bytes = start;
+        self.next_seq = seq;
         Ok(())
     }
 
@@ -108,22 +303,26 @@ impl Wal {
         Ok(())
     }
 
-    // Rotate the WAL file by closing the current one and starting a new, empty file. This is typically done after a checkpoint to prevent the WAL from growing indefinitely and to allow old entries to be safely discarded.
+    // Rotate after a checkpoint: start a fresh segment and delete every older one, since the checkpoint now covers all of their entries. The fresh segment is always written in the current format.
     pub fn rotate(&mut self) -> Result<()> {
         if self.file.is_none() {
             return Ok(());
         }
-        // Drop current writer to release handle
-        drop(self.file.take());
-        // Open a fresh, truncated WAL file
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.path)?;
-        file.sync_all()?;
-        self.file = Some(BufWriter::new(file));
-        self.ensure_header()?;
+        self.roll()?;
+        self.purge_before(self.next_seq)
+    }
+
+    /// Delete (or archive) segments whose entries all have a sequence below `seq`. The active
+    /// segment is kept.
+    pub fn purge_before(&mut self, seq: u64) -> Result<()> {
+        while self.segments.len() > 1 && self.segments[1].first_seq <= seq {
+            let segment = self.segments.remove(0);
+            if self.archive {
+                archive_segment(&*self.vfs, &self.path, &segment)?;
+                continue;
+            }
+            vfs::remove_if_exists(&*self.vfs, &segment.path)?;
+        }
         Ok(())
     }
 
@@ -134,22 +333,208 @@ impl Wal {
         Ok(())
     }
 
-    // Ensure the WAL file has a header with the correct version. If the file is new (size 0), we write the header. If the file already exists, we assume it has a valid header and do not modify it.
-    fn ensure_header(&mut self) -> Result<()> {
-        if self.file.is_none() {
-            return Ok(());
+    /// Durability tracker shared with writers waiting on group commit.
+    pub fn sync(&self) -> &WalSync {
+        &self.sync
+    }
+
+    /// Acknowledgement to wait on before reporting the latest append as durable, if any.
+    pub fn pending_ack(&self) -> Option<WalAck> {
+        self.sync.pending_ack()
+    }
+
+    /// On-disk format version of the segment currently being appended to.
+    pub fn version(&self) -> u32 {
+        self.segment_version
+    }
+
+    /// Base path the segment files are named after.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Segments on disk, oldest first; the last one is being appended to.
+    pub fn segments(&self) -> &[WalSegment] {
+        &self.segments
+    }
+
+    /// Memory held by the write buffer of the active segment.
+    pub fn buffer_bytes(&self) -> usize {
+        self.file.as_ref().map_or(0, BufWriter::capacity)
+    }
+
+    /// Size of one segment of this WAL.
+    pub fn segment_bytes(&self, segment: &WalSegment) -> u64 {
+        segment.size_bytes(&*self.vfs)
+    }
+
+    /// Total size of all segments.
+    pub fn size_bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| self.segment_bytes(segment))
+            .sum()
+    }
+
+    /// Entries with seq greater than `min_seq` still on disk for the WAL at `base`, archived
+    /// segments included, in sequence order and with the time each was appended where the segment
+    /// recorded it. Reading stops at the first torn or corrupt frame; nothing is modified.
+    pub fn read_history(base: &Path, min_seq: u64) -> Result<Vec<WalRecord>> {
+        Self::read_history_in(&OsVfs, base, min_seq)
+    }
+
+    /// `read_history` for a WAL whose segments live in `vfs`.
+    pub fn read_history_in(vfs: &dyn Vfs, base: &Path, min_seq: u64) -> Result<Vec<WalRecord>> {
+        let mut records: Vec<WalRecord> = Vec::new();
+        let segments = list_archived_segments_in(vfs, base)?
+            .into_iter()
+            .chain(list_segments_in(vfs, base)?);
+        for segment in segments {
+            let decoded = format::decode(&vfs.read(&segment.path)?)?;
+            records.extend(
+                decoded
+                    .records
+                    .into_iter()
+                    .filter(|record| record.entry.seq() > min_seq),
+            );
+            if decoded.torn.is_some() {
+                break;
+            }
+        }
+        records.sort_by_key(|record| record.entry.seq());
+        records.dedup_by_key(|record| record.entry.seq());
+        Ok(records)
+    }
+
+    /// Decode every live and archived segment of the WAL at `base` without modifying anything, and
+    /// report each segment that has a torn or corrupt frame.
+    pub fn inspect(base: &Path) -> Result<Vec<WalTruncation>> {
+        Self::inspect_in(&OsVfs, base)
+    }
+
+    /// `inspect` for a WAL whose segments live in `vfs`.
+    pub fn inspect_in(vfs: &dyn Vfs, base: &Path) -> Result<Vec<WalTruncation>> {
+        let mut damaged = Vec::new();
+        let segments = list_archived_segments_in(vfs, base)?
+            .into_iter()
+            .chain(list_segments_in(vfs, base)?);
+        for segment in segments {
+            let bytes = vfs.read(&segment.path)?;
+            let (offset, reason) = match format::decode(&bytes) {
+                Ok(decoded) => match decoded.torn {
+                    Some(torn) => torn,
+                    None => continue,
+                },
+                Err(error) => (0, error.to_string()),
+            };
+            damaged.push(WalTruncation {
+                segment: segment.path,
+                offset,
+                dropped_bytes: bytes.len() as u64 - offset,
+                reason,
+            });
         }
-        let metadata = std::fs::metadata(&self.path)?;
-        if metadata.len() == 0 {
-            if let Some(writer) = &mut self.file {
-                let header = WalHeader {
-                    version: WAL_VERSION,
-                };
-                let json = serde_json::to_string(&header)?;
-                writeln!(writer, "{}", json)?;
-                writer.flush()?;
+        Ok(damaged)
+    }
+
+    /// Move the first damaged sealed segment of the WAL at `base`, and every segment after it, out
+    /// of the log by renaming them to `<segment>.quarantine`. A copy of the damaged segment cut at
+    /// its last good frame takes its place, so the entries before the damage still replay. Returns
+    /// the quarantined paths; a torn tail in the last segment is left for `recover` to truncate.
+    pub fn quarantine_damaged(vfs: &dyn Vfs, base: &Path) -> Result<Vec<PathBuf>> {
+        let segments = list_segments_in(vfs, base)?;
+        let last = segments.len().saturating_sub(1);
+        for (idx, segment) in segments.iter().enumerate().take(last) {
+            let bytes = vfs.read(&segment.path)?;
+            let offset = match format::decode(&bytes) {
+                Ok(decoded) => match decoded.torn {
+                    Some((offset, _)) => offset,
+                    None => continue,
+                },
+                Err(_) => 0,
+            };
+
+            let mut quarantined = Vec::new();
+            for damaged in &segments[idx..] {
+                let target = PathBuf::from(format!("{}.quarantine", damaged.path.display()));
+                vfs.rename(&damaged.path, &target)?;
+                quarantined.push(target);
             }
+            if offset > 0 {
+                let mut file = vfs.open(&segment.path, FileOptions::truncate())?;
+                file.write_all(&bytes[..offset as usize])?;
+                file.sync_all()?;
+            }
+            vfs.sync_dir(&vfs::parent_dir(&segment.path))?;
+            return Ok(quarantined);
+        }
+        Ok(Vec::new())
+    }
+
+    // Close the active segment and start a new one named after the next sequence. The old segment is synced and reported as synced before the switch, since group commit waiters can only fsync the active file and nothing else marks the old appends durable.
+    fn roll(&mut self) -> Result<()> {
+        let active = self.segments.last().map(|segment| segment.first_seq);
+        if active == Some(self.next_seq) && self.segment_version == WAL_VERSION {
+            // Nothing has been appended since this segment was started.
+            return Ok(());
         }
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+            file.get_ref().sync_data()?;
+            self.sync.appended(self.next_seq.saturating_sub(1), true);
+        }
+        // The new segment gets its header before the switch, so a full disk leaves the active one
+        // in place.
+        let path = segment_path(&self.path, self.next_seq);
+        if let Err(error) = self.create_segment(&path) {
+            let _ = vfs::remove_if_exists(&*self.vfs, &path);
+            return Err(error);
+        }
+        self.last_append = None;
+        self.segments.push(WalSegment {
+            path,
+            first_seq: self.next_seq,
+        });
+        self.open_active()
+    }
+
+    fn create_segment(&self, path: &Path) -> Result<()> {
+        let mut file = self.vfs.open(path, FileOptions::truncate())?;
+        format::write_header(&mut file)?;
+        file.sync_all()?;
+        // A new segment's name must be durable before any fsynced append in it counts
+        self.vfs.sync_dir(&vfs::parent_dir(path))?;
         Ok(())
     }
+
+    // Open the last segment for appending, writing the header if it is new.
+    fn open_active(&mut self) -> Result<()> {
+        let Some(segment) = self.segments.last() else {
+            return Ok(());
+        };
+        drop(self.file.take());
+        let file = self.vfs.open(&segment.path, FileOptions::append())?;
+        self.segment_version =
+            format::detect_version(&*self.vfs, &segment.path)?.unwrap_or(WAL_VERSION);
+        self.sync.set_file(Some(file.try_clone()?));
+        let mut writer = BufWriter::new(file);
+        if self.vfs.file_len(&segment.path)? == 0 {
+            format::write_header(&mut writer)?;
+            writer.flush()?;
+            // A new segment's name must be durable before any fsynced append in it counts
+            self.vfs.sync_dir(&vfs::parent_dir(&segment.path))?;
+        }
+        self.active_bytes = self.vfs.file_len(&segment.path)?;
+        self.file = Some(writer);
+        Ok(())
+    }
+}
+
+fn append(file: &mut BufWriter<Box<dyn VfsFile>>, bytes: &[u8], sync: bool) -> Result<()> {
+    file.write_all(bytes)?;
+    file.flush()?;
+    if sync {
+        file.get_ref().sync_data()?;
+    }
+    Ok(())
 }
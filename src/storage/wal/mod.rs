@@ -1,5 +1,14 @@
 mod entry;
+mod format;
 mod log;
+mod segment;
+mod sync;
 
-pub use entry::WalEntry;
-pub use log::Wal;
+pub use entry::{WalEntry, WalRecord};
+pub use log::{Wal, WalReplay, WalTruncation};
+pub use segment::{
+    archive_dir, list_archived_segments, list_archived_segments_in, list_segments,
+    list_segments_in, remove_wal_archive, remove_wal_archive_in, remove_wal_files,
+    remove_wal_files_in, set_aside_archive, set_aside_archive_in, WalSegment,
+};
+pub use sync::{WalAck, WalSync};
@@ -0,0 +1,151 @@
+// WAL segment files. A collection's WAL is split into numbered files next to the base path (`<collection>.wal.db.<first_seq>`), each named after the first sequence it may contain. A single pre-segment `<collection>.wal.db` file is treated as the oldest segment. With archiving enabled, segments a checkpoint covers are moved under the same names into a `wal_archive/` directory beside the collection.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::storage::vfs::{self, OsVfs, Vfs};
+
+/// One file of the WAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalSegment {
+    pub path: PathBuf,
+    pub first_seq: u64,
+}
+
+impl WalSegment {
+    /// Size of the segment in `vfs`, zero if it is gone.
+    pub fn size_bytes(&self, vfs: &dyn Vfs) -> u64 {
+        vfs.file_len(&self.path).unwrap_or(0)
+    }
+}
+
+pub(super) fn segment_path(base: &Path, first_seq: u64) -> PathBuf {
+    PathBuf::from(format!("{}.{:020}", base.display(), first_seq))
+}
+
+/// All segments of the WAL at `base`, oldest first.
+pub fn list_segments(base: &Path) -> Result<Vec<WalSegment>> {
+    list_segments_in(&OsVfs, base)
+}
+
+/// All segments of the WAL at `base` of `vfs`, oldest first.
+pub fn list_segments_in(vfs: &dyn Vfs, base: &Path) -> Result<Vec<WalSegment>> {
+    let mut segments = Vec::new();
+    if vfs.exists(base) {
+        segments.push(WalSegment {
+            path: base.to_path_buf(),
+            first_seq: 0,
+        });
+    }
+
+    let Some(file_name) = base.file_name().and_then(|name| name.to_str()) else {
+        return Ok(segments);
+    };
+    let prefix = format!("{}.", file_name);
+    let entries = match vfs.read_dir(&vfs::parent_dir(base)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(segments),
+        Err(error) => return Err(error.into()),
+    };
+    let mut numbered = Vec::new();
+    for entry in entries {
+        let Some(suffix) = entry
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(&prefix))
+        else {
+            continue;
+        };
+        if suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        if let Ok(first_seq) = suffix.parse::<u64>() {
+            numbered.push(WalSegment {
+                path: segment_path(base, first_seq),
+                first_seq,
+            });
+        }
+    }
+    numbered.sort_by_key(|segment| segment.first_seq);
+    segments.extend(numbered);
+    Ok(segments)
+}
+
+/// Directory archived segments of the WAL at `base` are moved to.
+pub fn archive_dir(base: &Path) -> PathBuf {
+    match base.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.join("wal_archive"),
+        _ => PathBuf::from("wal_archive"),
+    }
+}
+
+// Base path the archived segments of `base` are listed under.
+fn archive_base(base: &Path) -> PathBuf {
+    archive_dir(base).join(base.file_name().unwrap_or_default())
+}
+
+/// Archived segments of the WAL at `base`, oldest first.
+pub fn list_archived_segments(base: &Path) -> Result<Vec<WalSegment>> {
+    list_archived_segments_in(&OsVfs, base)
+}
+
+/// Archived segments of the WAL at `base` of `vfs`, oldest first.
+pub fn list_archived_segments_in(vfs: &dyn Vfs, base: &Path) -> Result<Vec<WalSegment>> {
+    list_segments_in(vfs, &archive_base(base))
+}
+
+pub(super) fn archive_segment(vfs: &dyn Vfs, base: &Path, segment: &WalSegment) -> Result<()> {
+    let dir = archive_dir(base);
+    vfs.create_dir_all(&dir)?;
+    let name = segment.path.file_name().unwrap_or_default();
+    vfs.rename(&segment.path, &dir.join(name))?;
+    Ok(())
+}
+
+/// Move the archived segments of `base` out of the way, into a directory named after `label`.
+/// Used when a collection is restored: its new history must not be mixed with the old one.
+pub fn set_aside_archive(base: &Path, label: &str) -> Result<()> {
+    set_aside_archive_in(&OsVfs, base, label)
+}
+
+/// Move the archived segments of `base` of `vfs` into a directory named after `label`.
+pub fn set_aside_archive_in(vfs: &dyn Vfs, base: &Path, label: &str) -> Result<()> {
+    let segments = list_archived_segments_in(vfs, base)?;
+    if segments.is_empty() {
+        return Ok(());
+    }
+    let dir = archive_dir(base).join(format!(
+        "{}.{}",
+        base.file_name().unwrap_or_default().to_string_lossy(),
+        label
+    ));
+    vfs.create_dir_all(&dir)?;
+    for segment in segments {
+        let name = segment.path.file_name().unwrap_or_default();
+        vfs.rename(&segment.path, &dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Delete every archived segment of the WAL at `base`.
+pub fn remove_wal_archive(base: &Path) -> Result<()> {
+    remove_wal_archive_in(&OsVfs, base)
+}
+
+/// Delete every archived segment of the WAL at `base` of `vfs`.
+pub fn remove_wal_archive_in(vfs: &dyn Vfs, base: &Path) -> Result<()> {
+    remove_wal_files_in(vfs, &archive_base(base))
+}
+
+/// Delete every segment of the WAL at `base`.
+pub fn remove_wal_files(base: &Path) -> Result<()> {
+    remove_wal_files_in(&OsVfs, base)
+}
+
+/// Delete every segment of the WAL at `base` of `vfs`.
+pub fn remove_wal_files_in(vfs: &dyn Vfs, base: &Path) -> Result<()> {
+    for segment in list_segments_in(vfs, base)? {
+        vfs::remove_if_exists(vfs, &segment.path)?;
+    }
+    Ok(())
+}
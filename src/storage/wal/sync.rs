@@ -0,0 +1,269 @@
+// Durability bookkeeping for the WAL. Appends are tracked by sequence number; a write is durable once an fsync has covered its sequence. Group commit lets one waiter sync on behalf of everyone who appended during its window, and periodic mode syncs from one background thread shared by every WAL in the process.
+
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::time::{Duration, Instant};
+
+use crate::config::WalSyncMode;
+use crate::error::Result;
+use crate::storage::vfs::VfsFile;
+
+#[derive(Default)]
+struct SyncState {
+    // Handle to the active WAL file, cloned from the writer so syncing never takes the writer
+    file: Option<Box<dyn VfsFile>>,
+    written: u64,
+    synced: u64,
+    syncing: bool,
+    syncs: u64,
+    // Bumped when appends are taken back, so an fsync already under way does not vouch for the
+    // entries that later reuse their sequence numbers
+    retractions: u64,
+}
+
+struct SyncInner {
+    mode: WalSyncMode,
+    window: Duration,
+    state: Mutex<SyncState>,
+    synced: Condvar,
+}
+
+/// Shared handle that tracks which WAL appends have reached disk.
+#[derive(Clone)]
+pub struct WalSync {
+    inner: Arc<SyncInner>,
+}
+
+/// A pending acknowledgement: the caller may report the write once `wait` returns.
+pub struct WalAck {
+    sync: WalSync,
+    seq: u64,
+}
+
+impl WalAck {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Block until the entry with this sequence is durable under the WAL's sync mode.
+    pub fn wait(self) -> Result<()> {
+        self.sync.wait_for(self.seq)
+    }
+}
+
+impl WalSync {
+    pub fn new(mode: WalSyncMode, group_commit_window: Duration, sync_interval: Duration) -> Self {
+        let sync = WalSync {
+            inner: Arc::new(SyncInner {
+                mode,
+                window: group_commit_window,
+                state: Mutex::new(SyncState::default()),
+                synced: Condvar::new(),
+            }),
+        };
+        if mode == WalSyncMode::Periodic {
+            PeriodicSyncer::shared().register(Arc::downgrade(&sync.inner), sync_interval);
+        }
+        sync
+    }
+
+    pub fn mode(&self) -> WalSyncMode {
+        self.inner.mode
+    }
+
+    /// Point syncing at a new WAL file. Appends still pending in the previous file are not marked
+    /// durable here: whoever switches files has to fsync the old one and report it through
+    /// `appended` first, as `Wal::roll` does, or their waiters would sync the wrong file.
+    pub(super) fn set_file(&self, file: Option<Box<dyn VfsFile>>) {
+        self.lock().file = file;
+    }
+
+    /// Record an append. `synced` is true when the writer already fsynced it (per-write mode).
+    pub(super) fn appended(&self, seq: u64, synced: bool) {
+        let mut state = self.lock();
+        state.written = state.written.max(seq);
+        if synced {
+            state.synced = state.synced.max(seq);
+            state.syncs += 1;
+            self.inner.synced.notify_all();
+        }
+    }
+
+    /// Forget appends from `seq` on, which the writer has cut off the end of the file.
+    pub(super) fn retracted(&self, seq: u64) {
+        let mut state = self.lock();
+        let last = seq.saturating_sub(1);
+        state.written = state.written.min(last);
+        state.synced = state.synced.min(last);
+        state.retractions += 1;
+    }
+
+    /// The acknowledgement callers still have to wait for, if the last append is not yet durable.
+    pub fn pending_ack(&self) -> Option<WalAck> {
+        if self.inner.mode != WalSyncMode::GroupCommit {
+            return None;
+        }
+        let state = self.lock();
+        (state.written > state.synced).then(|| WalAck {
+            sync: self.clone(),
+            seq: state.written,
+        })
+    }
+
+    /// Highest sequence known to be on disk.
+    pub fn synced_seq(&self) -> u64 {
+        self.lock().synced
+    }
+
+    /// Number of fsyncs issued for WAL appends.
+    pub fn sync_count(&self) -> u64 {
+        self.lock().syncs
+    }
+
+    // Wait for `seq` to be synced. The first waiter becomes the leader: it sleeps for the group
+    // commit window so concurrent writers can append, then syncs everything written so far.
+    fn wait_for(&self, seq: u64) -> Result<()> {
+        if self.inner.mode != WalSyncMode::GroupCommit {
+            return Ok(());
+        }
+        let mut state = self.lock();
+        loop {
+            if state.synced >= seq {
+                return Ok(());
+            }
+            if state.syncing {
+                state = self
+                    .inner
+                    .synced
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            }
+            state.syncing = true;
+            drop(state);
+            if !self.inner.window.is_zero() {
+                std::thread::sleep(self.inner.window);
+            }
+            let result = self.sync_written();
+            state = self.lock();
+            state.syncing = false;
+            self.inner.synced.notify_all();
+            result?;
+        }
+    }
+
+    /// fsync everything appended so far.
+    pub fn sync_written(&self) -> Result<()> {
+        let (file, target, retractions) = {
+            let state = self.lock();
+            if state.synced >= state.written {
+                return Ok(());
+            }
+            match &state.file {
+                Some(file) => (file.try_clone()?, state.written, state.retractions),
+                None => return Ok(()),
+            }
+        };
+        file.sync_data()?;
+        let mut state = self.lock();
+        if state.retractions == retractions {
+            state.synced = state.synced.max(target);
+        }
+        state.syncs += 1;
+        self.inner.synced.notify_all();
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SyncState> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// A periodic-mode WAL the shared syncer flushes every `interval`
+struct PeriodicWal {
+    inner: Weak<SyncInner>,
+    interval: Duration,
+    next: Instant,
+}
+
+// One thread syncs every periodic-mode WAL in the process, however many collections are loaded.
+// It holds only weak references and forgets a WAL once the WAL is gone.
+struct PeriodicSyncer {
+    wals: Mutex<Vec<PeriodicWal>>,
+    registered: Condvar,
+}
+
+impl PeriodicSyncer {
+    fn shared() -> &'static PeriodicSyncer {
+        static SYNCER: OnceLock<PeriodicSyncer> = OnceLock::new();
+        SYNCER.get_or_init(|| {
+            // The thread's first call waits until this initialisation has finished
+            std::thread::Builder::new()
+                // Thread names past 15 bytes are cut off in /proc and debuggers
+                .name("piramid-walsync".to_string())
+                .spawn(|| PeriodicSyncer::shared().run())
+                .expect("failed to spawn the WAL sync thread");
+            PeriodicSyncer {
+                wals: Mutex::new(Vec::new()),
+                registered: Condvar::new(),
+            }
+        })
+    }
+
+    fn register(&self, inner: Weak<SyncInner>, interval: Duration) {
+        let mut wals = self.lock();
+        wals.push(PeriodicWal {
+            inner,
+            interval,
+            next: Instant::now() + interval,
+        });
+        self.registered.notify_one();
+    }
+
+    fn run(&self) {
+        let mut wals = self.lock();
+        loop {
+            let now = Instant::now();
+            wals.retain(|wal| wal.inner.strong_count() > 0);
+            let mut due = Vec::new();
+            for wal in wals.iter_mut().filter(|wal| wal.next <= now) {
+                wal.next = now + wal.interval;
+                due.extend(wal.inner.upgrade());
+            }
+
+            if !due.is_empty() {
+                // Sync without the registry lock so new WALs can register meanwhile
+                drop(wals);
+                for inner in due {
+                    if let Err(e) = (WalSync { inner }).sync_written() {
+                        tracing::warn!(error = %e, "wal_periodic_sync_failed");
+                    }
+                }
+                wals = self.lock();
+                continue;
+            }
+
+            wals = match wals.iter().map(|wal| wal.next).min() {
+                Some(next) => {
+                    let timeout = next.saturating_duration_since(now);
+                    self.registered
+                        .wait_timeout(wals, timeout)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => self
+                    .registered
+                    .wait(wals)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<PeriodicWal>> {
+        self.wals
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -112,6 +112,19 @@ pub fn validate_collection_name(name: &str) -> Result<()> {
     Ok(())
 }
 
+// Snapshot ids become file names, so they follow the same rules as collection names
+pub fn validate_snapshot_id(id: &str) -> Result<()> {
+    if id.is_empty()
+        || id.len() > 255
+        || !id
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(ServerError::InvalidRequest(format!("Invalid snapshot id '{}'", id)).into());
+    }
+    Ok(())
+}
+
 // Validate batch size limits
 pub fn validate_batch_size(size: usize, max_size: usize, operation: &str) -> Result<()> {
     if size == 0 {
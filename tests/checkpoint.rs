@@ -0,0 +1,134 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::{
+    storage::persistence::{checkpoint_files, load_manifest, manifest_path},
+    Collection, Document,
+};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn checkpoint_switches_manifest_and_drops_old_generation() {
+    let path = test_path("checkpoint_generations.db");
+
+    let mut collection = Collection::open(&path).unwrap();
+    collection
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "first".into()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    let first = load_manifest(&path).unwrap().unwrap();
+    let first_files = checkpoint_files(&path).unwrap();
+
+    collection
+        .insert(Document::new(vec![0.0, 1.0, 0.0], "second".into()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    let second = load_manifest(&path).unwrap().unwrap();
+
+    assert_eq!(second.generation, first.generation + 1);
+    assert!(second.last_checkpoint_seq > first.last_checkpoint_seq);
+    for file in &first_files {
+        assert!(!file.exists(), "{} should be removed", file.display());
+    }
+    for file in checkpoint_files(&path).unwrap() {
+        assert!(file.exists(), "{} should exist", file.display());
+    }
+    for legacy in [".index.db", ".vecindex.db", ".metadata.db", ".wal.meta"] {
+        assert!(!Path::new(&format!("{}{}", path, legacy)).exists());
+    }
+
+    drop(collection);
+    cleanup_collection(&path);
+}
+
+#[test]
+fn crash_before_manifest_switch_recovers_previous_generation() {
+    let path = test_path("checkpoint_crash.db");
+
+    let mut collection = Collection::open(&path).unwrap();
+    let before = collection
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "checkpointed".into()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    let after = collection
+        .insert(Document::new(vec![0.0, 1.0, 0.0], "only in wal".into()))
+        .unwrap();
+    let generation = load_manifest(&path).unwrap().unwrap().generation;
+    drop(collection);
+
+    // Simulate a crash part way through the next checkpoint: one file of the new generation was
+    // renamed into place, another was still a temporary and the manifest was never switched.
+    let next = generation + 1;
+    let partial = format!("{}.index.db.{:020}", path, next);
+    let temporary = format!("{}.vecindex.db.{:020}.tmp", path, next);
+    let manifest_tmp = format!("{}.tmp", manifest_path(&path));
+    fs::write(&partial, b"partial generation").unwrap();
+    fs::write(&temporary, b"half written").unwrap();
+    fs::write(&manifest_tmp, b"{\"version\":").unwrap();
+
+    let collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.count(), 2);
+    assert_eq!(
+        collection.get(&before).unwrap().unwrap().text,
+        "checkpointed"
+    );
+    assert_eq!(collection.get(&after).unwrap().unwrap().text, "only in wal");
+    assert!(!Path::new(&temporary).exists());
+    assert!(!Path::new(&manifest_tmp).exists());
+
+    // Replaying the WAL checkpoints again, so the live generation is a complete one.
+    let manifest = load_manifest(&path).unwrap().unwrap();
+    assert!(manifest.generation > generation);
+    for file in checkpoint_files(&path).unwrap() {
+        assert!(file.exists(), "{} should exist", file.display());
+    }
+
+    drop(collection);
+    cleanup_collection(&path);
+}
+
+#[test]
+fn legacy_sidecar_files_still_load() {
+    let path = test_path("checkpoint_legacy.db");
+
+    let mut collection = Collection::open(&path).unwrap();
+    let id = collection
+        .insert(Document::new(vec![1.0, 2.0, 3.0], "legacy".into()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    let manifest = load_manifest(&path).unwrap().unwrap();
+    let files = checkpoint_files(&path).unwrap();
+    drop(collection);
+
+    // Rewrite the checkpoint into the layout used before manifests existed.
+    for (file, legacy) in files
+        .iter()
+        .zip([".index.db", ".vecindex.db", ".metadata.db"])
+    {
+        fs::rename(file, format!("{}{}", path, legacy)).unwrap();
+    }
+    fs::remove_file(manifest_path(&path)).unwrap();
+    fs::write(
+        format!("{}.wal.meta", path),
+        format!(
+            "{{\"last_checkpoint_seq\":{}}}",
+            manifest.last_checkpoint_seq
+        ),
+    )
+    .unwrap();
+
+    let mut collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.get(&id).unwrap().unwrap().text, "legacy");
+
+    // The next checkpoint moves the collection onto a manifest.
+    collection.checkpoint().unwrap();
+    assert!(load_manifest(&path).unwrap().is_some());
+    for legacy in [".index.db", ".vecindex.db", ".metadata.db", ".wal.meta"] {
+        assert!(!Path::new(&format!("{}{}", path, legacy)).exists());
+    }
+    assert_eq!(collection.get(&id).unwrap().unwrap().text, "legacy");
+
+    drop(collection);
+    cleanup_collection(&path);
+}
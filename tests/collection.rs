@@ -1,8 +1,14 @@
 use piramid::{
-    collections::{compact, CollectionOpenOptions},
+    collections::{compact, migrate_index, repair_index, retrain_index, CollectionOpenOptions},
+    config::MaintenanceConfig,
+    index::{IndexDetails, IndexType},
     metadata,
     search::SearchParams,
-    CacheConfig, Collection, CollectionConfig, Document, MemoryConfig, Metric,
+    services::collection::{index_maintenance_due, IndexMaintenance},
+    storage::persistence::{checkpoint_files, load_manifest, remove_checkpoint_files},
+    storage::wal::{remove_wal_files, Wal, WalEntry},
+    CacheConfig, Collection, CollectionConfig, Document, Filter, MemoryConfig, MetadataValue,
+    Metric,
 };
 use std::fs;
 
@@ -14,6 +20,10 @@ fn cleanup_test_files(paths: &[&str]) {
     ensure_test_dir();
     for path in paths {
         let _ = fs::remove_file(path);
+        let _ = remove_checkpoint_files(path);
+        if path.ends_with(".wal.db") {
+            let _ = remove_wal_files(std::path::Path::new(path));
+        }
     }
 }
 
@@ -60,7 +70,7 @@ fn configured_quantization_does_not_quantize_stored_documents() {
     let vector = vec![0.1, 0.2, 0.3, 0.4];
     let id = {
         let mut storage =
-            Collection::open_with_options(test_path, CollectionOpenOptions { config }).unwrap();
+            Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
         let id = storage
             .insert(Document::new(vector.clone(), "raw vector".into()))
             .unwrap();
@@ -201,7 +211,7 @@ fn no_mmap_insert_grows_file_without_panicking() {
     };
 
     let mut storage =
-        Collection::open_with_options(test_path, CollectionOpenOptions { config }).unwrap();
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
     let vector = vec![0.25; 1_100_000];
     let id = storage
         .insert(Document::new(
@@ -249,25 +259,14 @@ fn updates_write_one_wal_entry_each() {
         vec![3.0, 2.0, 1.0]
     );
 
-    let wal = fs::read_to_string(format!("{}.wal.db", test_path)).unwrap();
-    assert_eq!(
-        wal.lines()
-            .filter(|line| line.contains("\"Insert\""))
-            .count(),
-        1
-    );
-    assert_eq!(
-        wal.lines()
-            .filter(|line| line.contains("\"Update\""))
-            .count(),
-        2
-    );
-    assert_eq!(
-        wal.lines()
-            .filter(|line| line.contains("\"Delete\""))
-            .count(),
-        0
-    );
+    let wal = Wal::new(format!("{}.wal.db", test_path).into(), 0)
+        .unwrap()
+        .replay(0)
+        .unwrap();
+    let count = |pred: fn(&WalEntry) -> bool| wal.iter().filter(|entry| pred(entry)).count();
+    assert_eq!(count(|entry| matches!(entry, WalEntry::Insert { .. })), 1);
+    assert_eq!(count(|entry| matches!(entry, WalEntry::Update { .. })), 2);
+    assert_eq!(count(|entry| matches!(entry, WalEntry::Delete { .. })), 0);
 
     drop(storage);
     cleanup_test_files(&files);
@@ -325,13 +324,17 @@ fn sidecar_files_persist_at_checkpoint_only() {
         .insert(Document::new(vec![1.0, 2.0, 3.0], "checkpoint only".into()))
         .unwrap();
 
+    assert!(load_manifest(test_path).unwrap().is_none());
     assert!(fs::metadata(format!("{}.index.db", test_path)).is_err());
     assert!(fs::metadata(format!("{}.vecindex.db", test_path)).is_err());
 
     storage.checkpoint().unwrap();
 
-    assert!(fs::metadata(format!("{}.index.db", test_path)).is_ok());
-    assert!(fs::metadata(format!("{}.vecindex.db", test_path)).is_ok());
+    let manifest = load_manifest(test_path).unwrap().unwrap();
+    assert_eq!(manifest.generation, 1);
+    for file in checkpoint_files(test_path).unwrap() {
+        assert!(fs::metadata(&file).is_ok(), "missing {}", file.display());
+    }
 
     drop(storage);
     cleanup_test_files(&files);
@@ -356,7 +359,7 @@ fn metadata_cache_is_bounded_without_evicting_vectors() {
         ..CollectionConfig::default()
     };
     let mut storage =
-        Collection::open_with_options(test_path, CollectionOpenOptions { config }).unwrap();
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
 
     let id_a = storage
         .insert(Document::with_metadata(
@@ -374,7 +377,7 @@ fn metadata_cache_is_bounded_without_evicting_vectors() {
         .unwrap();
 
     assert_eq!(storage.get_vectors().len(), 2);
-    assert_eq!(storage.metadata_view().len(), 1);
+    assert_eq!(storage.metadata_cache_stats().entries, 1);
     assert!(storage.get_vectors().contains_key(&id_a));
     assert!(storage.get_vectors().contains_key(&id_b));
 
@@ -382,6 +385,152 @@ fn metadata_cache_is_bounded_without_evicting_vectors() {
     cleanup_test_files(&files);
 }
 
+fn metadata_test_files(name: &str) -> Vec<String> {
+    let path = format!(".piramid/tests/{name}.db");
+    [
+        "",
+        ".index.db",
+        ".wal.db",
+        ".vecindex.db",
+        ".metadata.db",
+        ".wal.meta",
+    ]
+    .iter()
+    .map(|suffix| format!("{path}{suffix}"))
+    .collect()
+}
+
+fn insert_kind(storage: &mut Collection, kind: &str) -> uuid::Uuid {
+    storage
+        .insert(Document::with_metadata(
+            vec![1.0, 0.0, 0.0],
+            kind.to_string(),
+            metadata([("kind", kind.into())]),
+        ))
+        .unwrap()
+}
+
+#[test]
+fn metadata_cache_evicts_least_recently_used_and_reads_misses_from_records() {
+    ensure_test_dir();
+    let files = metadata_test_files("test_metadata_lru");
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        cache: CacheConfig::with_size(2),
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(files[0], CollectionOpenOptions::from(config)).unwrap();
+    let id_a = insert_kind(&mut storage, "a");
+    let id_b = insert_kind(&mut storage, "b");
+    // Reading `a` makes `b` the least recently used entry
+    storage.metadata_of(&id_a).unwrap().unwrap();
+    insert_kind(&mut storage, "c");
+
+    let stats = storage.metadata_cache_stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.evictions, 1);
+    assert_eq!(stats.hits, 1);
+
+    // `b` was evicted, so its metadata comes from the record store and is cached again
+    let metadata_b = storage.metadata_of(&id_b).unwrap().unwrap();
+    assert_eq!(metadata_b.get("kind"), Some(&MetadataValue::from("b")));
+    assert_eq!(storage.metadata_cache_stats().misses, 1);
+    storage.metadata_of(&id_b).unwrap().unwrap();
+    assert_eq!(storage.metadata_cache_stats().hits, 2);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn metadata_cache_expires_entries_after_ttl() {
+    ensure_test_dir();
+    let files = metadata_test_files("test_metadata_ttl");
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        cache: CacheConfig::with_size_and_ttl(10, 0),
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(files[0], CollectionOpenOptions::from(config)).unwrap();
+    let id = storage
+        .insert(Document::with_metadata(
+            vec![1.0, 0.0, 0.0],
+            "doc".to_string(),
+            metadata([("kind", "a".into())]),
+        ))
+        .unwrap();
+
+    // A zero TTL expires every entry by its first lookup; the record store still answers
+    let metadata_a = storage.metadata_of(&id).unwrap().unwrap();
+    assert_eq!(metadata_a.get("kind"), Some(&MetadataValue::from("a")));
+    let stats = storage.metadata_cache_stats();
+    assert_eq!(stats.hits, 0);
+    assert_eq!(stats.expirations, 1);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn filtered_hnsw_search_reads_metadata_missing_from_the_cache() {
+    ensure_test_dir();
+    let files = metadata_test_files("test_metadata_filtered_hnsw");
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Hnsw {
+            m: 8,
+            m_max: 16,
+            ef_construction: 64,
+            ef_search: 64,
+            ml: 1.0 / (8.0f32).ln(),
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        cache: CacheConfig::with_size(1),
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(files[0], CollectionOpenOptions::from(config)).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..60 {
+        let vector = vec![(i % 5) as f32 + 1.0, (i % 7) as f32, (i % 3) as f32 + 0.5];
+        let kind = if i % 2 == 0 { "even" } else { "odd" };
+        ids.push(
+            storage
+                .insert(Document::with_metadata(
+                    vector,
+                    format!("doc {i}"),
+                    metadata([("kind", kind.into())]),
+                ))
+                .unwrap(),
+        );
+    }
+
+    let filter = Filter::new().eq("kind", "odd");
+    let query = storage.get(&ids[7]).unwrap().unwrap().get_vector();
+    let params = SearchParams {
+        filter: Some(&filter),
+        ..SearchParams::default()
+    };
+    let hits = storage.search(&query, 5, Metric::Cosine, params).unwrap();
+    assert_eq!(hits.len(), 5);
+    assert!((hits[0].score - 1.0).abs() < 1e-5);
+    assert!(hits.iter().all(|hit| filter.matches(&hit.metadata)));
+    assert!(storage.metadata_cache_stats().misses > 0);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
 #[test]
 fn append_cursor_survives_reopen_and_preserves_existing_records() {
     ensure_test_dir();
@@ -457,3 +606,423 @@ fn compaction_rewrites_live_records_through_temp_record_store() {
     drop(storage);
     cleanup_test_files(&files);
 }
+
+#[test]
+fn retrain_swaps_ivf_index_without_losing_vectors() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_ivf_retrain.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_ivf_retrain.db.index.db",
+        ".piramid/tests/test_ivf_retrain.db.wal.db",
+        ".piramid/tests/test_ivf_retrain.db.vecindex.db",
+        ".piramid/tests/test_ivf_retrain.db.metadata.db",
+        ".piramid/tests/test_ivf_retrain.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Ivf {
+            num_clusters: 4,
+            num_probes: 4,
+            max_iterations: 10,
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..40 {
+        let vector = vec![(i % 4) as f32 + 1.0, (i % 7) as f32, (i % 3) as f32 + 0.5];
+        ids.push(
+            storage
+                .insert(Document::new(vector, format!("doc {i}")))
+                .unwrap(),
+        );
+    }
+
+    let handle = std::sync::Arc::new(parking_lot::RwLock::new(storage));
+    assert!(retrain_index(&handle, true).unwrap());
+
+    let storage = handle.read();
+    assert_eq!(storage.vector_index().stats().total_vectors, 40);
+    let query = storage.get(&ids[5]).unwrap().unwrap().get_vector();
+    let hits = storage
+        .search(&query, 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!((hits[0].score - 1.0).abs() < 1e-5);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn auto_index_migrates_once_collection_outgrows_flat() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_index_migration.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_index_migration.db.index.db",
+        ".piramid/tests/test_index_migration.db.wal.db",
+        ".piramid/tests/test_index_migration.db.vecindex.db",
+        ".piramid/tests/test_index_migration.db.metadata.db",
+        ".piramid/tests/test_index_migration.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Auto {
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+            auto: piramid::index::AutoIndexConfig {
+                flat_max_vectors: 10,
+                ivf_num_clusters: Some(2),
+                ..Default::default()
+            },
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    assert_eq!(storage.vector_index().index_type(), IndexType::Flat);
+
+    let mut ids = Vec::new();
+    for i in 0..20 {
+        let vector = vec![(i % 5) as f32 + 1.0, (i % 3) as f32, 1.0];
+        ids.push(
+            storage
+                .insert(Document::new(vector, format!("doc {i}")))
+                .unwrap(),
+        );
+    }
+    storage.delete(&ids[0]).unwrap();
+    assert_eq!(storage.index_migration_target(), Some(IndexType::Ivf));
+
+    let handle = std::sync::Arc::new(parking_lot::RwLock::new(storage));
+    assert_eq!(migrate_index(&handle).unwrap(), Some(IndexType::Ivf));
+    assert_eq!(migrate_index(&handle).unwrap(), None);
+
+    let storage = handle.read();
+    assert_eq!(storage.vector_index().index_type(), IndexType::Ivf);
+    assert_eq!(storage.vector_index().stats().total_vectors, 19);
+    assert_eq!(storage.index_migration_target(), None);
+    drop(storage);
+
+    // The migrated index is persisted and picked up on reopen
+    drop(handle);
+    let storage = Collection::open(test_path).unwrap();
+    assert_eq!(storage.vector_index().index_type(), IndexType::Ivf);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn hnsw_deletes_leave_tombstones_for_the_background_repair() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_hnsw_repair_job.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_hnsw_repair_job.db.index.db",
+        ".piramid/tests/test_hnsw_repair_job.db.wal.db",
+        ".piramid/tests/test_hnsw_repair_job.db.vecindex.db",
+        ".piramid/tests/test_hnsw_repair_job.db.metadata.db",
+        ".piramid/tests/test_hnsw_repair_job.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Hnsw {
+            m: 8,
+            m_max: 16,
+            ef_construction: 64,
+            ef_search: 64,
+            ml: 1.0 / (8.0f32).ln(),
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        maintenance: MaintenanceConfig {
+            tombstone_ratio: 0.1,
+            repair_batch: 16,
+            ..MaintenanceConfig::default()
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..200 {
+        let vector = vec![(i % 11) as f32 + 1.0, (i % 7) as f32, (i % 5) as f32 + 0.5];
+        ids.push(
+            storage
+                .insert(Document::new(vector, format!("doc {i}")))
+                .unwrap(),
+        );
+    }
+    for id in &ids[..50] {
+        storage.delete(id).unwrap();
+    }
+    let tombstones = |storage: &Collection| match storage.vector_index().stats().details {
+        IndexDetails::Hnsw { tombstones, .. } => tombstones,
+        _ => panic!("expected an HNSW index"),
+    };
+    // Deletes only mark nodes; the repair runs later, off the write path
+    assert_eq!(tombstones(&storage), 50);
+    assert!(storage.vector_index().needs_repair(0.1));
+
+    let handle = std::sync::Arc::new(parking_lot::RwLock::new(storage));
+    assert_eq!(repair_index(&handle).unwrap(), Some(50));
+    assert_eq!(repair_index(&handle).unwrap(), None);
+
+    let storage = handle.read();
+    assert_eq!(tombstones(&storage), 0);
+    assert_eq!(storage.vector_index().stats().total_vectors, 150);
+    let query = storage.get(&ids[120]).unwrap().unwrap().get_vector();
+    let hits = storage
+        .search(&query, 5, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!((hits[0].score - 1.0).abs() < 1e-5);
+    assert!(hits.iter().all(|hit| !ids[..50].contains(&hit.id)));
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+fn drifted_ivf_docs() -> Vec<Document> {
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut next = move || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 40) as f32 / (1u64 << 24) as f32
+    };
+    // Four spread clusters, then many more vectors piling into one of them
+    let mut docs: Vec<Document> = (0..400)
+        .map(|i| {
+            let vector = (0..8)
+                .map(|d| next() + if d == i % 4 { 2.0 } else { 0.0 })
+                .collect();
+            Document::new(vector, format!("doc {i}"))
+        })
+        .collect();
+    docs.extend((0..1_200).map(|i| {
+        let vector = (0..8)
+            .map(|d| next() * 0.1 + if d == 0 { 2.0 } else { 0.0 })
+            .collect();
+        Document::new(vector, format!("drift {i}"))
+    }));
+    docs
+}
+
+#[test]
+fn background_retrain_needs_an_improvement_and_waits_out_its_cooldown() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_ivf_retrain_cooldown.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_ivf_retrain_cooldown.db.index.db",
+        ".piramid/tests/test_ivf_retrain_cooldown.db.wal.db",
+        ".piramid/tests/test_ivf_retrain_cooldown.db.vecindex.db",
+        ".piramid/tests/test_ivf_retrain_cooldown.db.metadata.db",
+        ".piramid/tests/test_ivf_retrain_cooldown.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Ivf {
+            num_clusters: 8,
+            num_probes: 8,
+            max_iterations: 10,
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        maintenance: MaintenanceConfig {
+            // No retrain can bring the imbalance ratio below 1.0
+            retrain_min_improvement: 0.99,
+            ..MaintenanceConfig::default()
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    let docs = drifted_ivf_docs();
+    storage.insert_batch(docs[..400].to_vec()).unwrap();
+    for doc in &docs[400..] {
+        storage.insert(doc.clone()).unwrap();
+    }
+    assert!(storage.retrain_due());
+    let before = storage.vector_index().drift().unwrap();
+
+    // A retrain that does not cut the drift enough is discarded, and starts the cooldown
+    let handle = std::sync::Arc::new(parking_lot::RwLock::new(storage));
+    assert!(!retrain_index(&handle, false).unwrap());
+    assert_eq!(handle.read().vector_index().drift(), Some(before));
+    assert!(handle.read().vector_index().needs_retrain());
+    assert!(!handle.read().retrain_due());
+
+    // Once the cooldown is over, a retrain that helps is swapped in
+    {
+        let mut storage = handle.write();
+        storage.config.maintenance.retrain_cooldown_secs = 0;
+        storage.config.maintenance.retrain_min_improvement = 0.1;
+    }
+    assert!(handle.read().retrain_due());
+    assert!(retrain_index(&handle, false).unwrap());
+    let storage = handle.read();
+    assert!(storage.vector_index().drift().unwrap() < before);
+    assert!(!storage.retrain_due());
+    assert_eq!(storage.vector_index().stats().total_vectors, 1_600);
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn due_compaction_is_not_shadowed_by_a_due_retrain() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_maintenance_order.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_maintenance_order.db.index.db",
+        ".piramid/tests/test_maintenance_order.db.wal.db",
+        ".piramid/tests/test_maintenance_order.db.vecindex.db",
+        ".piramid/tests/test_maintenance_order.db.metadata.db",
+        ".piramid/tests/test_maintenance_order.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Ivf {
+            num_clusters: 8,
+            num_probes: 8,
+            max_iterations: 10,
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        compaction: piramid::config::CompactionConfig {
+            min_dead_bytes: 1,
+            dead_ratio: 0.01,
+            ..piramid::config::CompactionConfig::default()
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    let docs = drifted_ivf_docs();
+    storage.insert_batch(docs[..400].to_vec()).unwrap();
+    let ids: Vec<_> = docs[400..]
+        .iter()
+        .map(|doc| storage.insert(doc.clone()).unwrap())
+        .collect();
+    assert!(matches!(
+        index_maintenance_due(&storage),
+        Some(IndexMaintenance::Retrain)
+    ));
+
+    for id in &ids[..20] {
+        storage.delete(id).unwrap();
+    }
+    assert!(storage.retrain_due());
+    assert!(matches!(
+        index_maintenance_due(&storage),
+        Some(IndexMaintenance::Compact)
+    ));
+
+    drop(storage);
+    cleanup_test_files(&files);
+}
+
+#[test]
+fn writes_during_an_off_lock_migration_reach_the_new_index() {
+    ensure_test_dir();
+    let test_path = ".piramid/tests/test_index_migration_writes.db";
+    let files = vec![
+        test_path,
+        ".piramid/tests/test_index_migration_writes.db.index.db",
+        ".piramid/tests/test_index_migration_writes.db.wal.db",
+        ".piramid/tests/test_index_migration_writes.db.vecindex.db",
+        ".piramid/tests/test_index_migration_writes.db.metadata.db",
+        ".piramid/tests/test_index_migration_writes.db.wal.meta",
+    ];
+    cleanup_test_files(&files);
+
+    let config = CollectionConfig {
+        index: piramid::index::IndexConfig::Auto {
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+            auto: piramid::index::AutoIndexConfig {
+                flat_max_vectors: 100,
+                ivf_num_clusters: Some(16),
+                ..Default::default()
+            },
+        },
+        ..CollectionConfig::default()
+    };
+    let vector = |i: usize| -> Vec<f32> {
+        (0..16)
+            .map(|d| ((i * 16 + d) as f32 * 0.37).sin() + if d == i % 16 { 2.0 } else { 0.0 })
+            .collect()
+    };
+    let mut storage =
+        Collection::open_with_options(test_path, CollectionOpenOptions::from(config)).unwrap();
+    let ids = storage
+        .insert_batch(
+            (0..3_000)
+                .map(|i| Document::new(vector(i), format!("doc {i}")))
+                .collect(),
+        )
+        .unwrap();
+    assert_eq!(storage.index_migration_target(), Some(IndexType::Ivf));
+
+    let handle = std::sync::Arc::new(parking_lot::RwLock::new(storage));
+    let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let writer = {
+        let handle = handle.clone();
+        let done = done.clone();
+        let deletes = ids[..200].to_vec();
+        std::thread::spawn(move || {
+            let mut inserted = Vec::new();
+            let mut deleted = Vec::new();
+            // Keep writing until the migration has swapped, with at least a few writes either way
+            for (n, id) in deletes.into_iter().enumerate() {
+                if n >= 20 && done.load(std::sync::atomic::Ordering::Acquire) {
+                    break;
+                }
+                let mut storage = handle.write();
+                storage.delete(&id).unwrap();
+                deleted.push(id);
+                let doc = Document::new(vector(10_000 + n), format!("new {n}"));
+                inserted.push(storage.insert(doc).unwrap());
+            }
+            (inserted, deleted)
+        })
+    };
+    assert_eq!(migrate_index(&handle).unwrap(), Some(IndexType::Ivf));
+    done.store(true, std::sync::atomic::Ordering::Release);
+    let (inserted, deleted) = writer.join().unwrap();
+
+    let storage = handle.read();
+    assert_eq!(storage.vector_index().index_type(), IndexType::Ivf);
+    let mut expected: std::collections::HashSet<_> = ids.iter().copied().collect();
+    for id in &deleted {
+        expected.remove(id);
+    }
+    expected.extend(inserted.iter().copied());
+    let indexed: std::collections::HashSet<_> = storage.vector_index().ids().into_iter().collect();
+    assert_eq!(indexed, expected);
+    assert_eq!(storage.count(), expected.len());
+
+    // A second migration is not refused because of leftover change tracking
+    drop(storage);
+    assert_eq!(migrate_index(&handle).unwrap(), None);
+
+    cleanup_test_files(&files);
+}
@@ -3,15 +3,15 @@ use axum::{
     Json,
 };
 use piramid::{
-    config::AppConfig,
+    config::{AppConfig, IdleConfig},
     error::PiramidError,
     metadata,
-    runtime::AppState,
+    runtime::{self, AppState},
     server::{
         handlers::{collections, vectors},
         types::{InsertRequest, InsertResultsResponse, ListVectorsQuery},
     },
-    Collection, Document,
+    services, Collection, Document,
 };
 use std::{collections::HashMap, fs, sync::Arc};
 
@@ -93,7 +93,7 @@ async fn cache_budget_evicts_metadata_without_dropping_vectors() {
             ))
             .unwrap();
         assert_eq!(collection_guard.get_vectors().len(), 2);
-        assert_eq!(collection_guard.metadata_view().len(), 2);
+        assert_eq!(collection_guard.metadata_cache_stats().entries, 2);
     }
 
     state.enforce_cache_budget();
@@ -101,7 +101,7 @@ async fn cache_budget_evicts_metadata_without_dropping_vectors() {
     {
         let collection_guard = collection.read();
         assert_eq!(collection_guard.get_vectors().len(), 2);
-        assert_eq!(collection_guard.metadata_view().len(), 0);
+        assert_eq!(collection_guard.metadata_cache_stats().entries, 0);
         assert_eq!(collection_guard.count(), 2);
     }
 
@@ -165,3 +165,254 @@ async fn read_endpoint_loads_existing_collection_from_disk() {
 
     cleanup_dir(data_dir);
 }
+
+fn insert_docs(state: &AppState, name: &str, count: usize) {
+    let collection = state
+        .collection_manager
+        .get_or_create(name)
+        .expect("create collection");
+    let mut collection_guard = collection.write();
+    for i in 0..count {
+        collection_guard
+            .insert(Document::new(
+                vec![i as f32, 1.0, 0.0],
+                format!("{name} {i}"),
+            ))
+            .expect("insert document");
+    }
+}
+
+fn report_bytes(state: &AppState, name: &str) -> usize {
+    state
+        .collection_manager
+        .get_existing(name)
+        .expect("loaded collection")
+        .read()
+        .memory_report()
+        .total_bytes()
+}
+
+fn assert_unavailable<T>(result: piramid::Result<T>) {
+    match result {
+        Err(PiramidError::Server(error)) => {
+            assert_eq!(
+                error.status_code(),
+                axum::http::StatusCode::SERVICE_UNAVAILABLE
+            );
+        }
+        Err(error) => panic!("expected service-unavailable error, got {error:?}"),
+        Ok(_) => panic!("expected service-unavailable error"),
+    }
+}
+
+#[test]
+fn memory_budget_unloads_least_recently_used_collection() {
+    let data_dir = ".piramid/tests/collection_manager_memory_unload";
+    let state = test_state(data_dir);
+    insert_docs(&state, "old", 20);
+    insert_docs(&state, "recent", 20);
+    let recent_bytes = report_bytes(&state, "recent");
+    let total = state.memory_usage().total.total_bytes();
+    assert!(total > recent_bytes);
+
+    state.app_config.write().hardware.memory_budget_bytes = Some((total - 1) as u64);
+    state.enforce_memory_budget();
+
+    assert!(!state.collection_manager.contains_loaded("old"));
+    assert!(state.collection_manager.contains_loaded("recent"));
+    let usage = state.memory_usage();
+    assert_eq!(usage.unloads, 1);
+    assert!(!usage.exhausted);
+    state.ensure_write_allowed().expect("writes stay allowed");
+
+    // The unloaded collection was checkpointed and reopens with its data
+    let reopened = state.get_existing_collection("old").expect("reopen");
+    assert_eq!(reopened.read().count(), 20);
+
+    cleanup_dir(data_dir);
+}
+
+#[test]
+fn memory_budget_evicts_vectors_of_collections_in_use() {
+    let data_dir = ".piramid/tests/collection_manager_memory_evict";
+    let state = test_state(data_dir);
+    insert_docs(&state, "docs", 200);
+    // A handle held elsewhere keeps the collection from being unloaded
+    let collection = state.get_existing_collection("docs").unwrap();
+    let hot_bytes = collection.read().vector_tier_stats().hot_bytes;
+    let total = state.memory_usage().total.total_bytes();
+
+    state.app_config.write().hardware.memory_budget_bytes = Some((total - hot_bytes / 2) as u64);
+    state.enforce_memory_budget();
+
+    assert!(state.collection_manager.contains_loaded("docs"));
+    {
+        let collection_guard = collection.read();
+        assert!(collection_guard.vector_memory_cap().is_some());
+        assert!(collection_guard.vector_tier_stats().hot_bytes < hot_bytes);
+        assert_eq!(collection_guard.get_vectors().len(), 200);
+    }
+    let usage = state.memory_usage();
+    assert!(usage.evicted_vector_bytes > 0);
+    assert!(!usage.exhausted);
+
+    // Without a budget the cap is lifted again
+    state.app_config.write().hardware.memory_budget_bytes = None;
+    state.enforce_memory_budget();
+    assert!(collection.read().vector_memory_cap().is_none());
+
+    cleanup_dir(data_dir);
+}
+
+#[test]
+fn memory_budget_rejects_writes_until_memory_is_freed() {
+    let data_dir = ".piramid/tests/collection_manager_memory_exhausted";
+    let state = test_state(data_dir);
+    insert_docs(&state, "docs", 20);
+    let collection = state.get_existing_collection("docs").unwrap();
+
+    state.app_config.write().hardware.memory_budget_bytes = Some(1);
+    state.enforce_memory_budget();
+
+    assert!(state.memory_usage().exhausted);
+    assert_unavailable(state.ensure_write_allowed());
+    // Reads keep working
+    assert_eq!(collection.read().count(), 20);
+
+    state.app_config.write().hardware.memory_budget_bytes = None;
+    state.ensure_write_allowed().expect("writes resume");
+    assert!(!state.memory_usage().exhausted);
+
+    cleanup_dir(data_dir);
+}
+
+#[test]
+fn idle_collections_unload_and_reopen_on_next_access() {
+    let data_dir = ".piramid/tests/collection_manager_idle_unload";
+    let app_config = AppConfig {
+        idle: IdleConfig::unload_after(3600),
+        ..Default::default()
+    };
+    let state = test_state_with_config(data_dir, app_config);
+    insert_docs(&state, "idle", 5);
+    insert_docs(&state, "held", 5);
+    assert_eq!(state.collection_manager.opens(), 2);
+
+    // Nothing has been idle for an hour yet
+    assert!(state.unload_idle_collections().is_empty());
+
+    state.app_config.write().idle = IdleConfig::unload_after(0);
+    let held = state.get_existing_collection("held").unwrap();
+    assert_eq!(state.unload_idle_collections(), vec!["idle".to_string()]);
+    assert!(!state.collection_manager.contains_loaded("idle"));
+    assert!(state.collection_manager.contains_loaded("held"));
+    assert_eq!(state.collection_manager.unloads(), 1);
+
+    let reopened = state.get_existing_collection("idle").expect("reopen");
+    assert_eq!(reopened.read().count(), 5);
+    assert_eq!(state.collection_manager.opens(), 3);
+    assert_eq!(held.read().count(), 5);
+
+    cleanup_dir(data_dir);
+}
+
+#[test]
+fn reopening_during_an_unload_keeps_the_new_instance_tracked() {
+    let data_dir = ".piramid/tests/collection_manager_unload_race";
+    let state = test_state(data_dir);
+    insert_docs(&state, "docs", 5);
+
+    let unloader = {
+        let state = state.clone();
+        std::thread::spawn(move || {
+            for _ in 0..2000 {
+                let _ = state.collection_manager.unload("docs");
+            }
+        })
+    };
+    for _ in 0..2000 {
+        let handle = state.get_existing_collection("docs").unwrap();
+        // While a handle is out the collection stays loaded along with its bookkeeping
+        assert!(state.collection_manager.idle_for("docs").is_some());
+        assert!(state.collection_manager.tracker("docs").is_some());
+        assert_eq!(handle.read().count(), 5);
+    }
+    unloader.join().unwrap();
+
+    cleanup_dir(data_dir);
+}
+
+#[tokio::test]
+async fn idle_unloader_runs_in_the_background() {
+    let data_dir = ".piramid/tests/collection_manager_idle_task";
+    let app_config = AppConfig {
+        idle: IdleConfig {
+            unload_after_secs: Some(0),
+            check_interval_secs: 1,
+        },
+        ..Default::default()
+    };
+    let state = test_state_with_config(data_dir, app_config);
+    insert_docs(&state, "docs", 3);
+
+    let task = runtime::spawn_idle_unloader(state.clone());
+    for _ in 0..50 {
+        if !state.collection_manager.contains_loaded("docs") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert!(!state.collection_manager.contains_loaded("docs"));
+    state.initiate_shutdown();
+    task.abort();
+
+    cleanup_dir(data_dir);
+}
+
+#[test]
+fn unloaded_collections_are_listed_and_deleted_from_disk() {
+    let data_dir = ".piramid/tests/collection_manager_unloaded";
+    let app_config = AppConfig {
+        idle: IdleConfig::unload_after(0),
+        ..Default::default()
+    };
+    let state = test_state_with_config(data_dir, app_config);
+    insert_docs(&state, "idle", 5);
+    insert_docs(&state, "gone", 3);
+    let mut unloaded = state.unload_idle_collections();
+    unloaded.sort();
+    assert_eq!(unloaded, vec!["gone".to_string(), "idle".to_string()]);
+
+    let listed = services::collection::list_collections(&state)
+        .unwrap()
+        .collections;
+    let summary: Vec<(String, usize)> = listed
+        .iter()
+        .map(|info| (info.name.clone(), info.count))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![("gone".to_string(), 3), ("idle".to_string(), 5)]
+    );
+    assert!(listed.iter().all(|info| info.dimensions == Some(3)));
+    // Listing reads checkpoints without reopening anything
+    assert!(!state.collection_manager.contains_loaded("idle"));
+
+    let deleted = services::collection::delete_collection(&state, "gone".to_string()).unwrap();
+    assert!(deleted.deleted);
+    let left: Vec<String> = fs::read_dir(data_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("gone"))
+        .collect();
+    assert!(left.is_empty(), "files left behind: {left:?}");
+    assert!(state.get_existing_collection("gone").is_err());
+
+    let listed = services::collection::list_collections(&state)
+        .unwrap()
+        .collections;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].name, "idle");
+
+    cleanup_dir(data_dir);
+}
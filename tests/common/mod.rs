@@ -0,0 +1,25 @@
+// Fixtures shared by the integration tests
+
+use piramid::storage::persistence::remove_checkpoint_files;
+use piramid::storage::vector_arena::arena_path;
+use piramid::storage::wal::remove_wal_files;
+use std::fs;
+use std::path::Path;
+
+// A path under the test directory, with whatever an earlier run left there removed
+pub fn test_path(name: &str) -> String {
+    let _ = fs::create_dir_all(".piramid/tests");
+    let path = format!(".piramid/tests/{}", name);
+    cleanup_collection(&path);
+    path
+}
+
+// Remove a collection's files, and any WAL based at `path` itself for tests that use it as one
+pub fn cleanup_collection(path: &str) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(format!("{}.index.db", path));
+    let _ = fs::remove_file(arena_path(path));
+    let _ = remove_checkpoint_files(path);
+    let _ = remove_wal_files(Path::new(&format!("{}.wal.db", path)));
+    let _ = remove_wal_files(Path::new(path));
+}
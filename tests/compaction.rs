@@ -0,0 +1,257 @@
+mod common;
+
+use axum::extract::{Path, State};
+use common::{cleanup_collection, test_path};
+use parking_lot::RwLock;
+use piramid::{
+    collections::{compact, compact_online, compaction_due, CollectionOpenOptions},
+    config::AppConfig,
+    runtime::AppState,
+    server::handlers::{collections, vectors},
+    server::types::{DeleteResponse, DeleteResultsResponse},
+    storage::persistence::checkpoint_files,
+    storage::wal::remove_wal_files,
+    Collection, CollectionConfig, CompactionConfig, Document,
+};
+use std::{fs, sync::Arc, time::Duration};
+use uuid::Uuid;
+
+fn insert_docs(collection: &mut Collection, count: usize) -> Vec<Uuid> {
+    (0..count)
+        .map(|i| {
+            collection
+                .insert(Document::new(
+                    vec![i as f32, 1.0, 0.0],
+                    format!("doc {}", i),
+                ))
+                .unwrap()
+        })
+        .collect()
+}
+
+// Thresholds low enough for a handful of small documents
+fn eager_compaction() -> CompactionConfig {
+    CompactionConfig {
+        enabled: true,
+        dead_ratio: 0.5,
+        dead_bytes: u64::MAX,
+        min_dead_bytes: 1,
+        max_bytes_per_sec: None,
+    }
+}
+
+#[test]
+fn storage_usage_tracks_dead_records() {
+    let path = test_path("compaction_usage.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let ids = insert_docs(&mut collection, 4);
+    let usage = collection.storage_usage();
+    assert!(usage.live_bytes > 0);
+    assert_eq!(usage.dead_bytes, 0);
+
+    let mut updated = collection.get(&ids[0]).unwrap().unwrap();
+    updated.text = "updated".to_string();
+    collection.upsert(updated).unwrap();
+    collection.delete(&ids[1]).unwrap();
+    let after_writes = collection.storage_usage();
+    assert!(after_writes.dead_bytes > 0);
+    assert!(after_writes.live_bytes < usage.live_bytes);
+
+    // Reopening derives the same numbers from the index and the data file
+    drop(collection);
+    let mut collection = Collection::open(&path).unwrap();
+    assert_eq!(
+        collection.storage_usage().live_bytes,
+        after_writes.live_bytes
+    );
+
+    compact(&mut collection).unwrap();
+    let compacted = collection.storage_usage();
+    assert_eq!(compacted.dead_bytes, 0);
+    assert_eq!(compacted.live_bytes, after_writes.live_bytes);
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn compaction_is_due_once_thresholds_are_crossed() {
+    let path = test_path("compaction_due.db");
+    let options = CollectionOpenOptions::from(CollectionConfig {
+        compaction: eager_compaction(),
+        ..Default::default()
+    });
+    let mut collection = Collection::open_with_options(&path, options).unwrap();
+    let ids = insert_docs(&mut collection, 4);
+    collection.delete(&ids[0]).unwrap();
+    assert!(!compaction_due(&collection));
+    collection.delete(&ids[1]).unwrap();
+    collection.delete(&ids[2]).unwrap();
+    assert!(compaction_due(&collection));
+
+    collection.config.compaction.enabled = false;
+    assert!(!compaction_due(&collection));
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn online_compaction_keeps_live_documents() {
+    let path = test_path("compaction_background.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let ids = insert_docs(&mut collection, 6);
+    collection.delete_batch(&ids[..3]).unwrap();
+    let handle = Arc::new(RwLock::new(collection));
+
+    // A tight throttle still finishes for a few hundred bytes
+    let stats = compact_online(&handle, Some(64 * 1024)).unwrap().unwrap();
+    assert_eq!(stats.compacted_entries, 3);
+    let collection = handle.read();
+    assert_eq!(collection.storage_usage().dead_bytes, 0);
+    for (i, id) in ids.iter().enumerate().skip(3) {
+        assert_eq!(
+            collection.get(id).unwrap().unwrap().text,
+            format!("doc {}", i)
+        );
+    }
+    assert!(collection.get(&ids[0]).unwrap().is_none());
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn writes_proceed_during_online_compaction() {
+    let path = test_path("compaction_online_writes.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let ids = insert_docs(&mut collection, 200);
+    collection.delete_batch(&ids[..100]).unwrap();
+    let handle = Arc::new(RwLock::new(collection));
+
+    // Throttled so the copy takes about a second
+    let compactor = {
+        let handle = handle.clone();
+        std::thread::spawn(move || compact_online(&handle, Some(8 * 1024)).unwrap())
+    };
+    std::thread::sleep(Duration::from_millis(100));
+
+    let added = {
+        let mut guard = handle.write();
+        let mut updated = guard.get(&ids[100]).unwrap().unwrap();
+        updated.text = "updated during compaction".to_string();
+        guard.upsert(updated).unwrap();
+        guard.delete(&ids[101]).unwrap();
+        guard
+            .insert(Document::new(vec![0.0, 0.0, 1.0], "added".to_string()))
+            .unwrap()
+    };
+    assert!(compact_online(&handle, None).unwrap().is_none());
+    assert!(!compactor.is_finished(), "writes waited for the copy");
+
+    let stats = compactor.join().unwrap().unwrap();
+    assert_eq!(stats.original_entries, 100);
+    let check = |collection: &Collection| {
+        assert_eq!(collection.count(), 100);
+        assert_eq!(
+            collection.get(&ids[100]).unwrap().unwrap().text,
+            "updated during compaction"
+        );
+        assert!(collection.get(&ids[101]).unwrap().is_none());
+        assert_eq!(collection.get(&added).unwrap().unwrap().text, "added");
+        assert_eq!(collection.get(&ids[150]).unwrap().unwrap().text, "doc 150");
+    };
+    check(&handle.read());
+    drop(handle);
+
+    // The compacted file alone still rebuilds the same state
+    let index_file = checkpoint_files(&path).unwrap().remove(0);
+    fs::write(&index_file, b"garbage").unwrap();
+    let _ = remove_wal_files(std::path::Path::new(&format!("{}.wal.db", path)));
+    check(&Collection::open(&path).unwrap());
+
+    cleanup_collection(&path);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deletes_schedule_compaction_in_the_background() {
+    let data_dir = ".piramid/tests/compaction_service";
+    let _ = fs::remove_dir_all(data_dir);
+    let config = AppConfig {
+        compaction: eager_compaction(),
+        ..Default::default()
+    };
+    let state = Arc::new(AppState::new(data_dir, config, 500, None, true).unwrap());
+
+    let handle = state.get_or_create_collection("docs").unwrap();
+    let ids = insert_docs(&mut handle.write(), 4);
+    for id in &ids[..3] {
+        let deleted = vectors::delete_vector(
+            State(state.clone()),
+            Path(("docs".to_string(), id.to_string())),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            deleted.0,
+            DeleteResultsResponse::Single(DeleteResponse { deleted: true, .. })
+        ));
+    }
+
+    let mut status = None;
+    for _ in 0..200 {
+        let response =
+            collections::rebuild_index_status(State(state.clone()), Path("docs".to_string())).await;
+        if let Ok(response) = response {
+            if response.status != "running" {
+                status = Some(response.0);
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let status = status.expect("compaction did not finish");
+    assert_eq!(status.job, "compact");
+    assert_eq!(status.status, "completed");
+
+    let collection = handle.read();
+    assert_eq!(collection.storage_usage().dead_bytes, 0);
+    assert_eq!(collection.count(), 1);
+    assert!(collection.get(&ids[3]).unwrap().is_some());
+    drop(collection);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compaction_gives_up_when_the_collection_is_deleted_meanwhile() {
+    let data_dir = ".piramid/tests/compaction_dropped";
+    let _ = fs::remove_dir_all(data_dir);
+    let state = Arc::new(AppState::new(data_dir, AppConfig::default(), 500, None, true).unwrap());
+
+    let handle = state.get_or_create_collection("docs").unwrap();
+    let ids = insert_docs(&mut handle.write(), 200);
+    handle.write().delete_batch(&ids[..100]).unwrap();
+
+    // Throttled so the delete lands while the copy is still running
+    let compactor = {
+        let handle = handle.clone();
+        std::thread::spawn(move || compact_online(&handle, Some(8 * 1024)))
+    };
+    drop(handle);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let deleted = collections::delete_collection(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap();
+    assert!(deleted.0.deleted);
+
+    assert!(compactor.join().unwrap().is_err());
+    let left: Vec<_> = fs::read_dir(data_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("docs"))
+        .collect();
+    assert!(left.is_empty(), "files came back: {left:?}");
+
+    let _ = fs::remove_dir_all(data_dir);
+}
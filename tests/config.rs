@@ -1,4 +1,7 @@
-use piramid::config::{AppConfig, HardwareProfile, LogLevel, QuantizationLevel, QuantizationStage};
+use piramid::config::{
+    AppConfig, CompactionConfig, HardwareProfile, IdleConfig, LogLevel, MaintenanceConfig,
+    QuantizationLevel, QuantizationStage, WalConfig, WalSyncMode,
+};
 use piramid::index::{AutoIndexConfig, IndexConfig, IndexType};
 use piramid::Metric;
 
@@ -47,9 +50,31 @@ limits: {}
     assert!(cfg.quantization.preserve_raw_vectors);
     assert!(!cfg.search.adaptive.enabled);
     assert_eq!(cfg.search.budget.latency_budget_ms, None);
+    assert_eq!(cfg.wal.effective_sync_mode(), WalSyncMode::Flush);
+    assert_eq!(cfg.wal.group_commit_window_ms, 2);
     cfg.validate().unwrap();
 }
 
+#[test]
+fn wal_sync_mode_parses_and_honours_sync_on_write() {
+    let wal: WalConfig = serde_yaml::from_str(
+        "enabled: true\ncheckpoint_frequency: 100\nmax_log_size: 1024\nsync_on_write: false\nsync_mode: group_commit\ngroup_commit_window_ms: 5\n",
+    )
+    .unwrap();
+    assert_eq!(wal.effective_sync_mode(), WalSyncMode::GroupCommit);
+    assert_eq!(wal.group_commit_window_ms, 5);
+
+    let legacy = WalConfig {
+        sync_on_write: true,
+        ..WalConfig::default()
+    };
+    assert_eq!(legacy.effective_sync_mode(), WalSyncMode::PerWrite);
+    assert_eq!(
+        WalConfig::high_durability().effective_sync_mode(),
+        WalSyncMode::PerWrite
+    );
+}
+
 #[test]
 fn quantization_can_express_pre_and_post_search_experiments() {
     let mut cfg = AppConfig::default();
@@ -76,6 +101,9 @@ fn auto_index_thresholds_are_configurable() {
             ivf_num_clusters: Some(3),
             ivf_num_probes: Some(2),
             ivf_max_iterations: 4,
+            ivf_pq_max_vectors: Some(20),
+            ivf_pq_subquantizers: 4,
+            ivf_pq_rerank: true,
             hnsw_m: 8,
             hnsw_ef_construction: 64,
             hnsw_ef_search: 32,
@@ -84,7 +112,8 @@ fn auto_index_thresholds_are_configurable() {
 
     assert_eq!(cfg.select_type(4), IndexType::Flat);
     assert_eq!(cfg.select_type(7), IndexType::Ivf);
-    assert_eq!(cfg.select_type(12), IndexType::Hnsw);
+    assert_eq!(cfg.select_type(12), IndexType::IvfPq);
+    assert_eq!(cfg.select_type(25), IndexType::Hnsw);
 }
 
 #[test]
@@ -94,3 +123,36 @@ fn invalid_research_config_fails_validation() {
 
     assert!(cfg.validate().is_err());
 }
+
+#[test]
+fn partial_compaction_config_takes_defaults_for_missing_fields() {
+    let compaction: CompactionConfig = serde_yaml::from_str("dead_ratio: 0.3\n").unwrap();
+    let defaults = CompactionConfig::default();
+    assert_eq!(compaction.dead_ratio, 0.3);
+    assert!(compaction.enabled);
+    assert_eq!(compaction.dead_bytes, defaults.dead_bytes);
+    assert_eq!(compaction.min_dead_bytes, defaults.min_dead_bytes);
+    assert_eq!(compaction.max_bytes_per_sec, defaults.max_bytes_per_sec);
+}
+
+#[test]
+fn partial_maintenance_config_takes_defaults_for_missing_fields() {
+    let maintenance: MaintenanceConfig = serde_yaml::from_str("repair_batch: 64\n").unwrap();
+    let defaults = MaintenanceConfig::default();
+    assert_eq!(maintenance.repair_batch, 64);
+    assert_eq!(maintenance.tombstone_ratio, defaults.tombstone_ratio);
+    assert_eq!(
+        maintenance.retrain_cooldown_secs,
+        defaults.retrain_cooldown_secs
+    );
+}
+
+#[test]
+fn partial_idle_config_takes_defaults_for_missing_fields() {
+    let idle: IdleConfig = serde_yaml::from_str("unload_after_secs: 300\n").unwrap();
+    assert_eq!(idle.unload_after_secs, Some(300));
+    assert_eq!(
+        idle.check_interval_secs,
+        IdleConfig::default().check_interval_secs
+    );
+}
@@ -0,0 +1,341 @@
+// Crash-consistency harness. Random insert/update/delete, batch, checkpoint and compact sequences
+// run against a collection stored in a simulated file system that injects crashes, failed fsyncs
+// and full disks. After every failure (and at random points, as a plain power loss) the collection
+// is dropped, the file system is reset to what a power loss would leave, and the collection is
+// reopened. Every acknowledged write must survive, and every document must read back as a whole
+// version that was written to it: either its last acknowledged state or the one write in flight.
+use piramid::{
+    collections::{compact, CollectionBuilder, CollectionOpenOptions},
+    index::IndexConfig,
+    search::SearchParams,
+    storage::vfs::{Fault, FaultVfs, MemoryVfs},
+    Collection, CollectionConfig, Document, Metric, WalSyncMode,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const PATH: &str = "data/crash.db";
+const SEEDS: u64 = 24;
+const STEPS: usize = 300;
+const DIMENSIONS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Version {
+    text: String,
+    vector: Vec<f32>,
+}
+
+#[derive(Debug, Default)]
+struct Expected {
+    // None once deleted (or before the first acknowledged insert)
+    acked: Option<Version>,
+    // The write that failed before the last crash, which may or may not have landed
+    in_flight: Option<Option<Version>>,
+}
+
+struct Harness {
+    rng: StdRng,
+    seed: u64,
+    disk: MemoryVfs,
+    faults: FaultVfs,
+    docs: HashMap<Uuid, Expected>,
+    crashes: usize,
+    // Full disks that were freed with the collection still open
+    freed: usize,
+}
+
+impl Harness {
+    fn new(seed: u64) -> Self {
+        let disk = MemoryVfs::with_crash_simulation();
+        let faults = FaultVfs::new(Arc::new(disk.clone()));
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            disk,
+            faults,
+            docs: HashMap::new(),
+            crashes: 0,
+            freed: 0,
+        }
+    }
+
+    fn open(&self) -> Collection {
+        let mut config = CollectionConfig {
+            index: IndexConfig::Flat {
+                metric: Metric::Euclidean,
+                mode: Default::default(),
+                search: Default::default(),
+            },
+            ..Default::default()
+        };
+        // Acknowledged means durable only when every append is fsynced
+        config.wal.sync_mode = WalSyncMode::PerWrite;
+        config.wal.checkpoint_frequency = 25;
+        config.wal.max_log_size = 4096;
+        let options = CollectionOpenOptions::from(config).with_vfs(Arc::new(self.faults.clone()));
+        CollectionBuilder::open(PATH, options)
+            .unwrap_or_else(|e| panic!("seed {}: reopen failed: {e}", self.seed))
+    }
+
+    fn arm_fault(&mut self) {
+        let fault = match self.rng.gen_range(0..4) {
+            0 => Fault::CrashAfterBytes(self.rng.gen_range(0..20_000)),
+            1 => Fault::FailSync(self.rng.gen_range(0..40)),
+            2 => Fault::NoSpaceAfterBytes(self.rng.gen_range(0..20_000)),
+            _ => return self.faults.clear(),
+        };
+        self.faults.inject(fault);
+    }
+
+    fn version(&mut self) -> Version {
+        let tag: u32 = self.rng.gen();
+        Version {
+            text: format!("doc {tag}"),
+            vector: (0..DIMENSIONS)
+                .map(|_| self.rng.gen_range(-1.0..1.0))
+                .collect(),
+        }
+    }
+
+    fn pick_existing(&mut self) -> Option<Uuid> {
+        let mut live: Vec<Uuid> = self
+            .docs
+            .iter()
+            .filter(|(_, expected)| expected.acked.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        live.sort();
+        (!live.is_empty()).then(|| live[self.rng.gen_range(0..live.len())])
+    }
+
+    // One random operation. Returns false if it failed and the process has to "crash".
+    fn step(&mut self, collection: &mut Collection) -> bool {
+        let roll = self.rng.gen_range(0..100);
+        let existing = self.pick_existing();
+        let (id, write) = match (roll, existing) {
+            (0..=39, _) | (40..=74, None) => {
+                let id = Uuid::from_u128(self.rng.gen());
+                (id, Some(self.version()))
+            }
+            (40..=59, Some(id)) => (id, Some(self.version())),
+            (60..=74, Some(id)) => (id, None),
+            (75..=82, _) | (83..=89, None) => return self.insert_batch(collection),
+            (83..=89, Some(_)) => return self.delete_batch(collection),
+            (90..=96, _) => return collection.checkpoint().is_ok(),
+            _ => return compact(collection).is_ok(),
+        };
+
+        let result = match &write {
+            Some(version) => {
+                let mut document = Document::new(version.vector.clone(), version.text.clone());
+                document.id = id;
+                collection.upsert(document).map(|_| ())
+            }
+            None => collection.delete(&id).map(|_| ()),
+        };
+        self.settle(vec![(id, write)], result.is_ok())
+    }
+
+    fn insert_batch(&mut self, collection: &mut Collection) -> bool {
+        let mut writes = Vec::new();
+        let mut documents = Vec::new();
+        for _ in 0..self.rng.gen_range(2..8) {
+            let version = self.version();
+            let mut document = Document::new(version.vector.clone(), version.text.clone());
+            document.id = Uuid::from_u128(self.rng.gen());
+            writes.push((document.id, Some(version)));
+            documents.push(document);
+        }
+        let ok = collection.insert_batch(documents).is_ok();
+        self.settle(writes, ok)
+    }
+
+    fn delete_batch(&mut self, collection: &mut Collection) -> bool {
+        let mut ids: Vec<Uuid> = (0..self.rng.gen_range(1..6))
+            .filter_map(|_| self.pick_existing())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let ok = collection.delete_batch(&ids).is_ok();
+        self.settle(ids.into_iter().map(|id| (id, None)).collect(), ok)
+    }
+
+    // Acknowledged writes become the expected state; a failed one may or may not have landed, as
+    // may each document of a failed batch
+    fn settle(&mut self, writes: Vec<(Uuid, Option<Version>)>, ok: bool) -> bool {
+        for (id, write) in writes {
+            let expected = self.docs.entry(id).or_default();
+            if ok {
+                expected.acked = write;
+            } else {
+                expected.in_flight = Some(write);
+            }
+        }
+        ok
+    }
+
+    // Read back every document the collection should know about. After a crash what was read is
+    // durable and becomes the expected state; a live collection is only checked.
+    fn verify(&mut self, collection: &Collection, context: &str, durable: bool) {
+        let mut live = 0;
+        let mut ids: Vec<Uuid> = self.docs.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let actual = collection
+                .get(&id)
+                .unwrap_or_else(|e| {
+                    panic!("seed {} {context}: read of {id} failed: {e}", self.seed)
+                })
+                .map(|document| Version {
+                    vector: document.get_vector(),
+                    text: document.text,
+                });
+            let expected = self.docs.get_mut(&id).unwrap();
+            let allowed = actual == expected.acked || expected.in_flight.as_ref() == Some(&actual);
+            assert!(
+                allowed,
+                "seed {} {context}: {id} reads {actual:?}, expected {:?} or in-flight {:?}",
+                self.seed, expected.acked, expected.in_flight
+            );
+            if let Some(version) = &actual {
+                live += 1;
+                let hits = collection
+                    .search(
+                        &version.vector,
+                        1,
+                        Metric::Euclidean,
+                        SearchParams::default(),
+                    )
+                    .unwrap();
+                assert_eq!(
+                    hits.first().map(|hit| hit.id),
+                    Some(id),
+                    "seed {} {context}: {id} is missing from the vector index",
+                    self.seed
+                );
+            }
+            if durable {
+                expected.acked = actual;
+                expected.in_flight = None;
+            }
+        }
+        assert_eq!(
+            collection.count(),
+            live,
+            "seed {} {context}: unexpected documents",
+            self.seed
+        );
+    }
+
+    fn crash(&mut self, collection: Collection, step: usize) -> Collection {
+        drop(collection);
+        self.faults.clear();
+        self.disk.simulate_crash(self.seed ^ (step as u64) << 16);
+        self.crashes += 1;
+        let collection = self.open();
+        self.verify(&collection, &format!("after crash at step {step}"), true);
+        self.arm_fault();
+        collection
+    }
+
+    // Free the full disk and carry on with the same collection, as a process would once space is
+    // released. The write that hit the full disk was refused, so it must not show up later, live
+    // or after the next power loss.
+    fn free_space(&mut self, mut collection: Collection, step: usize) -> Collection {
+        self.faults.clear();
+        self.freed += 1;
+        for expected in self.docs.values_mut() {
+            expected.in_flight = None;
+        }
+        for _ in 0..self.rng.gen_range(1..10) {
+            assert!(
+                self.step(&mut collection),
+                "seed {}: write failed after freeing the disk at step {step}",
+                self.seed
+            );
+        }
+        self.verify(
+            &collection,
+            &format!("after freeing the disk at step {step}"),
+            false,
+        );
+        self.crash(collection, step)
+    }
+
+    fn run(mut self) -> (usize, usize) {
+        let mut collection = self.open();
+        self.arm_fault();
+        for step in 0..STEPS {
+            let ok = self.step(&mut collection);
+            if !ok && !self.faults.tripped() {
+                panic!(
+                    "seed {}: step {step} failed without an injected fault",
+                    self.seed
+                );
+            }
+            // A full disk refuses writes but reads keep working
+            if !ok && self.faults.is_full() {
+                self.verify(
+                    &collection,
+                    &format!("with a full disk at step {step}"),
+                    false,
+                );
+                if self.rng.gen_bool(0.75) {
+                    collection = self.free_space(collection, step);
+                    continue;
+                }
+            }
+            // A plain power loss now and then, faults or not
+            if !ok || self.rng.gen_range(0..100) < 3 {
+                collection = self.crash(collection, step);
+            }
+        }
+        self.faults.clear();
+        self.verify(&collection, "at the end", true);
+        (self.crashes, self.freed)
+    }
+}
+
+#[test]
+fn acknowledged_writes_survive_crashes_and_injected_faults() {
+    let (mut crashes, mut freed) = (0, 0);
+    for seed in 0..SEEDS {
+        let (seed_crashes, seed_freed) = Harness::new(seed).run();
+        crashes += seed_crashes;
+        freed += seed_freed;
+    }
+    // Make sure the faults actually fire
+    assert!(crashes >= SEEDS as usize * 5, "only {crashes} crashes");
+    assert!(freed >= SEEDS as usize, "only {freed} full disks freed");
+}
+
+#[test]
+fn collection_survives_a_crash_during_recovery() {
+    for seed in 0..SEEDS {
+        let mut harness = Harness::new(seed);
+        let mut collection = harness.open();
+        for _ in 0..40 {
+            assert!(harness.step(&mut collection));
+        }
+        drop(collection);
+        harness.disk.simulate_crash(seed);
+
+        // Recovery replays the WAL and checkpoints; kill it part-way and recover again
+        harness
+            .faults
+            .inject(Fault::CrashAfterBytes(harness.rng.gen_range(0..4_000)));
+        if let Ok(collection) = CollectionBuilder::open(PATH, {
+            let options = CollectionOpenOptions::from(CollectionConfig::default());
+            options.with_vfs(Arc::new(harness.faults.clone()))
+        }) {
+            drop(collection);
+        }
+        harness.faults.clear();
+        harness.disk.simulate_crash(seed + 1);
+        let collection = harness.open();
+        harness.verify(&collection, "after a crash during recovery", true);
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::{
+    collections::{check_collection, repair_collection, CollectionOpenOptions, FsckProblem},
+    search::SearchParams,
+    storage::persistence::{checkpoint_files, load_checkpoint, write_checkpoint, EntryPointer},
+    storage::wal::list_segments,
+    Collection, Document, Metric,
+};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use uuid::Uuid;
+
+// A checkpointed collection of `count` documents, closed again.
+fn seed(path: &str, count: usize) -> Vec<Uuid> {
+    let mut collection = Collection::open(path).unwrap();
+    let ids = (0..count)
+        .map(|i| {
+            collection
+                .insert(Document::new(
+                    vec![i as f32, 1.0, 0.0],
+                    format!("doc {}", i),
+                ))
+                .unwrap()
+        })
+        .collect();
+    collection.checkpoint().unwrap();
+    ids
+}
+
+#[test]
+fn clean_collection_passes_without_being_modified() {
+    let path = test_path("fsck_clean.db");
+    seed(&path, 5);
+    let mut files = checkpoint_files(&path).unwrap();
+    files.push(path.clone().into());
+    let before: Vec<Vec<u8>> = files.iter().map(|file| fs::read(file).unwrap()).collect();
+
+    let report = check_collection(&path).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(report.collection, "fsck_clean");
+    assert_eq!(report.documents, 5);
+
+    let after: Vec<Vec<u8>> = files.iter().map(|file| fs::read(file).unwrap()).collect();
+    assert_eq!(before, after);
+    assert_eq!(checkpoint_files(&path).unwrap(), files[..3]);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn damaged_checkpoint_and_wal_are_reported_and_repaired() {
+    let path = test_path("fsck_damaged.db");
+    let ids = seed(&path, 6);
+
+    // An index entry pointing past the data file, a vector missing from the vector index and a
+    // torn WAL frame.
+    let loaded = load_checkpoint(&path).unwrap();
+    let manifest = loaded.manifest.unwrap();
+    let mut index = loaded.index;
+    let mut vector_index = loaded.vector_index.unwrap();
+    let dangling = Uuid::new_v4();
+    index.insert(dangling, EntryPointer::new(1 << 40, 64));
+    vector_index.remove(&ids[0]);
+    write_checkpoint(
+        &path,
+        manifest.last_checkpoint_seq,
+        manifest.timestamp,
+        &index,
+        vector_index.as_ref(),
+        &loaded.metadata.unwrap(),
+    )
+    .unwrap();
+    let segment = list_segments(Path::new(&format!("{}.wal.db", path)))
+        .unwrap()
+        .pop()
+        .unwrap();
+    let mut file = OpenOptions::new().append(true).open(&segment.path).unwrap();
+    file.write_all(&[9, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    let report = check_collection(&path).unwrap();
+    assert_eq!(report.documents, 6);
+    assert!(report.problems.iter().any(|problem| matches!(
+        problem,
+        FsckProblem::DanglingPointer { id, .. } if *id == dangling
+    )));
+    assert!(report
+        .problems
+        .contains(&FsckProblem::MissingFromVectorIndex(ids[0])));
+    assert!(report
+        .problems
+        .iter()
+        .any(|problem| matches!(problem, FsckProblem::Wal { .. })));
+
+    let repaired = repair_collection(&path, CollectionOpenOptions::default()).unwrap();
+    assert!(!repaired.rebuilt_index);
+    assert_eq!(repaired.dropped_documents, 1);
+    assert!(repaired.after.is_clean(), "{:?}", repaired.after.problems);
+
+    let collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.count(), 6);
+    // The vector that was missing from the vector index is searchable again.
+    let hits = collection
+        .search(&[0.0, 1.0, 0.0], 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert_eq!(hits[0].id, ids[0]);
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn unreadable_index_is_rebuilt_from_the_data_file() {
+    let path = test_path("fsck_rebuild.db");
+    let ids = seed(&path, 4);
+    let index_file = checkpoint_files(&path).unwrap().remove(0);
+    fs::write(&index_file, b"not an index").unwrap();
+
+    let report = check_collection(&path).unwrap();
+    assert!(report.problems.iter().any(
+        |problem| matches!(problem, FsckProblem::Unreadable { file, .. } if *file == index_file)
+    ));
+
+    let repaired = repair_collection(&path, CollectionOpenOptions::default()).unwrap();
+    assert!(repaired.rebuilt_index);
+    assert!(repaired.after.is_clean(), "{:?}", repaired.after.problems);
+    assert_eq!(repaired.after.documents, 4);
+
+    let collection = Collection::open(&path).unwrap();
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(
+            collection.get(id).unwrap().unwrap().text,
+            format!("doc {}", i)
+        );
+    }
+    drop(collection);
+
+    cleanup_collection(&path);
+}
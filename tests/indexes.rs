@@ -1,10 +1,11 @@
 use piramid::{
     index::{
         FlatConfig, FlatIndex, HnswConfig, HnswIndex, IndexConfig, IndexType, IvfConfig, IvfIndex,
+        IvfPqConfig, IvfPqIndex,
     },
     HashMapVectorReader, VectorIndex,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 #[test]
@@ -183,3 +184,596 @@ fn index_selector_prefers_expected_types() {
     assert_eq!(cfg.select_type(50_000), IndexType::Ivf);
     assert_eq!(cfg.select_type(500_000), IndexType::Hnsw);
 }
+
+// Deterministic LCG noise in [-0.5, 0.5)
+fn lcg(seed: u64) -> impl FnMut() -> f32 {
+    let mut state = seed;
+    move || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) as f32 / (1u64 << 31) as f32 - 0.5
+    }
+}
+
+fn clustered_vectors(count: usize, dims: usize) -> HashMap<Uuid, Vec<f32>> {
+    (0..count)
+        .map(|i| {
+            let vector = (0..dims)
+                .map(|d| ((i * dims + d) as f32 * 0.37).sin() + if d == i % 4 { 4.0 } else { 0.0 })
+                .collect();
+            (Uuid::new_v4(), vector)
+        })
+        .collect()
+}
+
+// Noise around four cluster axes
+fn noisy_clusters(count: usize, dims: usize) -> HashMap<Uuid, Vec<f32>> {
+    let mut next = lcg(0x9E37_79B9_7F4A_7C15);
+    (0..count)
+        .map(|i| {
+            let vector = (0..dims)
+                .map(|d| next() + if d == i % 4 { 2.0 } else { 0.0 })
+                .collect();
+            (Uuid::new_v4(), vector)
+        })
+        .collect()
+}
+
+fn random_vectors(count: usize, dims: usize) -> HashMap<Uuid, Vec<f32>> {
+    let mut next = lcg(0x2545_F491_4F6C_DD1D);
+    (0..count)
+        .map(|_| (Uuid::new_v4(), (0..dims).map(|_| next()).collect()))
+        .collect()
+}
+
+#[test]
+fn ivf_pq_search_with_rerank_finds_exact_match() {
+    let config = IvfPqConfig {
+        num_clusters: 4,
+        num_probes: 4,
+        subquantizers: 4,
+        ..IvfPqConfig::default()
+    };
+    let mut idx = IvfPqIndex::new(config);
+    let vectors = clustered_vectors(200, 16);
+    let reader = HashMapVectorReader::new(&vectors);
+    for (id, vector) in &vectors {
+        idx.insert(*id, vector, &reader);
+    }
+
+    let stats = idx.stats();
+    assert_eq!(stats.index_type, IndexType::IvfPq);
+    assert_eq!(stats.total_vectors, 200);
+    match stats.details {
+        piramid::index::IndexDetails::IvfPq {
+            vectors_per_cluster,
+            subquantizers,
+            centroids_computed,
+            ..
+        } => {
+            assert!(centroids_computed);
+            assert_eq!(subquantizers, 4);
+            assert_eq!(vectors_per_cluster.iter().sum::<usize>(), 200);
+        }
+        other => panic!("expected IVF-PQ stats, got {other:?}"),
+    }
+
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    for (id, vector) in vectors.iter().take(10) {
+        let results = idx
+            .search(
+                vector,
+                3,
+                &reader,
+                piramid::config::SearchConfig::default(),
+                None,
+                &empty_meta,
+            )
+            .unwrap();
+        assert_eq!(results.first(), Some(id));
+    }
+}
+
+#[test]
+fn ivf_pq_remove_keeps_lists_aligned() {
+    let config = IvfPqConfig {
+        num_clusters: 2,
+        num_probes: 2,
+        subquantizers: 2,
+        rerank: false,
+        ..IvfPqConfig::default()
+    };
+    let mut idx = IvfPqIndex::new(config);
+    let vectors = clustered_vectors(50, 8);
+    let reader = HashMapVectorReader::new(&vectors);
+    for (id, vector) in &vectors {
+        idx.insert(*id, vector, &reader);
+    }
+
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    for id in ids.iter().take(20) {
+        idx.remove(id);
+    }
+    assert_eq!(idx.stats().total_vectors, 30);
+
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    let results = idx
+        .search(
+            &vectors[&ids[30]],
+            50,
+            &reader,
+            piramid::config::SearchConfig::default(),
+            None,
+            &empty_meta,
+        )
+        .unwrap();
+    assert_eq!(results.len(), 30);
+    assert!(results.iter().all(|id| !ids[..20].contains(id)));
+}
+
+#[test]
+fn hnsw_bulk_insert_matches_brute_force_recall() {
+    let mut idx = HnswIndex::new(HnswConfig::default());
+    let vectors = random_vectors(2_000, 16);
+    let reader = HashMapVectorReader::new(&vectors);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    idx.insert_bulk(&ids, &reader);
+
+    let stats = idx.stats();
+    assert_eq!(stats.total_nodes, 2_000);
+
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    let metric = piramid::Metric::Cosine;
+    let mode = piramid::config::ExecutionMode::Auto;
+    let mut hits = 0;
+    let queries = 50;
+    for query_id in ids.iter().take(queries) {
+        let query = &vectors[query_id];
+        let mut exact: Vec<(Uuid, f32)> = vectors
+            .iter()
+            .map(|(id, v)| (*id, metric.calculate(query, v, mode)))
+            .collect();
+        exact.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let expected: Vec<Uuid> = exact.iter().take(10).map(|(id, _)| *id).collect();
+
+        let results = idx
+            .search(query, 10, 100, &reader, None, &empty_meta)
+            .unwrap();
+        hits += results.iter().filter(|id| expected.contains(id)).count();
+    }
+    let recall = hits as f32 / (queries * 10) as f32;
+    assert!(recall >= 0.9, "bulk-built HNSW recall too low: {recall}");
+}
+
+// search_layer must drop the furthest kept candidate when it overflows, not the closest. With the
+// ordering reversed, a stored vector is no longer its own nearest neighbour and recall collapses.
+#[test]
+fn hnsw_incremental_search_keeps_closest_candidates() {
+    let mut idx = HnswIndex::new(HnswConfig::default());
+    let vectors = random_vectors(1_000, 16);
+    let reader = HashMapVectorReader::new(&vectors);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    for id in &ids {
+        idx.insert(*id, &vectors[id], &reader);
+    }
+
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    let metric = piramid::Metric::Cosine;
+    let mode = piramid::config::ExecutionMode::Auto;
+    let mut hits = 0;
+    let queries = 50;
+    for query_id in ids.iter().take(queries) {
+        let query = &vectors[query_id];
+        let mut exact: Vec<(Uuid, f32)> = vectors
+            .iter()
+            .map(|(id, v)| (*id, metric.calculate(query, v, mode)))
+            .collect();
+        exact.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let expected: Vec<Uuid> = exact.iter().take(10).map(|(id, _)| *id).collect();
+
+        let results = idx
+            .search(query, 10, 64, &reader, None, &empty_meta)
+            .unwrap();
+        assert_eq!(results.first(), Some(query_id));
+        hits += results.iter().filter(|id| expected.contains(id)).count();
+    }
+    let recall = hits as f32 / (queries * 10) as f32;
+    assert!(recall >= 0.9, "incremental HNSW recall too low: {recall}");
+}
+
+#[test]
+fn hnsw_repair_purges_tombstones_past_ratio() {
+    let mut idx = HnswIndex::new(HnswConfig::default());
+    let vectors = random_vectors(1_000, 16);
+    let reader = HashMapVectorReader::new(&vectors);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    for id in &ids {
+        idx.insert(*id, &vectors[id], &reader);
+    }
+
+    for id in &ids[..150] {
+        idx.remove(id);
+    }
+    assert!(!idx.needs_repair(0.2));
+    assert_eq!(idx.stats().tombstones, 150);
+
+    for id in &ids[150..250] {
+        idx.remove(id);
+    }
+    assert!(idx.needs_repair(0.2));
+    let purged = idx.repair(&reader);
+    assert_eq!(purged.len(), 250);
+    let stats = idx.stats();
+    assert_eq!(stats.tombstones, 0);
+    assert_eq!(stats.total_nodes, 750);
+
+    let live = &ids[250..];
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    let metric = piramid::Metric::Cosine;
+    let mode = piramid::config::ExecutionMode::Auto;
+    let mut hits = 0;
+    let queries = 50;
+    for query_id in live.iter().take(queries) {
+        let query = &vectors[query_id];
+        let mut exact: Vec<(Uuid, f32)> = live
+            .iter()
+            .map(|id| (*id, metric.calculate(query, &vectors[id], mode)))
+            .collect();
+        exact.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let expected: Vec<Uuid> = exact.iter().take(10).map(|(id, _)| *id).collect();
+
+        let results = idx
+            .search(query, 10, 100, &reader, None, &empty_meta)
+            .unwrap();
+        assert!(results.iter().all(|id| !ids[..250].contains(id)));
+        hits += results.iter().filter(|id| expected.contains(id)).count();
+    }
+    let recall = hits as f32 / (queries * 10) as f32;
+    assert!(recall >= 0.9, "repaired HNSW recall too low: {recall}");
+}
+
+#[test]
+fn ivf_retrain_rebalances_drifted_lists() {
+    let config = IvfConfig {
+        num_clusters: 8,
+        ..IvfConfig::default()
+    };
+    let mut idx = IvfIndex::new(config);
+    let mut vectors = noisy_clusters(400, 8);
+    {
+        let reader = HashMapVectorReader::new(&vectors);
+        for (id, vector) in &vectors {
+            idx.insert(*id, vector, &reader);
+        }
+    }
+    assert!(!idx.needs_retrain());
+
+    // New data piles up tightly inside a single existing cluster
+    let mut next = lcg(7);
+    let drifted: Vec<(Uuid, Vec<f32>)> = (0..1_200)
+        .map(|_| {
+            let vector = (0..8)
+                .map(|d| next() * 0.1 + if d == 0 { 2.0 } else { 0.0 })
+                .collect();
+            (Uuid::new_v4(), vector)
+        })
+        .collect();
+    vectors.extend(drifted.iter().cloned());
+    let reader = HashMapVectorReader::new(&vectors);
+    for (id, vector) in &drifted {
+        idx.insert(*id, vector, &reader);
+    }
+    let before = idx.imbalance_ratio();
+    assert!(idx.needs_retrain(), "imbalance {before}");
+
+    let retrained = idx.retrained(&reader);
+    assert_eq!(retrained.stats().total_vectors, 1_600);
+    assert!(retrained.imbalance_ratio() < before);
+    assert!(
+        !retrained.needs_retrain(),
+        "imbalance after retrain {}",
+        retrained.imbalance_ratio()
+    );
+    match retrained.stats().details {
+        piramid::index::IndexDetails::Ivf {
+            vectors_per_cluster,
+            empty_lists,
+            ..
+        } => {
+            assert_eq!(vectors_per_cluster.iter().sum::<usize>(), 1_600);
+            assert_eq!(empty_lists, 0);
+        }
+        other => panic!("expected IVF stats, got {other:?}"),
+    }
+
+    let (query_id, query) = &drifted[0];
+    let results = retrained
+        .search(query, 1, &reader, Default::default(), None, &HashMap::new())
+        .unwrap();
+    assert_eq!(results, vec![*query_id]);
+}
+
+// The shape vector index files had before the indexes used dense internal ids
+#[derive(serde::Serialize)]
+enum LegacyIndexFile {
+    #[allow(dead_code)]
+    Flat {
+        config: FlatConfig,
+        vector_ids: Vec<Uuid>,
+    },
+    Hnsw {
+        config: HnswConfig,
+        nodes: HashMap<Uuid, LegacyHnswNode>,
+        max_level: isize,
+        start_node: Option<Uuid>,
+    },
+    Ivf {
+        config: IvfConfig,
+        centroids: Vec<Vec<f32>>,
+        inverted_lists: Vec<Vec<Uuid>>,
+        vector_to_cluster: HashMap<Uuid, usize>,
+        pending_vectors: std::collections::HashSet<Uuid>,
+        dimensions: usize,
+    },
+}
+
+#[derive(serde::Serialize)]
+struct LegacyHnswNode {
+    connections: Vec<Vec<Uuid>>,
+    tombstone: bool,
+}
+
+fn load_index(bytes: &[u8]) -> Box<dyn VectorIndex> {
+    bincode::deserialize::<piramid::index::SerializableIndex>(bytes)
+        .unwrap()
+        .to_trait_object()
+}
+
+#[test]
+fn legacy_hnsw_files_migrate_to_dense_ids() {
+    let vectors: HashMap<Uuid, Vec<f32>> = [
+        vec![1.0, 0.0],
+        vec![0.9, 0.1],
+        vec![0.0, 1.0],
+        vec![-1.0, 0.0],
+    ]
+    .into_iter()
+    .map(|vector| (Uuid::new_v4(), vector))
+    .collect();
+    let reader = HashMapVectorReader::new(&vectors);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    // Every node linked to every other on layer 0; the last one is deleted
+    let nodes = ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let others = ids.iter().filter(|other| *other != id).copied().collect();
+            let node = LegacyHnswNode {
+                connections: vec![others],
+                tombstone: i == 3,
+            };
+            (*id, node)
+        })
+        .collect();
+    let legacy = LegacyIndexFile::Hnsw {
+        config: HnswConfig::default(),
+        nodes,
+        max_level: 0,
+        start_node: Some(ids[0]),
+    };
+
+    let mut idx = load_index(&bincode::serialize(&legacy).unwrap());
+    assert_eq!(idx.index_type(), IndexType::Hnsw);
+    assert_eq!(idx.stats().total_vectors, 3);
+    assert!(!idx.ids().contains(&ids[3]));
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    let query = vectors[&ids[2]].clone();
+    let results = idx
+        .search(&query, 1, &reader, Default::default(), None, &empty_meta)
+        .unwrap();
+    assert_eq!(results, vec![ids[2]]);
+
+    // Once migrated, the index saves and reloads in the current format
+    let added = Uuid::new_v4();
+    let mut vectors = vectors;
+    vectors.insert(added, vec![0.1, 0.9]);
+    let reader = HashMapVectorReader::new(&vectors);
+    idx.insert(added, &vectors[&added], &reader);
+    let reloaded = load_index(&bincode::serialize(&idx.to_serializable()).unwrap());
+    assert_eq!(reloaded.stats().total_vectors, 4);
+    let results = reloaded
+        .search(
+            &[0.1, 0.9],
+            1,
+            &reader,
+            Default::default(),
+            None,
+            &empty_meta,
+        )
+        .unwrap();
+    assert_eq!(results, vec![added]);
+}
+
+#[test]
+fn legacy_ivf_files_migrate_to_dense_ids() {
+    let left: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    let right: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    let mut vectors = HashMap::new();
+    for (i, id) in left.iter().enumerate() {
+        vectors.insert(*id, vec![1.0, i as f32 * 0.1]);
+    }
+    for (i, id) in right.iter().enumerate() {
+        vectors.insert(*id, vec![i as f32 * 0.1, 1.0]);
+    }
+    let reader = HashMapVectorReader::new(&vectors);
+    let vector_to_cluster = left
+        .iter()
+        .map(|id| (*id, 0))
+        .chain(right.iter().map(|id| (*id, 1)))
+        .collect();
+    let legacy = LegacyIndexFile::Ivf {
+        config: IvfConfig {
+            num_clusters: 2,
+            num_probes: 1,
+            ..IvfConfig::default()
+        },
+        centroids: vec![vec![1.0, 0.1], vec![0.1, 1.0]],
+        inverted_lists: vec![left.clone(), right.clone()],
+        vector_to_cluster,
+        pending_vectors: Default::default(),
+        dimensions: 2,
+    };
+
+    let mut idx = load_index(&bincode::serialize(&legacy).unwrap());
+    assert_eq!(idx.index_type(), IndexType::Ivf);
+    assert_eq!(idx.stats().total_vectors, 6);
+    let results = idx
+        .search(
+            &[0.2, 1.0],
+            1,
+            &reader,
+            Default::default(),
+            None,
+            &HashMap::new(),
+        )
+        .unwrap();
+    assert_eq!(results, vec![right[2]]);
+
+    // A removed vector's offset goes to the next insert without disturbing the lists
+    idx.remove(&right[2]);
+    let added = Uuid::new_v4();
+    vectors.insert(added, vec![1.0, -0.5]);
+    let reader = HashMapVectorReader::new(&vectors);
+    idx.insert(added, &vectors[&added], &reader);
+    assert_eq!(idx.stats().total_vectors, 6);
+    let results = idx
+        .search(
+            &[0.2, 1.0],
+            3,
+            &reader,
+            Default::default(),
+            None,
+            &HashMap::new(),
+        )
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(!results.contains(&right[2]));
+    let results = idx
+        .search(
+            &[1.0, -0.5],
+            1,
+            &reader,
+            Default::default(),
+            None,
+            &HashMap::new(),
+        )
+        .unwrap();
+    assert_eq!(results, vec![added]);
+}
+
+#[test]
+fn hnsw_repair_cuts_edges_of_nodes_deleted_mid_pass() {
+    let mut idx = HnswIndex::new(HnswConfig::default());
+    let mut vectors = random_vectors(1_000, 16);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    {
+        let reader = HashMapVectorReader::new(&vectors);
+        for id in &ids {
+            idx.insert(*id, &vectors[id], &reader);
+        }
+    }
+    let dead: HashSet<Uuid> = ids[..300].iter().copied().collect();
+    for id in &dead {
+        idx.remove(id);
+    }
+
+    // The first step only reaches the early offsets; delete a later node that links to a tombstone
+    let reader = HashMapVectorReader::new(&vectors);
+    assert!(idx.repair_step(&reader, 100).is_none());
+    let late = *ids[900..]
+        .iter()
+        .find(|id| {
+            let edges = idx.neighbors(id).unwrap();
+            edges.iter().flatten().any(|n| dead.contains(n))
+        })
+        .expect("some late node links to a tombstone");
+    idx.remove(&late);
+    let removed = loop {
+        if let Some(removed) = idx.repair_step(&reader, 100) {
+            break removed;
+        }
+    };
+    assert_eq!(removed.len(), 300);
+    assert_eq!(idx.stats().tombstones, 1);
+
+    // The freed offsets go to new nodes, which the late tombstone must not lead to
+    let fresh: Vec<Uuid> = (0..300).map(|_| Uuid::new_v4()).collect();
+    for id in &fresh {
+        vectors.insert(*id, vectors[&ids[500]].clone());
+    }
+    let reader = HashMapVectorReader::new(&vectors);
+    for id in &fresh {
+        idx.insert(*id, &vectors[id], &reader);
+    }
+    let edges = idx.neighbors(&late).unwrap();
+    assert!(edges
+        .iter()
+        .flatten()
+        .all(|n| !dead.contains(n) && !fresh.contains(n)));
+}
+
+#[test]
+fn hnsw_repair_steps_are_bounded_and_skip_later_changes() {
+    let mut idx = HnswIndex::new(HnswConfig::default());
+    let mut vectors = random_vectors(1_000, 16);
+    let ids: Vec<Uuid> = vectors.keys().copied().collect();
+    {
+        let reader = HashMapVectorReader::new(&vectors);
+        for id in &ids {
+            idx.insert(*id, &vectors[id], &reader);
+        }
+    }
+    for id in &ids[..300] {
+        idx.remove(id);
+    }
+    assert!(idx.needs_repair(0.2));
+
+    // Writes land between steps: a new node, a revived node and a fresh delete
+    let extra = Uuid::new_v4();
+    vectors.insert(extra, vectors[&ids[500]].clone());
+    let mut steps = 0;
+    let removed = loop {
+        let reader = HashMapVectorReader::new(&vectors);
+        if let Some(removed) = idx.repair_step(&reader, 100) {
+            break removed;
+        }
+        steps += 1;
+        if steps == 2 {
+            idx.insert(extra, &vectors[&extra], &reader);
+            idx.insert(ids[0], &vectors[&ids[0]], &reader);
+            idx.remove(&ids[900]);
+        }
+    };
+    // 700 live nodes at 100 per step
+    assert!(steps >= 6, "repair finished in {steps} steps");
+    assert_eq!(removed.len(), 299);
+    assert!(!removed.contains(&ids[0]));
+    assert_eq!(idx.stats().tombstones, 1);
+    assert_eq!(idx.stats().total_nodes, 701);
+    assert!(!idx.needs_repair(0.2));
+
+    let reader = HashMapVectorReader::new(&vectors);
+    let empty_meta: HashMap<Uuid, piramid::metadata::Metadata> = HashMap::new();
+    for query_id in [ids[0], extra, ids[400], ids[700]] {
+        let results = idx
+            .search(&vectors[&query_id], 5, 100, &reader, None, &empty_meta)
+            .unwrap();
+        let query = &vectors[&query_id];
+        let best = &vectors[&results[0]];
+        assert_eq!(best, query);
+        assert!(results
+            .iter()
+            .all(|id| !removed.contains(id) && *id != ids[900]));
+    }
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::collections::CollectionOpenOptions;
+use piramid::config::{AppConfig, ParallelismConfig};
+use piramid::runtime::AppState;
+use piramid::server::request_id::RequestId;
+use piramid::server::types::{SearchRequest, SearchResultsResponse};
+use piramid::services::{admin, vector};
+use piramid::{
+    metadata, CacheConfig, Collection, CollectionConfig, Document, Filter, Metric, SearchParams,
+};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use std::{fs, thread};
+
+fn hnsw_config() -> CollectionConfig {
+    CollectionConfig {
+        index: piramid::index::IndexConfig::Hnsw {
+            m: 8,
+            m_max: 16,
+            ef_construction: 64,
+            ef_search: 64,
+            ml: 1.0 / (8.0f32).ln(),
+            metric: Metric::Cosine,
+            mode: Default::default(),
+            search: Default::default(),
+        },
+        ..CollectionConfig::default()
+    }
+}
+
+fn doc(i: usize) -> Document {
+    let vector = vec![(i % 5) as f32 + 1.0, (i % 7) as f32, (i % 3) as f32 + 0.5];
+    let kind = if i.is_multiple_of(2) { "even" } else { "odd" };
+    Document::with_metadata(
+        vector,
+        format!("doc {i}"),
+        metadata([("kind", kind.into())]),
+    )
+}
+
+#[test]
+fn views_are_published_once_a_reader_asks_and_never_change() {
+    let test_db = &test_path("test_read_views_publish.db");
+
+    let mut storage = Collection::open(test_db).unwrap();
+    let first = storage.insert(doc(0)).unwrap();
+    let slot = storage
+        .view_slot()
+        .expect("snapshot reads are on by default");
+
+    // Nothing is copied until a reader asks; the next write publishes
+    assert!(slot.load().is_none());
+    let second = storage.insert(doc(1)).unwrap();
+    let before = slot.load().expect("published after the write");
+    assert_eq!(before.count(), 2);
+    assert_eq!(before.generation(), 2);
+    assert_eq!(before.get(&first).unwrap().unwrap().text, "doc 0");
+
+    storage
+        .update_metadata(&second, metadata([("kind", "even".into())]))
+        .unwrap();
+    storage.delete(&first).unwrap();
+    let after = slot.load().unwrap();
+    assert_eq!(after.count(), 1);
+    assert!(after.get(&first).unwrap().is_none());
+    let filter = Filter::new().eq("kind", "even");
+    let params = SearchParams {
+        filter: Some(&filter),
+        ..SearchParams::default()
+    };
+    let hits = after
+        .search(&[1.0, 0.0, 0.0], 5, Metric::Cosine, params)
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, second);
+
+    // The older view still answers as of its own write
+    assert_eq!(before.count(), 2);
+    assert_eq!(before.get(&first).unwrap().unwrap().text, "doc 0");
+    let hits = before
+        .search(
+            &doc(0).get_vector(),
+            1,
+            Metric::Cosine,
+            SearchParams::default(),
+        )
+        .unwrap();
+    assert_eq!(hits[0].id, first);
+
+    drop(storage);
+    cleanup_collection(test_db);
+}
+
+#[test]
+fn views_match_the_collection_across_hnsw_deletes_and_batches() {
+    let test_db = &test_path("test_read_views_hnsw.db");
+
+    let config = CollectionConfig {
+        cache: CacheConfig::default().with_result_cache(16, None),
+        ..hnsw_config()
+    };
+    let mut storage =
+        Collection::open_with_options(test_db, CollectionOpenOptions::from(config)).unwrap();
+    let slot = storage.view_slot().unwrap();
+    assert!(slot.load().is_none());
+
+    let ids = storage.insert_batch((0..60).map(doc).collect()).unwrap();
+    // Deleted nodes stay in the graph for traversal, so the view must keep their vectors
+    for id in ids.iter().step_by(3) {
+        storage.delete(id).unwrap();
+    }
+    storage
+        .upsert(Document {
+            id: ids[1],
+            ..doc(4)
+        })
+        .unwrap();
+
+    let view = slot.load().unwrap();
+    assert_eq!(view.count(), storage.count());
+    for i in [1, 7, 20, 44] {
+        let query = doc(i).get_vector();
+        let expected = storage
+            .search(&query, 5, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        let (hits, _) = view
+            .search_cached(&query, 5, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        let expected: Vec<_> = expected.iter().map(|hit| hit.id).collect();
+        let actual: Vec<_> = hits.iter().map(|hit| hit.id).collect();
+        assert_eq!(actual, expected);
+    }
+    let batch = view
+        .search_batch(&[doc(2).get_vector()], 3, Metric::Cosine)
+        .unwrap();
+    assert_eq!(batch[0].len(), 3);
+    assert_eq!(view.get(&ids[1]).unwrap().unwrap().text, "doc 4");
+
+    drop(storage);
+    cleanup_collection(test_db);
+}
+
+#[test]
+fn one_view_serves_concurrent_reads() {
+    let test_db = &test_path("test_read_views_concurrent.db");
+
+    let mut storage = Collection::open(test_db).unwrap();
+    let slot = storage.view_slot().unwrap();
+    assert!(slot.load().is_none());
+    let ids = storage.insert_batch((0..40).map(doc).collect()).unwrap();
+    let view = slot.load().unwrap();
+
+    // Readers share one handle on the data file; none of them moves a cursor the others rely on
+    thread::scope(|scope| {
+        for reader in 0..8 {
+            let (view, ids) = (&view, &ids);
+            scope.spawn(move || {
+                for round in 0..50 {
+                    let i = (reader * 7 + round) % ids.len();
+                    let document = view.get(&ids[i]).unwrap().unwrap();
+                    assert_eq!(document.text, format!("doc {i}"));
+                }
+            });
+        }
+    });
+
+    drop(storage);
+    cleanup_collection(test_db);
+}
+
+#[test]
+fn snapshot_reads_can_be_turned_off() {
+    let test_db = &test_path("test_read_views_off.db");
+
+    let config = CollectionConfig {
+        parallelism: ParallelismConfig {
+            snapshot_reads: false,
+            ..ParallelismConfig::default()
+        },
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_db, CollectionOpenOptions::from(config)).unwrap();
+    storage.insert(doc(0)).unwrap();
+    assert!(storage.view_slot().is_none());
+
+    drop(storage);
+    cleanup_collection(test_db);
+}
+
+fn search_request(vector: Vec<f32>) -> SearchRequest {
+    SearchRequest {
+        vector: Some(vector),
+        vectors: None,
+        k: 3,
+        metric: None,
+        ef: None,
+        nprobe: None,
+        overfetch: None,
+        preset: None,
+    }
+}
+
+#[test]
+fn searches_do_not_wait_for_a_held_write_lock() {
+    let data_dir = ".piramid/tests/read_views_write_lock";
+    let _ = fs::remove_dir_all(data_dir);
+    let state = Arc::new(AppState::new(data_dir, AppConfig::default(), 500, None, true).unwrap());
+    let handle = state.collection_manager.get_or_create("docs").unwrap();
+    handle.write().insert(doc(0)).unwrap();
+
+    // The first search goes through the lock and asks for views; the next write publishes one
+    let request_id = || RequestId("test".to_string());
+    vector::search_vectors(
+        &state,
+        "docs".into(),
+        request_id(),
+        search_request(doc(0).get_vector()),
+    )
+    .unwrap();
+    let id = handle.write().insert(doc(1)).unwrap();
+
+    // A writer holding the lock (e.g. a long batch insert) no longer blocks searches
+    let writer = handle.write();
+    let (done, finished) = mpsc::channel();
+    let reader_state = state.clone();
+    let reader = thread::spawn(move || {
+        let response = vector::search_vectors(
+            &reader_state,
+            "docs".into(),
+            RequestId("test".to_string()),
+            search_request(doc(1).get_vector()),
+        );
+        done.send(response).unwrap();
+    });
+    let response = finished
+        .recv_timeout(Duration::from_secs(10))
+        .expect("search waited on the write lock")
+        .unwrap();
+    drop(writer);
+    reader.join().unwrap();
+    match response {
+        SearchResultsResponse::Single(response) => {
+            assert_eq!(response.results[0].id, id.to_string())
+        }
+        SearchResultsResponse::Multi(_) => panic!("expected a single search response"),
+    }
+
+    let metrics = admin::metrics(&state).unwrap();
+    assert_eq!(metrics.collections[0].snapshot_reads, 1);
+
+    drop(handle);
+    let _ = fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::{
+    collections::CollectionOpenOptions,
+    storage::persistence::{checkpoint_files, save_index, EntryPointer},
+    storage::record_store::{scan_records, RecordStore, RECORD_VERSION},
+    storage::wal::remove_wal_files,
+    Collection, Document,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn new_data_files_are_framed() {
+    let path = test_path("records_framed.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let id = collection
+        .insert(Document::new(vec![1.0, 0.0], "framed".to_string()))
+        .unwrap();
+    collection.flush().unwrap();
+    drop(collection);
+
+    let data = fs::read(&path).unwrap();
+    assert_eq!(&data[..4], b"PDAT");
+    assert_eq!(
+        u32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+        RECORD_VERSION
+    );
+    let scan = scan_records(&data);
+    assert_eq!(scan.records, 1);
+    assert!(scan.index.contains_key(&id));
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn lost_index_is_rebuilt_by_scanning_the_data_file() {
+    let path = test_path("records_rebuild.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let kept = collection
+        .insert(Document::new(vec![1.0, 0.0], "kept".to_string()))
+        .unwrap();
+    let updated = collection
+        .insert(Document::new(vec![0.0, 1.0], "first".to_string()))
+        .unwrap();
+    let deleted = collection
+        .insert(Document::new(vec![1.0, 1.0], "deleted".to_string()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    // Written after the checkpoint, so only the data file and the WAL know about them.
+    let mut newer = collection.get(&updated).unwrap().unwrap();
+    newer.text = "second".to_string();
+    collection.upsert(newer).unwrap();
+    assert!(collection.delete(&deleted).unwrap());
+    collection.flush().unwrap();
+    drop(collection);
+
+    // Losing the WAL as well leaves the data file as the only record of the last writes.
+    let _ = remove_wal_files(Path::new(&format!("{}.wal.db", path)));
+    let index_file = checkpoint_files(&path).unwrap().remove(0);
+    fs::write(&index_file, b"garbage").unwrap();
+
+    let collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.count(), 2);
+    assert_eq!(collection.get(&kept).unwrap().unwrap().text, "kept");
+    assert_eq!(collection.get(&updated).unwrap().unwrap().text, "second");
+    assert!(collection.get(&deleted).unwrap().is_none());
+    drop(collection);
+
+    // The rebuilt index was checkpointed, so it survives without another scan.
+    let collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.count(), 2);
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn legacy_unframed_files_still_open() {
+    let path = test_path("records_legacy.db");
+    let documents = vec![
+        Document::new(vec![1.0, 0.0], "one".to_string()),
+        Document::new(vec![0.0, 1.0], "two".to_string()),
+    ];
+    let mut data = Vec::new();
+    let mut index = HashMap::new();
+    for document in &documents {
+        let bytes = RecordStore::encode_document(document).unwrap();
+        index.insert(
+            document.id,
+            EntryPointer::new(data.len() as u64, bytes.len() as u32),
+        );
+        data.extend_from_slice(&bytes);
+    }
+    fs::write(&path, &data).unwrap();
+    save_index(&path, &index).unwrap();
+    assert_eq!(scan_records(&data).index.len(), 2);
+
+    let options = CollectionOpenOptions::default();
+    let mut collection = Collection::open_with_options(&path, options.clone()).unwrap();
+    assert_eq!(collection.count(), 2);
+    let third = collection
+        .insert(Document::new(vec![1.0, 1.0], "three".to_string()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    drop(collection);
+
+    let collection = Collection::open_with_options(&path, options).unwrap();
+    assert_eq!(collection.count(), 3);
+    assert_eq!(
+        collection.get(&documents[1].id).unwrap().unwrap().text,
+        "two"
+    );
+    assert_eq!(collection.get(&third).unwrap().unwrap().text, "three");
+    drop(collection);
+
+    let data = fs::read(&path).unwrap();
+    assert_ne!(&data[..4], b"PDAT");
+
+    cleanup_collection(&path);
+}
@@ -1,16 +1,16 @@
-use piramid::{metadata, Collection, Document, Filter, Metric, SearchParams};
+use piramid::collections::CollectionOpenOptions;
+use piramid::storage::persistence::remove_checkpoint_files;
+use piramid::storage::wal::remove_wal_files;
+use piramid::{
+    metadata, CacheConfig, Collection, CollectionConfig, Document, Filter, Metric, SearchParams,
+};
 use std::fs;
 
 fn cleanup(path: &str) {
-    let sidecars = [
-        format!("{}.index.db", path),
-        format!("{}.wal.db", path),
-        format!("{}.vecindex.db", path),
-        format!("{}.metadata.db", path),
-    ];
-    for p in std::iter::once(path.to_string()).chain(sidecars) {
-        let _ = fs::remove_file(p);
-    }
+    let _ = fs::create_dir_all(".piramid/tests");
+    let _ = fs::remove_file(path);
+    let _ = remove_checkpoint_files(path);
+    let _ = remove_wal_files(std::path::Path::new(&format!("{}.wal.db", path)));
 }
 
 #[test]
@@ -57,3 +57,113 @@ fn search_respects_filter() {
 
     cleanup(test_db);
 }
+
+#[test]
+fn result_cache_answers_repeats_until_the_next_write() {
+    let test_db = ".piramid/tests/test_result_cache.db";
+    cleanup(test_db);
+
+    let config = CollectionConfig {
+        cache: CacheConfig::default().with_result_cache(16, None),
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_db, CollectionOpenOptions::from(config)).unwrap();
+    let rust = storage
+        .insert(Document::with_metadata(
+            vec![1.0, 0.0, 0.0],
+            "rust doc".to_string(),
+            metadata([("lang", "rust".into())]),
+        ))
+        .unwrap();
+    storage
+        .insert(Document::with_metadata(
+            vec![0.0, 1.0, 0.0],
+            "python doc".to_string(),
+            metadata([("lang", "python".into())]),
+        ))
+        .unwrap();
+
+    let query = [0.9, 0.1, 0.0];
+    let (hits, cache_hit) = storage
+        .search_cached(&query, 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!(!cache_hit);
+    assert_eq!(hits[0].id, rust);
+    let (hits, cache_hit) = storage
+        .search_cached(&query, 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!(cache_hit);
+    assert_eq!(hits[0].id, rust);
+
+    // A different k or filter is a different entry
+    let (_, cache_hit) = storage
+        .search_cached(&query, 2, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!(!cache_hit);
+    let filter = Filter::new().eq("lang", "python");
+    let params = SearchParams {
+        filter: Some(&filter),
+        ..SearchParams::default()
+    };
+    let (hits, cache_hit) = storage
+        .search_cached(&query, 1, Metric::Cosine, params)
+        .unwrap();
+    assert!(!cache_hit);
+    assert_eq!(hits[0].text, "python doc");
+
+    // Any write makes earlier results stale
+    let closer = storage
+        .insert(Document::new(vec![0.9, 0.1, 0.0], "closer".to_string()))
+        .unwrap();
+    let (hits, cache_hit) = storage
+        .search_cached(&query, 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert!(!cache_hit);
+    assert_eq!(hits[0].id, closer);
+
+    let stats = storage.result_cache_stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 4);
+
+    drop(storage);
+    cleanup(test_db);
+}
+
+#[test]
+fn result_cache_respects_ttl_and_is_off_by_default() {
+    let test_db = ".piramid/tests/test_result_cache_ttl.db";
+    cleanup(test_db);
+
+    let config = CollectionConfig {
+        cache: CacheConfig::default().with_result_cache(16, Some(0)),
+        ..CollectionConfig::default()
+    };
+    let mut storage =
+        Collection::open_with_options(test_db, CollectionOpenOptions::from(config)).unwrap();
+    storage
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "doc".to_string()))
+        .unwrap();
+    for _ in 0..2 {
+        let (_, cache_hit) = storage
+            .search_cached(&[1.0, 0.0, 0.0], 1, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        assert!(!cache_hit);
+    }
+    drop(storage);
+    cleanup(test_db);
+
+    let mut storage = Collection::open(test_db).unwrap();
+    storage
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "doc".to_string()))
+        .unwrap();
+    for _ in 0..2 {
+        let (_, cache_hit) = storage
+            .search_cached(&[1.0, 0.0, 0.0], 1, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        assert!(!cache_hit);
+    }
+    assert_eq!(storage.result_cache_stats().entries, 0);
+    drop(storage);
+    cleanup(test_db);
+}
@@ -0,0 +1,538 @@
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+};
+use piramid::{
+    config::AppConfig,
+    error::PiramidError,
+    runtime::{AppState, DataDirLock},
+    server::handlers::snapshots,
+    server::types::RestoreResponse,
+    storage::persistence::checkpoint_files,
+    Document,
+};
+use std::{fs, sync::Arc};
+use uuid::Uuid;
+
+fn test_state(data_dir: &str) -> Arc<AppState> {
+    state_with_config(data_dir, AppConfig::default())
+}
+
+fn state_with_config(data_dir: &str, config: AppConfig) -> Arc<AppState> {
+    let _ = fs::remove_dir_all(data_dir);
+    Arc::new(AppState::new(data_dir, config, 500, None, true).unwrap())
+}
+
+fn archiving_state(data_dir: &str) -> Arc<AppState> {
+    let mut config = AppConfig::default();
+    config.wal.archive = true;
+    state_with_config(data_dir, config)
+}
+
+fn last_seq(state: &AppState, collection: &str) -> u64 {
+    let handle = state.get_existing_collection(collection).unwrap();
+    let guard = handle.read();
+    guard.checkpoint.wal.next_seq - 1
+}
+
+async fn restore_until(
+    state: &Arc<AppState>,
+    collection: &str,
+    request: String,
+) -> piramid::Result<RestoreResponse> {
+    snapshots::restore_collection(
+        State(state.clone()),
+        Path(collection.to_string()),
+        json_headers(),
+        Body::from(request),
+    )
+    .await
+    .map(|response| response.0)
+}
+
+fn insert(state: &AppState, collection: &str, text: &str) -> Uuid {
+    let handle = state.get_or_create_collection(collection).unwrap();
+    let mut guard = handle.write();
+    guard
+        .insert(Document::new(
+            vec![1.0, text.len() as f32, 0.0],
+            text.into(),
+        ))
+        .unwrap()
+}
+
+fn json_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers
+}
+
+async fn download(state: &Arc<AppState>, collection: &str, id: &str) -> Bytes {
+    let response = snapshots::download_snapshot(
+        State(state.clone()),
+        Path((collection.to_string(), id.to_string())),
+        Request::new(Body::empty()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    to_bytes(response.into_body(), usize::MAX).await.unwrap()
+}
+
+fn assert_bad_request<T>(result: piramid::Result<T>) {
+    match result {
+        Err(PiramidError::Server(error)) => {
+            assert_eq!(error.status_code(), StatusCode::BAD_REQUEST)
+        }
+        Err(error) => panic!("expected a bad request, got {error:?}"),
+        Ok(_) => panic!("corrupt snapshot should not restore"),
+    }
+}
+
+#[tokio::test]
+async fn snapshots_can_be_created_listed_downloaded_and_deleted() {
+    let data_dir = ".piramid/tests/snapshot_lifecycle";
+    let state = test_state(data_dir);
+    for text in ["a", "bb", "ccc"] {
+        insert(&state, "docs", text);
+    }
+
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(info.collection, "docs");
+    assert_eq!(info.vector_count, 3);
+    assert!(info.size_bytes > 0);
+
+    let listed = snapshots::list_snapshots(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0
+        .snapshots;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, info.id);
+
+    let bytes = download(&state, "docs", &info.id).await;
+    assert_eq!(bytes.len() as u64, info.size_bytes);
+
+    let deleted = snapshots::delete_snapshot(
+        State(state.clone()),
+        Path(("docs".to_string(), info.id.clone())),
+    )
+    .await
+    .unwrap();
+    assert!(deleted.0.deleted);
+    let listed = snapshots::list_snapshots(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    assert!(listed.snapshots.is_empty());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn restore_rolls_a_loaded_collection_back_to_a_stored_snapshot() {
+    let data_dir = ".piramid/tests/snapshot_restore_stored";
+    let state = test_state(data_dir);
+    let kept = insert(&state, "docs", "kept");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+
+    // Changes made after the snapshot, including one only in the WAL.
+    let handle = state.get_existing_collection("docs").unwrap();
+    let later = insert(&state, "docs", "later");
+    handle.write().delete(&kept).unwrap();
+
+    let restored = snapshots::restore_collection(
+        State(state.clone()),
+        Path("docs".to_string()),
+        json_headers(),
+        Body::from(format!("{{\"snapshot\":\"{}\"}}", info.id)),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(restored.name, "docs");
+    assert_eq!(restored.source_collection, "docs");
+    assert_eq!(restored.count, 1);
+
+    // Handles taken before the restore see the restored collection.
+    let guard = handle.read();
+    assert_eq!(guard.get(&kept).unwrap().unwrap().text, "kept");
+    assert!(guard.get(&later).unwrap().is_none());
+    drop(guard);
+
+    // And so does a fresh open of the files.
+    state.collection_manager.remove("docs");
+    let reopened = state.get_existing_collection("docs").unwrap();
+    assert_eq!(reopened.read().count(), 1);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn uploaded_snapshot_restores_into_a_new_collection() {
+    let data_dir = ".piramid/tests/snapshot_restore_upload";
+    let state = test_state(data_dir);
+    let ids: Vec<Uuid> = ["one", "two"]
+        .iter()
+        .map(|text| insert(&state, "docs", text))
+        .collect();
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    let bytes = download(&state, "docs", &info.id).await;
+
+    let restored = snapshots::restore_collection(
+        State(state.clone()),
+        Path("copy".to_string()),
+        HeaderMap::new(),
+        Body::from(bytes),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(restored.name, "copy");
+    assert_eq!(restored.source_collection, "docs");
+    assert_eq!(restored.count, 2);
+
+    let copy = state.get_existing_collection("copy").unwrap();
+    for (id, text) in ids.iter().zip(["one", "two"]) {
+        assert_eq!(copy.read().get(id).unwrap().unwrap().text, text);
+    }
+    // The copy is independent of the original.
+    copy.write()
+        .insert(Document::new(vec![0.0, 0.0, 1.0], "copy only".into()))
+        .unwrap();
+    assert_eq!(
+        state
+            .get_existing_collection("docs")
+            .unwrap()
+            .read()
+            .count(),
+        2
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn corrupt_upload_is_rejected_and_leaves_the_collection_alone() {
+    let data_dir = ".piramid/tests/snapshot_restore_corrupt";
+    let state = test_state(data_dir);
+    let id = insert(&state, "docs", "original");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    let mut bytes = download(&state, "docs", &info.id).await.to_vec();
+    // Flip one byte of the data file's contents; its tar header still parses.
+    let header = bytes
+        .windows(14)
+        .position(|window| window == b"collection.db\0")
+        .expect("data file entry");
+    bytes[header + 512] ^= 0xff;
+
+    let result = snapshots::restore_collection(
+        State(state.clone()),
+        Path("docs".to_string()),
+        HeaderMap::new(),
+        Body::from(bytes),
+    )
+    .await;
+    assert_bad_request(result);
+
+    // A truncated archive is rejected the same way.
+    let truncated = download(&state, "docs", &info.id).await.slice(..700);
+    assert_bad_request(
+        snapshots::restore_collection(
+            State(state.clone()),
+            Path("docs".to_string()),
+            HeaderMap::new(),
+            Body::from(truncated),
+        )
+        .await,
+    );
+
+    state.collection_manager.remove("docs");
+    let reopened = state.get_existing_collection("docs").unwrap();
+    assert_eq!(reopened.read().get(&id).unwrap().unwrap().text, "original");
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn point_in_time_restore_undoes_a_bulk_delete() {
+    let data_dir = ".piramid/tests/snapshot_pitr_seq";
+    let state = archiving_state(data_dir);
+    let mut ids = vec![insert(&state, "docs", "a"), insert(&state, "docs", "bb")];
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+
+    // Writes after the snapshot, pushed past a checkpoint so their segment gets archived.
+    ids.push(insert(&state, "docs", "ccc"));
+    let handle = state.get_existing_collection("docs").unwrap();
+    handle.write().checkpoint().unwrap();
+    ids.push(insert(&state, "docs", "dddd"));
+    let before_delete = last_seq(&state, "docs");
+
+    // The bad bulk delete.
+    for id in &ids {
+        handle.write().delete(id).unwrap();
+    }
+    handle.write().checkpoint().unwrap();
+    assert_eq!(handle.read().count(), 0);
+
+    let restored = restore_until(
+        &state,
+        "docs",
+        format!(
+            "{{\"snapshot\":\"{}\",\"until_seq\":{}}}",
+            info.id, before_delete
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(restored.count, 4);
+    assert_eq!(restored.recovered_seq, Some(before_delete));
+    let guard = handle.read();
+    for (id, text) in ids.iter().zip(["a", "bb", "ccc", "dddd"]) {
+        assert_eq!(guard.get(id).unwrap().unwrap().text, text);
+    }
+    drop(guard);
+
+    // The recovered state is durable.
+    state.collection_manager.remove("docs");
+    assert_eq!(
+        state
+            .get_existing_collection("docs")
+            .unwrap()
+            .read()
+            .count(),
+        4
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn writes_after_point_in_time_restore_outrank_the_replayed_history() {
+    let data_dir = ".piramid/tests/snapshot_pitr_next_seq";
+    let state = archiving_state(data_dir);
+    insert(&state, "docs", "a");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    insert(&state, "docs", "bb");
+    insert(&state, "docs", "ccc");
+    let replayed = insert(&state, "docs", "dddd");
+    let until = last_seq(&state, "docs");
+
+    let restored = restore_until(
+        &state,
+        "docs",
+        format!("{{\"snapshot\":\"{}\",\"until_seq\":{}}}", info.id, until),
+    )
+    .await
+    .unwrap();
+    assert_eq!(restored.recovered_seq, Some(until));
+    assert!(last_seq(&state, "docs") >= until);
+
+    // A write after the restore, then the index is lost and rebuilt from the data file alone
+    let handle = state.get_existing_collection("docs").unwrap();
+    assert!(handle
+        .write()
+        .update_vector(&replayed, vec![0.0, 0.0, 1.0])
+        .unwrap());
+    handle.write().checkpoint().unwrap();
+    drop(handle);
+    state.collection_manager.remove("docs");
+    let path = state.collection_manager.collection_path("docs");
+    let index_file = checkpoint_files(&path).unwrap().remove(0);
+    fs::write(&index_file, b"garbage").unwrap();
+
+    let handle = state.get_existing_collection("docs").unwrap();
+    assert_eq!(
+        handle.read().get(&replayed).unwrap().unwrap().vector,
+        vec![0.0, 0.0, 1.0]
+    );
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn point_in_time_restore_by_time_stops_at_the_last_earlier_write() {
+    let data_dir = ".piramid/tests/snapshot_pitr_time";
+    let state = archiving_state(data_dir);
+    let kept = insert(&state, "docs", "kept");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    let added = insert(&state, "docs", "added");
+    let handle = state.get_existing_collection("docs").unwrap();
+    let recovery_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    // Recovery targets have one-second resolution; no checkpoint is needed to stop between writes.
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    handle.write().delete(&kept).unwrap();
+    handle.write().checkpoint().unwrap();
+
+    let restored = restore_until(
+        &state,
+        "docs",
+        format!(
+            "{{\"snapshot\":\"{}\",\"until_time\":{}}}",
+            info.id, recovery_time
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(restored.count, 2);
+    let guard = handle.read();
+    assert!(guard.get(&kept).unwrap().is_some());
+    assert!(guard.get(&added).unwrap().is_some());
+    drop(guard);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn point_in_time_restore_rejects_missing_history() {
+    let data_dir = ".piramid/tests/snapshot_pitr_gap";
+    // Without archiving, checkpoints delete the segments a recovery would need.
+    let state = test_state(data_dir);
+    let id = insert(&state, "docs", "original");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+    insert(&state, "docs", "later");
+    let handle = state.get_existing_collection("docs").unwrap();
+    handle.write().checkpoint().unwrap();
+    let target = last_seq(&state, "docs");
+
+    assert_bad_request(
+        restore_until(
+            &state,
+            "docs",
+            format!("{{\"snapshot\":\"{}\",\"until_seq\":{}}}", info.id, target),
+        )
+        .await,
+    );
+    assert_bad_request(
+        restore_until(
+            &state,
+            "docs",
+            format!(
+                "{{\"snapshot\":\"{}\",\"until_seq\":1,\"until_time\":1}}",
+                info.id
+            ),
+        )
+        .await,
+    );
+
+    // The failed attempts left the collection as it was.
+    assert_eq!(handle.read().count(), 2);
+    assert!(handle.read().get(&id).unwrap().is_some());
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[tokio::test]
+async fn archives_stream_through_disk_without_leaving_spooled_uploads() {
+    let data_dir = ".piramid/tests/snapshot_streaming";
+    let state = test_state(data_dir);
+    insert(&state, "docs", "one");
+    let info = snapshots::create_snapshot(State(state.clone()), Path("docs".to_string()))
+        .await
+        .unwrap()
+        .0;
+
+    // Downloads are served from the file, so ranges work.
+    let request = Request::builder()
+        .header(header::RANGE, "bytes=0-511")
+        .body(Body::empty())
+        .unwrap();
+    let response = snapshots::download_snapshot(
+        State(state.clone()),
+        Path(("docs".to_string(), info.id.clone())),
+        request,
+    )
+    .await
+    .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-tar"
+    );
+    let head = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(head.len(), 512);
+
+    // Uploads are spooled to a file that is gone once the restore finishes, whether it worked.
+    let bytes = download(&state, "docs", &info.id).await;
+    let restored = snapshots::restore_collection(
+        State(state.clone()),
+        Path("copy".to_string()),
+        HeaderMap::new(),
+        Body::from(bytes.clone()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(restored.0.count, 1);
+    assert_bad_request(
+        snapshots::restore_collection(
+            State(state.clone()),
+            Path("copy".to_string()),
+            HeaderMap::new(),
+            Body::from(bytes.slice(..700)),
+        )
+        .await,
+    );
+    assert_bad_request(
+        snapshots::restore_collection(
+            State(state.clone()),
+            Path("copy".to_string()),
+            HeaderMap::new(),
+            Body::empty(),
+        )
+        .await,
+    );
+    let uploads = std::path::Path::new(data_dir).join("snapshots/.uploads");
+    assert_eq!(fs::read_dir(uploads).unwrap().count(), 0);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn a_data_dir_can_only_be_locked_once() {
+    let data_dir = ".piramid/tests/snapshot_data_dir_lock";
+    let _ = fs::remove_dir_all(data_dir);
+
+    // The server holds this while it runs; the snapshot and repair subcommands take it too.
+    let held = DataDirLock::acquire(data_dir).unwrap();
+    let error = DataDirLock::acquire(data_dir).unwrap_err();
+    assert!(error.to_string().contains("in use"), "{error}");
+
+    drop(held);
+    let again = DataDirLock::acquire(data_dir).unwrap();
+    assert!(again.path().exists());
+    drop(again);
+
+    let _ = fs::remove_dir_all(data_dir);
+}
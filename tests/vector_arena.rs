@@ -0,0 +1,334 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::{
+    collections::CollectionOpenOptions,
+    index::VectorReader,
+    search::SearchParams,
+    storage::persistence::load_manifest,
+    storage::vector_arena::{arena_path, VectorArena},
+    storage::vfs::{FileOptions, OsVfs, Vfs, VfsFile},
+    Collection, CollectionConfig, Document, MemoryConfig, Metric,
+};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+// The real file system, noting the files opened and removed through it
+#[derive(Default)]
+struct RecordingVfs {
+    opened: Mutex<Vec<PathBuf>>,
+    removed: Mutex<Vec<PathBuf>>,
+}
+
+impl Vfs for RecordingVfs {
+    fn open(&self, path: &Path, options: FileOptions) -> io::Result<Box<dyn VfsFile>> {
+        self.opened.lock().unwrap().push(path.to_path_buf());
+        OsVfs.open(path, options)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        OsVfs.rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.removed.lock().unwrap().push(path.to_path_buf());
+        OsVfs.remove_file(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        OsVfs.exists(path)
+    }
+
+    fn file_len(&self, path: &Path) -> io::Result<u64> {
+        OsVfs.file_len(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        OsVfs.create_dir_all(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        OsVfs.remove_dir_all(path)
+    }
+
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        OsVfs.read_dir(dir)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        OsVfs.sync_dir(dir)
+    }
+
+    fn supports_mmap(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn arena_reuses_freed_rows_and_reopens_only_when_synced() {
+    let path = test_path("arena_rows.vectors.db");
+    let (mut arena, trusted) = VectorArena::open(&path, None).unwrap();
+    assert!(!trusted);
+    let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    for (i, id) in ids.iter().enumerate() {
+        arena.put(*id, &[i as f32, 0.5, -1.0]).unwrap();
+    }
+    assert_eq!(arena.get(&ids[1]).unwrap(), &[1.0, 0.5, -1.0]);
+    assert!(arena.put(Uuid::new_v4(), &[1.0]).is_err());
+
+    // A freed row is handed to the next vector instead of growing the file
+    arena.remove(&ids[0]).unwrap();
+    let size = fs::metadata(&path).unwrap().len();
+    let reused = Uuid::new_v4();
+    arena.put(reused, &[9.0, 9.0, 9.0]).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), size);
+    assert_eq!(arena.len(), 3);
+    arena.sync(4, 20).unwrap();
+    drop(arena);
+
+    // The checkpoint that synced the arena gets its rows back
+    let (mut arena, trusted) = VectorArena::open(&path, Some((4, 20))).unwrap();
+    assert!(trusted);
+    assert_eq!(arena.get(&reused).unwrap(), &[9.0, 9.0, 9.0]);
+    assert_eq!(arena.get(&ids[2]).unwrap(), &[2.0, 0.5, -1.0]);
+    assert!(!arena.contains_key(&ids[0]));
+
+    // A change after the sync makes the arena untrusted until the next one
+    arena.remove(&ids[2]).unwrap();
+    drop(arena);
+    let (mut arena, trusted) = VectorArena::open(&path, Some((4, 20))).unwrap();
+    assert!(!trusted);
+    assert!(arena.is_empty());
+
+    // Any other checkpoint starts from an empty arena
+    arena.put(reused, &[1.0, 2.0]).unwrap();
+    arena.sync(5, 30).unwrap();
+    drop(arena);
+    let (arena, trusted) = VectorArena::open(&path, Some((5, 31))).unwrap();
+    assert!(!trusted);
+    assert!(arena.is_empty());
+    drop(arena);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn arena_files_are_opened_and_removed_through_the_collection_vfs() {
+    let path = test_path("arena_vfs.db");
+    let arena = PathBuf::from(arena_path(&path));
+    let vfs = Arc::new(RecordingVfs::default());
+    let options = CollectionOpenOptions::from(CollectionConfig::default()).with_vfs(vfs.clone());
+
+    let mut collection = Collection::open_with_options(&path, options.clone()).unwrap();
+    let id = collection
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "a".into()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    drop(collection);
+    // A fresh arena file replaces whatever was left at its path
+    assert!(vfs.opened.lock().unwrap().contains(&arena));
+    assert!(vfs.removed.lock().unwrap().contains(&arena));
+
+    vfs.opened.lock().unwrap().clear();
+    let collection = Collection::open_with_options(&path, options).unwrap();
+    assert!(vfs.opened.lock().unwrap().contains(&arena));
+    assert_eq!(collection.get_vectors().get(&id).unwrap(), &[1.0, 0.0, 0.0]);
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn collection_reopens_vectors_from_the_arena() {
+    let path = test_path("arena_collection.db");
+    let mut collection = Collection::open(&path).unwrap();
+    let ids: Vec<Uuid> = (0..5)
+        .map(|i| {
+            collection
+                .insert(Document::new(
+                    vec![i as f32, 1.0, 0.0],
+                    format!("doc {}", i),
+                ))
+                .unwrap()
+        })
+        .collect();
+    collection.delete(&ids[0]).unwrap();
+    collection.checkpoint().unwrap();
+    drop(collection);
+
+    let manifest = load_manifest(&path).unwrap().unwrap();
+    let (arena, trusted) = VectorArena::open(
+        &arena_path(&path),
+        Some((manifest.generation, manifest.last_checkpoint_seq)),
+    )
+    .unwrap();
+    assert!(trusted);
+    assert_eq!(arena.len(), 4);
+    drop(arena);
+
+    // Writes after the checkpoint dirty the arena, so the next open rebuilds it from the WAL replay
+    let mut collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.get_vectors().len(), 4);
+    assert_eq!(
+        collection.get_vectors().get(&ids[3]).unwrap(),
+        &[3.0, 1.0, 0.0]
+    );
+    let added = collection
+        .insert(Document::new(vec![0.0, 0.0, 1.0], "added".to_string()))
+        .unwrap();
+    collection.delete(&ids[1]).unwrap();
+    collection.flush().unwrap();
+    drop(collection);
+
+    let collection = Collection::open(&path).unwrap();
+    let vectors = collection.get_vectors();
+    assert_eq!(vectors.len(), 4);
+    assert!(vectors.contains_key(&added));
+    assert!(!vectors.contains_key(&ids[1]));
+    drop(collection);
+
+    cleanup_collection(&path);
+    assert!(!Path::new(&arena_path(&path)).exists());
+}
+
+#[test]
+fn collections_without_mmap_keep_vectors_in_memory() {
+    let path = test_path("arena_no_mmap.db");
+    let options = CollectionOpenOptions::from(CollectionConfig {
+        memory: MemoryConfig::no_mmap(),
+        ..Default::default()
+    });
+    let mut collection = Collection::open_with_options(&path, options.clone()).unwrap();
+    let id = collection
+        .insert(Document::new(vec![1.0, 2.0], "heap".to_string()))
+        .unwrap();
+    collection.checkpoint().unwrap();
+    drop(collection);
+    assert!(!Path::new(&arena_path(&path)).exists());
+
+    let collection = Collection::open_with_options(&path, options).unwrap();
+    assert_eq!(collection.get_vectors().get(&id).unwrap(), &[1.0, 2.0]);
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn memory_budget_keeps_cold_vectors_in_the_arena_file() {
+    let path = test_path("arena_tiered.db");
+    let options = CollectionOpenOptions::from(CollectionConfig {
+        memory: MemoryConfig::with_limit_mb(1),
+        ..Default::default()
+    });
+    let mut collection = Collection::open_with_options(&path, options.clone()).unwrap();
+    let dims = 256;
+    let docs: Vec<Document> = (0..3000)
+        .map(|i| {
+            let mut vector = vec![0.0f32; dims];
+            vector[i % dims] = 1.0;
+            vector[(i * 7 + 1) % dims] = i as f32 / 3000.0;
+            Document::new(vector, format!("doc {}", i))
+        })
+        .collect();
+    let vectors: Vec<Vec<f32>> = docs.iter().map(|doc| doc.get_vector()).collect();
+    let ids = collection.insert_batch(docs).unwrap();
+    collection.checkpoint().unwrap();
+
+    // Three million bytes of vectors cannot all be hot under a one megabyte budget
+    let stats = collection.vector_tier_stats();
+    let budget = stats.budget_bytes.unwrap();
+    assert!(budget < 1024 * 1024);
+    assert!(stats.hot_bytes <= budget + 2 * piramid::storage::persistence::page_size());
+    assert!(stats.cold_bytes > 0);
+    assert!(stats.evictions > 0);
+
+    // Every vector reads back intact, whether it was hot or paged back in from the file
+    for (id, vector) in ids.iter().zip(&vectors).step_by(97) {
+        let hits = collection
+            .search(vector, 1, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        assert_eq!(hits[0].id, *id);
+    }
+    let stats = collection.vector_tier_stats();
+    assert!(stats.misses > 0);
+    assert!(stats.hot_bytes <= budget + 2 * piramid::storage::persistence::page_size());
+    drop(collection);
+
+    let collection = Collection::open_with_options(&path, options).unwrap();
+    assert_eq!(
+        collection.get_vectors().get(&ids[1234]).unwrap(),
+        &vectors[1234][..]
+    );
+    drop(collection);
+
+    cleanup_collection(&path);
+}
+
+#[test]
+fn read_views_stay_within_the_memory_budget() {
+    let path = test_path("arena_views_budget.db");
+    let limit = 2 * 1024 * 1024;
+    let options = CollectionOpenOptions::from(CollectionConfig {
+        memory: MemoryConfig::with_limit_mb(2),
+        ..Default::default()
+    });
+    let mut collection = Collection::open_with_options(&path, options).unwrap();
+    let slot = collection.view_slot().unwrap();
+    assert!(slot.load().is_none());
+
+    let dims = 256;
+    let vector = |i: usize| {
+        let mut vector = vec![0.0f32; dims];
+        vector[i % dims] = 1.0;
+        vector[(i * 7 + 1) % dims] = i as f32 / 4000.0;
+        vector
+    };
+    let ids = collection
+        .insert_batch(
+            (0..4000)
+                .map(|i| Document::new(vector(i), format!("doc {}", i)))
+                .collect(),
+        )
+        .unwrap();
+    let before = slot.load().expect("published after the batch");
+
+    // Rewrites move vectors to fresh rows while the older view still reads the old ones
+    for (i, id) in ids.iter().enumerate().take(500) {
+        collection.update_vector(id, vector(i + 1)).unwrap();
+    }
+    assert_eq!(
+        VectorReader::get(&*before, &ids[0]).unwrap(),
+        &vector(0)[..]
+    );
+    assert_eq!(
+        before.get(&ids[0]).unwrap().unwrap().get_vector(),
+        vector(0)
+    );
+    collection.checkpoint().unwrap();
+
+    // Four million bytes of vectors, read by views and the collection alike, fit in two megabytes
+    let view = slot.load().unwrap();
+    for i in (0..4000).step_by(37) {
+        let expected = if i < 500 { vector(i + 1) } else { vector(i) };
+        assert_eq!(VectorReader::get(&*view, &ids[i]).unwrap(), &expected[..]);
+        let document = view.get(&ids[i]).unwrap().unwrap();
+        assert_eq!(document.get_vector(), expected);
+        let hits = view
+            .search(&expected, 1, Metric::Cosine, SearchParams::default())
+            .unwrap();
+        assert!(hits[0].score > 0.999);
+    }
+    let report = collection.memory_report();
+    let slack = 2 * piramid::storage::persistence::page_size();
+    assert!(
+        report.cache_bytes + report.index_bytes <= limit + slack,
+        "{report:?} over a {limit} byte budget"
+    );
+    assert!(collection.vector_tier_stats().evictions > 0);
+    drop((before, view, collection));
+
+    cleanup_collection(&path);
+}
@@ -0,0 +1,207 @@
+use piramid::{
+    collections::{compact, CollectionOpenOptions},
+    search::SearchParams,
+    storage::persistence::load_manifest_in,
+    storage::vfs::{FileOptions, MemoryVfs, Vfs},
+    storage::wal::{
+        list_archived_segments_in, list_segments_in, set_aside_archive_in, Wal, WalEntry,
+    },
+    Collection, CollectionConfig, Document, Metric, WalConfig,
+};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn open_in_memory(path: &str, vfs: &MemoryVfs) -> Collection {
+    let options =
+        CollectionOpenOptions::from(CollectionConfig::default()).with_vfs(Arc::new(vfs.clone()));
+    Collection::open_with_options(path, options).unwrap()
+}
+
+#[test]
+fn memory_vfs_files_behave_like_files() {
+    let vfs = MemoryVfs::new();
+    let path = Path::new("data/file.bin");
+    assert!(!vfs.exists(path));
+    assert!(vfs.read(path).is_err());
+
+    let mut file = vfs.open(path, FileOptions::read_write()).unwrap();
+    file.write_all(b"hello world").unwrap();
+    file.seek(SeekFrom::Start(6)).unwrap();
+    file.write_all(b"there").unwrap();
+    file.set_len(20).unwrap();
+    assert_eq!(file.file_len().unwrap(), 20);
+    drop(file);
+
+    let mut appender = vfs.open(path, FileOptions::append()).unwrap();
+    appender.write_all(b"!").unwrap();
+    let mut contents = Vec::new();
+    let mut reader = vfs.open(path, FileOptions::read_only()).unwrap();
+    reader.read_to_end(&mut contents).unwrap();
+    assert_eq!(&contents[..11], b"hello there");
+    assert_eq!(contents.len(), 21);
+    assert_eq!(contents[20], b'!');
+
+    vfs.rename(path, Path::new("./data/moved.bin")).unwrap();
+    assert!(!vfs.exists(path));
+    assert_eq!(
+        vfs.read_dir(Path::new("data")).unwrap(),
+        vec![Path::new("data/moved.bin").to_path_buf()]
+    );
+    assert_eq!(vfs.file_len(Path::new("data/moved.bin")).unwrap(), 21);
+
+    vfs.remove_file(Path::new("data/moved.bin")).unwrap();
+    assert!(vfs.files().is_empty());
+    assert!(vfs.read_dir(Path::new("missing")).is_err());
+}
+
+#[test]
+fn wal_in_memory_vfs_replays_after_reopen() {
+    let vfs: Arc<dyn Vfs> = Arc::new(MemoryVfs::new());
+    let base = Path::new("wal/test.wal.db").to_path_buf();
+    let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+    {
+        let mut wal = Wal::open_in(vfs.clone(), base.clone(), 1, &WalConfig::default()).unwrap();
+        for id in &ids {
+            wal.log(&mut WalEntry::Insert {
+                id: *id,
+                vector: vec![1.0; 4],
+                text: String::new(),
+                metadata: HashMap::new(),
+                seq: 0,
+            })
+            .unwrap();
+        }
+        wal.flush().unwrap();
+    }
+
+    assert_eq!(list_segments_in(&*vfs, &base).unwrap().len(), 1);
+    let mut wal = Wal::open_in(vfs.clone(), base.clone(), 1, &WalConfig::default()).unwrap();
+    let replayed: Vec<Uuid> = wal
+        .replay(0)
+        .unwrap()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            WalEntry::Insert { id, .. } => Some(id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(replayed, ids);
+    assert!(wal.size_bytes() > 0);
+    assert!(!base.exists());
+}
+
+#[test]
+fn wal_archive_in_memory_vfs_never_touches_disk() {
+    let memory = MemoryVfs::new();
+    let vfs: Arc<dyn Vfs> = Arc::new(memory.clone());
+    let base = Path::new("wal/archived.wal.db").to_path_buf();
+    let config = WalConfig {
+        max_log_size: 256,
+        archive: true,
+        ..WalConfig::default()
+    };
+    let mut wal = Wal::open_in(vfs.clone(), base.clone(), 1, &config).unwrap();
+    for _ in 0..10 {
+        wal.log(&mut WalEntry::Insert {
+            id: Uuid::new_v4(),
+            vector: vec![1.0; 4],
+            text: String::new(),
+            metadata: HashMap::new(),
+            seq: 0,
+        })
+        .unwrap();
+    }
+    wal.checkpoint(0).unwrap();
+    wal.rotate().unwrap();
+    wal.flush().unwrap();
+
+    let archived = list_archived_segments_in(&*vfs, &base).unwrap();
+    assert!(!archived.is_empty());
+    assert!(archived.iter().all(|segment| segment.size_bytes(&*vfs) > 0));
+    let history = Wal::read_history_in(&*vfs, &base, 0).unwrap();
+    assert_eq!(
+        history
+            .iter()
+            .map(|record| record.entry.seq())
+            .collect::<Vec<_>>(),
+        (1..=11).collect::<Vec<_>>()
+    );
+    assert!(Wal::inspect_in(&*vfs, &base).unwrap().is_empty());
+
+    set_aside_archive_in(&*vfs, &base, "old").unwrap();
+    assert!(list_archived_segments_in(&*vfs, &base).unwrap().is_empty());
+    assert!(memory
+        .files()
+        .iter()
+        .any(|file| file.starts_with("wal/wal_archive/archived.wal.db.old")));
+    assert!(!Path::new("wal").exists());
+}
+
+#[test]
+fn collection_in_memory_vfs_never_touches_disk() {
+    let vfs = MemoryVfs::new();
+    let path = ".piramid/tests/vfs_memory_collection.db";
+    let (kept, deleted) = {
+        let mut collection = open_in_memory(path, &vfs);
+        let kept = collection
+            .insert(Document::new(vec![1.0, 0.0, 0.0], "kept".into()))
+            .unwrap();
+        let deleted = collection
+            .insert(Document::new(vec![0.0, 1.0, 0.0], "deleted".into()))
+            .unwrap();
+        collection.checkpoint().unwrap();
+        collection.delete(&deleted).unwrap();
+        (kept, deleted)
+    };
+
+    assert!(!Path::new(path).exists());
+    assert!(vfs.exists(Path::new(path)));
+    assert!(load_manifest_in(&vfs, path).unwrap().is_some());
+
+    // The delete after the checkpoint is only in the WAL and comes back through replay
+    let collection = open_in_memory(path, &vfs);
+    assert_eq!(collection.count(), 1);
+    assert_eq!(collection.get(&kept).unwrap().unwrap().text, "kept");
+    assert!(collection.get(&deleted).unwrap().is_none());
+    let hits = collection
+        .search(&[1.0, 0.0, 0.0], 1, Metric::Cosine, SearchParams::default())
+        .unwrap();
+    assert_eq!(hits[0].id, kept);
+}
+
+#[test]
+fn collection_in_memory_vfs_compacts_and_reopens() {
+    let vfs = MemoryVfs::new();
+    let path = "vfs_compaction.db";
+    let ids = {
+        let mut collection = open_in_memory(path, &vfs);
+        let ids: Vec<Uuid> = (0..20)
+            .map(|i| {
+                collection
+                    .insert(Document::new(vec![i as f32, 1.0], format!("doc {i}")))
+                    .unwrap()
+            })
+            .collect();
+        for id in &ids[..10] {
+            collection.delete(id).unwrap();
+        }
+        let stats = compact(&mut collection).unwrap();
+        assert_eq!(stats.original_entries, 10);
+        assert_eq!(stats.compacted_entries, 10);
+        ids
+    };
+
+    assert!(!vfs.exists(Path::new("vfs_compaction.db.compact")));
+    let collection = open_in_memory(path, &vfs);
+    assert_eq!(collection.count(), 10);
+    for (i, id) in ids.iter().enumerate().skip(10) {
+        assert_eq!(
+            collection.get(id).unwrap().unwrap().text,
+            format!("doc {i}")
+        );
+    }
+    assert!(!Path::new(path).exists());
+}
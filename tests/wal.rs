@@ -0,0 +1,572 @@
+mod common;
+
+use common::{cleanup_collection, test_path};
+use piramid::{
+    storage::vfs::{Fault, FaultVfs, MemoryVfs, OsVfs},
+    storage::wal::{
+        list_archived_segments, list_segments, remove_wal_archive, remove_wal_files, Wal, WalEntry,
+    },
+    Collection, CollectionConfig, Document, WalConfig,
+};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+fn active_segment(wal: &Wal) -> PathBuf {
+    wal.segments().last().unwrap().path.clone()
+}
+
+fn insert_entry(value: f32) -> WalEntry {
+    WalEntry::Insert {
+        id: Uuid::new_v4(),
+        vector: vec![value; 8],
+        text: format!("doc {}", value),
+        metadata: HashMap::new(),
+        seq: 0,
+    }
+}
+
+#[test]
+fn torn_tail_is_truncated_and_reported() {
+    let path = test_path("wal_torn_tail.wal.db");
+    let mut wal = Wal::new(path.clone().into(), 1).unwrap();
+    for i in 0..3 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+    }
+    let segment = active_segment(&wal);
+    drop(wal);
+    let intact_len = fs::metadata(&segment).unwrap().len();
+
+    // Simulate a crash halfway through appending a fourth frame.
+    let scratch = test_path("wal_torn_tail_scratch.wal.db");
+    let mut torn = Wal::new(scratch.clone().into(), 4).unwrap();
+    torn.log(&mut insert_entry(3.0)).unwrap();
+    let frame = fs::read(active_segment(&torn)).unwrap()[8..].to_vec();
+    drop(torn);
+    remove_wal_files(Path::new(&scratch)).unwrap();
+    let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+    file.write_all(&frame[..frame.len() / 2]).unwrap();
+    drop(file);
+
+    let mut wal = Wal::new(path.clone().into(), 1).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert_eq!(replay.version, 3);
+    assert_eq!(
+        replay.entries.iter().map(WalEntry::seq).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    let truncated = replay.truncated.expect("torn frame should be reported");
+    assert_eq!(truncated.segment, segment);
+    assert_eq!(truncated.offset, intact_len);
+    assert_eq!(truncated.dropped_bytes, (frame.len() / 2) as u64);
+    assert_eq!(fs::metadata(&segment).unwrap().len(), intact_len);
+    assert_eq!(wal.next_seq, 4);
+
+    // Appends after recovery land right after the last good frame.
+    wal.log(&mut insert_entry(4.0)).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert!(replay.truncated.is_none());
+    assert_eq!(replay.entries.len(), 4);
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn checksum_mismatch_drops_frame_and_everything_after() {
+    let path = test_path("wal_bad_crc.wal.db");
+    let mut wal = Wal::new(path.clone().into(), 1).unwrap();
+    for i in 0..3 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+    }
+    let segment = active_segment(&wal);
+    drop(wal);
+
+    // Flip a byte inside the second frame's payload.
+    let mut bytes = fs::read(&segment).unwrap();
+    let first_len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+    let second_frame = 8 + 8 + first_len;
+    bytes[second_frame + 8 + 4] ^= 0xFF;
+    fs::write(&segment, &bytes).unwrap();
+
+    let replay = Wal::new(path.clone().into(), 4)
+        .unwrap()
+        .recover(0)
+        .unwrap();
+    assert_eq!(replay.entries.len(), 1);
+    let truncated = replay.truncated.unwrap();
+    assert_eq!(truncated.offset, second_frame as u64);
+    assert_eq!(truncated.reason, "checksum mismatch");
+    assert_eq!(fs::metadata(&segment).unwrap().len(), second_frame as u64);
+
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn version_one_wal_still_replays_and_upgrades_on_rotate() {
+    let path = test_path("wal_legacy.wal.db");
+    let first = Uuid::new_v4();
+    let second = Uuid::new_v4();
+    let legacy = format!(
+        "{{\"version\":1}}\n{}\n{}\n{{\"Insert\":{{\"id\":\"{}\",\"vec",
+        serde_json::json!({"Insert": {"id": first, "vector": [1.0, 2.0], "text": "a", "metadata": {}, "seq": 1}}),
+        serde_json::json!({"Delete": {"id": first, "seq": 2}}),
+        second,
+    );
+    fs::write(&path, legacy).unwrap();
+
+    let mut wal = Wal::new(path.clone().into(), 1).unwrap();
+    assert_eq!(wal.version(), 1);
+    let replay = wal.recover(0).unwrap();
+    assert_eq!(replay.version, 1);
+    assert_eq!(replay.entries.len(), 2);
+    assert!(matches!(replay.entries[0], WalEntry::Insert { id, .. } if id == first));
+    assert!(replay.truncated.is_some());
+
+    // Until rotation the file keeps its original format so it stays readable.
+    wal.log(&mut WalEntry::Delete { id: second, seq: 0 })
+        .unwrap();
+    assert_eq!(wal.replay(0).unwrap().len(), 3);
+
+    wal.rotate().unwrap();
+    assert_eq!(wal.version(), 3);
+    assert!(!Path::new(&path).exists());
+    wal.log(&mut insert_entry(1.0)).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert_eq!(replay.version, 3);
+    assert_eq!(replay.entries.len(), 1);
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn version_two_wal_keeps_untimed_frames_until_rotate() {
+    let path = test_path("wal_untimed.wal.db");
+    let mut bytes = b"PWAL".to_vec();
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    let payload = bincode::serialize(&WalEntry::Delete {
+        id: Uuid::new_v4(),
+        seq: 1,
+    })
+    .unwrap();
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    fs::write(&path, bytes).unwrap();
+
+    let mut wal = Wal::new(path.clone().into(), 2).unwrap();
+    assert_eq!(wal.version(), 2);
+    assert_eq!(wal.recover(0).unwrap().entries.len(), 1);
+    wal.log(&mut insert_entry(1.0)).unwrap();
+    let history = Wal::read_history(Path::new(&path), 0).unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|record| record.written_at.is_none()));
+
+    wal.rotate().unwrap();
+    assert_eq!(wal.version(), 3);
+    wal.log(&mut insert_entry(2.0)).unwrap();
+    let history = Wal::read_history(Path::new(&path), 0).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].entry.seq(), 3);
+    assert!(history[0].written_at.is_some());
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn failed_append_leaves_nothing_to_replay() {
+    let faults = Arc::new(FaultVfs::new(Arc::new(MemoryVfs::new())));
+    let path = PathBuf::from("/wal/full_disk.wal.db");
+    let config = WalConfig::default();
+    let mut wal = Wal::open_in(faults.clone(), path.clone(), 1, &config).unwrap();
+    wal.log(&mut insert_entry(1.0)).unwrap();
+
+    // The disk fills part way through the second frame, then frees up again
+    faults.inject(Fault::NoSpaceAfterBytes(10));
+    assert!(wal.log(&mut insert_entry(2.0)).is_err());
+    assert!(faults.is_full());
+    faults.clear();
+    let mut kept = insert_entry(3.0);
+    wal.log(&mut kept).unwrap();
+    drop(wal);
+
+    let mut wal = Wal::open_in(faults, path, 1, &config).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert!(replay.truncated.is_none());
+    assert_eq!(
+        replay.entries.iter().map(WalEntry::seq).collect::<Vec<_>>(),
+        vec![1, kept.seq()]
+    );
+    assert!(matches!(&replay.entries[1], WalEntry::Insert { text, .. } if text == "doc 3"));
+}
+
+#[test]
+fn retracted_entry_is_not_replayed_and_its_seq_is_reused() {
+    let vfs = Arc::new(MemoryVfs::new());
+    let path = PathBuf::from("/wal/retract.wal.db");
+    let config = WalConfig::default();
+    let mut wal = Wal::open_in(vfs.clone(), path.clone(), 1, &config).unwrap();
+    wal.log(&mut insert_entry(1.0)).unwrap();
+    let mut abandoned = insert_entry(2.0);
+    wal.log(&mut abandoned).unwrap();
+    wal.retract(abandoned.seq()).unwrap();
+    let mut kept = insert_entry(3.0);
+    wal.log(&mut kept).unwrap();
+    assert_eq!(kept.seq(), abandoned.seq());
+    // Only the last entry can be taken back
+    assert!(wal.retract(1).is_err());
+    assert!(wal.log(&mut insert_entry(4.0)).is_err());
+    drop(wal);
+
+    let mut wal = Wal::open_in(vfs, path, 1, &config).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert!(replay.truncated.is_none());
+    assert_eq!(replay.entries.len(), 2);
+    assert!(matches!(&replay.entries[1], WalEntry::Insert { text, .. } if text == "doc 3"));
+}
+
+#[test]
+fn retracted_batch_is_taken_back_as_a_whole() {
+    let vfs = Arc::new(MemoryVfs::new());
+    let path = PathBuf::from("/wal/retract_batch.wal.db");
+    let config = WalConfig::default();
+    let mut wal = Wal::open_in(vfs.clone(), path.clone(), 1, &config).unwrap();
+    wal.log(&mut insert_entry(1.0)).unwrap();
+    let mut batch: Vec<WalEntry> = (2..5).map(|value| insert_entry(value as f32)).collect();
+    wal.log_batch(&mut batch).unwrap();
+    assert_eq!(
+        batch.iter().map(WalEntry::seq).collect::<Vec<_>>(),
+        vec![2, 3, 4]
+    );
+    wal.retract(2).unwrap();
+    let mut kept = insert_entry(5.0);
+    wal.log(&mut kept).unwrap();
+    assert_eq!(kept.seq(), 2);
+    drop(wal);
+
+    let mut wal = Wal::open_in(vfs, path, 1, &config).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert!(replay.truncated.is_none());
+    assert_eq!(
+        replay.entries.iter().map(WalEntry::seq).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert!(matches!(&replay.entries[1], WalEntry::Insert { text, .. } if text == "doc 5"));
+}
+
+#[test]
+fn collection_reopens_after_torn_wal_append() {
+    let path = test_path("wal_torn_collection.db");
+    cleanup_collection(&path);
+
+    let mut collection = Collection::open(&path).unwrap();
+    let kept = collection
+        .insert(Document::new(vec![1.0, 0.0, 0.0], "kept".to_string()))
+        .unwrap();
+    drop(collection);
+
+    let wal_path = format!("{}.wal.db", path);
+    let segment = list_segments(Path::new(&wal_path))
+        .unwrap()
+        .pop()
+        .unwrap()
+        .path;
+    let mut file = OpenOptions::new().append(true).open(segment).unwrap();
+    file.write_all(&[0x40, 0, 0, 0, 0xde, 0xad]).unwrap();
+    drop(file);
+
+    let collection = Collection::open(&path).unwrap();
+    assert_eq!(collection.get(&kept).unwrap().unwrap().text, "kept");
+
+    drop(collection);
+    cleanup_collection(&path);
+}
+
+#[test]
+fn per_write_mode_syncs_before_returning() {
+    let path = test_path("wal_per_write.wal.db");
+    let mut wal = Wal::open(path.clone().into(), 1, &WalConfig::high_durability()).unwrap();
+    for i in 0..3 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+        assert!(wal.pending_ack().is_none());
+    }
+    assert_eq!(wal.sync().synced_seq(), 3);
+    assert_eq!(wal.sync().sync_count(), 3);
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn group_commit_shares_fsyncs_across_writers() {
+    let path = test_path("wal_group_commit.wal.db");
+    let config = WalConfig {
+        group_commit_window_ms: 20,
+        ..WalConfig::group_commit()
+    };
+    let wal = Arc::new(Mutex::new(
+        Wal::open(path.clone().into(), 1, &config).unwrap(),
+    ));
+
+    let writers = 8;
+    let handles: Vec<_> = (0..writers)
+        .map(|i| {
+            let wal = Arc::clone(&wal);
+            std::thread::spawn(move || {
+                // Append under the lock, then wait for durability with the lock released.
+                let (seq, ack) = {
+                    let mut wal = wal.lock().unwrap();
+                    wal.log(&mut insert_entry(i as f32)).unwrap();
+                    (wal.next_seq - 1, wal.pending_ack())
+                };
+                if let Some(ack) = ack {
+                    ack.wait().unwrap();
+                }
+                seq
+            })
+        })
+        .collect();
+    let seqs: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let wal = wal.lock().unwrap();
+    let synced = wal.sync().synced_seq();
+    assert!(seqs.iter().all(|seq| *seq <= synced));
+    assert_eq!(synced, writers as u64);
+    assert!(wal.pending_ack().is_none());
+    assert!(
+        wal.sync().sync_count() < writers as u64,
+        "expected batched fsyncs, got {}",
+        wal.sync().sync_count()
+    );
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn periodic_mode_syncs_every_wal_from_one_shared_thread() {
+    let config = WalConfig {
+        sync_mode: piramid::config::WalSyncMode::Periodic,
+        sync_interval_ms: 10,
+        ..WalConfig::default()
+    };
+    let mut wals: Vec<(String, Wal)> = (0..8)
+        .map(|i| {
+            let path = test_path(&format!("wal_periodic_{i}.wal.db"));
+            let wal = Wal::open(path.clone().into(), 1, &config).unwrap();
+            (path, wal)
+        })
+        .collect();
+    for (_, wal) in &mut wals {
+        wal.log(&mut insert_entry(1.0)).unwrap();
+        assert!(wal.pending_ack().is_none());
+    }
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while wals.iter().any(|(_, wal)| wal.sync().synced_seq() < 1) {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "periodic sync never ran"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let syncers = fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(|task| fs::read_to_string(task.unwrap().path().join("comm")).ok())
+            .filter(|name| name.trim() == "piramid-walsync")
+            .count();
+        assert_eq!(syncers, 1);
+    }
+
+    for (path, wal) in wals {
+        drop(wal);
+        remove_wal_files(Path::new(&path)).unwrap();
+    }
+}
+
+#[test]
+fn segments_roll_at_max_log_size_and_are_purged_by_checkpoint() {
+    let path = test_path("wal_segments.wal.db");
+    let config = WalConfig {
+        max_log_size: 256,
+        ..WalConfig::default()
+    };
+    let mut wal = Wal::open(path.clone().into(), 1, &config).unwrap();
+    for i in 0..10 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+    }
+
+    let segments = wal.segments().to_vec();
+    assert!(segments.len() > 2, "expected rollover, got {:?}", segments);
+    assert_eq!(segments[0].first_seq, 1);
+    assert!(segments
+        .windows(2)
+        .all(|pair| pair[0].first_seq < pair[1].first_seq));
+    for segment in &segments[..segments.len() - 1] {
+        assert!(segment.size_bytes(&OsVfs) >= 256);
+    }
+    assert_eq!(
+        list_segments(Path::new(&path)).unwrap(),
+        segments,
+        "every segment should be on disk until a checkpoint covers it"
+    );
+
+    // Replay walks every segment in order, including after a reopen.
+    drop(wal);
+    let mut wal = Wal::open(path.clone().into(), 1, &config).unwrap();
+    let seqs: Vec<u64> = wal.replay(0).unwrap().iter().map(WalEntry::seq).collect();
+    assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+    assert_eq!(wal.next_seq, 11);
+
+    wal.checkpoint(0).unwrap();
+    wal.rotate().unwrap();
+    assert_eq!(wal.segments().len(), 1);
+    assert_eq!(wal.segments()[0].first_seq, 12);
+    assert_eq!(list_segments(Path::new(&path)).unwrap().len(), 1);
+    assert!(wal.replay(11).unwrap().is_empty());
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+}
+
+#[test]
+fn damage_in_a_sealed_segment_fails_recovery_instead_of_dropping_later_segments() {
+    let path = test_path("wal_sealed_damage.wal.db");
+    let config = WalConfig {
+        max_log_size: 256,
+        ..WalConfig::default()
+    };
+    let mut wal = Wal::open(path.clone().into(), 1, &config).unwrap();
+    for i in 0..10 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+    }
+    let segments = wal.segments().to_vec();
+    assert!(segments.len() > 2, "expected rollover, got {:?}", segments);
+    drop(wal);
+
+    // Flip a byte inside the first frame's payload of the oldest segment
+    let mut bytes = fs::read(&segments[0].path).unwrap();
+    bytes[8 + 8 + 4] ^= 0xFF;
+    fs::write(&segments[0].path, &bytes).unwrap();
+
+    let mut wal = Wal::open(path.clone().into(), 11, &config).unwrap();
+    let error = wal.recover(0).unwrap_err().to_string();
+    assert!(error.contains("sealed WAL segment"), "{error}");
+    assert_eq!(list_segments(Path::new(&path)).unwrap(), segments);
+    assert_eq!(fs::read(&segments[0].path).unwrap(), bytes);
+
+    // Damage a checkpoint already covers does not matter
+    let covered = segments[1].first_seq - 1;
+    let replay = wal.recover(covered).unwrap();
+    assert!(replay.truncated.is_none());
+    assert_eq!(replay.entries.first().unwrap().seq(), covered + 1);
+    drop(wal);
+
+    // Repair moves the damaged segment and everything after it out of the log
+    let quarantined =
+        Wal::quarantine_damaged(&piramid::storage::vfs::OsVfs, Path::new(&path)).unwrap();
+    assert_eq!(quarantined.len(), segments.len());
+    assert!(quarantined.iter().all(|segment| segment.exists()));
+    let mut wal = Wal::open(path.clone().into(), 11, &config).unwrap();
+    let replay = wal.recover(0).unwrap();
+    assert!(replay.entries.is_empty());
+    assert!(replay.truncated.is_none());
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+    for segment in quarantined {
+        fs::remove_file(segment).unwrap();
+    }
+}
+
+#[test]
+fn archive_mode_keeps_purged_segments_for_history() {
+    let path = test_path("wal_archive.wal.db");
+    let _ = remove_wal_archive(Path::new(&path));
+    let config = WalConfig {
+        max_log_size: 256,
+        archive: true,
+        ..WalConfig::default()
+    };
+    let mut wal = Wal::open(path.clone().into(), 1, &config).unwrap();
+    for i in 0..10 {
+        wal.log(&mut insert_entry(i as f32)).unwrap();
+    }
+    wal.checkpoint(0).unwrap();
+    wal.rotate().unwrap();
+    wal.log(&mut insert_entry(10.0)).unwrap();
+
+    // The purged segments moved to the archive instead of being deleted.
+    assert_eq!(wal.segments().len(), 1);
+    let archived = list_archived_segments(Path::new(&path)).unwrap();
+    assert!(!archived.is_empty());
+    assert_eq!(archived[0].first_seq, 1);
+    assert_eq!(wal.replay(11).unwrap().len(), 1);
+
+    // History stitches archived and live segments back into one sequence.
+    let history = Wal::read_history(Path::new(&path), 0).unwrap();
+    assert_eq!(
+        history
+            .iter()
+            .map(|record| record.entry.seq())
+            .collect::<Vec<_>>(),
+        (1..=12).collect::<Vec<_>>()
+    );
+    assert!(history.iter().all(|record| record.written_at.is_some()));
+    let tail = Wal::read_history(Path::new(&path), 11).unwrap();
+    assert_eq!(
+        tail.iter()
+            .map(|record| record.entry.seq())
+            .collect::<Vec<_>>(),
+        vec![12]
+    );
+
+    drop(wal);
+    remove_wal_files(Path::new(&path)).unwrap();
+    remove_wal_archive(Path::new(&path)).unwrap();
+    assert!(list_archived_segments(Path::new(&path)).unwrap().is_empty());
+}
+
+#[test]
+fn collection_replays_writes_spread_over_segments() {
+    let path = test_path("wal_segment_collection.db");
+    cleanup_collection(&path);
+    let mut config = CollectionConfig::default();
+    config.wal.max_log_size = 512;
+
+    let mut collection = Collection::open_with_options(&path, config.clone().into()).unwrap();
+    let mut ids = Vec::new();
+    for i in 0..20 {
+        ids.push(
+            collection
+                .insert(Document::new(
+                    vec![i as f32, 1.0, 0.0],
+                    format!("doc {}", i),
+                ))
+                .unwrap(),
+        );
+    }
+    assert!(collection.checkpoint.wal.segments().len() > 1);
+    drop(collection);
+
+    let collection = Collection::open_with_options(&path, config.into()).unwrap();
+    assert_eq!(collection.count(), ids.len());
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(
+            collection.get(id).unwrap().unwrap().text,
+            format!("doc {}", i)
+        );
+    }
+    // Recovery checkpoints, which leaves only the fresh segment behind.
+    assert_eq!(collection.checkpoint.wal.segments().len(), 1);
+
+    drop(collection);
+    cleanup_collection(&path);
+}